@@ -46,6 +46,8 @@ pub struct ImageInfo {
     pub installation_type: String,
     /// 镜像描述
     pub description: String,
+    /// 处理器架构（"x86" / "x64" / "ARM" / "ARM64"，未知时为空）
+    pub architecture: String,
     /// Windows 主版本号
     pub major_version: Option<u16>,
     /// Windows 次版本号
@@ -108,6 +110,7 @@ fn parse_image_info_roxml(xml: &str) -> Option<Vec<ImageInfo>> {
             .unwrap_or(0);
         let installation_type = node_text(image, "INSTALLATIONTYPE").unwrap_or_default();
         let description = node_text(image, "DESCRIPTION").unwrap_or_default();
+        let architecture = node_text(image, "ARCH").map(|s| arch_name(&s)).unwrap_or_default();
         let major_version = node_text(image, "MAJOR").and_then(|s| s.parse::<u16>().ok());
         let minor_version = node_text(image, "MINOR").and_then(|s| s.parse::<u16>().ok());
         let name = build_image_name_node(image, &description, index);
@@ -118,6 +121,7 @@ fn parse_image_info_roxml(xml: &str) -> Option<Vec<ImageInfo>> {
             size_bytes,
             installation_type,
             description,
+            architecture,
             major_version,
             minor_version,
             image_type: WimImageType::Unknown,
@@ -132,6 +136,17 @@ fn parse_image_info_roxml(xml: &str) -> Option<Vec<ImageInfo>> {
     }
 }
 
+/// 将 `<ARCH>` 中的 PROCESSOR_ARCHITECTURE 数值转换为架构名称，未知数值原样返回
+pub fn arch_name(code: &str) -> String {
+    match code.trim() {
+        "0" => "x86".to_string(),
+        "5" => "ARM".to_string(),
+        "9" => "x64".to_string(),
+        "12" => "ARM64".to_string(),
+        other => other.to_string(),
+    }
+}
+
 /// 在某节点的所有后代里查找第一个指定标签元素的文本（去空白、过滤空串）。
 fn node_text(node: roxmltree::Node, tag: &str) -> Option<String> {
     node.descendants()
@@ -288,6 +303,9 @@ fn parse_image_info_fallback(xml: &str) -> Vec<ImageInfo> {
         let installation_type =
             extract_xml_tag(image_block, "INSTALLATIONTYPE").unwrap_or_default();
         let description = extract_xml_tag(image_block, "DESCRIPTION").unwrap_or_default();
+        let architecture = extract_xml_tag(image_block, "ARCH")
+            .map(|s| arch_name(&s))
+            .unwrap_or_default();
         let major_version = extract_version_number(image_block, "MAJOR");
         let minor_version = extract_version_number(image_block, "MINOR");
         let name = build_image_name(image_block, &description, parsed_index);
@@ -298,6 +316,7 @@ fn parse_image_info_fallback(xml: &str) -> Vec<ImageInfo> {
             size_bytes,
             installation_type,
             description,
+            architecture,
             major_version,
             minor_version,
             image_type: WimImageType::Unknown,
@@ -517,6 +536,19 @@ mod tests {
         assert_eq!(v[0].index, 3);
     }
 
+    // <ARCH> 数值 → 架构名称（完整解析与字符串扫描两条路径）
+    #[test]
+    fn architecture_parsed() {
+        let xml = r#"<WIM><IMAGE INDEX="1"><NAME>A</NAME><WINDOWS><ARCH>9</ARCH></WINDOWS></IMAGE>
+<IMAGE INDEX="2"><NAME>B</NAME><WINDOWS><ARCH>12</ARCH></WINDOWS></IMAGE></WIM>"#;
+        let v = parse_image_info_from_xml(xml);
+        assert_eq!(v[0].architecture, "x64");
+        assert_eq!(v[1].architecture, "ARM64");
+
+        let broken = r#"<WIM><IMAGE INDEX="1"><NAME>A & B</NAME><ARCH>0</ARCH></IMAGE></WIM>"#;
+        assert_eq!(parse_image_info_from_xml(broken)[0].architecture, "x86");
+    }
+
     // 完全没有名称信息 → 回退到“镜像 N”
     #[test]
     fn fallback_name_placeholder() {
@@ -535,6 +567,7 @@ mod tests {
             size_bytes: size,
             installation_type: it.into(),
             description: String::new(),
+            architecture: String::new(),
             major_version: major,
             minor_version: None,
             image_type: WimImageType::Unknown,
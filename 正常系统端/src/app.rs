@@ -1097,8 +1097,7 @@ impl App {
         self.start_remote_config_loading();
 
         // 设置默认下载路径
-        let exe_dir = crate::utils::path::get_exe_dir();
-        self.download_save_path = exe_dir.join("downloads").to_string_lossy().to_string();
+        self.download_save_path = crate::utils::path::get_downloads_dir().to_string_lossy().to_string();
 
         // 设置默认备份名称
         self.backup_name = format!("系统备份_{}", chrono::Local::now().format("%Y%m%d_%H%M%S"));
@@ -1220,8 +1219,7 @@ impl App {
         }
 
         // 设置默认下载路径
-        let exe_dir = crate::utils::path::get_exe_dir();
-        self.download_save_path = exe_dir.join("downloads").to_string_lossy().to_string();
+        self.download_save_path = crate::utils::path::get_downloads_dir().to_string_lossy().to_string();
 
         // 设置默认备份名称
         self.backup_name = format!("系统备份_{}", chrono::Local::now().format("%Y%m%d_%H%M%S"));
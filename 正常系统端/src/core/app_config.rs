@@ -36,6 +36,14 @@ pub struct AppConfig {
     /// PE 配置缓存（原 pe_cache.json，已并入 config.json）
     #[serde(default)]
     pub pe_cache: crate::download::config::PeCache,
+
+    /// 下载的时段限速规则
+    #[serde(default)]
+    pub bandwidth_schedule: crate::download::schedule::BandwidthSchedule,
+
+    /// 外部工具所在的 bin 目录，None 时使用环境变量 LETRECOVERY_BIN_DIR 或程序所在目录下的 bin
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bin_dir: Option<PathBuf>,
 }
 
 /// 日志默认启用
@@ -63,6 +71,8 @@ impl Default for AppConfig {
             log_retention_days: 7,  // 默认保留7天
             language: String::from("zh-CN"),  // 默认简体中文
             pe_cache: crate::download::config::PeCache::default(),
+            bandwidth_schedule: crate::download::schedule::BandwidthSchedule::default(),
+            bin_dir: None,
         }
     }
 }
@@ -185,6 +195,14 @@ impl AppConfig {
         self.log_enabled
     }
     
+    /// 设置下载的时段限速规则并保存（运行中的下载管理器需另行调用 `Aria2Manager::set_bandwidth_schedule`）
+    pub fn set_bandwidth_schedule(&mut self, schedule: crate::download::schedule::BandwidthSchedule) {
+        self.bandwidth_schedule = schedule;
+        if let Err(e) = self.save() {
+            log::warn!("保存配置失败: {}", e);
+        }
+    }
+
     /// 设置界面语言并保存
     /// 
     /// # Arguments
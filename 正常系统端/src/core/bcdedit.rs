@@ -3,7 +3,8 @@ use std::path::Path;
 
 use crate::utils::cmd::create_command;
 use crate::utils::encoding::gbk_to_utf8;
-use crate::utils::path::get_bin_dir;
+use crate::utils::path::{get_bin_dir, temp_file};
+use crate::utils::tool_error::{Tool, ToolError};
 
 pub struct BootManager {
     bcdedit_path: String,
@@ -53,7 +54,7 @@ impl BootManager {
 detail volume
 "#, drive_letter);
         
-        let script1_path = std::env::temp_dir().join("find_disk.txt");
+        let script1_path = temp_file("find_disk", "txt")?;
         std::fs::write(&script1_path, &script1)?;
         
         let output = create_command("diskpart")
@@ -91,7 +92,7 @@ detail volume
 list partition
 "#, disk_num);
         
-        let script2_path = std::env::temp_dir().join("list_part.txt");
+        let script2_path = temp_file("list_part", "txt")?;
         std::fs::write(&script2_path, &script2)?;
         
         let output = create_command("diskpart")
@@ -138,7 +139,7 @@ select partition {}
 assign letter=S
 "#, disk_num, esp_partition);
         
-        let script3_path = std::env::temp_dir().join("assign_esp.txt");
+        let script3_path = temp_file("assign_esp", "txt")?;
         std::fs::write(&script3_path, &script3)?;
         
         let output = create_command("diskpart")
@@ -195,7 +196,7 @@ assign letter=S
 list partition
 "#, disk);
             
-            let script_path = std::env::temp_dir().join("check_disk.txt");
+            let script_path = temp_file("check_disk", "txt")?;
             std::fs::write(&script_path, &script)?;
             
             let output = create_command("diskpart")
@@ -220,7 +221,7 @@ select partition {}
 assign letter=S
 "#, disk, part_num);
                                     
-                                    let assign_path = std::env::temp_dir().join("assign_esp2.txt");
+                                    let assign_path = temp_file("assign_esp", "txt")?;
                                     std::fs::write(&assign_path, &assign_script)?;
                                     
                                     let _ = create_command("diskpart")
@@ -364,9 +365,9 @@ assign letter=S
                                 ])
                                 .output()?;
                             
-                            let stderr = gbk_to_utf8(&output.stderr);
                             if !output.status.success() {
-                                anyhow::bail!("UEFI 引导修复失败: {}", stderr);
+                                println!("[BOOT] UEFI 引导修复失败");
+                                return Err(bcdboot_error(&output));
                             }
                         }
                     }
@@ -407,7 +408,8 @@ assign letter=S
                     println!("[BOOT] bcdboot (auto) stderr: {}", stderr);
                     
                     if !output.status.success() {
-                        anyhow::bail!("引导修复失败: {}", stderr);
+                        println!("[BOOT] 引导修复失败");
+                        return Err(bcdboot_error(&output));
                     }
                 }
             }
@@ -450,9 +452,9 @@ assign letter=S
                     .args([&windows_path, "/l", "zh-cn"])
                     .output()?;
                 
-                let stderr = gbk_to_utf8(&output.stderr);
                 if !output.status.success() {
-                    anyhow::bail!("Legacy 引导修复失败: {}", stderr);
+                    println!("[BOOT] Legacy 引导修复失败");
+                    return Err(bcdboot_error(&output));
                 }
             }
             
@@ -474,3 +476,10 @@ impl Default for BootManager {
         Self::new()
     }
 }
+
+/// bcdboot 执行失败的错误（bcdboot 把错误信息写到标准输出）
+fn bcdboot_error(output: &std::process::Output) -> anyhow::Error {
+    let stderr = gbk_to_utf8(&output.stderr);
+    let detail = if stderr.trim().is_empty() { gbk_to_utf8(&output.stdout) } else { stderr };
+    ToolError::from_exit(Tool::Bcdboot, output.status.code(), &detail).into()
+}
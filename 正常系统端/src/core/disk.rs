@@ -2,7 +2,7 @@ use anyhow::Result;
 use std::path::Path;
 use crate::utils::cmd::create_command;
 use crate::utils::encoding::gbk_to_utf8;
-use crate::utils::path::get_bin_dir;
+use crate::utils::path::{get_bin_dir, temp_file};
 use crate::core::bitlocker::{BitLockerManager, VolumeStatus};
 
 #[cfg(windows)]
@@ -383,8 +383,7 @@ impl DiskManager {
             new_letter.chars().next().unwrap_or('Y').to_ascii_lowercase()
         );
 
-        let temp_dir = std::env::temp_dir();
-        let script_path = temp_dir.join("dp_script.txt");
+        let script_path = temp_file("dp_script", "txt")?;
         std::fs::write(&script_path, &script_content)?;
 
         let output = create_command(&get_diskpart_path())
@@ -403,8 +402,7 @@ impl DiskManager {
             partition_letter.chars().next().unwrap_or('Y')
         );
 
-        let temp_dir = std::env::temp_dir();
-        let script_path = temp_dir.join("dp_delete.txt");
+        let script_path = temp_file("dp_delete", "txt")?;
         std::fs::write(&script_path, &script_content)?;
 
         let output = create_command(&get_diskpart_path())
@@ -539,6 +537,63 @@ impl DiskManager {
         false
     }
 
+    /// 检查指定盘符是否为可移动磁盘（U 盘、读卡器等）
+    #[cfg(windows)]
+    pub fn is_removable_drive(letter: char) -> bool {
+        let path = format!("{}:\\", letter);
+        let wide_path: Vec<u16> = path.encode_utf16().chain(std::iter::once(0)).collect();
+        unsafe {
+            let drive_type = GetDriveTypeW(PCWSTR(wide_path.as_ptr()));
+            drive_type == DRIVE_REMOVABLE
+        }
+    }
+
+    #[cfg(not(windows))]
+    pub fn is_removable_drive(_letter: char) -> bool {
+        false
+    }
+
+    /// 检查指定盘符是否为映射的网络驱动器
+    #[cfg(windows)]
+    pub fn is_network_drive(letter: char) -> bool {
+        let path = format!("{}:\\", letter);
+        let wide_path: Vec<u16> = path.encode_utf16().chain(std::iter::once(0)).collect();
+        unsafe {
+            let drive_type = GetDriveTypeW(PCWSTR(wide_path.as_ptr()));
+            drive_type == DRIVE_REMOTE
+        }
+    }
+
+    #[cfg(not(windows))]
+    pub fn is_network_drive(_letter: char) -> bool {
+        false
+    }
+
+    /// 获取指定分区的卷标，读取失败时返回 None
+    #[cfg(windows)]
+    pub fn get_volume_label(partition: &str) -> Option<String> {
+        let path = format!("{}\\", partition);
+        let wide_path: Vec<u16> = path.encode_utf16().chain(std::iter::once(0)).collect();
+        let mut volume_name = [0u16; 261];
+        unsafe {
+            GetVolumeInformationW(
+                PCWSTR(wide_path.as_ptr()),
+                Some(&mut volume_name),
+                None,
+                None,
+                None,
+                None,
+            )
+            .ok()?;
+        }
+        Some(String::from_utf16_lossy(&volume_name).trim_end_matches('\0').to_string())
+    }
+
+    #[cfg(not(windows))]
+    pub fn get_volume_label(_partition: &str) -> Option<String> {
+        None
+    }
+
     /// 获取指定分区的剩余空间（字节）
     #[cfg(windows)]
     pub fn get_free_space_bytes(partition: &str) -> Option<u64> {
@@ -606,8 +661,7 @@ impl DiskManager {
             letter
         );
 
-        let temp_dir = std::env::temp_dir();
-        let script_path = temp_dir.join("lr_query_shrink.txt");
+        let script_path = temp_file("query_shrink", "txt")?;
         std::fs::write(&script_path, &script_content)?;
 
         // 首先尝试使用内置 diskpart，如果失败则使用系统 diskpart
@@ -849,8 +903,7 @@ impl DiskManager {
             new_letter
         );
 
-        let temp_dir = std::env::temp_dir();
-        let script_path = temp_dir.join("lr_shrink_script.txt");
+        let script_path = temp_file("shrink_script", "txt")?;
         std::fs::write(&script_path, &script_content)?;
 
         println!("[DISK] Diskpart 脚本内容:\n{}", script_content);
@@ -942,8 +995,7 @@ impl DiskManager {
             letter
         );
 
-        let temp_dir = std::env::temp_dir();
-        let script_path = temp_dir.join("lr_delete_script.txt");
+        let script_path = temp_file("delete_script", "txt")?;
         std::fs::write(&script_path, &script_content)?;
 
         let output = create_command(&get_diskpart_path())
@@ -30,6 +30,10 @@ pub struct DismProgress {
 pub struct ImageInfo {
     pub index: u32,
     pub name: String,
+    /// 镜像描述（XML 中的 DESCRIPTION）
+    pub description: String,
+    /// 处理器架构（"x86" / "x64" / "ARM64" 等，未知时为空）
+    pub architecture: String,
     pub size_bytes: u64,
     /// 安装类型，用于过滤 WindowsPE 等非系统镜像
     /// 值如: "Client", "WindowsPE", "Server" 等
@@ -44,6 +48,95 @@ pub struct ImageInfo {
     pub verified_installable: bool,
 }
 
+/// WIM 文件头长度
+const WIM_HEADER_SIZE: usize = 208;
+
+/// 镜像文件本身有问题（而不是缺少 wimlib 等环境问题），调用方可据此提示用户重新下载
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ImageInfoError {
+    #[error("镜像文件 {} 似乎已损坏或不完整（{reason}），是否重新下载？", path.display())]
+    Damaged { path: PathBuf, reason: String },
+}
+
+/// WIM/ESD 文件头中读取镜像信息用到的字段
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct WimHeader {
+    /// 可流式传输的 WIM（wimlib 的 pipable 格式），XML 不在文件头指向的固定位置
+    pipable: bool,
+    image_count: u32,
+    xml_offset: u64,
+    xml_size: u64,
+}
+
+impl WimHeader {
+    fn parse(header: &[u8]) -> std::result::Result<Self, String> {
+        if header.len() < WIM_HEADER_SIZE {
+            return Err(format!("文件只有 {} 字节，不足一个文件头", header.len()));
+        }
+        let pipable = match &header[0..8] {
+            b"MSWIM\0\0\0" => false,
+            b"WLPWM\0\0\0" => true,
+            _ => return Err("文件头不是 WIM/ESD 格式".to_string()),
+        };
+        let u32_at = |pos: usize| u32::from_le_bytes(header[pos..pos + 4].try_into().unwrap());
+        let u64_at = |pos: usize| u64::from_le_bytes(header[pos..pos + 8].try_into().unwrap());
+        // XML 资源头位于偏移 72：7 字节大小 + 1 字节标志，随后是 8 字节偏移
+        Ok(Self {
+            pipable,
+            image_count: u32_at(44),
+            xml_size: u64_at(72) & 0x00FF_FFFF_FFFF_FFFF,
+            xml_offset: u64_at(80),
+        })
+    }
+
+    /// 检查 XML 元数据是否完整地落在文件内，返回损坏原因
+    fn damage(&self, file_len: u64) -> Option<String> {
+        if self.pipable {
+            return None;
+        }
+        if self.image_count == 0 || self.xml_offset == 0 || self.xml_size == 0 {
+            return Some("文件头中没有镜像元数据".to_string());
+        }
+        match self.xml_offset.checked_add(self.xml_size) {
+            Some(end) if end <= file_len => None,
+            _ => Some(format!(
+                "文件大小 {} 字节，镜像元数据却位于 {} 之后，文件可能没有下载完整",
+                file_len, self.xml_offset
+            )),
+        }
+    }
+
+    fn read(image_file: &str) -> std::result::Result<(Self, u64), String> {
+        use std::io::Read;
+
+        let file = std::fs::File::open(image_file).map_err(|e| format!("无法打开文件: {}", e))?;
+        let file_len = file.metadata().map(|m| m.len()).unwrap_or(0);
+        let mut header = Vec::with_capacity(WIM_HEADER_SIZE);
+        file.take(WIM_HEADER_SIZE as u64)
+            .read_to_end(&mut header)
+            .map_err(|e| format!("读取文件头失败: {}", e))?;
+        Ok((Self::parse(&header)?, file_len))
+    }
+}
+
+/// 检查 WIM/ESD 文件头是否完好，返回损坏原因（无法打开文件时不算损坏）
+fn wim_damage(image_file: &str) -> Option<String> {
+    if !Path::new(image_file).is_file() {
+        return None;
+    }
+    match WimHeader::read(image_file) {
+        Ok((header, file_len)) => header.damage(file_len),
+        Err(reason) => Some(reason),
+    }
+}
+
+/// 列出 WIM/ESD/SWM 中的所有镜像（索引、名称、描述、架构、大小等）
+///
+/// 文件截断或不是有效镜像时返回 [`ImageInfoError::Damaged`]。
+pub fn get_image_info(path: &Path) -> Result<Vec<ImageInfo>> {
+    Dism::new().get_image_info(&path.to_string_lossy())
+}
+
 pub struct Dism {
     is_pe: bool,
 }
@@ -384,6 +477,8 @@ impl Dism {
                         return Ok(images.into_iter().map(|img| ImageInfo {
                             index: img.index,
                             name: img.name,
+                            description: img.description,
+                            architecture: img.architecture,
                             size_bytes: img.size_bytes,
                             installation_type: img.installation_type,
                             major_version: img.major_version,
@@ -418,6 +513,11 @@ impl Dism {
             }
         }
 
+        if let Some(reason) = wim_damage(image_file) {
+            println!("[Dism] 镜像文件头检查未通过: {}", reason);
+            return Err(ImageInfoError::Damaged { path: PathBuf::from(image_file), reason }.into());
+        }
+
         anyhow::bail!("无法获取镜像信息：wimlib 打开文件失败。可能原因：1.镜像文件损坏 2.libwim-15.dll 缺失或版本过旧不支持此格式（程序会自动释放内置的 libwim-15.dll 到程序目录，请确认其存在）")
     }
 
@@ -512,24 +612,21 @@ impl Dism {
 
         println!("[Dism] 尝试直接解析 WIM XML 元数据: {}", image_file);
 
-        let mut file = File::open(image_file)?;
-        let mut header = [0u8; 208];
-        file.read_exact(&mut header)?;
-
-        let signature = &header[0..8];
-        if signature != b"MSWIM\0\0\0" {
-            anyhow::bail!("不是有效的 WIM 文件");
+        let (header, file_len) = WimHeader::read(image_file).map_err(|e| anyhow::anyhow!(e))?;
+        if header.pipable {
+            anyhow::bail!("可流式传输的 WIM 需要 wimlib 读取");
         }
-
-        let xml_offset = u64::from_le_bytes(header[48..56].try_into().unwrap());
-        let xml_size = u64::from_le_bytes(header[56..64].try_into().unwrap());
-
-        if xml_offset == 0 || xml_size == 0 || xml_size > 100_000_000 {
+        if let Some(reason) = header.damage(file_len) {
+            anyhow::bail!(reason);
+        }
+        let WimHeader { xml_offset, xml_size, .. } = header;
+        if xml_size > 100_000_000 {
             anyhow::bail!("XML 元数据位置无效");
         }
 
         println!("[Dism] XML 偏移: {}, 大小: {}", xml_offset, xml_size);
 
+        let mut file = File::open(image_file)?;
         file.seek(SeekFrom::Start(xml_offset))?;
         let mut xml_data = vec![0u8; xml_size as usize];
         file.read_exact(&mut xml_data)?;
@@ -621,6 +718,12 @@ impl Dism {
                     let size_bytes = Self::extract_xml_tag(image_block, "TOTALBYTES")
                         .and_then(|s| s.parse().ok())
                         .unwrap_or(0);
+
+                    let description = Self::extract_xml_tag(image_block, "DESCRIPTION")
+                        .unwrap_or_default();
+                    let architecture = Self::extract_xml_tag(image_block, "ARCH")
+                        .map(|s| lr_core::image_meta::arch_name(&s))
+                        .unwrap_or_default();
                     
                     let installation_type = Self::extract_xml_tag(image_block, "INSTALLATIONTYPE")
                         .unwrap_or_default();
@@ -645,6 +748,8 @@ impl Dism {
                         images.push(ImageInfo {
                             index,
                             name,
+                            description,
+                            architecture,
                             size_bytes,
                             installation_type,
                             major_version,
@@ -758,3 +863,34 @@ impl Default for Dism {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(image_count: u32, xml_offset: u64, xml_size: u64) -> Vec<u8> {
+        let mut h = vec![0u8; WIM_HEADER_SIZE];
+        h[0..8].copy_from_slice(b"MSWIM\0\0\0");
+        h[44..48].copy_from_slice(&image_count.to_le_bytes());
+        // 大小的最高字节是资源标志，不应计入大小
+        h[72..80].copy_from_slice(&(xml_size | (0x02 << 56)).to_le_bytes());
+        h[80..88].copy_from_slice(&xml_offset.to_le_bytes());
+        h
+    }
+
+    #[test]
+    fn test_wim_header_damage() {
+        let parsed = WimHeader::parse(&header(2, 4096, 1000)).unwrap();
+        assert_eq!((parsed.xml_offset, parsed.xml_size), (4096, 1000));
+        assert_eq!(parsed.damage(5096), None);
+        // 下载被截断：XML 元数据在文件末尾之后
+        assert!(parsed.damage(5000).is_some());
+        assert!(WimHeader::parse(&header(0, 4096, 1000)).unwrap().damage(5096).is_some());
+
+        assert!(WimHeader::parse(&header(2, 4096, 1000)[..100]).is_err());
+        assert!(WimHeader::parse(b"<html>404 Not Found</html>").is_err());
+        let mut pipable = header(2, 0, 0);
+        pipable[0..8].copy_from_slice(b"WLPWM\0\0\0");
+        assert_eq!(WimHeader::parse(&pipable).unwrap().damage(10), None);
+    }
+}
@@ -4,6 +4,7 @@
 //! - 离线驱动导入（Add-Driver）
 //! - 离线 CAB 包导入（Add-Package）
 //! - 驱动导出
+//! - ESD 转换为 WIM（Export-Image，bin 目录有 wimlib-imagex 时优先使用）
 //!
 //! 优先使用程序目录下的 `bin\Dism\dism.exe`，
 //! 如果不存在则回退到系统 DISM。
@@ -20,7 +21,9 @@ use anyhow::{bail, Context, Result};
 
 use crate::utils::command::new_command;
 use crate::utils::encoding::gbk_to_utf8;
-use crate::utils::path::get_exe_dir;
+use crate::utils::cmd::{kill_process_tree, spawn_streaming, OutputLine};
+use crate::utils::path::{get_bin_dir, get_temp_dir, session_temp_dir, to_extended_path, volume_space};
+use crate::utils::tool_error::{Tool, ToolError};
 
 /// DISM 操作进度
 #[derive(Debug, Clone)]
@@ -58,8 +61,8 @@ impl DismCmd {
 
     /// 查找 DISM 可执行文件
     fn find_dism_executable() -> Result<PathBuf> {
-        // 优先级1: 程序目录下的 bin\Dism\dism.exe
-        let local_dism = get_exe_dir().join("bin").join("Dism").join("dism.exe");
+        // 优先级1: bin 目录下的 Dism\dism.exe
+        let local_dism = get_bin_dir().join("Dism").join("dism.exe");
         if local_dism.exists() {
             log::info!("[DismCmd] 找到本地 DISM: {}", local_dism.display());
             return Ok(local_dism);
@@ -177,16 +180,16 @@ impl DismCmd {
             }
         }
 
-        // 尝试使用程序运行目录下的 temp 目录
-        let exe_temp = get_exe_dir().join("temp");
+        // 尝试使用程序的临时目录（便携模式下在程序所在目录）
+        let exe_temp = get_temp_dir();
         if std::fs::create_dir_all(&exe_temp).is_ok() {
             let temp_str = exe_temp.to_string_lossy().to_string();
             log::info!("[DismCmd] 使用程序临时目录: {}", temp_str);
             return temp_str;
         }
 
-        // 最后回退到系统临时目录
-        let system_temp = std::env::temp_dir();
+        // 最后回退到本次运行的临时目录（系统临时目录下，退出时清理）
+        let system_temp = session_temp_dir().unwrap_or_else(|_| std::env::temp_dir());
         let temp_str = system_temp.to_string_lossy().to_string();
         log::info!("[DismCmd] 使用系统临时目录: {}", temp_str);
 
@@ -638,15 +641,14 @@ impl DismCmd {
                 }
             };
 
-            let error_msg = if !stderr.trim().is_empty() {
+            let detail = if !stderr.trim().is_empty() {
                 stderr
-            } else if !stdout.trim().is_empty() {
-                Self::extract_error_from_output(&stdout)
             } else {
-                format!("DISM 退出码: {:?}", output.status.code())
+                Self::extract_error_from_output(&stdout)
             };
+            log::warn!("[DismCmd] 失败输出: {}", detail);
 
-            bail!("DISM 操作失败: {}", error_msg);
+            return Err(ToolError::from_exit(Tool::Dism, output.status.code(), &detail).into());
         }
 
         Ok(stdout)
@@ -684,7 +686,8 @@ impl DismCmd {
                     Self::send_progress(&progress_tx, 100, &format!("{}完成", operation_name));
                     Ok(())
                 } else {
-                    bail!("{}失败，退出代码: {:?}", operation_name, status.code())
+                    log::warn!("[DismCmd] {}失败", operation_name);
+                    Err(ToolError::from_exit(Tool::Dism, status.code(), "").into())
                 }
             }
             Err(e) => Err(e),
@@ -892,6 +895,185 @@ impl Default for DismCmd {
     }
 }
 
+// ============================================================================
+// ESD 转换为 WIM
+// ============================================================================
+
+/// 转换后的 WIM 约为 ESD 的 1.5 倍（LZX 的压缩率低于 ESD 的 LZMS）
+const WIM_SIZE_FACTOR: f64 = 1.5;
+
+/// bin 目录下依次查找的 wimlib-imagex
+const WIMLIB_IMAGEX_CANDIDATES: [&str; 2] = ["wimlib-imagex.exe", r"wimlib\wimlib-imagex.exe"];
+
+/// `convert_esd_to_wim` 在调用转换工具之前或被取消时的失败原因（工具本身的失败以 `ToolError` 返回）
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum EsdConvertError {
+    #[error("镜像中没有索引 {index}（可用的索引: {available:?}）")]
+    IndexNotFound { index: u32, available: Vec<u32> },
+
+    #[error("{} 已存在，请先删除或换一个文件名（DISM 会把镜像追加到已有的文件中）", path.display())]
+    DestinationExists { path: PathBuf },
+
+    #[error("目标磁盘空间不足：预计需要 {} MB，剩余 {} MB", required / (1024 * 1024), available / (1024 * 1024))]
+    InsufficientSpace { required: u64, available: u64 },
+
+    #[error("转换已取消")]
+    Cancelled,
+}
+
+/// 把 ESD 中的一个镜像导出为 LZX 压缩（`/Compress:max`）的 WIM
+///
+/// 先按镜像信息确认 `index` 存在，并确认目标磁盘剩余空间不少于 ESD 的 1.5 倍。
+/// bin 目录有 wimlib-imagex 时用它转换（更快），否则用 DISM。
+/// `progress_cb(百分比)` 返回 false 时结束转换工具；失败或取消时删除不完整的 `wim_path`。
+pub async fn convert_esd_to_wim(
+    esd_path: &Path,
+    index: u32,
+    wim_path: &Path,
+    mut progress_cb: impl FnMut(u32) -> bool,
+) -> Result<()> {
+    let image_file = esd_path.to_string_lossy().to_string();
+    let images = tokio::task::spawn_blocking(move || crate::core::dism::Dism::new().get_image_info(&image_file)).await??;
+    let available: Vec<u32> = images.iter().map(|image| image.index).collect();
+    let esd_size = std::fs::metadata(to_extended_path(esd_path))
+        .with_context(|| format!("读取 {} 失败", esd_path.display()))?
+        .len();
+    let target_dir = wim_path.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let free = match volume_space(target_dir) {
+        Ok(space) => Some(space.free),
+        Err(e) => {
+            log::warn!("[DismCmd] 无法读取 {} 的剩余空间，跳过检查: {}", target_dir.display(), e);
+            None
+        }
+    };
+    check_export(index, &available, wim_path, to_extended_path(wim_path).exists(), esd_size, free)?;
+
+    let wimlib = WIMLIB_IMAGEX_CANDIDATES
+        .iter()
+        .map(|name| get_bin_dir().join(name))
+        .find(|path| path.is_file());
+    let (tool, program, args) = match wimlib {
+        Some(wimlib) => (
+            Tool::Wimlib,
+            wimlib,
+            vec![
+                "export".to_string(),
+                esd_path.display().to_string(),
+                index.to_string(),
+                wim_path.display().to_string(),
+                "--compress=LZX".to_string(),
+            ],
+        ),
+        None => (
+            Tool::Dism,
+            DismCmd::find_dism_executable()?,
+            vec![
+                "/Export-Image".to_string(),
+                format!("/SourceImageFile:{}", esd_path.display()),
+                format!("/SourceIndex:{}", index),
+                format!("/DestinationImageFile:{}", wim_path.display()),
+                "/Compress:max".to_string(),
+            ],
+        ),
+    };
+    log::info!(
+        "[DismCmd] 使用 {} 将 {} 的索引 {} 转换为 {}",
+        tool,
+        esd_path.display(),
+        index,
+        wim_path.display()
+    );
+
+    export_or_clean_up(tool, &program, &args, wim_path, &mut progress_cb).await
+}
+
+/// 转换前的检查：`index` 在 `available` 中、`wim_path` 尚不存在、剩余空间 `free`（读不到时为 None，跳过）不少于 ESD 的 1.5 倍
+fn check_export(
+    index: u32,
+    available: &[u32],
+    wim_path: &Path,
+    destination_exists: bool,
+    esd_size: u64,
+    free: Option<u64>,
+) -> std::result::Result<(), EsdConvertError> {
+    if !available.contains(&index) {
+        return Err(EsdConvertError::IndexNotFound {
+            index,
+            available: available.to_vec(),
+        });
+    }
+    if destination_exists {
+        return Err(EsdConvertError::DestinationExists {
+            path: wim_path.to_path_buf(),
+        });
+    }
+    let required = (esd_size as f64 * WIM_SIZE_FACTOR) as u64;
+    match free {
+        Some(free) if free < required => Err(EsdConvertError::InsufficientSpace {
+            required,
+            available: free,
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// 运行导出命令，失败或取消时删除不完整的 `wim_path`
+async fn export_or_clean_up(
+    tool: Tool,
+    program: &Path,
+    args: &[String],
+    wim_path: &Path,
+    progress_cb: &mut impl FnMut(u32) -> bool,
+) -> Result<()> {
+    let result = run_export(tool, program, args, progress_cb).await;
+    if let Err(e) = &result {
+        log::warn!("[DismCmd] 转换失败，删除不完整的 {}: {}", wim_path.display(), e);
+        // 刚结束的转换工具可能还没释放文件句柄
+        for _ in 0..5 {
+            match std::fs::remove_file(to_extended_path(wim_path)) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                    tokio::time::sleep(std::time::Duration::from_millis(200)).await
+                }
+                _ => break,
+            }
+        }
+    }
+    result
+}
+
+/// 运行导出命令，按输出中的百分比回调进度；退出码非 0 时返回 `ToolError`
+async fn run_export(tool: Tool, program: &Path, args: &[String], progress_cb: &mut impl FnMut(u32) -> bool) -> Result<()> {
+    let arg_refs: Vec<&str> = args.iter().map(String::as_str).collect();
+    let mut child = spawn_streaming(program, &arg_refs)?;
+    // DISM 把错误信息写到标准输出，两者都保留（不含进度行）作为错误详情
+    let mut messages = Vec::new();
+    let mut last = None;
+    while let Some(line) = child.next_line().await {
+        let (OutputLine::Stdout(line) | OutputLine::Stderr(line)) = line;
+        match DismCmd::parse_progress_line(&line) {
+            Some(percent) if last != Some(percent) => {
+                last = Some(percent);
+                if !progress_cb(percent as u32) {
+                    if let Some(pid) = child.pid() {
+                        let _ = kill_process_tree(pid);
+                    }
+                    return Err(EsdConvertError::Cancelled.into());
+                }
+            }
+            Some(_) => {}
+            None if line.trim().is_empty() => {}
+            None => messages.push(line.trim().to_string()),
+        }
+    }
+    match child.wait().await? {
+        Some(0) => {
+            log::info!("[DismCmd] 转换完成");
+            Ok(())
+        }
+        code => Err(ToolError::from_exit(tool, code, &messages.join("\n")).into()),
+    }
+}
+
 // ============================================================================
 // 便捷函数
 // ============================================================================
@@ -947,6 +1129,69 @@ mod tests {
         assert_eq!(DismCmd::parse_progress_line("Processing: 75%"), Some(75));
         assert_eq!(DismCmd::parse_progress_line("完成 100.0%"), Some(100));
         assert_eq!(DismCmd::parse_progress_line("No progress here"), None);
+        // wimlib-imagex export 的进度行
+        assert_eq!(
+            DismCmd::parse_progress_line("Exporting image: 512 MiB of 2048 MiB (25%) done"),
+            Some(25)
+        );
+        assert_eq!(
+            DismCmd::parse_progress_line("\rExporting image: 2048 MiB of 2048 MiB (100%) done"),
+            Some(100)
+        );
+    }
+
+    #[test]
+    fn test_check_export() {
+        let wim = Path::new(r"D:\out\install.wim");
+        assert_eq!(check_export(2, &[1, 2, 3], wim, false, 1000, Some(1500)), Ok(()));
+        assert_eq!(check_export(2, &[1, 2, 3], wim, false, 1000, None), Ok(()));
+        assert_eq!(
+            check_export(6, &[1, 2, 3], wim, false, 1000, Some(u64::MAX)),
+            Err(EsdConvertError::IndexNotFound {
+                index: 6,
+                available: vec![1, 2, 3]
+            })
+        );
+        assert_eq!(
+            check_export(1, &[1], wim, true, 1000, Some(u64::MAX)),
+            Err(EsdConvertError::DestinationExists { path: wim.to_path_buf() })
+        );
+        assert_eq!(
+            check_export(1, &[1], wim, false, 1000, Some(1499)),
+            Err(EsdConvertError::InsufficientSpace {
+                required: 1500,
+                available: 1499
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_failed_export_removes_partial_wim() {
+        let dir = std::env::temp_dir().join(format!("lr_esd_export_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let wim = dir.join("partial.wim");
+        std::fs::write(&wim, b"MSWIM\0\0\0").unwrap();
+
+        // 模拟写了一半就失败的转换工具
+        let args = ["/C".to_string(), "echo 50.0% && exit 3".to_string()];
+        let mut seen = Vec::new();
+        let error = export_or_clean_up(Tool::Dism, Path::new("cmd.exe"), &args, &wim, &mut |p| {
+            seen.push(p);
+            true
+        })
+        .await
+        .unwrap_err();
+        assert!(error.is::<ToolError>());
+        assert_eq!(seen, vec![50]);
+        assert!(!wim.exists());
+
+        std::fs::write(&wim, b"MSWIM\0\0\0").unwrap();
+        let error = export_or_clean_up(Tool::Dism, Path::new("cmd.exe"), &args, &wim, &mut |_| false)
+            .await
+            .unwrap_err();
+        assert_eq!(error.downcast_ref::<EsdConvertError>(), Some(&EsdConvertError::Cancelled));
+        assert!(!wim.exists());
+        let _ = std::fs::remove_dir_all(&dir);
     }
 
     #[test]
@@ -120,8 +120,7 @@ impl DismApi {
 
         // 1) 随附的 bin\Dism\dismapi.dll —— 用 LOAD_WITH_ALTERED_SEARCH_PATH 让其依赖
         //    (dismcore.dll / providers 等)从该 DLL 所在目录解析，而不是仅从系统目录。
-        let bundled = crate::utils::path::get_exe_dir()
-            .join("bin")
+        let bundled = crate::utils::path::get_bin_dir()
             .join("Dism")
             .join("dismapi.dll");
         if bundled.exists() {
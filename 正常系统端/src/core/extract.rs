@@ -0,0 +1,406 @@
+//! 压缩包解压（驱动包、工具包等 .zip/.7z），调用 bin 目录中自带的 7-Zip
+//!
+//! 7-Zip 以 `-bsp1` 把完成百分比写到标准输出（用退格符改写同一行），逐行读取后回调进度。
+//! 退出码 1 表示有警告（如个别文件无法写入）但解压已完成，返回 `ExtractOutcome`；
+//! 2 及以上为失败，返回 `ToolError`，并清理本次在目标目录中新建的文件，以免后续步骤用到解压了一半的目录。
+
+use anyhow::Result;
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::utils::cmd::{spawn_streaming, OutputLine};
+use crate::utils::path::{get_bin_dir, to_extended_path};
+use crate::utils::tool_error::{Tool, ToolError};
+
+/// bin 目录下依次查找的 7-Zip 程序（完整版 7z.exe 支持的格式更多）
+const SEVEN_ZIP_CANDIDATES: [&str; 4] = ["7z.exe", "7za.exe", r"7z\7z.exe", r"7z\7za.exe"];
+
+/// 7-Zip 表示有警告、但操作已完成的退出码
+const EXIT_WARNING: i32 = 1;
+
+/// 没有提供密码时使用的占位密码（见 `password_arg`）
+const NO_PASSWORD: &str = "letrecovery-no-password";
+
+/// 清理失败时的重试次数和间隔（取消后 7-Zip 进程可能还没完全退出，仍占用文件）
+const CLEANUP_ATTEMPTS: u32 = 5;
+const CLEANUP_RETRY_DELAY: Duration = Duration::from_millis(200);
+
+/// 解压参数
+#[derive(Debug, Clone, Default)]
+pub struct ExtractOptions {
+    /// 压缩包密码；None 时遇到加密的压缩包返回 `ExtractError::WrongPassword`，不会等待输入
+    pub password: Option<String>,
+    /// 只解压这些路径（压缩包内的相对路径，可用 `*` 通配符），为空时解压全部
+    pub paths: Vec<String>,
+}
+
+/// 解压完成（退出码 0 或 1）
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExtractOutcome {
+    /// 7-Zip 的警告（退出码 1 时的错误输出），没有警告时为空
+    pub warnings: Vec<String>,
+}
+
+impl ExtractOutcome {
+    pub fn has_warnings(&self) -> bool {
+        !self.warnings.is_empty()
+    }
+}
+
+/// 压缩包中的一项（`list_contents` 返回）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchiveEntry {
+    /// 压缩包内的相对路径
+    pub path: String,
+    pub size: u64,
+    /// 压缩后的大小（固实压缩的 7z 中只有每块的第一个文件有值）
+    pub packed_size: Option<u64>,
+    pub is_dir: bool,
+    pub encrypted: bool,
+}
+
+/// 解压失败的原因（7-Zip 退出码表示的失败以 `ToolError` 返回）
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ExtractError {
+    #[error("未找到 7-Zip，已查找: {}", checked.join("、"))]
+    SevenZipNotFound { checked: Vec<String> },
+
+    #[error("压缩包 {} 已加密，密码不正确或未提供密码", archive.display())]
+    WrongPassword { archive: PathBuf },
+
+    #[error("解压已取消")]
+    Cancelled,
+}
+
+/// 查找 bin 目录中的 7-Zip
+pub fn find_7z() -> Result<PathBuf> {
+    let bin_dir = get_bin_dir();
+    let candidates: Vec<PathBuf> = SEVEN_ZIP_CANDIDATES.iter().map(|name| bin_dir.join(name)).collect();
+    match candidates.iter().find(|path| path.is_file()) {
+        Some(found) => Ok(found.clone()),
+        None => Err(ExtractError::SevenZipNotFound {
+            checked: candidates.iter().map(|p| p.display().to_string()).collect(),
+        }
+        .into()),
+    }
+}
+
+/// 解压全部内容到 `dest`（不存在时创建），`progress_cb(百分比)` 返回 false 时取消
+pub async fn extract_all(
+    archive: &Path,
+    dest: &Path,
+    password: Option<&str>,
+    progress_cb: impl FnMut(u32) -> bool,
+) -> Result<ExtractOutcome> {
+    let options = ExtractOptions {
+        password: password.map(str::to_string),
+        ..Default::default()
+    };
+    extract(archive, dest, &options, progress_cb).await
+}
+
+/// 按 `options` 解压到 `dest`（保留压缩包内的目录结构，已有的同名文件被覆盖）
+///
+/// `progress_cb(百分比)` 返回 false 时结束 7-Zip，返回 `ExtractError::Cancelled`。
+/// 失败或取消时删除本次在 `dest` 中新建的顶层文件和目录（`dest` 原本不存在时整个删除）；
+/// 已有的子目录中被覆盖的文件无法恢复。
+pub async fn extract(
+    archive: &Path,
+    dest: &Path,
+    options: &ExtractOptions,
+    mut progress_cb: impl FnMut(u32) -> bool,
+) -> Result<ExtractOutcome> {
+    let seven_zip = find_7z()?;
+    let snapshot = DestSnapshot::take(dest)?;
+    std::fs::create_dir_all(to_extended_path(dest))
+        .map_err(|e| anyhow::anyhow!("创建解压目录 {} 失败: {}", dest.display(), e))?;
+
+    let mut args = vec![
+        "x".to_string(),
+        format!("-o{}", dest.display()),
+        "-y".to_string(),
+        "-bsp1".to_string(),
+        "-sccWIN".to_string(),
+    ];
+    args.push(password_arg(options.password.as_deref()));
+    args.push("--".to_string());
+    args.push(archive.display().to_string());
+    args.extend(options.paths.iter().cloned());
+
+    log::info!("[7-Zip] 解压 {} 到 {}", archive.display(), dest.display());
+    let mut last = None;
+    let result = run_7z(&seven_zip, &args, archive, |line| match parse_percent(line) {
+        Some(percent) if last != Some(percent) => {
+            last = Some(percent);
+            progress_cb(percent)
+        }
+        _ => true,
+    })
+    .await;
+
+    match result {
+        Ok(run) => {
+            if run.code == Some(EXIT_WARNING) {
+                log::warn!("[7-Zip] 解压完成但有警告: {}", run.stderr.join(" | "));
+            } else {
+                log::info!("[7-Zip] 解压完成");
+            }
+            Ok(ExtractOutcome {
+                warnings: if run.code == Some(EXIT_WARNING) { run.stderr } else { Vec::new() },
+            })
+        }
+        Err(e) => {
+            log::warn!("[7-Zip] 解压 {} 失败，清理 {}: {}", archive.display(), dest.display(), e);
+            snapshot.restore().await;
+            Err(e)
+        }
+    }
+}
+
+/// 列出压缩包内容
+pub async fn list_contents(archive: &Path, password: Option<&str>) -> Result<Vec<ArchiveEntry>> {
+    let seven_zip = find_7z()?;
+    let args = vec![
+        "l".to_string(),
+        "-slt".to_string(),
+        "-sccWIN".to_string(),
+        password_arg(password),
+        "--".to_string(),
+        archive.display().to_string(),
+    ];
+    let run = run_7z(&seven_zip, &args, archive, |_| true).await?;
+    Ok(parse_technical_listing(&run.stdout))
+}
+
+/// 没有密码时传入占位密码：只写 `-p` 时 7-Zip 会在标准输入上等待输入，加密的压缩包应直接报密码错误
+fn password_arg(password: Option<&str>) -> String {
+    format!("-p{}", password.filter(|p| !p.is_empty()).unwrap_or(NO_PASSWORD))
+}
+
+/// 成功（退出码 0 或 1）的一次 7-Zip 运行
+struct SevenZipRun {
+    code: Option<i32>,
+    stdout: Vec<String>,
+    stderr: Vec<String>,
+}
+
+/// 运行 7-Zip：每行标准输出交给 `on_line`，返回 false 时结束进程并返回 `ExtractError::Cancelled`
+async fn run_7z(seven_zip: &Path, args: &[String], archive: &Path, mut on_line: impl FnMut(&str) -> bool) -> Result<SevenZipRun> {
+    let arg_refs: Vec<&str> = args.iter().map(String::as_str).collect();
+    let mut child = spawn_streaming(seven_zip, &arg_refs)?;
+
+    let (mut stdout, mut stderr) = (Vec::new(), Vec::new());
+    while let Some(line) = child.next_line().await {
+        match line {
+            OutputLine::Stdout(line) => {
+                if !on_line(&line) {
+                    // 丢弃时结束 7-Zip 进程
+                    drop(child);
+                    return Err(ExtractError::Cancelled.into());
+                }
+                if !line.trim().is_empty() && parse_percent(&line).is_none() {
+                    stdout.push(line);
+                }
+            }
+            OutputLine::Stderr(line) if !line.trim().is_empty() => stderr.push(line.trim().to_string()),
+            OutputLine::Stderr(_) => {}
+        }
+    }
+
+    let code = child.wait().await?;
+    if matches!(code, Some(0) | Some(EXIT_WARNING)) {
+        return Ok(SevenZipRun { code, stdout, stderr });
+    }
+    if stderr.iter().any(|l| l.to_ascii_lowercase().contains("wrong password")) {
+        return Err(ExtractError::WrongPassword {
+            archive: archive.to_path_buf(),
+        }
+        .into());
+    }
+    Err(ToolError::from_exit(Tool::SevenZip, code, &stderr.join("\n")).into())
+}
+
+/// 进度行开头的百分比（如 " 42% 7 - drivers\net.inf"）
+fn parse_percent(line: &str) -> Option<u32> {
+    let line = line.trim_start();
+    let digits = line.find(|c: char| !c.is_ascii_digit())?;
+    if digits == 0 || !line[digits..].starts_with('%') {
+        return None;
+    }
+    line[..digits].parse().ok().filter(|&p| p <= 100)
+}
+
+/// 解析 `7z l -slt` 的输出：分隔线 `----------` 之后每个空行分隔的块是一项，每行为 `键 = 值`
+fn parse_technical_listing(lines: &[String]) -> Vec<ArchiveEntry> {
+    let mut entries = Vec::new();
+    let mut block: Vec<(&str, &str)> = Vec::new();
+    let mut in_entries = false;
+    // 输出已去掉空行，新的一项从 `Path = ` 开始
+    for line in lines.iter().map(|l| l.trim()) {
+        if line.starts_with("----------") {
+            in_entries = true;
+            continue;
+        }
+        let Some((key, value)) = line.split_once(" = ").or_else(|| line.strip_suffix(" =").map(|k| (k, ""))) else {
+            continue;
+        };
+        if !in_entries {
+            continue;
+        }
+        if key == "Path" && !block.is_empty() {
+            entries.extend(entry_from_block(&block));
+            block.clear();
+        }
+        block.push((key, value));
+    }
+    entries.extend(entry_from_block(&block));
+    entries
+}
+
+fn entry_from_block(block: &[(&str, &str)]) -> Option<ArchiveEntry> {
+    let get = |key: &str| block.iter().find(|(k, _)| *k == key).map(|(_, v)| v.trim());
+    Some(ArchiveEntry {
+        path: get("Path")?.to_string(),
+        size: get("Size").and_then(|v| v.parse().ok()).unwrap_or(0),
+        packed_size: get("Packed Size").and_then(|v| v.parse().ok()),
+        is_dir: get("Folder") == Some("+") || get("Attributes").is_some_and(|a| a.starts_with('D')),
+        encrypted: get("Encrypted") == Some("+"),
+    })
+}
+
+/// 解压前目标目录中已有的顶层项目，失败时据此清理
+struct DestSnapshot {
+    dest: PathBuf,
+    /// 目录原本不存在时为 None，清理时整个删除
+    existing: Option<HashSet<OsString>>,
+}
+
+impl DestSnapshot {
+    fn take(dest: &Path) -> Result<Self> {
+        let existing = match std::fs::read_dir(to_extended_path(dest)) {
+            Ok(entries) => Some(entries.filter_map(|e| e.ok()).map(|e| e.file_name()).collect()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => anyhow::bail!("读取解压目录 {} 失败: {}", dest.display(), e),
+        };
+        Ok(Self {
+            dest: dest.to_path_buf(),
+            existing,
+        })
+    }
+
+    /// 删除本次新建的文件和目录；删除失败只记录日志
+    async fn restore(&self) {
+        let dest = to_extended_path(&self.dest);
+        let targets: Vec<PathBuf> = match &self.existing {
+            None => vec![dest],
+            Some(existing) => match std::fs::read_dir(&dest) {
+                Ok(entries) => entries
+                    .filter_map(|e| e.ok())
+                    .filter(|e| !existing.contains(&e.file_name()))
+                    .map(|e| e.path())
+                    .collect(),
+                Err(_) => return,
+            },
+        };
+        for target in targets {
+            for attempt in 1..=CLEANUP_ATTEMPTS {
+                let result = match target.is_dir() {
+                    true => std::fs::remove_dir_all(&target),
+                    false => std::fs::remove_file(&target),
+                };
+                match result {
+                    Ok(()) => break,
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => break,
+                    Err(e) if attempt == CLEANUP_ATTEMPTS => {
+                        log::warn!("[7-Zip] 清理 {} 失败: {}", target.display(), e);
+                    }
+                    Err(_) => tokio::time::sleep(CLEANUP_RETRY_DELAY).await,
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_percent() {
+        assert_eq!(parse_percent("  5% 3 - drivers\\net.inf"), Some(5));
+        assert_eq!(parse_percent("100%"), Some(100));
+        assert_eq!(parse_percent(" 42"), None);
+        assert_eq!(parse_percent("Everything is Ok"), None);
+        assert_eq!(parse_percent("%"), None);
+    }
+
+    #[test]
+    fn test_parse_technical_listing() {
+        let output = "\
+7-Zip 23.01 (x64) : Copyright (c) 1999-2023 Igor Pavlov
+Listing archive: drivers.7z
+--
+Path = drivers.7z
+Type = 7z
+Physical Size = 2048
+----------
+Path = net
+Size = 0
+Attributes = D
+Encrypted = -
+Path = net\\e1d.inf
+Size = 4096
+Packed Size = 1500
+Attributes = A
+CRC =
+Encrypted = +";
+        let lines: Vec<String> = output.lines().map(str::to_string).collect();
+        let entries = parse_technical_listing(&lines);
+        assert_eq!(
+            entries,
+            vec![
+                ArchiveEntry {
+                    path: "net".to_string(),
+                    size: 0,
+                    packed_size: None,
+                    is_dir: true,
+                    encrypted: false,
+                },
+                ArchiveEntry {
+                    path: "net\\e1d.inf".to_string(),
+                    size: 4096,
+                    packed_size: Some(1500),
+                    is_dir: false,
+                    encrypted: true,
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_restore_keeps_existing_entries() {
+        let dest = std::env::temp_dir().join(format!("letrecovery_extract_{}", std::process::id()));
+        std::fs::create_dir_all(dest.join("old")).unwrap();
+        std::fs::write(dest.join("keep.txt"), b"keep").unwrap();
+        let snapshot = DestSnapshot::take(&dest).unwrap();
+        std::fs::create_dir_all(dest.join("new").join("sub")).unwrap();
+        std::fs::write(dest.join("half.sys"), b"half").unwrap();
+        std::fs::write(dest.join("old").join("inner.txt"), b"x").unwrap();
+
+        snapshot.restore().await;
+        let mut left: Vec<_> = std::fs::read_dir(&dest).unwrap().map(|e| e.unwrap().file_name()).collect();
+        left.sort();
+        assert_eq!(left, ["keep.txt", "old"]);
+
+        // 原本不存在的目录整个删除
+        let fresh = dest.join("fresh");
+        let snapshot = DestSnapshot::take(&fresh).unwrap();
+        std::fs::create_dir_all(fresh.join("a")).unwrap();
+        snapshot.restore().await;
+        assert!(!fresh.exists());
+
+        let _ = std::fs::remove_dir_all(&dest);
+    }
+}
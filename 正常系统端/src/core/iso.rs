@@ -1,5 +1,7 @@
 use anyhow::Result;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+
+use crate::utils::cmd::run_powershell_json;
 
 #[cfg(windows)]
 use windows::{
@@ -494,3 +496,241 @@ impl Drop for IsoMounter {
         }
     }
 }
+
+/// `mount_iso` 失败的原因
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum IsoMountError {
+    /// 文件正被其它程序写入（如下载完成后仍在改名、校验），稍后重试即可
+    #[error("{} 正被其它程序使用（可能仍在下载或改名），请稍后再试", path.display())]
+    FileInUse { path: PathBuf },
+
+    #[error("找不到 {}", path.display())]
+    NotFound { path: PathBuf },
+
+    /// 没有 Mount-DiskImage（PE、精简系统），或虚拟光驱功能被禁用
+    #[error("系统不支持挂载 ISO（虚拟光驱功能不可用或已被禁用）：{reason}")]
+    VirtualDvdUnavailable { reason: String },
+
+    #[error("{} 已挂载，但没有分配盘符", path.display())]
+    NoDriveLetter { path: PathBuf },
+
+    /// 其它失败，`code` 为 PowerShell 报告的错误码
+    #[error("挂载 {} 失败：{message}", path.display())]
+    Failed { path: PathBuf, code: Option<i64>, message: String },
+}
+
+/// 等待挂载后分配盘符的脚本轮询次数（每次 250 毫秒）
+const DRIVE_LETTER_POLLS: u32 = 20;
+
+/// 挂载脚本的结果
+#[derive(Debug, serde::Deserialize)]
+struct MountReport {
+    /// "mounted"（本次挂载）、"existing"（之前已挂载）、"failed"、"unavailable"
+    status: String,
+    code: Option<i64>,
+    #[serde(default)]
+    message: String,
+    letter: Option<String>,
+}
+
+/// 用 `Mount-DiskImage` 挂载的 ISO，丢弃或调用 `dismount` 时卸载
+///
+/// 挂载前 ISO 已经挂载（用户在资源管理器中打开过）时直接使用已有的盘符，丢弃时不卸载。
+#[derive(Debug)]
+pub struct MountedIso {
+    image_path: PathBuf,
+    /// 盘符（如 "F:"）
+    drive: String,
+    /// 由本程序挂载，需要卸载
+    owned: bool,
+    dismounted: bool,
+}
+
+impl MountedIso {
+    /// 盘符（如 "F:"）
+    pub fn drive(&self) -> &str {
+        &self.drive
+    }
+
+    /// 卷根目录（如 "F:\\"）
+    pub fn root(&self) -> PathBuf {
+        PathBuf::from(format!("{}\\", self.drive))
+    }
+
+    pub fn image_path(&self) -> &Path {
+        &self.image_path
+    }
+
+    /// 挂载前 ISO 已经挂载，丢弃时不会卸载
+    pub fn was_already_mounted(&self) -> bool {
+        !self.owned
+    }
+
+    /// 卸载（之前已挂载的不卸载），返回卸载失败的错误
+    pub fn dismount(mut self) -> Result<()> {
+        self.dismounted = true;
+        match self.owned {
+            true => dismount_image(&self.image_path),
+            false => Ok(()),
+        }
+    }
+}
+
+impl Drop for MountedIso {
+    fn drop(&mut self) {
+        if self.owned && !self.dismounted {
+            if let Err(e) = dismount_image(&self.image_path) {
+                log::warn!("[ISO] 卸载 {} 失败: {}", self.image_path.display(), e);
+            }
+        }
+    }
+}
+
+/// 用 PowerShell `Mount-DiskImage` 挂载 ISO 并取得分配的盘符
+///
+/// 失败时的错误可 downcast 为 `IsoMountError`。PE 中没有 Mount-DiskImage，请使用 `IsoMounter::mount_iso`。
+pub fn mount_iso(path: &Path) -> Result<MountedIso> {
+    let quoted = path.display().to_string().replace('\'', "''");
+    let script = format!(
+        r#"$path = '{quoted}'
+        if (-not (Get-Command Mount-DiskImage -ErrorAction SilentlyContinue)) {{
+            return [pscustomobject]@{{ status = 'unavailable'; code = $null; message = 'Mount-DiskImage 不可用'; letter = $null }}
+        }}
+        function Report-Failure($err) {{
+            $e = $err.Exception
+            $code = if ($e.ErrorData -and $e.ErrorData.error_Code) {{ [int64]$e.ErrorData.error_Code }} else {{ [int64]$e.HResult }}
+            [pscustomobject]@{{ status = 'failed'; code = $code; message = $e.Message; letter = $null }}
+        }}
+        try {{
+            $image = Get-DiskImage -ImagePath $path
+        }} catch {{
+            return Report-Failure $_
+        }}
+        $existing = [bool]$image.Attached
+        if (-not $existing) {{
+            try {{
+                $image = Mount-DiskImage -ImagePath $path -StorageType ISO -PassThru
+            }} catch {{
+                return Report-Failure $_
+            }}
+        }}
+        $letter = $null
+        for ($i = 0; $i -lt {DRIVE_LETTER_POLLS} -and -not $letter; $i++) {{
+            $letter = (Get-Volume -DiskImage $image -ErrorAction SilentlyContinue).DriveLetter
+            if (-not $letter) {{ Start-Sleep -Milliseconds 250 }}
+        }}
+        [pscustomobject]@{{
+            status = if ($existing) {{ 'existing' }} else {{ 'mounted' }}
+            code = $null
+            message = ''
+            letter = if ($letter) {{ [string]$letter }} else {{ $null }}
+        }}"#
+    );
+
+    if !crate::utils::path::to_extended_path(path).is_file() {
+        return Err(IsoMountError::NotFound { path: path.to_path_buf() }.into());
+    }
+    log::info!("[ISO] 挂载 {}", path.display());
+    let report: MountReport = run_powershell_json(&script)?;
+    let owned = match report.status.as_str() {
+        "mounted" => true,
+        "existing" => false,
+        "unavailable" => return Err(IsoMountError::VirtualDvdUnavailable { reason: report.message }.into()),
+        _ => return Err(classify_mount_failure(path, report.code, report.message, file_in_use(path)).into()),
+    };
+    let mounted = MountedIso {
+        image_path: path.to_path_buf(),
+        drive: report.letter.map(|l| format!("{}:", l.trim_end_matches(':'))).unwrap_or_default(),
+        owned,
+        dismounted: false,
+    };
+    if mounted.drive.is_empty() {
+        // 丢弃时卸载本次的挂载
+        return Err(IsoMountError::NoDriveLetter { path: path.to_path_buf() }.into());
+    }
+    match owned {
+        true => log::info!("[ISO] 已挂载到 {}", mounted.drive),
+        false => log::info!("[ISO] {} 之前已挂载到 {}，直接使用", path.display(), mounted.drive),
+    }
+    Ok(mounted)
+}
+
+/// 卸载 ISO（`Dismount-DiskImage`）
+fn dismount_image(path: &Path) -> Result<()> {
+    let quoted = path.display().to_string().replace('\'', "''");
+    run_powershell_json::<()>(&format!("Dismount-DiskImage -ImagePath '{}' | Out-Null", quoted))?;
+    log::info!("[ISO] 已卸载 {}", path.display());
+    Ok(())
+}
+
+/// 文件当前能否以「禁止其它程序写入」的方式打开；不能时说明其它程序正在写入
+fn file_in_use(path: &Path) -> bool {
+    #[cfg(windows)]
+    {
+        use crate::utils::path::to_extended_path;
+        use std::os::windows::fs::OpenOptionsExt;
+        let opened = std::fs::OpenOptions::new()
+            .read(true)
+            .share_mode(FILE_SHARE_READ.0)
+            .open(to_extended_path(path));
+        // ERROR_SHARING_VIOLATION、ERROR_LOCK_VIOLATION
+        matches!(opened.map_err(|e| e.raw_os_error()), Err(Some(32 | 33)))
+    }
+    #[cfg(not(windows))]
+    {
+        let _ = path;
+        false
+    }
+}
+
+/// 按错误码（Win32 错误码或 `HRESULT_FROM_WIN32` 形式）区分挂载失败的原因
+fn classify_mount_failure(path: &Path, code: Option<i64>, message: String, in_use: bool) -> IsoMountError {
+    let win32 = code.map(|code| code as u32).map(|code| match code & 0xFFFF_0000 {
+        0x8007_0000 => code & 0xFFFF,
+        _ => code,
+    });
+    match win32 {
+        _ if in_use => IsoMountError::FileInUse { path: path.to_path_buf() },
+        Some(32 | 33) => IsoMountError::FileInUse { path: path.to_path_buf() },
+        // ERROR_FILE_NOT_FOUND、ERROR_PATH_NOT_FOUND：检查之后文件被删除或移动
+        Some(2 | 3) => IsoMountError::NotFound { path: path.to_path_buf() },
+        // ERROR_INVALID_FUNCTION、ERROR_NOT_SUPPORTED：光驱驱动被禁用或系统不支持虚拟光驱
+        Some(1 | 50) => IsoMountError::VirtualDvdUnavailable { reason: message },
+        _ => IsoMountError::Failed {
+            path: path.to_path_buf(),
+            code,
+            message,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify_mount_failure() {
+        let path = Path::new(r"D:\downloads\win11.iso");
+        let classify = |code: Option<i64>, in_use| classify_mount_failure(path, code, "x".to_string(), in_use);
+        assert_eq!(classify(Some(0x8007_0020), false), IsoMountError::FileInUse { path: path.to_path_buf() });
+        assert_eq!(classify(Some(-2147024864), false), IsoMountError::FileInUse { path: path.to_path_buf() });
+        assert!(matches!(classify(Some(50), false), IsoMountError::VirtualDvdUnavailable { .. }));
+        assert_eq!(classify(Some(0x8007_0002), false), IsoMountError::NotFound { path: path.to_path_buf() });
+        assert!(matches!(classify(Some(0x8004_1001), false), IsoMountError::Failed { .. }));
+        // 错误码不明确，但文件确实被占用
+        assert_eq!(classify(None, true), IsoMountError::FileInUse { path: path.to_path_buf() });
+    }
+
+    #[test]
+    fn test_mount_missing_iso() {
+        let path = std::env::temp_dir().join("letrecovery_missing.iso");
+        let error = mount_iso(&path).unwrap_err();
+        assert_eq!(error.downcast_ref::<IsoMountError>(), Some(&IsoMountError::NotFound { path }));
+    }
+
+    #[test]
+    fn test_parse_mount_report() {
+        let report: MountReport = serde_json::from_str(r#"{"status":"existing","code":null,"message":"","letter":"F"}"#).unwrap();
+        assert_eq!((report.status.as_str(), report.letter.as_deref()), ("existing", Some("F")));
+    }
+}
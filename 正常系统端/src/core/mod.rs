@@ -8,6 +8,7 @@ pub mod dism;
 pub mod dism_cmd;
 pub mod dismapi;
 pub mod driver;
+pub mod extract;
 pub mod ghost;
 pub mod gho_password;
 pub mod hardware_info;
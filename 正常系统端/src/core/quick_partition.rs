@@ -27,7 +27,8 @@ const IOCTL_VOLUME_GET_VOLUME_DISK_EXTENTS: u32 = 0x00560000;
 
 use crate::utils::cmd::create_command;
 use crate::utils::encoding::gbk_to_utf8;
-use crate::utils::path::get_bin_dir;
+use crate::utils::path::{get_bin_dir, temp_file};
+use crate::utils::tool_error::{Tool, ToolError};
 
 use super::disk::PartitionStyle;
 use super::system_info::BootMode;
@@ -878,8 +879,7 @@ pub fn execute_quick_partition(
 
 /// 执行 diskpart 脚本
 fn execute_diskpart_script(script: &str) -> Result<String> {
-    let temp_dir = std::env::temp_dir();
-    let script_path = temp_dir.join("lr_quick_partition.txt");
+    let script_path = temp_file("quick_partition", "txt")?;
 
     log::debug!("Diskpart 脚本内容:\n{}", script);
 
@@ -900,7 +900,7 @@ fn execute_diskpart_script(script: &str) -> Result<String> {
     }
 
     if !error_text.is_empty() && !output.status.success() {
-        anyhow::bail!("{}", error_text);
+        return Err(ToolError::from_exit(Tool::Diskpart, output.status.code(), &error_text).into());
     }
 
     Ok(output_text)
@@ -531,9 +531,11 @@ pub fn cleanup_component_store() -> Result<()> {
 
     // 方法2: 使用 cleanmgr.exe
     // /sagerun:1 使用预设配置
-    let output = Command::new("cleanmgr.exe")
-        .args(["/d", "C:", "/VERYLOWDISK"])
-        .spawn();
+    // cleanmgr 在本程序退出后继续清理
+    let output = crate::utils::cmd::spawn_detached(
+        std::path::Path::new("cleanmgr.exe"),
+        &["/d", "C:", "/VERYLOWDISK"],
+    );
 
     match output {
         Ok(_) => {
@@ -666,26 +668,14 @@ pub fn analyze_component_store(system_root: &str) -> Result<ComponentStoreAnalys
     })
 }
 
-/// 获取目录大小（递归）
+/// 获取目录大小（递归，不进入目录联接）
 fn get_dir_size(path: &Path) -> Result<u64> {
-    let mut total: u64 = 0;
-
     if !path.exists() {
         return Ok(0);
     }
-
-    for entry in walkdir::WalkDir::new(path)
-        .into_iter()
-        .filter_map(|e| e.ok())
-    {
-        if entry.path().is_file() {
-            if let Ok(metadata) = entry.metadata() {
-                total += metadata.len();
-            }
-        }
-    }
-
-    Ok(total)
+    let cancel = std::sync::atomic::AtomicBool::new(false);
+    let stats = crate::utils::path::dir_size_blocking(path, 0, &cancel, |_, _| {})?;
+    Ok(stats.total_bytes)
 }
 
 // ============================================================================
@@ -4,16 +4,54 @@
 //! - 支持预启动aria2进程
 //! - 更快的RPC连接（减少等待时间）
 //! - 全局单例模式，避免重复启动
+//! - RPC 端口动态选择（6800-6900 中第一个空闲端口），避免与其它 aria2/Motrix 冲突
+//! - RPC 使用随机密钥（--rpc-secret），防止本机其它进程操纵下载任务
+//! - 会话持久化（data/aria2.session），程序被杀后重启可继续未完成的下载
+//! - 看门狗：aria2c 崩溃或连接断开时自动恢复（有次数上限）
+//! - 程序被重复打开时附加到已运行的 aria2c（data/aria2.rpc 记录端口和密钥）
+//! - 指定文件名的任务先写入 `<文件名>.part`，完成后改名，中途失败不会留下看似完整的文件
 
 use anyhow::Result;
-use aria2_ws::response::TaskStatus;
-use std::process::Child;
-use std::sync::atomic::{AtomicBool, Ordering};
+use aria2_ws::response::{File, Status, TaskStatus, UriStatus};
+use base64::prelude::*;
+use aria2_ws::{Event, Notification};
+use futures::Stream;
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicU16, AtomicU32, Ordering};
 use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+use tokio::sync::mpsc;
+use tokio::sync::watch;
 use tokio::sync::Mutex as TokioMutex;
 
-use crate::utils::cmd::create_command;
-use crate::utils::path::get_bin_dir;
+pub use super::error::DownloadErrorKind;
+use super::aria2_config::{Aria2Config, DownloadProfile, FileAllocation, TlsOptions};
+use super::aria2_output::OutputTail;
+use super::error::DownloadError;
+use super::group::DownloadGroup;
+pub use super::handle::DownloadHandle;
+pub use super::headers::Cookie;
+use super::headers::{cookie_header_value, header_lines, parse_header_line};
+use super::job_store::{JobRecord, JobState, JobStore, VerifiedFile};
+use super::location::{self, LocationFilter};
+use super::mirror::{self, MirrorRotation};
+pub use super::mirror::MirrorScore;
+use super::preflight;
+use super::resume::{control_file_path, is_resumable};
+use super::schedule::{BandwidthSchedule, ScheduleEffect, TimeOfDay};
+use super::speed::SpeedHistory;
+use super::verify;
+use super::proxy::{read_system_proxy, ProxyConfig};
+use crate::core::app_config::AppConfig;
+use crate::utils::cmd::{create_command_with, is_pid_alive, kill_process_tree, process_info, CommandOptions};
+use crate::utils::tool_error::{Tool, ToolError};
+pub use crate::utils::hash::HashType;
+use crate::utils::hash::hash_file_blocking;
+use crate::utils::path::{ensure_writable, exceeds_max_path, find_in_path, get_bin_dir, get_data_dir, normalize_path, sanitize_filename, strip_extended_prefix, to_extended_path, unique_path, write_private_file};
 
 /// 全局aria2管理器（延迟初始化）
 static GLOBAL_ARIA2: OnceLock<Arc<TokioMutex<Option<Aria2Manager>>>> = OnceLock::new();
@@ -21,315 +59,5485 @@ static GLOBAL_ARIA2: OnceLock<Arc<TokioMutex<Option<Aria2Manager>>>> = OnceLock:
 /// aria2是否已预热
 static ARIA2_WARMED_UP: AtomicBool = AtomicBool::new(false);
 
+/// 事件广播缓冲大小（订阅者消费过慢时丢弃最旧事件）
+const EVENT_CHANNEL_CAPACITY: usize = 256;
+
+/// tellWaiting / tellStopped 分页大小
+const TELL_PAGE_SIZE: i32 = 100;
+
+/// RPC 端口探测范围（含两端）
+const RPC_PORT_FIRST: u16 = 6800;
+const RPC_PORT_LAST: u16 = 6900;
+
+/// 探测到空闲端口后被其它进程抢占时，最多换端口重试的次数
+const MAX_PORT_ATTEMPTS: usize = 5;
+
+/// 会话文件名（位于数据目录）
+const SESSION_FILE_NAME: &str = "aria2.session";
+
+/// RPC 端点文件名（位于数据目录，记录端口和密钥，供第二个程序实例附加）
+const ENDPOINT_FILE_NAME: &str = "aria2.rpc";
+
+/// 附加到已运行 aria2 时连接和探测的超时
+const ATTACH_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// aria2 自动保存会话的间隔（秒），防止进程被强杀时丢失进度
+const SESSION_SAVE_INTERVAL_SECS: u32 = 30;
+
+/// 优雅关闭时等待 aria2c 退出的默认时长
+const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 结束进程后等待 RPC 端口释放、以及启动时等待指定端口被旧进程释放的时长
+const PORT_RELEASE_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// 等待 aria2c 退出时的轮询间隔
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// 按时段限速的检查间隔
+const SCHEDULE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// 看门狗检查 aria2c 存活的间隔
+const WATCHDOG_INTERVAL: Duration = Duration::from_secs(2);
+
+/// 存活检查中 RPC 探测的超时
+const WATCHDOG_PING_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 删除被 aria2 短暂占用的文件时的重试次数和间隔
+const DELETE_RETRIES: u32 = 5;
+const DELETE_RETRY_INTERVAL: Duration = Duration::from_millis(300);
+
+/// 停滞检测的检查间隔
+const STALL_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// 停滞检测暂停任务后，等待其进入暂停状态的最长时间
+const STALL_PAUSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// RPC 调用连续超时这么多次后，看门狗认为 aria2c 已卡死并重启它
+const RPC_TIMEOUTS_BEFORE_RESTART: u32 = 3;
+
+/// 看门狗最多重启 aria2c 的次数，超过后放弃，避免崩溃循环
+const MAX_ENGINE_RESTARTS: u32 = 3;
+
+/// RPC 连接断开后重连的等待间隔（依次递增，全部失败则放弃）
+const RECONNECT_BACKOFF: [Duration; 4] = [
+    Duration::from_millis(200),
+    Duration::from_millis(500),
+    Duration::from_secs(1),
+    Duration::from_secs(2),
+];
+
+/// 不带 `force` 时 `retry_download` 对校验失败的任务最多重试的次数（网络类错误不限次数）
+const CHECKSUM_RETRY_LIMIT: u32 = 2;
+
+/// 下载过程中临时文件名的后缀
+const PART_SUFFIX: &str = ".part";
+
+/// 支持的最低 aria2 版本
+const MIN_ARIA2_VERSION: (u32, u32, u32) = (1, 35, 0);
+
+/// 必需的编译特性（getVersion 的 enabledFeatures）：HTTPS 下载、哈希校验、Metalink
+const REQUIRED_FEATURES: [&str; 3] = ["HTTPS", "Message Digest", "Metalink"];
+
+/// BitTorrent 支持（`Aria2Config::require_bittorrent` 时必需）
+const FEATURE_BITTORRENT: &str = "BitTorrent";
+
 /// 下载进度信息
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, serde::Serialize)]
 pub struct DownloadProgress {
     pub gid: String,
     pub completed_length: u64,
     pub total_length: u64,
+    /// 瞬时速度（aria2 报告的值，波动较大）
     pub download_speed: u64,
-    pub percentage: f64,
+    /// 平滑后的速度，界面显示和剩余时间都用这个值；只有 `get_status` 会平滑，其它来源与瞬时速度相同
+    pub download_speed_avg: u64,
+    /// 完成百分比（0-100）；服务器没有给出大小（分块传输、无 Content-Length）时为 None，
+    /// 此时只有已下载字节数和速度有意义，界面应显示为不确定进度，`eta` 也为 None
+    pub percentage: Option<f64>,
     pub status: DownloadStatus,
+    /// 预计剩余时间（按平滑后的速度计算）；速度为 0 或总大小未知时为 None
+    pub eta: Option<Duration>,
+    /// 自添加任务起已用的时间（任务结束后不再增长）
+    pub elapsed: Duration,
+    /// 保存路径：下载中为 aria2 正在写入的文件（可能是 `.part`），`.part` 改名后为最终路径；
+    /// 多文件任务（种子）为第一个文件，磁力链接获取元数据期间为 None
+    pub file_path: Option<PathBuf>,
+    /// 任务包含的文件数
+    pub file_count: u32,
+    /// 当前建立的连接数
+    pub connections: u32,
+    /// 第一个文件正在使用的下载地址（多镜像任务可看出当前用的是哪个镜像）
+    pub active_uri: Option<String>,
+    /// 多文件任务（种子、Metalink）中每个文件的进度；单文件任务为空，见 `get_files`
+    pub files: Vec<TaskFile>,
+    /// 上传速度（字节/秒）；只有 BitTorrent 任务会上传
+    pub upload_speed: u64,
+    /// 分享率（已上传 / 已下载）；仅 BitTorrent 任务有值，可据此确认没有在做种
+    pub share_ratio: Option<f64>,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+/// 任务中的单个文件（getFiles）
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct TaskFile {
+    /// 文件序号（从 1 开始，与 aria2 的 `select-file` 一致）
+    pub index: u32,
+    /// 保存路径；磁力链接获取元数据期间为 None
+    pub path: Option<PathBuf>,
+    pub length: u64,
+    pub completed_length: u64,
+    /// 是否选择下载（未选择的文件不会下载）
+    pub selected: bool,
+}
+
+/// aria2 全局状态（状态栏显示用）
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize)]
+pub struct GlobalStat {
+    /// 总下载速度（字节/秒）
+    pub download_speed: u64,
+    /// 总上传速度（字节/秒，做种时非 0）
+    pub upload_speed: u64,
+    pub num_active: u32,
+    pub num_waiting: u32,
+    /// 已结束且结果仍保留在 aria2 中的任务数
+    pub num_stopped: u32,
+    /// 全局下载限速（字节/秒），0 表示不限速
+    pub speed_limit: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
 pub enum DownloadStatus {
     Waiting,
     Active,
     Paused,
     Complete,
-    Error(String),
+    Error(DownloadErrorKind),
+    /// 被用户取消（aria2 中已移除）：已结束但不是失败，不应自动重试
+    Removed,
 }
 
-/// aria2 下载管理器
-pub struct Aria2Manager {
-    client: Option<Arc<aria2_ws::Client>>,
-    aria2_process: Option<Child>,
+impl DownloadStatus {
+    /// 任务已结束（完成、出错或被取消），状态不会再变化
+    pub fn is_finished(&self) -> bool {
+        matches!(self, DownloadStatus::Complete | DownloadStatus::Error(_) | DownloadStatus::Removed)
+    }
+
+    /// 任务失败（不包括用户取消）
+    pub fn is_failed(&self) -> bool {
+        matches!(self, DownloadStatus::Error(_))
+    }
 }
 
-impl Aria2Manager {
-    /// 预热aria2（在后台启动进程并建立连接）
-    /// 
-    /// 可以在应用启动时或用户选择PE时调用，提前准备好aria2
-    pub async fn warmup() -> Result<()> {
-        if ARIA2_WARMED_UP.load(Ordering::SeqCst) {
-            log::info!("[aria2] 已经预热过，跳过");
-            return Ok(());
+/// aria2c 版本信息（getVersion）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Aria2Version {
+    /// 原始版本字符串，如 "1.37.0"
+    pub version: String,
+    /// 解析出的 (主, 次, 修订) 版本号，无法解析的部分为 0
+    pub number: (u32, u32, u32),
+    pub enabled_features: Vec<String>,
+}
+
+impl Aria2Version {
+    fn new(version: String, enabled_features: Vec<String>) -> Self {
+        let mut parts = version
+            .split(|c: char| !c.is_ascii_digit())
+            .filter(|p| !p.is_empty())
+            .map(|p| p.parse().unwrap_or(0));
+        let number = (
+            parts.next().unwrap_or(0),
+            parts.next().unwrap_or(0),
+            parts.next().unwrap_or(0),
+        );
+        Self {
+            version,
+            number,
+            enabled_features,
         }
+    }
 
-        log::info!("[aria2] 开始预热...");
-        
-        // 获取或创建全局管理器
-        let global = GLOBAL_ARIA2.get_or_init(|| Arc::new(TokioMutex::new(None)));
-        let mut guard = global.lock().await;
-        
-        if guard.is_some() {
-            log::info!("[aria2] 全局管理器已存在");
-            ARIA2_WARMED_UP.store(true, Ordering::SeqCst);
-            return Ok(());
+    pub fn has_feature(&self, feature: &str) -> bool {
+        self.enabled_features.iter().any(|f| f == feature)
+    }
+}
+
+/// 单个下载任务的可选参数
+#[derive(Debug, Clone, Default)]
+pub struct DownloadOptions {
+    /// 保存的文件名
+    ///
+    /// None 时先用 HEAD 请求取得服务器 `Content-Disposition` 中的文件名（处理为 Windows 可用的名称），
+    /// 取不到时由 aria2 根据地址推断。最终文件名可从 `get_status` 的 `file_path` 或任务元数据得到。
+    pub filename: Option<String>,
+    /// 自定义请求头（名称, 值），每一项对应一个 aria2 `header` 选项；值可能包含凭据，不会写入日志
+    pub headers: Option<Vec<(String, String)>>,
+    /// 期望的哈希值，设置后由 aria2 在下载完成时自行校验，不匹配则任务出错
+    pub checksum: Option<(HashType, String)>,
+    /// 随请求发送的 Cookie（合并为一个 `Cookie` 请求头），用于需要登录会话的下载
+    pub cookies: Vec<Cookie>,
+    /// 本任务的 User-Agent；优先于 `Aria2Config::user_agent`，None 时使用全局默认值
+    pub user_agent: Option<String>,
+    /// 本任务的 Referer（部分镜像要求与其站点一致）
+    pub referer: Option<String>,
+    /// 显示名称，记录到任务元数据中（None 时使用文件名或地址）
+    pub display_name: Option<String>,
+    /// 自定义标签，记录到任务元数据中，便于按批次查找（如某次安装计划的 ID）
+    pub tag: Option<String>,
+    /// 本任务的文件预分配方式，None 时使用 `Aria2Config::file_allocation`
+    ///
+    /// 机械硬盘上的大镜像文件建议使用 `Falloc`，减少碎片，加快后续释放镜像。
+    pub file_allocation: Option<FileAllocation>,
+    /// 完成后改名时目标文件已存在的处理方式
+    pub on_conflict: OnConflict,
+    /// 预期文件大小（字节）
+    ///
+    /// 用于添加前检查磁盘空间（None 时通过 HEAD 请求探测），并在完成时核对实际大小：
+    /// 不符时任务以 `DownloadErrorKind::SizeMismatch` 失败，文件改名为 `<文件名>.bad`，
+    /// 镜像轮换任务会换下一个镜像重新下载。
+    pub expected_size: Option<u64>,
+    /// 跳过磁盘空间检查（调用方已自行确认空间，或保存位置无法可靠读取剩余空间）
+    pub skip_space_check: bool,
+    /// 完成时不核对磁盘上的文件是否短于 aria2 记录的总大小（`DownloadErrorKind::TruncatedFile`）
+    ///
+    /// 用于流式生成内容的地址，服务器给出的长度本来就不可靠。`expected_size` 不受影响，仍会核对。
+    pub skip_length_check: bool,
+    /// 保存目录所在卷空间不足时，按此条件改存到其它卷（见 `location::best_save_dir`）；None 时直接报错
+    ///
+    /// 实际使用的目录记录在任务元数据的 `save_dir` 中，原目录记录在 `requested_save_dir` 中。
+    pub relocate: Option<LocationFilter>,
+    /// 跳过连通性检查（调用方已自行确认网络可用）
+    pub skip_connectivity_check: bool,
+    /// 添加前先对镜像测速，按速度从快到慢排列（只用于 `add_download_with_mirrors`）
+    pub rank_mirrors: bool,
+    /// 以暂停状态添加（`pause=true`），之后调用 `start` 才开始传输
+    pub paused: bool,
+    /// 本任务的分片数，None 时使用全局设置（小文件为 1，见 `Aria2Config::small_file_threshold`）
+    pub split: Option<u32>,
+    /// 本任务每个服务器的最大连接数（1-16），None 时使用 `Aria2Config::max_connection_per_server`
+    pub max_connections: Option<u32>,
+    /// 本任务的证书校验设置，整体替换 `Aria2Config::tls`；None 时使用全局设置
+    pub tls: Option<TlsOptions>,
+    /// 总是重新下载，不检查保存位置是否已有完整的文件
+    ///
+    /// 默认在指定了 `filename` 和 `expected_size` 时先检查：同名文件大小相符（设置了 `checksum` 时哈希值也相符）
+    /// 就不提交给 aria2，直接当作已完成（发送带 `EventDetail::AlreadyDownloaded` 的 `Complete` 事件）。
+    pub force_redownload: bool,
+}
+
+impl DownloadOptions {
+    /// 检查取值是否在 aria2 接受的范围内（超出范围时 aria2 会拒绝添加任务）
+    pub fn validate(&self) -> Result<()> {
+        if self.split == Some(0) {
+            anyhow::bail!("split 不能为 0");
         }
-        
-        // 启动新的管理器
-        match Self::start_internal().await {
-            Ok(manager) => {
-                *guard = Some(manager);
-                ARIA2_WARMED_UP.store(true, Ordering::SeqCst);
-                log::info!("[aria2] 预热完成");
-                Ok(())
-            }
-            Err(e) => {
-                log::warn!("[aria2] 预热失败: {}", e);
-                Err(e)
-            }
+        if let Some(n) = self.max_connections.filter(|n| !(1..=16).contains(n)) {
+            anyhow::bail!("max_connections 必须在 1-16 之间，当前为 {}", n);
+        }
+        match &self.tls {
+            Some(tls) => tls.validate(),
+            None => Ok(()),
         }
     }
+}
 
-    /// 获取全局aria2管理器（如果已预热）或创建新的
-    pub async fn get_or_start() -> Result<Arc<TokioMutex<Option<Aria2Manager>>>> {
-        let global = GLOBAL_ARIA2.get_or_init(|| Arc::new(TokioMutex::new(None)));
-        
-        {
-            let mut guard = global.lock().await;
-            if guard.is_none() {
-                log::info!("[aria2] 全局管理器不存在，正在创建...");
-                let manager = Self::start_internal().await?;
-                *guard = Some(manager);
-                ARIA2_WARMED_UP.store(true, Ordering::SeqCst);
-            }
+/// BitTorrent 任务的做种和上传限制
+///
+/// 默认只下载：完成后立即停止，不做种。按流量计费的网络和公司网络上不应让用户的电脑成为种子。
+#[derive(Debug, Clone, PartialEq)]
+pub struct TorrentOptions {
+    /// 下载完成后做种的分钟数，0 表示不做种；None 表示不按时间停止（只看 `seed_ratio`）
+    pub seed_time: Option<u32>,
+    /// 分享率达到该值后停止做种；None 表示不按分享率停止（只看 `seed_time`）
+    ///
+    /// 与 `seed_time` 同时设置时，满足任意一个即停止。
+    pub seed_ratio: Option<f64>,
+    /// 下载速度持续为 0 超过该秒数后停止任务（没有可用的节点），0 表示不限制
+    pub bt_stop_timeout: u32,
+    /// 下载期间也把上传限制在 1 KB/s
+    pub disable_upload: bool,
+    /// 以暂停状态添加，便于先用 `get_files` 查看内容、`select_files` 选择文件后再开始；
+    /// 磁力链接会先获取元数据，暂停的是元数据完成后创建的实际下载任务
+    pub paused: bool,
+}
+
+impl Default for TorrentOptions {
+    fn default() -> Self {
+        Self {
+            seed_time: Some(0),
+            seed_ratio: None,
+            bt_stop_timeout: 0,
+            disable_upload: false,
+            paused: false,
         }
-        
-        Ok(Arc::clone(global))
     }
+}
 
-    /// 内部启动方法
-    async fn start_internal() -> Result<Self> {
-        let bin_dir = get_bin_dir();
-        let aria2c_path = bin_dir.join("aria2c.exe");
+impl TorrentOptions {
+    /// `seed_time` 和 `seed_ratio` 都不设置时会一直做种，视为错误
+    pub fn validate(&self) -> Result<()> {
+        if self.seed_time.is_none() && self.seed_ratio.is_none() {
+            anyhow::bail!("seed_time 和 seed_ratio 至少要设置一个，否则下载完成后会一直做种");
+        }
+        if let Some(ratio) = self.seed_ratio.filter(|r| !r.is_finite() || *r <= 0.0) {
+            anyhow::bail!("seed_ratio 必须大于 0，当前为 {}", ratio);
+        }
+        Ok(())
+    }
 
-        if !aria2c_path.exists() {
-            anyhow::bail!("aria2c.exe not found at {:?}", aria2c_path);
+    /// 对应的 aria2 任务选项
+    ///
+    /// 未设置 `seed_ratio` 时传 0.0（aria2 中表示不按分享率停止），避免 aria2 默认的 1.0 生效。
+    fn aria2_options(&self) -> Vec<(&'static str, String)> {
+        let mut options = vec![
+            ("seed-ratio", format!("{:.2}", self.seed_ratio.unwrap_or(0.0))),
+            ("bt-stop-timeout", self.bt_stop_timeout.to_string()),
+        ];
+        if let Some(minutes) = self.seed_time {
+            options.push(("seed-time", minutes.to_string()));
+        }
+        if self.disable_upload {
+            options.push(("max-upload-limit", "1K".to_string()));
         }
+        options
+    }
+}
 
-        log::info!("[aria2] 正在启动 aria2c 进程...");
-        let start_time = std::time::Instant::now();
+/// 下载完成后目标文件已存在时的处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OnConflict {
+    /// 覆盖已有文件（默认，与之前 `--allow-overwrite=true` 的行为一致）
+    #[default]
+    Overwrite,
+    /// 保留已有文件，任务以错误结束（`.part` 文件保留）
+    Error,
+    /// 保留已有文件，改用 `名称 (1).扩展名` 这样的新文件名（见 `utils::path::unique_path`）
+    ///
+    /// 未指定文件名、由 aria2 推断文件名的任务由 aria2 自行改名（形如 `名称.1.扩展名`）。
+    /// 实际使用的文件名记录在任务元数据和 `DownloadProgress::file_path` 中。
+    Rename,
+}
 
-        // 启动 aria2c 进程，启用 RPC
-        let process = create_command(&aria2c_path)
-            .args([
-                "--daemon=true",
-                "--enable-rpc=true",
-                "--rpc-listen-port=6800",
-                "--rpc-allow-origin-all=true",
-                "--max-concurrent-downloads=5",
-                "--split=32",
-                "--max-connection-per-server=16",
-                "--min-split-size=1M",
-                "--file-allocation=none",
-                "--continue=true",
-                "--auto-file-renaming=false",
-                "--allow-overwrite=true",
-            ])
-            .spawn()?;
-
-        log::info!("[aria2] aria2c 进程已启动，正在等待 RPC 服务就绪...");
-
-        // 优化：使用更短的初始等待时间和更快的重试
-        // 第一次等待100ms，之后每次等待200ms，最多尝试20次（约4秒）
-        let mut client = None;
-        let mut last_error = String::new();
+/// `shutdown` 实际采用的关闭方式（按尝试顺序）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShutdownPath {
+    /// RPC `shutdown`：aria2 完成收尾（写会话文件）后退出
+    Graceful,
+    /// RPC `forceShutdown`：不等待正在进行的操作
+    Forced,
+    /// 以上都未能在超时内退出，直接结束进程
+    Killed,
+    /// aria2 本来就没有在运行
+    NotRunning,
+    /// aria2 属于另一个程序实例，只断开连接，不关闭进程
+    Detached,
+}
 
-        // 先短暂等待进程启动
-        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+/// 任务状态事件（由 aria2 通知推送，或由管理器干预任务时发出）
+#[derive(Debug, Clone)]
+pub struct DownloadEvent {
+    pub gid: String,
+    pub status: DownloadStatus,
+    /// 管理器干预任务时的说明；aria2 通知产生的事件为 None
+    pub detail: Option<EventDetail>,
+}
 
-        for i in 0..20 {
-            match aria2_ws::Client::connect("ws://127.0.0.1:6800/jsonrpc", None).await {
-                Ok(c) => {
-                    client = Some(c);
-                    let elapsed = start_time.elapsed();
-                    log::info!("[aria2] RPC 连接成功 (第 {} 次尝试)，总耗时: {:?}", i + 1, elapsed);
-                    break;
-                }
-                Err(e) => {
-                    last_error = e.to_string();
-                    if i < 5 {
-                        // 前5次尝试，每200ms重试一次
-                        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
-                    } else {
-                        // 之后每300ms重试一次
-                        tokio::time::sleep(std::time::Duration::from_millis(300)).await;
-                    }
-                }
-            }
+/// 管理器对任务的干预
+#[derive(Debug, Clone, PartialEq)]
+pub enum EventDetail {
+    /// 停滞检测暂停并恢复了该任务
+    StallRestarted { stalled_for: Duration },
+    /// 镜像轮换任务换到了另一个镜像（`mirror` 从 0 开始，`attempt` 为合计第几次尝试）
+    MirrorSwitched { mirror: usize, url: String, attempt: u32 },
+    /// 保存位置已有完整的文件，任务没有提交给 aria2，直接完成
+    AlreadyDownloaded { path: PathBuf },
+}
+
+/// 发现文件（数据目录下的 aria2.rpc）：本实例 aria2c 的 RPC 端点，供程序的其它实例附加
+///
+/// 文件中有 RPC 密钥，只写在当前用户的数据目录中，不要写入日志。
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+struct RpcEndpoint {
+    port: u16,
+    secret: String,
+    /// aria2c 进程 ID（旧版本写入的文件没有此项，为 0）
+    #[serde(default)]
+    pid: u32,
+    /// aria2c 启动时间（Unix 时间戳，秒）
+    #[serde(default)]
+    started_at: u64,
+}
+
+impl RpcEndpoint {
+    /// 记录的 aria2c 已不在：端口已关闭、进程已退出，或 PID 已被其它进程复用
+    fn is_stale(&self) -> bool {
+        if is_port_free(self.port) {
+            return true;
+        }
+        if self.pid == 0 {
+            return false;
         }
+        match process_info(self.pid) {
+            // 启动时间晚于写入发现文件的时刻，说明原进程已退出、PID 被复用
+            Some(process) => self.started_at != 0 && process.started_at.is_some_and(|t| t > self.started_at),
+            None => !is_pid_alive(self.pid),
+        }
+    }
+}
 
-        let client = client.ok_or_else(|| {
-            anyhow::anyhow!("初始化aria2失败: {}", last_error)
-        })?;
+/// 批量添加时的单个下载请求
+#[derive(Debug, Clone, Default)]
+pub struct DownloadRequest {
+    pub url: String,
+    pub save_dir: String,
+    pub filename: Option<String>,
+    /// 以暂停状态添加，见 `DownloadOptions::paused`
+    pub paused: bool,
+    /// 预期文件大小，见 `DownloadOptions::expected_size`
+    pub expected_size: Option<u64>,
+}
+
+/// aria2 下载管理器
+pub struct Aria2Manager {
+    /// aria2c 进程与 RPC 连接（与看门狗共享）
+    engine: Arc<Aria2Engine>,
+    /// 启动时从会话文件恢复的任务（会话文件保存了 gid，与上次运行时相同）
+    restored_tasks: Vec<String>,
+    /// 连接的 aria2c 版本
+    version: Aria2Version,
+    /// 每个 gid 的添加/结束时间（aria2 不提供任务开始时间，只能自己记录）
+    timings: parking_lot::Mutex<HashMap<String, TaskTiming>>,
+    /// 下载完成后需要从 `.part` 改为最终文件名的任务（改名后保留记录，用于报告最终路径）
+    renames: parking_lot::Mutex<HashMap<String, PendingRename>>,
+    /// gid 对应的任务元数据（持久化，重启后仍可查到）
+    jobs: JobStore,
+    /// `watch_progress` 最近一次查询的结果，同一 gid 的多个监视者共用，避免重复 RPC
+    watch_cache: parking_lot::Mutex<HashMap<String, (Instant, DownloadProgress)>>,
+    /// 下载中任务最近的速度样本（任务暂停或结束时清除）
+    speeds: parking_lot::Mutex<HashMap<String, SpeedHistory>>,
+    /// 每个任务添加时的参数，供 `retry_download` 原样重新添加（只保存在内存中）
+    requests: parking_lot::Mutex<HashMap<String, TaskRequest>>,
+    /// `DownloadHandle` 被丢弃时经此通道请求取消任务
+    abandoned: mpsc::UnboundedSender<String>,
+    /// 当前的时段限速规则，修改后后台任务立即重新计算
+    schedule: watch::Sender<BandwidthSchedule>,
+    /// 保存位置已有完整文件、没有提交给 aria2 的任务（gid 由本程序生成）及其最终进度
+    existing: parking_lot::Mutex<HashMap<String, DownloadProgress>>,
+}
+
+/// 添加任务时的参数
+#[derive(Debug, Clone)]
+struct TaskRequest {
+    uris: Vec<String>,
+    save_dir: String,
+    options: DownloadOptions,
+    /// 是否为镜像轮换任务
+    rotate: bool,
+    /// 已通过 `retry_download` 重试的次数
+    retries: u32,
+}
 
-        Ok(Self {
-            client: Some(Arc::new(client)),
-            aria2_process: Some(process),
+/// 等待完成后改名的文件
+#[derive(Debug, Clone)]
+struct PendingRename {
+    part_path: PathBuf,
+    final_path: PathBuf,
+    on_conflict: OnConflict,
+    /// 已经改为最终文件名
+    finalized: bool,
+}
+
+/// 镜像轮换任务：轮换状态和重新添加时沿用的任务参数（含 gid）
+struct MirrorTask {
+    rotation: MirrorRotation,
+    options: aria2_ws::TaskOptions,
+}
+
+/// 完成时的文件大小核对
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum SizeCheck {
+    /// 尚未完成，记录预期大小和是否核对磁盘上的文件短于 aria2 的 `total_length`
+    Pending { expected: Option<u64>, check_length: bool },
+    Verified,
+    /// 大小不符（文件已改名为 `.bad`）或文件不完整
+    Failed {
+        expected: Option<u64>,
+        check_length: bool,
+        kind: DownloadErrorKind,
+    },
+}
+
+impl SizeCheck {
+    /// 任务完成时要做的核对；没有预期大小且跳过长度核对时返回 None
+    fn for_task(expected: Option<u64>, skip_length_check: bool) -> Option<Self> {
+        (expected.is_some() || !skip_length_check).then_some(SizeCheck::Pending {
+            expected,
+            check_length: !skip_length_check,
         })
     }
+}
 
-    /// 启动 aria2c 进程并连接（公开接口，向后兼容）
-    pub async fn start() -> Result<Self> {
-        Self::start_internal().await
-    }
+/// 任务计时
+#[derive(Debug, Clone, Copy)]
+struct TaskTiming {
+    added: Instant,
+    finished: Option<Instant>,
+}
 
-    /// 添加下载任务
-    pub async fn add_download(
-        &self,
-        url: &str,
-        save_dir: &str,
-        filename: Option<&str>,
-    ) -> Result<String> {
-        self.add_download_with_headers(url, save_dir, filename, None).await
-    }
+/// aria2c 进程及其 RPC 连接
+///
+/// 由管理器和看门狗共享：看门狗重启 aria2c 后整体替换进程和连接，管理器的调用方无感知。
+struct Aria2Engine {
+    aria2c_path: PathBuf,
+    /// 启动配置（重启时沿用）
+    config: Aria2Config,
+    /// RPC 密钥（注意不要写入日志）
+    rpc_secret: String,
+    session_path: PathBuf,
+    /// aria2c 实际监听的 RPC 端口（重启时原端口被占用会换端口）
+    rpc_port: AtomicU16,
+    /// 新任务的分片数（初始为 `config.split`，`apply_profile` 可修改）
+    split: AtomicU32,
+    client: parking_lot::RwLock<Option<Arc<aria2_ws::Client>>>,
+    process: parking_lot::Mutex<Option<Child>>,
+    /// aria2c 最近的输出（重启后继续写入）
+    output: OutputTail,
+    /// 任务状态事件广播
+    events: broadcast::Sender<DownloadEvent>,
+    /// 当前代理（显式设置或系统代理），同时作用于全局选项和每个新任务
+    proxy: parking_lot::Mutex<Option<ProxyConfig>>,
+    /// 运行期间修改过的全局选项，重启 aria2c 后重新应用
+    global_options: parking_lot::Mutex<HashMap<String, String>>,
+    /// 镜像轮换任务（由轮换任务在后台换镜像，因此放在引擎中）
+    mirrors: parking_lot::Mutex<HashMap<String, MirrorTask>>,
+    /// 完成时要核对大小的任务（`get_status` 和镜像轮换都会核对，结果只算一次）
+    size_checks: parking_lot::Mutex<HashMap<String, SizeCheck>>,
+    /// 串行化重连，避免多个失败的调用同时重连
+    reconnect_lock: TokioMutex<()>,
+    /// aria2c 是否由本实例启动；附加到其它实例的 aria2c 时为 false，关闭时不结束进程
+    owns_process: AtomicBool,
+    /// 看门狗已重启 aria2c 的次数
+    restarts: AtomicU32,
+    /// RPC 调用连续超时的次数（任一调用按时返回即清零）
+    rpc_timeouts: AtomicU32,
+    /// 已主动关闭，看门狗不再重启
+    stopped: AtomicBool,
+}
 
-    /// 添加下载任务（支持自定义headers）
-    pub async fn add_download_with_headers(
-        &self,
-        url: &str,
-        save_dir: &str,
-        filename: Option<&str>,
-        headers: Option<Vec<String>>,
-    ) -> Result<String> {
-        let client = self
-            .client
-            .as_ref()
-            .ok_or_else(|| anyhow::anyhow!("aria2 client not connected"))?;
+impl Aria2Engine {
+    fn current_client(&self) -> Option<Arc<aria2_ws::Client>> {
+        self.client.read().clone()
+    }
 
-        let mut options = aria2_ws::TaskOptions::default();
-        options.dir = Some(save_dir.to_string());
-        options.split = Some(32);
-        options.max_connection_per_server = Some(16);
+    fn rpc_port(&self) -> u16 {
+        self.rpc_port.load(Ordering::SeqCst)
+    }
 
-        if let Some(name) = filename {
-            options.out = Some(name.to_string());
+    /// 核对已完成任务的文件大小，有问题时返回 `SizeMismatch` 或 `TruncatedFile`；不需要核对或大小正确时返回 None
+    ///
+    /// 只核对单文件任务。与预期大小不符时把文件改名为 `.bad`，避免被当作完整文件使用；
+    /// 文件短于 `total_length` 时保留原文件（`.part` 不会改为最终文件名）。结果会记录下来，重复调用不再检查。
+    fn check_size(&self, gid: &str, status: &Status) -> Option<DownloadErrorKind> {
+        let mut checks = self.size_checks.lock();
+        let check = checks.get_mut(gid)?;
+        let (expected, check_length) = match check {
+            SizeCheck::Pending { expected, check_length } => (*expected, *check_length),
+            SizeCheck::Verified => return None,
+            SizeCheck::Failed { kind, .. } => return Some(kind.clone()),
+        };
+        if status.files.len() != 1 {
+            *check = SizeCheck::Verified;
+            return None;
         }
 
-        // 设置自定义headers
-        if let Some(hdrs) = headers {
-            if !hdrs.is_empty() {
-                log::info!("[aria2] 设置自定义headers到请求选项，数量: {}", hdrs.len());
-                for (i, h) in hdrs.iter().enumerate() {
-                    let header_name = h.split(':').next().unwrap_or("Unknown");
-                    log::info!("[aria2] 设置Header[{}]: {}", i, header_name);
-                }
-                options.header = Some(hdrs);
-            } else {
-                log::warn!("[aria2] 收到空的headers列表");
+        let path = status.files.first().map(|f| PathBuf::from(&f.path)).filter(|p| !p.as_os_str().is_empty());
+        let on_disk = path.as_ref().and_then(|p| std::fs::metadata(to_extended_path(p)).ok()).map(|m| m.len());
+        let Some(kind) = completed_size_error(expected, check_length, status.total_length, on_disk) else {
+            *check = SizeCheck::Verified;
+            return None;
+        };
+
+        log::warn!("[aria2] 任务 {} 完成后核对大小失败: {}", gid, kind);
+        if let Some(path) = path.filter(|_| on_disk.is_some() && matches!(kind, DownloadErrorKind::SizeMismatch { .. })) {
+            let bad = bad_file_path(&path);
+            match std::fs::rename(to_extended_path(&path), to_extended_path(&bad)) {
+                Ok(()) => log::warn!("[aria2] 已将 {} 改名为 {}", path.display(), bad.display()),
+                Err(e) => log::warn!("[aria2] 改名 {} 失败: {}", path.display(), e),
             }
-        } else {
-            log::info!("[aria2] 未提供自定义headers");
         }
-
-        let gid = client
-            .add_uri(vec![url.to_string()], Some(options), None, None)
-            .await?;
-
-        Ok(gid)
+        *check = SizeCheck::Failed {
+            expected,
+            check_length,
+            kind: kind.clone(),
+        };
+        Some(kind)
     }
 
-    /// 获取下载状态
-    pub async fn get_status(&self, gid: &str) -> Result<DownloadProgress> {
+    /// 执行一次 RPC 调用；遇到连接级错误时重连并重放一次
+    ///
+    /// aria2 返回的应用错误（任务不存在、参数错误等）原样返回，不会重试；
+    /// 重连失败或重放后连接仍不可用时返回 `DownloadError::ConnectionLost`。
+    /// 每次调用最多等待 `Aria2Config::rpc_call_timeout`，超时返回 `DownloadError::RpcTimeout`。
+    async fn call<T, F, Fut>(&self, f: F) -> Result<T>
+    where
+        F: Fn(Arc<aria2_ws::Client>) -> Fut,
+        Fut: Future<Output = std::result::Result<T, aria2_ws::Error>>,
+    {
         let client = self
-            .client
-            .as_ref()
+            .current_client()
             .ok_or_else(|| anyhow::anyhow!("aria2 client not connected"))?;
 
-        let status = client.tell_status(gid).await?;
+        let error = match self.with_timeout(f(client.clone())).await? {
+            Err(e) if is_connection_error(&e) => e,
+            result => return result.map_err(Into::into),
+        };
+        log::warn!("[aria2] RPC 连接异常（{}），重连后重放调用", error);
 
-        let completed = status.completed_length;
-        let total = status.total_length;
-        let speed = status.download_speed;
+        let client = self.reconnect(&client).await.map_err(|e| DownloadError::ConnectionLost {
+            reason: format!("{}；重连失败: {}", error, e),
+        })?;
+        match self.with_timeout(f(client)).await? {
+            Err(e) if is_connection_error(&e) => Err(DownloadError::ConnectionLost {
+                reason: e.to_string(),
+            }
+            .into()),
+            result => result.map_err(Into::into),
+        }
+    }
 
-        let percentage = if total > 0 {
-            (completed as f64 / total as f64) * 100.0
-        } else {
-            0.0
-        };
+    /// 重新连接同一端口上的 aria2c（按 `RECONNECT_BACKOFF` 退避）
+    ///
+    /// `failed` 是调用失败时使用的连接；若其它调用已经完成重连，直接返回新连接。
+    async fn reconnect(&self, failed: &Arc<aria2_ws::Client>) -> Result<Arc<aria2_ws::Client>> {
+        let _guard = self.reconnect_lock.lock().await;
+        if let Some(current) = self.current_client() {
+            if !Arc::ptr_eq(&current, failed) {
+                return Ok(current);
+            }
+        }
+        if self.stopped.load(Ordering::SeqCst) {
+            anyhow::bail!("aria2 已关闭");
+        }
 
-        let download_status = match status.status {
-            TaskStatus::Waiting => DownloadStatus::Waiting,
-            TaskStatus::Active => DownloadStatus::Active,
-            TaskStatus::Paused => DownloadStatus::Paused,
-            TaskStatus::Complete => DownloadStatus::Complete,
-            TaskStatus::Error => DownloadStatus::Error(status.error_message.unwrap_or_default()),
-            TaskStatus::Removed => DownloadStatus::Error("已移除".to_string()),
-        };
+        let url = format!("ws://127.0.0.1:{}/jsonrpc", self.rpc_port());
+        let mut last_error = String::new();
+        for delay in RECONNECT_BACKOFF {
+            tokio::time::sleep(delay).await;
+            match aria2_ws::Client::connect(&url, Some(&self.rpc_secret)).await {
+                Ok(client) => {
+                    log::info!("[aria2] RPC 已重新连接");
+                    self.install_client(client);
+                    return self
+                        .current_client()
+                        .ok_or_else(|| anyhow::anyhow!("aria2 client not connected"));
+                }
+                Err(e) => last_error = e.to_string(),
+            }
+        }
+        anyhow::bail!("{} 次重连均失败: {}", RECONNECT_BACKOFF.len(), last_error)
+    }
 
-        Ok(DownloadProgress {
-            gid: gid.to_string(),
-            completed_length: completed,
-            total_length: total,
-            download_speed: speed,
-            percentage,
-            status: download_status,
-        })
+    /// 当前的全局下载限速（字节/秒），0 表示不限速
+    async fn global_speed_limit(&self) -> Result<u64> {
+        let options = self.call(|c| async move { c.get_global_option().await }).await?;
+        Ok(options
+            .extra_options
+            .get("max-overall-download-limit")
+            .and_then(|v| v.as_str())
+            .and_then(parse_speed_value)
+            .unwrap_or(0))
     }
 
-    /// 暂停下载
-    pub async fn pause(&self, gid: &str) -> Result<()> {
-        if let Some(client) = &self.client {
-            client.pause(gid).await?;
+    /// 修改 aria2 全局选项并记录下来，以便重启后恢复
+    async fn change_global_options(&self, pairs: &[(&str, String)]) -> Result<()> {
+        let mut options = aria2_ws::TaskOptions::default();
+        apply_extra_options(&mut options, pairs);
+        self.call(|c| {
+            let options = options.clone();
+            async move { c.change_global_option(options).await }
+        })
+        .await?;
+
+        let mut saved = self.global_options.lock();
+        for (key, value) in pairs {
+            saved.insert(key.to_string(), value.clone());
         }
         Ok(())
     }
 
-    /// 恢复下载
-    pub async fn resume(&self, gid: &str) -> Result<()> {
-        if let Some(client) = &self.client {
-            client.unpause(gid).await?;
+    /// aria2c 是否已退出：子进程已结束且 RPC 端口已释放
+    ///
+    /// 附加到其它实例的 aria2c 时没有子进程句柄，只能以端口是否释放判断。
+    fn exited(&self) -> bool {
+        let process_gone = self
+            .process
+            .lock()
+            .as_mut()
+            .is_none_or(|p| !matches!(p.try_wait(), Ok(None)));
+        process_gone && is_port_free(self.rpc_port())
+    }
+
+    /// 在 `timeout` 内等待 aria2c 退出
+    async fn wait_for_exit(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if self.exited() {
+                return true;
+            }
+            if Instant::now() >= deadline {
+                return false;
+            }
+            tokio::time::sleep(SHUTDOWN_POLL_INTERVAL).await;
         }
-        Ok(())
     }
 
-    /// 取消下载
-    pub async fn cancel(&self, gid: &str) -> Result<()> {
-        if let Some(client) = &self.client {
-            client.remove(gid).await?;
+    /// 结束进程后等待 RPC 端口释放（最多 `PORT_RELEASE_TIMEOUT`），超时只记录警告
+    async fn wait_for_port_release(&self) {
+        let port = self.rpc_port();
+        if !wait_for_port_free(port, PORT_RELEASE_TIMEOUT).await {
+            log::warn!("[aria2] aria2c 已结束，但端口 {} 在 {:?} 内仍未释放", port, PORT_RELEASE_TIMEOUT);
         }
-        Ok(())
     }
 
-    /// 获取全局状态
-    pub async fn get_global_stat(&self) -> Result<(u64, u64)> {
-        if let Some(client) = &self.client {
-            let stat = client.get_global_stat().await?;
-            return Ok((stat.download_speed, stat.num_active as u64));
+    /// 结束 aria2c 子进程（同步、尽力而为）
+    fn kill_process(&self) {
+        if let Some(mut process) = self.process.lock().take() {
+            if let Err(e) = kill_process_tree(process.id()) {
+                log::warn!("[aria2] {}", e);
+            }
+            let _ = process.kill();
+            let _ = process.wait();
         }
-        Ok((0, 0))
     }
 
-    /// 关闭 aria2c
-    pub async fn shutdown(&mut self) -> Result<()> {
-        if let Some(client) = self.client.take() {
-            let _ = client.shutdown().await;
+    /// 在 `rpc_call_timeout` 内等待一次 RPC 调用，并记录连续超时的次数
+    async fn with_timeout<T>(
+        &self,
+        call: impl Future<Output = std::result::Result<T, aria2_ws::Error>>,
+    ) -> Result<std::result::Result<T, aria2_ws::Error>> {
+        let timeout = self.config.rpc_call_timeout;
+        match tokio::time::timeout(timeout, call).await {
+            Ok(result) => {
+                self.rpc_timeouts.store(0, Ordering::SeqCst);
+                Ok(result)
+            }
+            Err(_) => {
+                let count = self.rpc_timeouts.fetch_add(1, Ordering::SeqCst) + 1;
+                log::warn!("[aria2] RPC 调用 {:?} 内没有响应（连续第 {} 次）", timeout, count);
+                Err(DownloadError::RpcTimeout { timeout_secs: timeout.as_secs() }.into())
+            }
         }
-        if let Some(mut process) = self.aria2_process.take() {
-            let _ = process.kill();
+    }
+
+    /// RPC 调用连续超时，aria2c 进程还在但已卡死（只判断本实例启动的 aria2c）
+    fn is_wedged(&self) -> bool {
+        self.owns_process.load(Ordering::SeqCst) && self.rpc_timeouts.load(Ordering::SeqCst) >= RPC_TIMEOUTS_BEFORE_RESTART
+    }
+
+    /// aria2c 是否仍在运行且 RPC 可用
+    async fn is_alive(&self) -> bool {
+        if self.exited() {
+            return false;
         }
-        Ok(())
+        let Some(client) = self.current_client() else {
+            return false;
+        };
+        matches!(
+            tokio::time::timeout(WATCHDOG_PING_TIMEOUT, client.get_version()).await,
+            Ok(Ok(_))
+        )
     }
-}
 
-impl Drop for Aria2Manager {
-    fn drop(&mut self) {
-        if let Some(mut process) = self.aria2_process.take() {
-            let _ = process.kill();
+    /// 恢复可用的 aria2c：进程仍在时只重连，否则（或 `force_restart` 时）用相同参数重新启动
+    ///
+    /// 返回是否重新启动了进程。会话文件中的任务由 aria2c 启动时自动恢复（gid 不变），
+    /// 但距上次自动保存会话之后添加的任务会丢失。
+    async fn recover(&self, force_restart: bool) -> Result<bool> {
+        let port = self.rpc_port();
+        if !force_restart && !self.exited() {
+            if let Ok(client) = Aria2Manager::connect_rpc(port, &self.rpc_secret, &self.config).await {
+                self.install_client(client);
+                log::info!("[aria2] 已重新连接 RPC（端口 {}）", port);
+                return Ok(false);
+            }
+        }
+
+        self.kill_process();
+        let port = if is_port_free(port) {
+            port
+        } else {
+            find_free_port(RPC_PORT_FIRST, RPC_PORT_LAST)
+                .ok_or_else(|| anyhow::anyhow!("重启aria2失败: 没有可用的 RPC 端口"))?
+        };
+        let process = Aria2Manager::spawn_aria2c(
+            &self.aria2c_path,
+            &aria2c_args(port, &self.rpc_secret, &self.session_path, &self.config),
+            &self.output,
+        )?;
+        let pid = process.id();
+        *self.process.lock() = Some(process);
+        self.rpc_port.store(port, Ordering::SeqCst);
+
+        let client = Aria2Manager::connect_rpc(port, &self.rpc_secret, &self.config)
+            .await
+            .map_err(|e| match self.process.lock().as_mut() {
+                Some(process) => Aria2Manager::startup_error(process, e, &self.output),
+                None => self.output.attach_to(e),
+            })?;
+        self.install_client(client);
+        self.rpc_timeouts.store(0, Ordering::SeqCst);
+        // 附加的 aria2c 退出后由本实例重新启动，此后归本实例所有
+        if !self.owns_process.swap(true, Ordering::SeqCst) {
+            log::info!("[aria2] 附加的 aria2c 已退出，改由本实例启动的 aria2c 接管");
+        }
+        write_endpoint(port, &self.rpc_secret, pid);
+
+        // 恢复运行期间修改过的全局选项（代理、限速等）
+        let pairs: Vec<(String, String)> = self
+            .global_options
+            .lock()
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        if !pairs.is_empty() {
+            let pairs: Vec<(&str, String)> = pairs.iter().map(|(k, v)| (k.as_str(), v.clone())).collect();
+            if let Err(e) = self.change_global_options(&pairs).await {
+                log::warn!("[aria2] 重启后恢复全局选项失败: {}", e);
+            }
         }
+        Ok(true)
+    }
+
+    /// 替换 RPC 连接并为其挂接事件转发
+    fn install_client(&self, client: aria2_ws::Client) {
+        let client = Arc::new(client);
+        spawn_event_forwarder(&client, self.events.clone(), self.config.rpc_call_timeout);
+        *self.client.write() = Some(client);
     }
 }
 
-/// 清理全局aria2管理器
-pub async fn cleanup_global_aria2() {
-    if let Some(global) = GLOBAL_ARIA2.get() {
-        let mut guard = global.lock().await;
-        if let Some(mut manager) = guard.take() {
-            let _ = manager.shutdown().await;
-        }
-        ARIA2_WARMED_UP.store(false, Ordering::SeqCst);
+/// 看门狗：定期检查 aria2c，进程退出或连接断开时透明地恢复
+///
+/// 管理器释放或主动关闭后自动退出；重启次数达到 `MAX_ENGINE_RESTARTS` 后放弃。
+fn spawn_watchdog(engine: &Arc<Aria2Engine>) {
+    let engine = Arc::downgrade(engine);
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(WATCHDOG_INTERVAL).await;
+            let Some(engine) = engine.upgrade() else {
+                break;
+            };
+            if engine.stopped.load(Ordering::SeqCst) {
+                break;
+            }
+            let wedged = engine.is_wedged();
+            if !wedged && (engine.is_alive().await || engine.stopped.load(Ordering::SeqCst)) {
+                continue;
+            }
+
+            let restarts = engine.restarts.load(Ordering::SeqCst);
+            if restarts >= MAX_ENGINE_RESTARTS {
+                log::error!("[aria2] aria2c 已重启 {} 次仍不可用，停止自动重启", restarts);
+                break;
+            }
+
+            if wedged {
+                log::warn!("[aria2] aria2c 连续 {} 次 RPC 调用超时，可能已卡死，正在重启...", RPC_TIMEOUTS_BEFORE_RESTART);
+            } else {
+                log::warn!("[aria2] 检测到 aria2c 已退出或连接断开，正在恢复...");
+            }
+            match engine.recover(wedged).await {
+                Ok(false) => {}
+                Ok(true) => {
+                    let count = engine.restarts.fetch_add(1, Ordering::SeqCst) + 1;
+                    log::warn!("[aria2] aria2c 已重启（第 {} 次），继续下载", count);
+                }
+                Err(e) => {
+                    let count = engine.restarts.fetch_add(1, Ordering::SeqCst) + 1;
+                    log::error!("[aria2] 重启 aria2c 失败（第 {} 次）: {}", count, e);
+                }
+            }
+        }
+    });
+}
+
+/// 停滞检测：活动任务的已下载量超过 `Aria2Config::stall_timeout` 没有增长时，暂停再恢复该任务
+///
+/// 恢复后 aria2 会重新建立连接（多镜像任务可能换到其它镜像），避免一条卡死的连接拖住整个任务。
+/// 每次干预都会记录日志并发送带 `EventDetail::StallRestarted` 的事件。
+fn spawn_stall_detector(engine: &Arc<Aria2Engine>) {
+    let Some(stall_timeout) = engine.config.stall_timeout else {
+        return;
+    };
+    let engine = Arc::downgrade(engine);
+    tokio::spawn(async move {
+        // gid -> (上次的已下载量, 该值首次出现的时间)
+        let mut last_progress: HashMap<String, (u64, Instant)> = HashMap::new();
+        loop {
+            tokio::time::sleep(STALL_CHECK_INTERVAL).await;
+            let Some(engine) = engine.upgrade() else {
+                break;
+            };
+            if engine.stopped.load(Ordering::SeqCst) {
+                break;
+            }
+            let Some(client) = engine.current_client() else {
+                continue;
+            };
+            let Ok(Ok(active)) = tokio::time::timeout(engine.config.rpc_call_timeout, client.tell_active()).await else {
+                continue;
+            };
+
+            let now = Instant::now();
+            last_progress.retain(|gid, _| active.iter().any(|s| &s.gid == gid));
+            for status in &active {
+                let entry = last_progress
+                    .entry(status.gid.clone())
+                    .or_insert((status.completed_length, now));
+                if entry.0 != status.completed_length {
+                    *entry = (status.completed_length, now);
+                    continue;
+                }
+                let stalled_for = now.duration_since(entry.1);
+                if stalled_for < stall_timeout {
+                    continue;
+                }
+
+                log::warn!(
+                    "[aria2] 任务 {} 已 {} 秒没有进展，暂停后重新开始",
+                    status.gid,
+                    stalled_for.as_secs()
+                );
+                if let Err(e) = restart_stalled(&engine, &status.gid).await {
+                    log::warn!("[aria2] 重新开始停滞的任务 {} 失败: {}", status.gid, e);
+                }
+                *entry = (status.completed_length, Instant::now());
+                let _ = engine.events.send(DownloadEvent {
+                    gid: status.gid.clone(),
+                    status: DownloadStatus::Active,
+                    detail: Some(EventDetail::StallRestarted { stalled_for }),
+                });
+            }
+        }
+    });
+}
+
+/// 强制暂停任务，等待其真正进入暂停状态后再恢复
+///
+/// 每次调用都经 `Aria2Engine::call`，aria2c 卡死时按 `rpc_call_timeout` 返回，不会拖住停滞检测。
+async fn restart_stalled(engine: &Aria2Engine, gid: &str) -> Result<()> {
+    engine.call(|c| async move { c.force_pause(gid).await }).await?;
+    let deadline = Instant::now() + STALL_PAUSE_TIMEOUT;
+    while Instant::now() < deadline {
+        if engine.call(|c| async move { c.tell_status(gid).await }).await?.status == TaskStatus::Paused {
+            break;
+        }
+        tokio::time::sleep(SHUTDOWN_POLL_INTERVAL).await;
+    }
+    engine.call(|c| async move { c.unpause(gid).await }).await?;
+    Ok(())
+}
+
+/// 镜像轮换：轮换任务出错且错误值得换镜像时，移除该任务并用下一个镜像以相同 gid 重新添加
+///
+/// 目录和文件名不变，aria2 通过 `.aria2` 控制文件续传已下载的部分。每次切换都记录日志并发送
+/// 带 `EventDetail::MirrorSwitched` 的事件；任务结束或放弃轮换后清除轮换状态。
+fn spawn_mirror_rotator(engine: &Arc<Aria2Engine>) {
+    let mut events = engine.events.subscribe();
+    let engine = Arc::downgrade(engine);
+    tokio::spawn(async move {
+        loop {
+            let event = match events.recv().await {
+                Ok(event) => event,
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    log::warn!("[aria2] 镜像轮换处理过慢，丢失 {} 条事件", n);
+                    continue;
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            };
+            let Some(engine) = engine.upgrade() else {
+                break;
+            };
+            // 管理器自己发出的事件（包括本任务的切换事件）不处理
+            if event.detail.is_none() {
+                rotate_mirror(&engine, &event).await;
+            }
+        }
+    });
+}
+
+/// 按时段限速：每 `SCHEDULE_INTERVAL` 按本地时间计算生效的限制，跨过时段边界（或规则被替换）时应用
+///
+/// 进入限速时段前记下当时的全局限速，离开所有限速时段后恢复；暂停时段内暂停全部未暂停的任务
+/// （包括期间新添加的），离开后只恢复由它暂停的任务，用户自己暂停的任务保持暂停。
+fn spawn_bandwidth_scheduler(engine: &Arc<Aria2Engine>) -> watch::Sender<BandwidthSchedule> {
+    let (tx, mut rx) = watch::channel(engine.config.bandwidth_schedule.clone());
+    let engine = Arc::downgrade(engine);
+    tokio::spawn(async move {
+        let mut state = ScheduleState::default();
+        loop {
+            let Some(engine) = engine.upgrade() else {
+                break;
+            };
+            if engine.stopped.load(Ordering::SeqCst) {
+                break;
+            }
+            let effect = rx.borrow().effect_at(TimeOfDay::now());
+            state.apply(&engine, effect).await;
+            drop(engine);
+
+            tokio::select! {
+                _ = tokio::time::sleep(SCHEDULE_INTERVAL) => {}
+                changed = rx.changed() => {
+                    if changed.is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+    });
+    tx
+}
+
+/// 时段限速已应用的状态
+#[derive(Debug, Default)]
+struct ScheduleState {
+    applied: ScheduleEffect,
+    /// 进入限速时段前的全局限速
+    baseline_limit: u64,
+    /// 因暂停时段而暂停的任务
+    paused: Vec<String>,
+}
+
+impl ScheduleState {
+    async fn apply(&mut self, engine: &Aria2Engine, effect: ScheduleEffect) {
+        if effect.limit != self.applied.limit {
+            if self.applied.limit.is_none() {
+                self.baseline_limit = engine.global_speed_limit().await.unwrap_or(0);
+            }
+            let limit = effect.limit.unwrap_or(self.baseline_limit);
+            match engine
+                .change_global_options(&[("max-overall-download-limit", limit.to_string())])
+                .await
+            {
+                Ok(()) => {
+                    match effect.limit {
+                        Some(limit) => log::info!("[aria2] 进入限速时段: {} B/s", limit),
+                        None => log::info!("[aria2] 离开限速时段，恢复限速: {} B/s", limit),
+                    }
+                    self.applied.limit = effect.limit;
+                }
+                Err(e) => log::warn!("[aria2] 应用时段限速失败，稍后重试: {}", e),
+            }
+        }
+
+        if effect.paused {
+            // 每次检查都暂停期间新添加或被手动恢复的任务
+            let running = match running_gids(engine).await {
+                Ok(gids) => gids,
+                Err(e) => {
+                    log::warn!("[aria2] 查询任务失败，暂停时段稍后重试: {}", e);
+                    return;
+                }
+            };
+            for gid in running {
+                let target = gid.as_str();
+                match engine.call(|c| async move { c.pause(target).await }).await {
+                    Ok(()) if !self.paused.contains(&gid) => self.paused.push(gid),
+                    Ok(()) => {}
+                    Err(e) => log::debug!("[aria2] 暂停时段暂停任务 {} 失败: {}", gid, e),
+                }
+            }
+            if !self.applied.paused {
+                log::info!("[aria2] 进入暂停时段，已暂停 {} 个任务", self.paused.len());
+            }
+            self.applied.paused = true;
+        } else if self.applied.paused {
+            for gid in std::mem::take(&mut self.paused) {
+                let target = gid.as_str();
+                if let Err(e) = engine.call(|c| async move { c.unpause(target).await }).await {
+                    log::debug!("[aria2] 离开暂停时段时恢复任务 {} 失败: {}", gid, e);
+                }
+            }
+            log::info!("[aria2] 离开暂停时段，已恢复下载");
+            self.applied.paused = false;
+        }
+    }
+}
+
+/// 下载中和排队中（未暂停）的任务
+async fn running_gids(engine: &Aria2Engine) -> Result<Vec<String>> {
+    let mut statuses = engine.call(|c| async move { c.tell_active().await }).await?;
+    statuses.extend(engine.call(|c| async move { tell_waiting_all(&c).await }).await?);
+    Ok(statuses
+        .into_iter()
+        .filter(|s| s.status != TaskStatus::Paused)
+        .map(|s| s.gid)
+        .collect())
+}
+
+/// 取消句柄已被丢弃的任务（见 `DownloadHandle`），返回发送取消请求的通道
+///
+/// 只在收到请求时临时持有引擎；管理器和所有句柄都释放后通道关闭，任务随之结束。
+fn spawn_abandoned_canceller(engine: &Arc<Aria2Engine>) -> mpsc::UnboundedSender<String> {
+    let (tx, mut rx) = mpsc::unbounded_channel::<String>();
+    let engine = Arc::downgrade(engine);
+    tokio::spawn(async move {
+        while let Some(gid) = rx.recv().await {
+            let Some(engine) = engine.upgrade() else {
+                break;
+            };
+            if engine.stopped.load(Ordering::SeqCst) {
+                break;
+            }
+            // 先清除轮换状态，避免移除后被当作出错换镜像重新添加
+            engine.mirrors.lock().remove(&gid);
+            let target = gid.as_str();
+            match engine.call(|c| async move { c.remove(target).await }).await {
+                Ok(()) => log::info!("[aria2] 任务句柄已释放，取消任务 {}", gid),
+                // 已完成或已移除的任务无需取消
+                Err(e) => log::debug!("[aria2] 句柄释放后取消任务 {} 未执行: {}", gid, e),
+            }
+        }
+    });
+    tx
+}
+
+/// 处理一条任务事件：出错时换下一个镜像，结束时清除轮换状态
+async fn rotate_mirror(engine: &Aria2Engine, event: &DownloadEvent) {
+    let gid = event.gid.as_str();
+    let size_mismatch;
+    let kind = match &event.status {
+        DownloadStatus::Error(kind) => kind,
+        // 完成但大小不符的轮换任务同样换镜像
+        DownloadStatus::Complete if engine.mirrors.lock().contains_key(gid) => {
+            let status = engine.call(|c| async move { c.tell_status(gid).await }).await.ok();
+            match status.and_then(|status| engine.check_size(gid, &status)) {
+                Some(kind) => {
+                    size_mismatch = kind;
+                    &size_mismatch
+                }
+                None => {
+                    engine.mirrors.lock().remove(gid);
+                    return;
+                }
+            }
+        }
+        status if status.is_finished() => {
+            engine.mirrors.lock().remove(gid);
+            return;
+        }
+        _ => return,
+    };
+
+    let (failed, mirror, url, attempt, options) = {
+        let mut mirrors = engine.mirrors.lock();
+        let Some(task) = mirrors.get_mut(gid) else {
+            return;
+        };
+        let failed = task.rotation.current();
+        match task.rotation.advance(kind) {
+            Some(mirror) => (
+                failed,
+                mirror,
+                task.rotation.current_url().to_string(),
+                task.rotation.total_attempts(),
+                task.options.clone(),
+            ),
+            None => {
+                log::warn!(
+                    "[aria2] 任务 {} 的镜像已尝试 {} 次仍失败（{}），不再切换",
+                    gid,
+                    task.rotation.total_attempts(),
+                    kind
+                );
+                mirrors.remove(gid);
+                return;
+            }
+        }
+    };
+
+    log::warn!("[aria2] 任务 {} 镜像 {} 出错（{}），切换到镜像 {}", gid, failed + 1, kind, mirror + 1);
+    let result = async {
+        engine
+            .call(|c| async move { c.remove_download_result(gid).await })
+            .await?;
+        engine
+            .call(|c| {
+                let uris = vec![url.clone()];
+                let options = options.clone();
+                async move {
+                    match c.add_uri(uris, Some(options), None, None).await {
+                        // 连接断开后重放，第一次其实已添加成功
+                        Err(aria2_ws::Error::Aria2 { .. }) if c.tell_status(gid).await.is_ok() => Ok(gid.to_string()),
+                        result => result,
+                    }
+                }
+            })
+            .await
+    }
+    .await;
+
+    match result {
+        Ok(_) => {
+            if let Some(check) = engine.size_checks.lock().get_mut(gid) {
+                if let SizeCheck::Failed { expected, check_length, .. } = *check {
+                    *check = SizeCheck::Pending { expected, check_length };
+                }
+            }
+            let _ = engine.events.send(DownloadEvent {
+                gid: gid.to_string(),
+                status: DownloadStatus::Waiting,
+                detail: Some(EventDetail::MirrorSwitched { mirror, url, attempt }),
+            });
+        }
+        Err(e) => {
+            log::error!("[aria2] 任务 {} 切换镜像失败: {}", gid, e);
+            engine.mirrors.lock().remove(gid);
+        }
+    }
+}
+
+impl Aria2Manager {
+    /// 预热aria2（在后台启动进程并建立连接）
+    /// 
+    /// 可以在应用启动时或用户选择PE时调用，提前准备好aria2
+    pub async fn warmup() -> Result<()> {
+        if ARIA2_WARMED_UP.load(Ordering::SeqCst) {
+            log::info!("[aria2] 已经预热过，跳过");
+            return Ok(());
+        }
+
+        log::info!("[aria2] 开始预热...");
+        
+        // 获取或创建全局管理器
+        let global = GLOBAL_ARIA2.get_or_init(|| Arc::new(TokioMutex::new(None)));
+        let mut guard = global.lock().await;
+        
+        if guard.is_some() {
+            log::info!("[aria2] 全局管理器已存在");
+            ARIA2_WARMED_UP.store(true, Ordering::SeqCst);
+            return Ok(());
+        }
+        
+        // 启动新的管理器
+        match Self::start_with(global_config()).await {
+            Ok(manager) => {
+                *guard = Some(manager);
+                ARIA2_WARMED_UP.store(true, Ordering::SeqCst);
+                log::info!("[aria2] 预热完成");
+                Ok(())
+            }
+            Err(e) => {
+                log::warn!("[aria2] 预热失败: {}", e);
+                Err(e)
+            }
+        }
+    }
+
+    /// 获取全局aria2管理器（如果已预热）或创建新的
+    pub async fn get_or_start() -> Result<Arc<TokioMutex<Option<Aria2Manager>>>> {
+        let global = GLOBAL_ARIA2.get_or_init(|| Arc::new(TokioMutex::new(None)));
+        
+        {
+            let mut guard = global.lock().await;
+            if guard.is_none() {
+                log::info!("[aria2] 全局管理器不存在，正在创建...");
+                let manager = Self::start_with(global_config()).await?;
+                *guard = Some(manager);
+                ARIA2_WARMED_UP.store(true, Ordering::SeqCst);
+            }
+        }
+        
+        Ok(Arc::clone(global))
+    }
+
+    /// 按配置启动 aria2c 进程并连接
+    pub async fn start_with(config: Aria2Config) -> Result<Self> {
+        config.validate()?;
+        config.tls.warn_if_insecure("全局设置");
+
+        let aria2c_path = resolve_aria2c(config.aria2c_path.as_deref())?;
+        log::info!("[aria2] 使用 aria2c: {}", aria2c_path.display());
+        let session_path = prepare_session_file()?;
+
+        // 程序被重复打开时，附加到第一个实例的 aria2c，而不是再启动一个去抢端口
+        if config.attach_to_running {
+            if let Some((endpoint, client)) = attach_existing().await {
+                log::info!("[aria2] 已附加到运行中的 aria2c（端口 {}）", endpoint.port);
+                let version = verify_version(&client, &config).await?;
+                let parts = EngineParts {
+                    aria2c_path,
+                    config,
+                    rpc_secret: endpoint.secret,
+                    session_path,
+                    port: endpoint.port,
+                    process: None,
+                    output: OutputTail::new(),
+                    version,
+                };
+                return Ok(Self::assemble(parts, client, Vec::new()).await);
+            }
+        }
+
+        let rpc_secret = match config.rpc_secret.clone() {
+            Some(secret) => secret,
+            None => generate_rpc_secret()?,
+        };
+        let start_time = std::time::Instant::now();
+        // 指定端口时只尝试该端口
+        let (first_port, last_port) = match config.rpc_port {
+            Some(port) => (port, port),
+            None => (RPC_PORT_FIRST, RPC_PORT_LAST),
+        };
+        let mut next_port = first_port;
+        let output = OutputTail::new();
+        // 指定的端口可能还被刚关闭的旧 aria2c 占着（如界面上的「重置下载」），稍等其释放
+        if config.rpc_port.is_some() && !wait_for_port_free(first_port, PORT_RELEASE_TIMEOUT).await {
+            anyhow::bail!("初始化aria2失败: RPC 端口 {} 被占用", first_port);
+        }
+
+        for attempt in 1..=MAX_PORT_ATTEMPTS {
+            let port = find_free_port(next_port, last_port).ok_or_else(|| {
+                anyhow::anyhow!(
+                    "初始化aria2失败: {}-{} 范围内没有可用的 RPC 端口",
+                    first_port,
+                    last_port
+                )
+            })?;
+
+            log::info!("[aria2] 正在启动 aria2c 进程 (RPC 端口: {})...", port);
+            let args = aria2c_args(port, &rpc_secret, &session_path, &config);
+            let mut process = Self::spawn_aria2c(&aria2c_path, &args, &output)?;
+            log::info!("[aria2] aria2c 进程已启动，正在等待 RPC 服务就绪...");
+
+            match Self::connect_rpc(port, &rpc_secret, &config).await {
+                Ok(client) => {
+                    log::info!("[aria2] RPC 就绪，端口: {}，总耗时: {:?}", port, start_time.elapsed());
+                    let version = match verify_version(&client, &config).await {
+                        Ok(version) => version,
+                        Err(e) => {
+                            let _ = client.shutdown().await;
+                            return Err(e);
+                        }
+                    };
+
+                    let restored_tasks = match list_unfinished_gids(&client, config.rpc_call_timeout).await {
+                        Ok(gids) => gids,
+                        Err(e) => {
+                            log::warn!("[aria2] 读取恢复的任务失败: {}", e);
+                            Vec::new()
+                        }
+                    };
+                    if !restored_tasks.is_empty() {
+                        log::info!("[aria2] 从会话文件恢复了 {} 个任务", restored_tasks.len());
+                    }
+
+                    write_endpoint(port, &rpc_secret, process.id());
+                    let parts = EngineParts {
+                        aria2c_path,
+                        config,
+                        rpc_secret,
+                        session_path,
+                        port,
+                        process: Some(process),
+                        output,
+                        version,
+                    };
+                    return Ok(Self::assemble(parts, client, restored_tasks).await);
+                }
+                Err(e) => {
+                    // 探测到启动之间端口被其它进程抢占：aria2c 绑定失败退出，换下一个端口重试。
+                    // 端口仍然空闲则说明是 aria2c 自身启动失败，重试无意义。
+                    if is_port_free(port) || port >= last_port || attempt == MAX_PORT_ATTEMPTS {
+                        return Err(Self::startup_error(&mut process, e, &output));
+                    }
+                    log::warn!("[aria2] 端口 {} 在启动期间被占用，改用下一个端口重试", port);
+                    next_port = port + 1;
+                }
+            }
+        }
+
+        anyhow::bail!("初始化aria2失败: 多次尝试后仍无法绑定 RPC 端口")
+    }
+
+    /// 由已连接的 aria2c 组装管理器：启动看门狗，并在未显式配置代理时应用系统代理
+    async fn assemble(parts: EngineParts, client: aria2_ws::Client, restored_tasks: Vec<String>) -> Self {
+        // 恢复的任务无法得知原始添加时间，从本次启动开始计时
+        let now = Instant::now();
+        let timings = restored_tasks
+            .iter()
+            .map(|gid| (gid.clone(), TaskTiming { added: now, finished: None }))
+            .collect();
+
+        let (events, _) = broadcast::channel(EVENT_CHANNEL_CAPACITY);
+        let owns_process = parts.process.is_some();
+        let split = parts.config.split;
+        let version = parts.version;
+
+        // 恢复的任务中使用 .part 临时文件的，完成后仍需改名
+        let jobs = JobStore::open_default();
+        let renames = restored_tasks
+            .iter()
+            .filter_map(|gid| jobs.get(gid))
+            .filter_map(|job| {
+                let name = job.file_name.as_ref().filter(|_| job.uses_part_file)?;
+                Some((
+                    job.gid.clone(),
+                    PendingRename {
+                        part_path: job.save_dir.join(format!("{}{}", name, PART_SUFFIX)),
+                        final_path: job.save_dir.join(name),
+                        on_conflict: OnConflict::default(),
+                        finalized: false,
+                    },
+                ))
+            })
+            .collect();
+        let size_checks = restored_tasks
+            .iter()
+            .filter_map(|gid| {
+                let job = jobs.get(gid)?;
+                Some((gid.clone(), SizeCheck::for_task(job.expected_size, job.skip_length_check)?))
+            })
+            .collect();
+        let engine = Arc::new(Aria2Engine {
+            aria2c_path: parts.aria2c_path,
+            config: parts.config,
+            rpc_secret: parts.rpc_secret,
+            session_path: parts.session_path,
+            rpc_port: AtomicU16::new(parts.port),
+            split: AtomicU32::new(split),
+            client: parking_lot::RwLock::new(None),
+            process: parking_lot::Mutex::new(parts.process),
+            output: parts.output,
+            events,
+            proxy: parking_lot::Mutex::new(None),
+            global_options: parking_lot::Mutex::new(HashMap::new()),
+            mirrors: parking_lot::Mutex::new(HashMap::new()),
+            size_checks: parking_lot::Mutex::new(size_checks),
+            reconnect_lock: TokioMutex::new(()),
+            owns_process: AtomicBool::new(owns_process),
+            restarts: AtomicU32::new(0),
+            rpc_timeouts: AtomicU32::new(0),
+            stopped: AtomicBool::new(false),
+        });
+        engine.install_client(client);
+        spawn_watchdog(&engine);
+        spawn_stall_detector(&engine);
+        spawn_mirror_rotator(&engine);
+        let abandoned = spawn_abandoned_canceller(&engine);
+        let schedule = spawn_bandwidth_scheduler(&engine);
+        let manager = Self {
+            engine,
+            restored_tasks,
+            version,
+            timings: parking_lot::Mutex::new(timings),
+            renames: parking_lot::Mutex::new(renames),
+            jobs,
+            watch_cache: parking_lot::Mutex::new(HashMap::new()),
+            speeds: parking_lot::Mutex::new(HashMap::new()),
+            requests: parking_lot::Mutex::new(HashMap::new()),
+            abandoned,
+            schedule,
+            existing: parking_lot::Mutex::new(HashMap::new()),
+        };
+
+        // 未显式配置代理时使用系统代理
+        if let Some(proxy) = read_system_proxy() {
+            log::info!("[aria2] 使用系统代理: {}", proxy.url);
+            if let Err(e) = manager.set_proxy_config(Some(proxy)).await {
+                log::warn!("[aria2] 应用系统代理失败: {}", e);
+            }
+        }
+        manager
+    }
+
+    /// 以给定参数启动 aria2c 进程，其 stdout/stderr 写入日志并保留在 `output` 中
+    ///
+    /// aria2c 不随本程序的作业对象结束：`--stop-with-process` 会在本程序退出后让它正常退出并保存会话，
+    /// 被作业直接结束则会丢失最近的会话和控制文件更新。
+    fn spawn_aria2c(aria2c_path: &Path, args: &[String], output: &OutputTail) -> Result<Child> {
+        let spawn = |kill_with_app: bool| {
+            let options = CommandOptions {
+                kill_with_app,
+                ..Default::default()
+            };
+            create_command_with(aria2c_path, &options)
+                .args(args)
+                .stdin(Stdio::null())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .spawn()
+        };
+        let mut process = match spawn(false) {
+            // 本程序的作业嵌套在不允许脱离的外层作业中
+            Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => {
+                log::warn!("[aria2] 无法脱离作业对象，aria2c 将随本程序一起结束");
+                spawn(true)
+            }
+            result => result,
+        }
+        .map_err(|e| ToolError::from_launch_error(Tool::Aria2c, &e))?;
+        output.capture(&mut process);
+        Ok(process)
+    }
+
+    /// RPC 未能就绪时的错误：aria2c 已退出则按退出码说明原因（`ToolError`），都附带最近的输出
+    fn startup_error(process: &mut Child, error: anyhow::Error, output: &OutputTail) -> anyhow::Error {
+        let error = match process.try_wait() {
+            Ok(Some(status)) => {
+                let recent = output.recent();
+                let last_line = recent.last().map_or("", String::as_str);
+                ToolError::from_exit(Tool::Aria2c, status.code(), last_line).into()
+            }
+            _ => error,
+        };
+        output.attach_to(error)
+    }
+
+    /// 等待 RPC 服务就绪并建立 WebSocket 连接
+    ///
+    /// 在 `config.rpc_connect_timeout` 内每隔 `rpc_connect_interval` 重试一次。
+    async fn connect_rpc(port: u16, secret: &str, config: &Aria2Config) -> Result<aria2_ws::Client> {
+        let url = format!("ws://127.0.0.1:{}/jsonrpc", port);
+        let deadline = Instant::now() + config.rpc_connect_timeout;
+        let mut last_error = String::new();
+
+        // 先短暂等待进程启动
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        for attempt in 1.. {
+            match aria2_ws::Client::connect(&url, Some(secret)).await {
+                Ok(c) => {
+                    log::info!("[aria2] RPC 连接成功 (第 {} 次尝试)", attempt);
+                    return Ok(c);
+                }
+                Err(e) => last_error = e.to_string(),
+            }
+            if Instant::now() + config.rpc_connect_interval > deadline {
+                break;
+            }
+            tokio::time::sleep(config.rpc_connect_interval).await;
+        }
+
+        anyhow::bail!("初始化aria2失败: {}", last_error)
+    }
+
+    /// aria2c 实际使用的 RPC 端口
+    pub fn rpc_port(&self) -> u16 {
+        self.engine.rpc_port()
+    }
+
+    /// aria2c 是否由本实例启动
+    ///
+    /// 为 false 时表示附加到了另一个程序实例的 aria2c，`shutdown`/`Drop` 不会结束该进程。
+    pub fn owns_process(&self) -> bool {
+        self.engine.owns_process.load(Ordering::SeqCst)
+    }
+
+    /// 看门狗自动重启 aria2c 的次数，可用于提示「下载引擎已重启，正在继续」
+    pub fn restart_count(&self) -> u32 {
+        self.engine.restarts.load(Ordering::SeqCst)
+    }
+
+    /// 当前 RPC 连接
+    fn client(&self) -> Result<Arc<aria2_ws::Client>> {
+        self.engine
+            .current_client()
+            .ok_or_else(|| anyhow::anyhow!("aria2 client not connected"))
+    }
+
+    /// 连接的 aria2c 版本和已启用的特性（供诊断页面显示）
+    pub fn version(&self) -> &Aria2Version {
+        &self.version
+    }
+
+    /// 启动时从会话文件恢复的任务 gid（供上层重新挂接进度显示）
+    pub fn restored_tasks(&self) -> &[String] {
+        &self.restored_tasks
+    }
+
+    /// 设置下载代理（支持 http:// 与 https:// 代理地址）
+    ///
+    /// 同时修改 aria2 全局选项（影响已在进行的任务的新连接）和之后添加的每个任务。
+    pub async fn set_proxy(&self, url: &str, user: Option<&str>, password: Option<&str>) -> Result<()> {
+        let proxy = ProxyConfig::new(url, user, password)?;
+        self.set_proxy_config(Some(proxy)).await
+    }
+
+    /// 设置或清除（None）代理配置，可携带 no-proxy 主机列表
+    pub async fn set_proxy_config(&self, proxy: Option<ProxyConfig>) -> Result<()> {
+        let pairs = match &proxy {
+            Some(p) => p.to_aria2_options(),
+            // 空字符串即清除 aria2 中的代理设置
+            None => vec![
+                ("all-proxy", String::new()),
+                ("all-proxy-user", String::new()),
+                ("all-proxy-passwd", String::new()),
+                ("no-proxy", String::new()),
+            ],
+        };
+        self.engine.change_global_options(&pairs).await?;
+
+        match &proxy {
+            Some(p) => log::info!("[aria2] 已设置代理: {}，例外主机 {} 个", p.url, p.no_proxy.len()),
+            None => log::info!("[aria2] 已清除代理"),
+        }
+        *self.engine.proxy.lock() = proxy;
+        Ok(())
+    }
+
+    /// 启动 aria2c 进程并连接（公开接口，向后兼容）
+    pub async fn start() -> Result<Self> {
+        Self::start_with(Aria2Config::default()).await
+    }
+
+    /// 使用指定的 RPC 密钥启动（None 时与 `start()` 相同，生成随机密钥）
+    ///
+    /// 主要供测试使用固定密钥
+    pub async fn start_with_token(token: Option<String>) -> Result<Self> {
+        Self::start_with(Aria2Config {
+            rpc_secret: token,
+            ..Default::default()
+        })
+        .await
+    }
+
+    /// 添加下载任务
+    pub async fn add_download(
+        &self,
+        url: &str,
+        save_dir: &str,
+        filename: Option<&str>,
+    ) -> Result<String> {
+        self.add_download_with_headers(url, save_dir, filename, None).await
+    }
+
+    /// 添加下载任务（支持自定义headers，"Name: Value" 形式，如 PE 下载接口返回的列表）
+    pub async fn add_download_with_headers(
+        &self,
+        url: &str,
+        save_dir: &str,
+        filename: Option<&str>,
+        headers: Option<Vec<String>>,
+    ) -> Result<String> {
+        let headers = headers
+            .map(|lines| lines.iter().map(|l| parse_header_line(l)).collect::<Result<Vec<_>>>())
+            .transpose()?;
+        let options = DownloadOptions {
+            filename: filename.map(str::to_string),
+            headers,
+            ..Default::default()
+        };
+        self.add_uris(vec![url.to_string()], save_dir, &options).await
+    }
+
+    /// 添加下载任务并返回句柄：句柄被丢弃时自动取消任务，`detach` 后不再取消
+    ///
+    /// 用于可能被用户中途退出的流程，避免留下无人管理、仍在下载的任务。
+    pub async fn add_download_guarded(
+        &self,
+        url: &str,
+        save_dir: &str,
+        options: &DownloadOptions,
+    ) -> Result<DownloadHandle> {
+        let gid = self.add_download_with_options(url, save_dir, options).await?;
+        Ok(DownloadHandle::new(gid, self.abandoned.clone()))
+    }
+
+    /// 添加下载任务（完整参数，如校验和）
+    pub async fn add_download_with_options(
+        &self,
+        url: &str,
+        save_dir: &str,
+        options: &DownloadOptions,
+    ) -> Result<String> {
+        self.add_uris(vec![url.to_string()], save_dir, options).await
+    }
+
+    /// 添加多镜像下载任务
+    ///
+    /// 所有镜像地址在同一个任务中交给 aria2，某个镜像卡顿时 aria2 会自动切换到其它镜像。
+    /// 未指定 `filename` 时，要求所有地址指向同名文件，否则返回错误。
+    pub async fn add_download_multi(
+        &self,
+        urls: &[String],
+        save_dir: &str,
+        filename: Option<&str>,
+    ) -> Result<String> {
+        validate_mirror_urls(urls, filename)?;
+        let options = DownloadOptions {
+            filename: filename.map(str::to_string),
+            ..Default::default()
+        };
+        self.add_uris(urls.to_vec(), save_dir, &options).await
+    }
+
+    /// 添加镜像轮换下载任务
+    ///
+    /// 与 `add_download_multi` 不同，aria2 每次只使用一个镜像：当前镜像超时、网络不可达或返回 404 时，
+    /// 管理器移除任务并以相同的 gid 换下一个镜像重新添加，已下载的部分继续使用（见 `mirror` 模块）。
+    /// 尝试次数受 `Aria2Config::mirror_attempts_per_url` 和 `mirror_max_attempts` 限制，
+    /// 每次切换发送带 `EventDetail::MirrorSwitched` 的事件。
+    ///
+    /// 设置 `DownloadOptions::rank_mirrors` 时先测速（见 `rank_mirrors`），从最快的镜像开始。
+    ///
+    /// 轮换状态只保存在内存中，程序重启后从会话恢复的任务不再换镜像。
+    pub async fn add_download_with_mirrors(
+        &self,
+        urls: &[String],
+        save_dir: &str,
+        options: &DownloadOptions,
+    ) -> Result<String> {
+        validate_mirror_urls(urls, options.filename.as_deref())?;
+        let urls = if options.rank_mirrors && urls.len() > 1 {
+            let ranked: Vec<String> = self.rank_mirrors(urls).await?.into_iter().map(|s| s.url).collect();
+            log::info!("[aria2] 镜像测速完成，最快的是 {}", ranked[0]);
+            ranked
+        } else {
+            urls.to_vec()
+        };
+        self.add_task(urls, save_dir, options, true).await
+    }
+
+    /// 对镜像测速（走当前代理，使用配置的 User-Agent），按速度从快到慢返回
+    ///
+    /// 不可用的镜像速度为 0，排在最后。
+    pub async fn rank_mirrors(&self, urls: &[String]) -> Result<Vec<MirrorScore>> {
+        let proxy = self.engine.proxy.lock().clone();
+        let config = &self.engine.config;
+        mirror::rank_mirrors(urls, proxy.as_ref(), &config.tls, &config.user_agent).await
+    }
+
+    /// 添加 BitTorrent 下载（本地 .torrent 文件或 magnet: 链接）
+    ///
+    /// 使用 `TorrentOptions::default()`：不做种，下载完成即停止上传。
+    /// 磁力链接在获取元数据阶段 total_length 为 0，`get_status` 会在元数据完成后自动跟随到实际下载任务。
+    pub async fn add_torrent(&self, path_or_magnet: &str, save_dir: &str) -> Result<String> {
+        self.add_torrent_with_options(path_or_magnet, save_dir, &TorrentOptions::default())
+            .await
+    }
+
+    /// 添加 BitTorrent 下载，并指定做种时间、分享率和上传限制
+    pub async fn add_torrent_with_options(
+        &self,
+        path_or_magnet: &str,
+        save_dir: &str,
+        torrent_options: &TorrentOptions,
+    ) -> Result<String> {
+        torrent_options.validate()?;
+        ensure_writable(Path::new(save_dir))?;
+        let mut options = aria2_ws::TaskOptions {
+            dir: Some(save_dir.to_string()),
+            ..Default::default()
+        };
+        apply_extra_options(&mut options, &torrent_options.aria2_options());
+        if torrent_options.paused {
+            let key = if is_magnet_link(path_or_magnet) { "pause-metadata" } else { "pause" };
+            apply_extra_options(&mut options, &[(key, "true".to_string())]);
+        }
+        if torrent_options.seed_time != Some(0) {
+            log::warn!(
+                "[aria2] 种子任务下载完成后将继续做种: seed_time={:?}, seed_ratio={:?}",
+                torrent_options.seed_time,
+                torrent_options.seed_ratio
+            );
+        }
+
+        if is_magnet_link(path_or_magnet) {
+            log::info!("[aria2] 添加磁力链接任务");
+            let uris = vec![path_or_magnet.to_string()];
+            self.submit(options, |c, o| {
+                let uris = uris.clone();
+                async move { c.add_uri(uris, Some(o), None, None).await }
+            })
+            .await
+        } else {
+            let torrent = std::fs::read(path_or_magnet)
+                .map_err(|e| anyhow::anyhow!("读取种子文件失败 {}: {}", path_or_magnet, e))?;
+            log::info!("[aria2] 添加种子任务: {}", path_or_magnet);
+            self.submit(options, |c, o| {
+                let torrent = torrent.clone();
+                async move { c.add_torrent(torrent, None, Some(o), None, None).await }
+            })
+            .await
+        }
+    }
+
+    /// 添加 Metalink 下载（.metalink / .meta4），返回每个文件对应的 gid
+    ///
+    /// Metalink 自带镜像列表和哈希值，aria2 会为其中每个文件各创建一个任务，
+    /// 每个 gid 都可以单独用 `get_status` 查询。
+    pub async fn add_metalink(&self, path: &Path, save_dir: &str) -> Result<Vec<String>> {
+        let client = self.client()?;
+
+        let metalink = std::fs::read(path)
+            .map_err(|e| anyhow::anyhow!("读取 Metalink 文件失败 {}: {}", path.display(), e))?;
+        let options = aria2_ws::TaskOptions {
+            dir: Some(save_dir.to_string()),
+            ..Default::default()
+        };
+
+        // aria2_ws 的 add_metalink 按单个 gid 解析返回值，而 addMetalink 实际返回 gid 数组，
+        // 因此直接调用底层 RPC。Metalink 的多个任务无法预先指定 gid，连接断开时不重放，避免重复添加；
+        // 但仍受 rpc_call_timeout 约束
+        let params = vec![
+            serde_json::Value::String(BASE64_STANDARD.encode(metalink)),
+            serde_json::to_value(options)?,
+        ];
+        let gids: Vec<String> = self
+            .engine
+            .with_timeout(client.call_and_wait("addMetalink", params))
+            .await??;
+
+        log::info!("[aria2] 添加 Metalink 任务: {}，共 {} 个文件", path.display(), gids.len());
+        for gid in &gids {
+            self.record_added(gid);
+        }
+        Ok(gids)
+    }
+
+    /// 批量添加下载任务，返回的 gid 与 `items` 一一对应
+    ///
+    /// 全有或全无：任意一项添加失败时，会移除本次已添加的任务并返回带序号的错误，
+    /// 不会出现部分任务悄悄丢失的情况。
+    pub async fn add_downloads(&self, items: &[DownloadRequest]) -> Result<Vec<String>> {
+        let mut gids = Vec::with_capacity(items.len());
+
+        for (i, item) in items.iter().enumerate() {
+            let options = DownloadOptions {
+                filename: item.filename.clone(),
+                paused: item.paused,
+                expected_size: item.expected_size,
+                ..Default::default()
+            };
+            match self.add_uris(vec![item.url.clone()], &item.save_dir, &options).await {
+                Ok(gid) => gids.push(gid),
+                Err(e) => {
+                    log::warn!("[aria2] 批量添加第 {} 项失败，回滚已添加的 {} 个任务", i + 1, gids.len());
+                    for gid in &gids {
+                        self.rollback_task(gid).await;
+                    }
+                    return Err(e.context(format!("批量添加第 {}/{} 项失败: {}", i + 1, items.len(), item.url)));
+                }
+            }
+        }
+
+        log::info!("[aria2] 批量添加 {} 个下载任务完成", gids.len());
+        Ok(gids)
+    }
+
+    /// 撤销刚添加的任务：从 aria2 中移除，并清理该 gid 在内存中的全部状态和持久化的任务元数据
+    async fn rollback_task(&self, gid: &str) {
+        if self.existing.lock().remove(gid).is_none() {
+            if let Err(e) = self.cancel(gid).await {
+                log::warn!("[aria2] 回滚任务 {} 失败: {}", gid, e);
+            }
+            // aria2 停止任务是异步的，结果可能暂时还移除不了；即使失败也要清理本地状态
+            if let Err(e) = self.engine.call(|c| async move { c.remove_download_result(gid).await }).await {
+                log::debug!("[aria2] 清理任务结果失败 {}: {}", gid, e);
+            }
+        }
+        self.forget_task(gid);
+        self.jobs.remove(gid);
+    }
+
+    /// 批量添加下载任务并作为一个下载组返回（全有或全无，同 `add_downloads`）
+    pub async fn add_group(&self, items: &[DownloadRequest]) -> Result<DownloadGroup> {
+        Ok(DownloadGroup::new(self.add_downloads(items).await?))
+    }
+
+    /// 向 aria2 提交一个任务（uris 为同一文件的一个或多个来源）
+    async fn add_uris(
+        &self,
+        uris: Vec<String>,
+        save_dir: &str,
+        task: &DownloadOptions,
+    ) -> Result<String> {
+        self.add_task(uris, save_dir, task, false).await
+    }
+
+    /// 提交任务；`rotate` 为 true 时 aria2 只拿到第一个地址，其余地址留给镜像轮换
+    async fn add_task(
+        &self,
+        uris: Vec<String>,
+        save_dir: &str,
+        task: &DownloadOptions,
+        rotate: bool,
+    ) -> Result<String> {
+        task.validate()?;
+        // aria2 拿到的是不带 \\?\ 前缀的普通 UTF-8 路径；本程序自己的文件操作再加上前缀
+        let save_dir = &normalize_path(save_dir);
+        // 网络共享需要区分未登录和共享不存在，本地目录只检查能否写入
+        match preflight::is_unc(Path::new(save_dir)) {
+            true => preflight::check_network_dir(Path::new(save_dir))?,
+            false => ensure_writable(Path::new(save_dir))?,
+        }
+        if let Some(gid) = self.reuse_existing_file(&uris, save_dir, task).await? {
+            return Ok(gid);
+        }
+        let mut options = aria2_ws::TaskOptions::default();
+        options.dir = Some(save_dir.to_string());
+        let config = &self.engine.config;
+        options.max_connection_per_server = Some(task.max_connections.unwrap_or(config.max_connection_per_server) as i32);
+        apply_extra_options(
+            &mut options,
+            &[
+                ("lowest-speed-limit", config.lowest_speed_limit.to_string()),
+                ("timeout", config.timeout.as_secs().to_string()),
+                ("connect-timeout", config.connect_timeout.as_secs().to_string()),
+            ],
+        );
+        if let Some(allocation) = task.file_allocation {
+            apply_extra_options(&mut options, &[("file-allocation", allocation.aria2_value().to_string())]);
+        }
+        if task.paused {
+            apply_extra_options(&mut options, &[("pause", "true".to_string())]);
+        }
+
+        if let Some(proxy) = self.engine.proxy.lock().as_ref() {
+            apply_extra_options(&mut options, &proxy.to_aria2_options());
+        }
+        // 每个任务都显式设置证书校验，附加到另一实例的 aria2c 时全局设置未必相同
+        let tls = task.tls.as_ref().unwrap_or(&config.tls);
+        tls.warn_if_insecure(&format!("任务 {}", uris[0]));
+        apply_extra_options(&mut options, &tls.aria2_options());
+
+        let expected_hash = match &task.checksum {
+            Some((hash_type, expected)) => {
+                let checksum = checksum_option(*hash_type, expected)?;
+                log::info!("[aria2] 任务启用 {} 校验", hash_type.aria2_name());
+                options
+                    .extra_options
+                    .insert("checksum".to_string(), serde_json::Value::String(checksum.clone()));
+                Some(checksum)
+            }
+            None => None,
+        };
+
+        // 设置自定义headers和Cookie（只在 debug 级别记录名称，值可能是凭据）
+        let mut headers = task.headers.clone().unwrap_or_default();
+        if let Some(cookie) = cookie_header_value(&task.cookies)? {
+            log::debug!("[aria2] 任务附带 {} 个 Cookie", task.cookies.len());
+            headers.push(("Cookie".to_string(), cookie));
+        }
+        if !headers.is_empty() {
+            let lines = header_lines(&headers)?;
+            for (name, _) in &headers {
+                log::debug!("[aria2] 设置Header: {}", name);
+            }
+            options.header = Some(lines);
+        }
+
+        // User-Agent 和 Referer 使用 aria2 的专用选项，避免与其默认 User-Agent 重复发送；
+        // 附加到另一实例的 aria2c 时全局默认值未必相同，因此每个任务都显式设置
+        let user_agent = task.user_agent.as_ref().unwrap_or(&self.engine.config.user_agent);
+        let mut probe_headers = headers.clone();
+        probe_headers.push(("User-Agent".to_string(), user_agent.clone()));
+        options
+            .extra_options
+            .insert("user-agent".to_string(), serde_json::Value::String(user_agent.clone()));
+        if let Some(referer) = &task.referer {
+            probe_headers.push(("Referer".to_string(), referer.clone()));
+            options
+                .extra_options
+                .insert("referer".to_string(), serde_json::Value::String(referer.clone()));
+        }
+        header_lines(&probe_headers)?;
+
+        if uris.len() > 1 {
+            log::info!(
+                "[aria2] 任务包含 {} 个镜像地址{}",
+                uris.len(),
+                if rotate { "，出错时依次轮换" } else { "" }
+            );
+        }
+
+        let proxy = self.engine.proxy.lock().clone();
+        if !task.skip_connectivity_check {
+            preflight_connectivity(&uris, proxy.as_ref()).await?;
+        }
+        // 未指定文件名时用 HEAD 请求取得服务器给出的文件名（Content-Disposition），顺便得到大小
+        let needs_size = !task.skip_space_check && task.expected_size.is_none();
+        let head = if task.filename.is_none() || needs_size {
+            preflight::probe_head(&uris[0], &probe_headers, proxy.as_ref(), tls).await
+        } else {
+            None
+        };
+        let known_size = task.expected_size.or(head.as_ref().and_then(|h| h.content_length));
+        let requested_dir = save_dir;
+        let save_dir = &match task.skip_space_check {
+            true => save_dir.clone(),
+            false => self.space_checked_dir(save_dir, known_size, task.relocate.as_ref())?,
+        };
+        options.dir = Some(save_dir.clone());
+        let split = task_split(task.split, known_size, self.engine.split.load(Ordering::SeqCst), config.small_file_threshold);
+        options.split = Some(split as i32);
+        let file_name = match &task.filename {
+            Some(name) => {
+                let sanitized = sanitize_filename(name);
+                if sanitized.is_empty() {
+                    anyhow::bail!("文件名无效: {}", name);
+                }
+                if sanitized != *name {
+                    log::info!("[aria2] 文件名 {} 含有 Windows 不允许的字符，改用 {}", name, sanitized);
+                }
+                Some(sanitized)
+            }
+            None => {
+                let name = head.and_then(|h| h.file_name);
+                match &name {
+                    Some(name) => log::info!("[aria2] 使用服务器提供的文件名: {}", name),
+                    // 交给 aria2 推断：它在 GET 时仍会参考 Content-Disposition
+                    None => apply_extra_options(&mut options, &[("content-disposition-default-utf8", "true".to_string())]),
+                }
+                name
+            }
+        };
+
+        // 只有明确知道最终文件名时才使用临时名；否则文件名由 aria2 推断，无法事先确定
+        let rename = file_name.as_ref().map(|name| {
+            options.out = Some(format!("{}{}", name, PART_SUFFIX));
+            PendingRename {
+                part_path: Path::new(save_dir).join(format!("{}{}", name, PART_SUFFIX)),
+                final_path: Path::new(save_dir).join(name),
+                on_conflict: task.on_conflict,
+                finalized: false,
+            }
+        });
+        // 文件名由 aria2 推断时不经过 .part 改名，冲突交给 aria2 处理（全局设置为覆盖）
+        if rename.is_none() && task.on_conflict != OnConflict::Overwrite {
+            apply_extra_options(
+                &mut options,
+                &[
+                    ("allow-overwrite", "false".to_string()),
+                    ("auto-file-renaming", (task.on_conflict == OnConflict::Rename).to_string()),
+                ],
+            );
+        }
+
+        let target = match &rename {
+            Some(rename) => Some(rename.part_path.clone()),
+            None => url_filename(&uris[0])
+                .map(sanitize_filename)
+                .filter(|name| !name.is_empty())
+                .map(|name| Path::new(save_dir).join(name)),
+        };
+        if let Some(target) = target {
+            if exceeds_max_path(&target) {
+                log::warn!(
+                    "[aria2] 保存路径超过 {} 个字符，aria2c 可能无法写入，建议换一个较短的目录: {}",
+                    crate::utils::path::MAX_PATH,
+                    target.display()
+                );
+            }
+            let resume = is_resumable(&target);
+            if resume.can_resume() {
+                log::info!(
+                    "[aria2] 发现未完成的下载 {}，已下载 {} MB，继续下载",
+                    target.display(),
+                    resume.downloaded_bytes() / (1024 * 1024)
+                );
+            } else {
+                log::info!("[aria2] 从头开始下载 {}", target.display());
+            }
+        }
+
+        let submit_uris = if rotate { vec![uris[0].clone()] } else { uris.clone() };
+        // 换镜像时任务已经开始过，重新添加不再暂停
+        let rotation = rotate.then(|| {
+            let mut options = options.clone();
+            options.extra_options.remove("pause");
+            options
+        });
+        let gid = self
+            .submit(options, |c, o| {
+                let uris = submit_uris.clone();
+                async move { c.add_uri(uris, Some(o), None, None).await }
+            })
+            .await?;
+        if let Some(mut options) = rotation {
+            options.gid = Some(gid.clone());
+            let config = &self.engine.config;
+            self.engine.mirrors.lock().insert(
+                gid.clone(),
+                MirrorTask {
+                    rotation: MirrorRotation::new(uris.clone(), config.mirror_attempts_per_url, config.mirror_max_attempts),
+                    options,
+                },
+            );
+        }
+
+        self.jobs.insert(JobRecord {
+            gid: gid.clone(),
+            display_name: task
+                .display_name
+                .clone()
+                .or_else(|| file_name.clone())
+                .unwrap_or_else(|| uris[0].clone()),
+            urls: uris.clone(),
+            save_dir: PathBuf::from(save_dir),
+            requested_save_dir: (save_dir != requested_dir).then(|| PathBuf::from(requested_dir)),
+            file_name,
+            uses_part_file: rename.is_some(),
+            expected_hash,
+            expected_size: task.expected_size,
+            skip_length_check: task.skip_length_check,
+            tag: task.tag.clone(),
+            created_at: JobStore::now(),
+            state: JobState::Pending,
+            verified: None,
+        });
+        if let Some(rename) = rename {
+            self.renames.lock().insert(gid.clone(), rename);
+        }
+        if let Some(check) = SizeCheck::for_task(task.expected_size, task.skip_length_check) {
+            self.engine.size_checks.lock().insert(gid.clone(), check);
+        }
+        self.requests.lock().insert(
+            gid.clone(),
+            TaskRequest {
+                uris,
+                save_dir: save_dir.to_string(),
+                options: task.clone(),
+                rotate,
+                retries: 0,
+            },
+        );
+        Ok(gid)
+    }
+
+    /// 保存位置已有大小（和已知的哈希值）相符的同名文件时直接当作已完成，不提交给 aria2
+    ///
+    /// 返回本程序生成的 gid：`get_status` 对它报告 `Complete`，并发送一个带
+    /// `EventDetail::AlreadyDownloaded` 的 `Complete` 事件，调用方不需要区别对待。
+    /// 大小相符但哈希值不符的文件改名为 `.bad` 后返回 None，照常下载，不覆盖原文件。
+    async fn reuse_existing_file(&self, uris: &[String], save_dir: &str, task: &DownloadOptions) -> Result<Option<String>> {
+        let name = task.filename.as_deref().map(sanitize_filename).filter(|name| !name.is_empty());
+        let (Some(name), Some(size), false) = (name, task.expected_size, task.force_redownload) else {
+            return Ok(None);
+        };
+        // 先确认预期哈希值格式正确，否则会把完好的文件当作不符改名
+        let expected_hash = task
+            .checksum
+            .as_ref()
+            .map(|(hash_type, expected)| checksum_option(*hash_type, expected))
+            .transpose()?;
+        let path = Path::new(save_dir).join(&name);
+        let (check_path, checksum) = (path.clone(), task.checksum.clone());
+        match tokio::task::spawn_blocking(move || check_existing_file(&check_path, size, checksum.as_ref())).await?? {
+            ExistingFile::Absent => return Ok(None),
+            ExistingFile::HashMismatch => {
+                let aside = set_aside(&path)?;
+                log::warn!("[aria2] {} 大小相符但哈希值不符，已改名为 {}，重新下载", path.display(), aside.display());
+                return Ok(None);
+            }
+            ExistingFile::Complete => {}
+        }
+
+        let gid = generate_gid()?;
+        log::info!(
+            "[aria2] {} 已存在且{}，跳过下载（任务 {}）",
+            path.display(),
+            if expected_hash.is_some() { "哈希值相符" } else { "大小相符" },
+            gid
+        );
+        self.jobs.insert(JobRecord {
+            gid: gid.clone(),
+            display_name: task.display_name.clone().unwrap_or_else(|| name.clone()),
+            urls: uris.to_vec(),
+            save_dir: PathBuf::from(save_dir),
+            requested_save_dir: None,
+            file_name: Some(name),
+            uses_part_file: false,
+            expected_hash,
+            expected_size: Some(size),
+            skip_length_check: task.skip_length_check,
+            tag: task.tag.clone(),
+            created_at: JobStore::now(),
+            state: JobState::Complete,
+            verified: None,
+        });
+        self.existing.lock().insert(
+            gid.clone(),
+            DownloadProgress {
+                completed_length: size,
+                total_length: size,
+                percentage: Some(100.0),
+                status: DownloadStatus::Complete,
+                file_path: Some(path.clone()),
+                file_count: 1,
+                ..empty_progress(&gid)
+            },
+        );
+        let _ = self.engine.events.send(DownloadEvent {
+            gid: gid.clone(),
+            status: DownloadStatus::Complete,
+            detail: Some(EventDetail::AlreadyDownloaded { path }),
+        });
+        Ok(Some(gid))
+    }
+
+    /// 以原始参数（地址、请求头、文件名、镜像列表等）重新添加失败的任务，返回新的 gid
+    ///
+    /// 先移除失败任务的结果再重新添加；保存路径与文件名不变，aria2 会沿用 `.aria2` 控制文件续传。
+    /// 校验失败时续传只会得到同样的数据，因此先删除已下载的文件，从头下载。
+    ///
+    /// 不带 `force` 时只重试可能成功的失败：网络类错误（`DownloadErrorKind::is_retryable`），
+    /// 以及不超过 `CHECKSUM_RETRY_LIMIT` 次的校验失败；资源不存在、磁盘已满等返回 `DownloadError::NotRetryable`。
+    /// 磁盘已满时应让用户换一个卷，再调用 `retry_download_to`。
+    /// 原始参数只保存在内存中，程序重启前添加的任务无法重试。
+    pub async fn retry_download(&self, gid: &str, force: bool) -> Result<String> {
+        self.retry_task(gid, None, force).await
+    }
+
+    /// 换一个保存目录重新下载失败的任务（如磁盘已满时用户选择了其它位置），返回新的 gid
+    ///
+    /// `DownloadErrorKind::DiskFull` 只有换到另一个卷才会重试；原位置已下载的部分会被删除以释放空间，
+    /// 新位置从头下载。其它错误的判断同 `retry_download(gid, false)`。
+    pub async fn retry_download_to(&self, gid: &str, save_dir: &str) -> Result<String> {
+        self.retry_task(gid, Some(save_dir), false).await
+    }
+
+    async fn retry_task(&self, gid: &str, save_dir: Option<&str>, force: bool) -> Result<String> {
+        let not_retryable = |reason: String| DownloadError::NotRetryable { gid: gid.to_string(), reason };
+        let status = self.get_status(gid).await?.status;
+        let DownloadStatus::Error(kind) = &status else {
+            return Err(not_retryable(format!("任务未失败（{:?}）", status)).into());
+        };
+        let Some(request) = self.requests.lock().get(gid).cloned() else {
+            return Err(not_retryable("没有该任务的添加参数（可能是程序重启前添加的）".to_string()).into());
+        };
+        let disk_full = matches!(kind, DownloadErrorKind::DiskFull { .. });
+        let relocated = save_dir.is_some_and(|dir| {
+            preflight::volume_of(Path::new(dir)) != preflight::volume_of(Path::new(&request.save_dir))
+        });
+        if !(force || retry_allowed(kind, request.retries) || disk_full && relocated) {
+            return Err(not_retryable(kind.to_string()).into());
+        }
+
+        let stale_files = if *kind == DownloadErrorKind::ChecksumMismatch || save_dir.is_some() {
+            self.engine.call(|c| async move { c.get_files(gid).await }).await?
+        } else {
+            Vec::new()
+        };
+        self.remove_result(gid).await?;
+        for file in stale_files.iter().filter(|f| !f.path.is_empty()) {
+            let path = PathBuf::from(&file.path);
+            delete_with_retry(&path).await?;
+            delete_with_retry(&control_file_path(&path)).await?;
+        }
+
+        let mut options = request.options.clone();
+        options.paused = false;
+        let save_dir = save_dir.unwrap_or(&request.save_dir);
+        let new_gid = self
+            .add_task(request.uris.clone(), save_dir, &options, request.rotate)
+            .await?;
+        if let Some(added) = self.requests.lock().get_mut(&new_gid) {
+            added.retries = request.retries + 1;
+        }
+        // 旧任务已由新任务取代
+        self.jobs.remove(gid);
+        log::info!(
+            "[aria2] 任务 {} 失败（{}），第 {} 次重试，新任务 {}",
+            gid,
+            kind,
+            request.retries + 1,
+            new_gid
+        );
+        Ok(new_gid)
+    }
+
+    /// 查询任务元数据（由 `JobStore` 持久化，程序重启后仍可查到）
+    pub fn job(&self, gid: &str) -> Option<JobRecord> {
+        self.jobs.get(gid)
+    }
+
+    /// 全部任务元数据，按添加时间排序
+    pub fn jobs(&self) -> Vec<JobRecord> {
+        self.jobs.list()
+    }
+
+    /// 按镜像站发布的校验文件（地址或本地路径，如 SHA256SUMS）核对已完成任务的文件，通过后记录到任务元数据
+    ///
+    /// 文件自上次核对通过以来大小和修改时间都没有变化时直接返回，不重新计算哈希（程序每次启动都会检查）。
+    /// 核对失败的错误可 downcast 为 `verify::ChecksumFileError`。
+    pub async fn verify_with_checksum_file(&self, gid: &str, checksum_url_or_path: &str) -> Result<()> {
+        let job = self.jobs.get(gid).ok_or_else(|| DownloadError::UnknownGid { gid: gid.to_string() })?;
+        let Some(file_name) = job.file_name.as_ref().filter(|_| job.state == JobState::Complete) else {
+            anyhow::bail!("任务 {} 尚未完成，无法校验", gid);
+        };
+        let path = job.save_dir.join(file_name);
+        let stamp = VerifiedFile::stamp(&path).map_err(|e| anyhow::anyhow!("读取 {} 失败: {}", path.display(), e))?;
+        if job.verified.as_ref().is_some_and(|v| v.still_valid(stamp)) {
+            log::debug!("[校验] {} 自上次校验以来没有变化，跳过", path.display());
+            return Ok(());
+        }
+
+        let proxy = self.engine.proxy.lock().clone();
+        let entry = verify::verify_against(
+            &path,
+            checksum_url_or_path,
+            proxy.as_ref(),
+            &self.engine.config.tls,
+            &self.engine.config.user_agent,
+        )
+        .await?;
+        self.jobs.set_verified(
+            gid,
+            VerifiedFile {
+                checksum: format!("{}={}", entry.algo.aria2_name(), entry.hash),
+                size: stamp.0,
+                modified: stamp.1,
+                verified_at: JobStore::now(),
+            },
+        );
+        Ok(())
+    }
+
+    /// 添加前检查保存目录所在卷的剩余空间
+    ///
+    /// 大小优先取 `expected_size`，否则由 `add_task` 用 HEAD 请求探测（带上任务的请求头和 Cookie）；
+    /// 都无法得到大小时不检查。
+    fn preflight_disk_space(&self, save_dir: &str, size: Option<u64>) -> Result<()> {
+        match size {
+            Some(size) => preflight::check_disk_space(Path::new(save_dir), size, self.engine.config.disk_space_margin),
+            None => Ok(()),
+        }
+    }
+
+    /// 检查 `save_dir` 的空间，不足且允许改存时返回其它卷上的目录
+    fn space_checked_dir(&self, save_dir: &str, size: Option<u64>, relocate: Option<&LocationFilter>) -> Result<String> {
+        let error = match self.preflight_disk_space(save_dir, size) {
+            Ok(()) => return Ok(save_dir.to_string()),
+            Err(e) => e,
+        };
+        let (Some(filter), Some(size)) = (relocate, size) else {
+            return Err(error);
+        };
+        if !matches!(error.downcast_ref::<DownloadError>(), Some(DownloadError::InsufficientDiskSpace { .. })) {
+            return Err(error);
+        }
+        let required = size.saturating_add(self.engine.config.disk_space_margin);
+        match location::best_save_dir(required, filter)? {
+            Some(dir) => {
+                log::warn!("[aria2] {}，改存到 {}", error, dir.display());
+                Ok(dir.to_string_lossy().into_owned())
+            }
+            None => Err(error),
+        }
+    }
+
+    /// 提交新任务并记录添加时间
+    ///
+    /// 预先指定 gid：连接断开后重放时，若第一次其实已添加成功（aria2 报 gid 重复），
+    /// 直接返回该 gid，不会重复下载。
+    async fn submit<F, Fut>(&self, mut options: aria2_ws::TaskOptions, add: F) -> Result<String>
+    where
+        F: Fn(Arc<aria2_ws::Client>, aria2_ws::TaskOptions) -> Fut,
+        Fut: Future<Output = std::result::Result<String, aria2_ws::Error>>,
+    {
+        let gid = generate_gid()?;
+        options.gid = Some(gid.clone());
+
+        let gid = self
+            .engine
+            .call(|c| {
+                let options = options.clone();
+                let gid = gid.clone();
+                let add = &add;
+                async move {
+                    match add(c.clone(), options).await {
+                        Err(aria2_ws::Error::Aria2 { .. }) if c.tell_status(&gid).await.is_ok() => Ok(gid),
+                        result => result,
+                    }
+                }
+            })
+            .await?;
+
+        self.record_added(&gid);
+        Ok(gid)
+    }
+
+    /// 记录任务添加时间
+    fn record_added(&self, gid: &str) {
+        self.timings.lock().insert(
+            gid.to_string(),
+            TaskTiming { added: Instant::now(), finished: None },
+        );
+    }
+
+    /// 计算任务已用时间；首次观察到任务结束时记录结束时间，之后不再增长
+    ///
+    /// 不是由本管理器添加的 gid（如 `list_tasks` 列出的旧任务）从首次查询时开始计时。
+    fn elapsed_for(&self, gid: &str, status: &DownloadStatus) -> Duration {
+        let now = Instant::now();
+        let mut timings = self.timings.lock();
+        let timing = timings
+            .entry(gid.to_string())
+            .or_insert(TaskTiming { added: now, finished: None });
+        if status.is_finished() && timing.finished.is_none() {
+            timing.finished = Some(now);
+        }
+        timing.finished.unwrap_or(now).duration_since(timing.added)
+    }
+
+    /// 获取下载状态
+    ///
+    /// 观察到 `Complete` 时先调用 `finalize` 把 `.part` 文件改为最终文件名，
+    /// 改名失败（如目标已存在且 `OnConflict::Error`）时返回 `Error` 状态，之后每次查询都会重试改名。
+    ///
+    /// 启用 `Aria2Config::auto_remove_results` 时，观察到 `Complete` 后会清理该任务在 aria2 中的结果，
+    /// 之后再查询该 gid 返回 `DownloadError::UnknownGid`；任务元数据（`job`）仍然保留。
+    pub async fn get_status(&self, gid: &str) -> Result<DownloadProgress> {
+        if let Some(progress) = self.existing.lock().get(gid) {
+            return Ok(progress.clone());
+        }
+        let mut status = match self.tell_status(gid).await {
+            // 镜像轮换正在移除并重新添加该任务，短暂查不到
+            Err(e)
+                if matches!(e.downcast_ref(), Some(DownloadError::UnknownGid { .. }))
+                    && self.engine.mirrors.lock().contains_key(gid) =>
+            {
+                let mut progress = empty_progress(gid);
+                progress.elapsed = self.elapsed_for(gid, &progress.status);
+                return Ok(progress);
+            }
+            result => result?,
+        };
+        let mut follower = None;
+
+        // 磁力链接先下载元数据，完成后 aria2 会创建真正的下载任务（followedBy），
+        // 此时跟随到真正的任务，对调用方保持同一个 gid
+        if status.status == TaskStatus::Complete {
+            if let Some(next) = status.followed_by.as_ref().and_then(|f| f.first()).cloned() {
+                status = self.tell_status(&next).await?;
+                follower = Some(next);
+            }
+        }
+
+        let mut progress = progress_from_status(gid, &status);
+        if progress.status == DownloadStatus::Complete {
+            if let Some(kind) = self.engine.check_size(gid, &status) {
+                progress.status = DownloadStatus::Error(kind);
+            }
+        }
+        // 即将换镜像重试的错误不报告给调用方，避免界面当作最终失败
+        if let DownloadStatus::Error(kind) = &progress.status {
+            if self.engine.mirrors.lock().get(gid).is_some_and(|m| m.rotation.can_advance(kind)) {
+                progress.status = DownloadStatus::Waiting;
+            }
+        }
+        progress.elapsed = self.elapsed_for(gid, &progress.status);
+        self.smooth_speed(gid, &mut progress);
+
+        if progress.status == DownloadStatus::Complete {
+            if let Err(e) = self.finalize(gid) {
+                log::warn!("[aria2] 任务 {} 完成后改名失败: {}", gid, e);
+                progress.status = DownloadStatus::Error(DownloadErrorKind::Other(0, e.to_string()));
+            }
+        }
+        // aria2 只知道 .part 临时名，改名后报告最终路径
+        if let Some(rename) = self.renames.lock().get(gid).filter(|r| r.finalized) {
+            progress.file_path = Some(rename.final_path.clone());
+        }
+
+        if let Some(state) = job_state(&progress.status) {
+            self.jobs.set_state(gid, state);
+        }
+        if progress.status == DownloadStatus::Complete {
+            if let Some(name) = progress.file_path.as_ref().and_then(|p| p.file_name()) {
+                self.jobs.set_file_name(gid, &name.to_string_lossy());
+            }
+        }
+
+        if progress.status == DownloadStatus::Complete && self.engine.config.auto_remove_results {
+            for finished in follower.iter().map(String::as_str).chain([gid]) {
+                if let Err(e) = self.remove_result(finished).await {
+                    log::warn!("[aria2] 清理任务结果失败 {}: {}", finished, e);
+                }
+            }
+        }
+        Ok(progress)
+    }
+
+    /// 用最近的速度样本平滑 `download_speed_avg`，并按平滑后的速度重新估算剩余时间
+    ///
+    /// 只有下载中的任务记录样本；暂停、排队或结束时清除该任务的样本，恢复后重新开始平滑。
+    fn smooth_speed(&self, gid: &str, progress: &mut DownloadProgress) {
+        let mut speeds = self.speeds.lock();
+        if progress.status != DownloadStatus::Active {
+            speeds.remove(gid);
+            return;
+        }
+        let avg = speeds.entry(gid.to_string()).or_default().push(progress.download_speed);
+        progress.download_speed_avg = avg;
+        progress.eta = estimate_eta(progress.completed_length, progress.total_length, avg);
+    }
+
+    /// 任务包含的文件及各自的进度（getFiles），单文件任务也返回一项
+    ///
+    /// `.part` 已改为最终文件名的任务报告最终路径。磁力链接与 `get_status` 一样跟随到元数据完成后的实际任务。
+    pub async fn get_files(&self, gid: &str) -> Result<Vec<TaskFile>> {
+        let target = self.follow_metadata(gid).await?;
+        let target = target.as_str();
+        let files = self.engine.call(|c| async move { c.get_files(target).await }).await?;
+        let renamed = self.renames.lock().get(gid).filter(|r| r.finalized).cloned();
+        Ok(files
+            .iter()
+            .map(|file| {
+                let mut file = task_file(file);
+                if let Some(rename) = &renamed {
+                    if file.path.as_ref() == Some(&rename.part_path) {
+                        file.path = Some(rename.final_path.clone());
+                    }
+                }
+                file
+            })
+            .collect())
+    }
+
+    /// 只下载多文件任务（种子）中的部分文件，`indices` 为 `get_files` 返回的文件序号
+    ///
+    /// 通过 changeOption 设置 `select-file`；任务处于暂停状态时（`TorrentOptions::paused`）随后自动开始。
+    /// 磁力链接尚未获取到元数据时返回错误，稍后再试。
+    pub async fn select_files(&self, gid: &str, indices: &[u32]) -> Result<()> {
+        let target = self.follow_metadata(gid).await?;
+        let files = self.get_files(gid).await?;
+        if files.iter().any(is_metadata_file) {
+            anyhow::bail!("任务 {} 正在获取种子元数据，文件列表尚不可用", gid);
+        }
+        if indices.is_empty() {
+            anyhow::bail!("至少要选择一个文件");
+        }
+        if let Some(index) = indices.iter().find(|&&i| !files.iter().any(|f| f.index == i)) {
+            anyhow::bail!("任务 {} 没有序号为 {} 的文件（共 {} 个文件）", gid, index, files.len());
+        }
+
+        let selection = indices.iter().map(u32::to_string).collect::<Vec<_>>().join(",");
+        let mut options = aria2_ws::TaskOptions::default();
+        apply_extra_options(&mut options, &[("select-file", selection.clone())]);
+        let target = target.as_str();
+        self.engine
+            .call(|c| {
+                let options = options.clone();
+                async move { c.change_option(target, options).await }
+            })
+            .await?;
+        log::info!("[aria2] 任务 {} 只下载文件: {}", gid, selection);
+
+        if self.tell_status(target).await?.status == TaskStatus::Paused {
+            self.engine.call(|c| async move { c.unpause(target).await }).await?;
+        }
+        Ok(())
+    }
+
+    /// 磁力链接的元数据任务完成后返回实际下载任务的 gid，其它任务原样返回
+    async fn follow_metadata(&self, gid: &str) -> Result<String> {
+        let status = self.tell_status(gid).await?;
+        if status.status == TaskStatus::Complete {
+            if let Some(next) = status.followed_by.as_ref().and_then(|f| f.first()) {
+                return Ok(next.clone());
+            }
+        }
+        Ok(gid.to_string())
+    }
+
+    /// 把已完成任务的 `.part` 文件改为最终文件名，返回最终路径
+    ///
+    /// `get_status` 观察到完成时会自动调用。没有待改名的文件（未指定文件名，或已经改过名）时返回 None。
+    /// 失败时保留待改名记录，可以处理冲突后再次调用。完成时核对大小失败的任务返回错误，不会改名。
+    pub fn finalize(&self, gid: &str) -> Result<Option<PathBuf>> {
+        if let Some(SizeCheck::Failed { kind, .. }) = self.engine.size_checks.lock().get(gid) {
+            anyhow::bail!("任务 {} 的文件未通过大小核对，不改名: {}", gid, kind);
+        }
+        let Some(rename) = self.renames.lock().get(gid).filter(|r| !r.finalized).cloned() else {
+            return Ok(None);
+        };
+        let final_path = finalize_part_file(&rename)?;
+        if let Some(r) = self.renames.lock().get_mut(gid) {
+            r.final_path = final_path.clone();
+            r.finalized = true;
+        }
+        if final_path != rename.final_path {
+            log::info!("[aria2] 任务 {} 的目标文件已存在，改存为 {}", gid, final_path.display());
+        }
+        log::info!("[aria2] 任务 {} 已完成: {}", gid, final_path.display());
+        Ok(Some(final_path))
+    }
+
+    /// 以 `interval` 为间隔持续获取任务进度
+    ///
+    /// 与上一次相同的进度不会重复产出；任务结束（`is_finished`）后产出最后一项并结束。
+    /// 查询失败时产出一项 `Error` 状态后结束。轮询在流被轮询时进行，丢弃流即停止查询；
+    /// 同一 gid 的多个监视者在 `interval` 内共用一次查询结果。
+    pub fn watch_progress(&self, gid: &str, interval: Duration) -> impl Stream<Item = DownloadProgress> + '_ {
+        let state = WatchState {
+            gid: gid.to_string(),
+            last: None,
+            finished: false,
+        };
+        futures::stream::unfold(state, move |mut state| async move {
+            if state.finished {
+                return None;
+            }
+            let mut first = state.last.is_none();
+            loop {
+                if !first {
+                    tokio::time::sleep(interval).await;
+                }
+                first = false;
+
+                let progress = match self.shared_status(&state.gid, interval).await {
+                    Ok(progress) => progress,
+                    Err(e) => {
+                        state.finished = true;
+                        if matches!(e.downcast_ref::<DownloadError>(), Some(DownloadError::UnknownGid { .. })) {
+                            return None;
+                        }
+                        let progress = DownloadProgress {
+                            status: DownloadStatus::Error(DownloadErrorKind::Other(0, e.to_string())),
+                            ..state.last.clone().unwrap_or_else(|| empty_progress(&state.gid))
+                        };
+                        return Some((progress, state));
+                    }
+                };
+
+                if state.last.as_ref().is_some_and(|last| same_update(last, &progress)) {
+                    continue;
+                }
+                state.finished = progress.status.is_finished();
+                state.last = Some(progress.clone());
+                return Some((progress, state));
+            }
+        })
+    }
+
+    /// 获取进度；缓存的结果不超过 `max_age` 时直接使用
+    async fn shared_status(&self, gid: &str, max_age: Duration) -> Result<DownloadProgress> {
+        if let Some((at, progress)) = self.watch_cache.lock().get(gid) {
+            if at.elapsed() < max_age {
+                return Ok(progress.clone());
+            }
+        }
+        let progress = self.get_status(gid).await?;
+        self.watch_cache
+            .lock()
+            .insert(gid.to_string(), (Instant::now(), progress.clone()));
+        Ok(progress)
+    }
+
+    /// 从 aria2 中移除已结束（完成、出错、已移除）任务的结果，之后该 gid 不再可查询
+    ///
+    /// 只清理 aria2 中的结果和内存中的状态，持久化的任务元数据（最终路径、校验记录等）保留，
+    /// 需要删除时调用 `purge_job`。
+    pub async fn remove_result(&self, gid: &str) -> Result<()> {
+        if self.existing.lock().remove(gid).is_some() {
+            return Ok(());
+        }
+        self.engine
+            .call(|c| async move { c.remove_download_result(gid).await })
+            .await?;
+        self.forget_task(gid);
+        Ok(())
+    }
+
+    /// 清除内存中按 gid 记录的所有状态（不含持久化的任务元数据）
+    fn forget_task(&self, gid: &str) {
+        self.timings.lock().remove(gid);
+        self.watch_cache.lock().remove(gid);
+        self.speeds.lock().remove(gid);
+        self.renames.lock().remove(gid);
+        self.engine.mirrors.lock().remove(gid);
+        self.engine.size_checks.lock().remove(gid);
+        self.requests.lock().remove(gid);
+    }
+
+    /// 删除任务的持久化元数据；任务在 aria2 中的结果（如果还在）一并移除
+    pub async fn purge_job(&self, gid: &str) -> Result<()> {
+        if let Err(e) = self.remove_result(gid).await {
+            log::debug!("[aria2] 清理任务结果失败 {}: {}", gid, e);
+        }
+        self.jobs.remove(gid);
+        Ok(())
+    }
+
+    /// 清理 aria2 中全部已结束任务的结果，避免长时间运行后 tellStopped 越来越大
+    ///
+    /// 与 `remove_result` 一样保留任务元数据。
+    pub async fn purge_results(&self) -> Result<()> {
+        self.engine
+            .call(|c| async move { c.purge_download_result().await })
+            .await?;
+        self.timings.lock().retain(|_, timing| timing.finished.is_none());
+        self.existing.lock().clear();
+        self.watch_cache.lock().clear();
+        self.speeds.lock().clear();
+        self.renames.lock().retain(|_, rename| !rename.finalized);
+        let pending = |gid: &str| self.jobs.get(gid).is_some_and(|job| job.state == JobState::Pending);
+        self.requests.lock().retain(|gid, _| pending(gid));
+        self.engine.size_checks.lock().retain(|gid, _| pending(gid));
+        log::info!("[aria2] 已清理全部已结束任务的结果");
+        Ok(())
+    }
+
+    /// 列出 aria2 当前的全部任务（活动、等待、已停止）
+    ///
+    /// `filter` 为 Some 时只返回该状态的任务；`Error` 过滤忽略错误信息，匹配所有出错任务。
+    /// 可用于崩溃或重启后找回仍在 aria2 中的下载。各任务的进度经 `get_statuses` 取得，
+    /// 与 `get_status` 一致；磁力链接元数据完成后创建的任务并入其元数据任务，不单独列出。
+    pub async fn list_tasks(&self, filter: Option<&DownloadStatus>) -> Result<Vec<DownloadProgress>> {
+        let engine = &self.engine;
+        let mut statuses = engine.call(|c| async move { c.tell_active().await }).await?;
+        statuses.extend(engine.call(|c| async move { tell_waiting_all(&c).await }).await?);
+        statuses.extend(engine.call(|c| async move { tell_stopped_all(&c).await }).await?);
+
+        let gids: Vec<String> = statuses
+            .iter()
+            .filter(|s| {
+                s.following
+                    .as_ref()
+                    .is_none_or(|parent| !statuses.iter().any(|p| &p.gid == parent))
+            })
+            .map(|s| s.gid.clone())
+            .collect();
+        Ok(self
+            .get_statuses(&gids)
+            .await?
+            .into_iter()
+            .filter(|p| filter.is_none_or(|f| status_kind_eq(&p.status, f)))
+            .collect())
+    }
+
+    /// 批量获取多个任务的进度，顺序与 `gids` 相同
+    ///
+    /// 各任务的查询同时发出，总耗时接近一次查询，界面上各文件同时刷新。
+    /// （aria2_ws 的方法名固定带 `aria2.` 前缀，无法调用 `system.multicall`。）
+    /// 单个任务查询失败时该项为 `Error` 状态，不影响其它任务；未连接 aria2 时返回错误。
+    pub async fn get_statuses(&self, gids: &[String]) -> Result<Vec<DownloadProgress>> {
+        self.client()?;
+        let results = futures::future::join_all(gids.iter().map(|gid| self.get_status(gid))).await;
+        Ok(gids
+            .iter()
+            .zip(results)
+            .map(|(gid, result)| {
+                result.unwrap_or_else(|e| {
+                    log::debug!("[aria2] 查询任务 {} 失败: {}", gid, e);
+                    DownloadProgress {
+                        status: DownloadStatus::Error(DownloadErrorKind::Other(0, e.to_string())),
+                        ..empty_progress(gid)
+                    }
+                })
+            })
+            .collect())
+    }
+
+    /// 订阅任务状态事件（基于 aria2 WebSocket 通知推送，无需轮询）
+    ///
+    /// 完成、出错事件即使从未对该 gid 调用过 `get_status` 也会送达。
+    /// 需要字节级进度时仍应使用 `get_status` 轮询。
+    pub fn subscribe(&self) -> broadcast::Receiver<DownloadEvent> {
+        self.engine.events.subscribe()
+    }
+
+    /// 查询原始任务状态
+    ///
+    /// aria2 找不到该 gid（从未存在，或结果已被清理）时返回 `DownloadError::UnknownGid`。
+    async fn tell_status(&self, gid: &str) -> Result<Status> {
+        self.engine
+            .call(|c| async move { c.tell_status(gid).await })
+            .await
+            .map_err(|e| match e.downcast_ref::<aria2_ws::Error>() {
+                Some(aria2_ws::Error::Aria2 { .. }) => DownloadError::UnknownGid {
+                    gid: gid.to_string(),
+                }
+                .into(),
+                _ => e,
+            })
+    }
+
+    /// 暂停下载
+    pub async fn pause(&self, gid: &str) -> Result<()> {
+        if self.engine.current_client().is_some() {
+            self.engine.call(|c| async move { c.pause(gid).await }).await?;
+        }
+        Ok(())
+    }
+
+    /// 恢复下载
+    pub async fn resume(&self, gid: &str) -> Result<()> {
+        if self.engine.current_client().is_some() {
+            self.engine.call(|c| async move { c.unpause(gid).await }).await?;
+        }
+        Ok(())
+    }
+
+    /// 开始以暂停状态添加的任务（`DownloadOptions::paused`）
+    ///
+    /// 只有处于暂停状态的任务会被恢复；已在排队、下载中或已结束的任务不做任何事，不返回错误。
+    /// （`start` 已用于启动管理器本身。）
+    pub async fn start_download(&self, gid: &str) -> Result<()> {
+        let status = self.tell_status(gid).await?;
+        if status.status != TaskStatus::Paused {
+            log::debug!("[aria2] 任务 {} 当前为 {:?}，无需开始", gid, status.status);
+            return Ok(());
+        }
+        self.engine.call(|c| async move { c.unpause(gid).await }).await
+    }
+
+    /// 移到等待队列最前面（下一个开始下载），返回新位置（从 0 开始）
+    pub async fn move_to_front(&self, gid: &str) -> Result<usize> {
+        self.change_position(gid, 0, "POS_SET").await
+    }
+
+    /// 移到等待队列最后面，返回新位置
+    pub async fn move_to_back(&self, gid: &str) -> Result<usize> {
+        self.change_position(gid, 0, "POS_END").await
+    }
+
+    /// 移到等待队列的第 `pos` 位（从 0 开始，超出队列长度时放到最后），返回新位置
+    pub async fn set_position(&self, gid: &str, pos: usize) -> Result<usize> {
+        self.change_position(gid, i32::try_from(pos).unwrap_or(i32::MAX), "POS_SET").await
+    }
+
+    /// 在等待队列中相对当前位置移动 `offset` 位（负数往前），返回新位置
+    pub async fn move_by(&self, gid: &str, offset: i32) -> Result<usize> {
+        self.change_position(gid, offset, "POS_CUR").await
+    }
+
+    /// 调用 changePosition
+    ///
+    /// 只有等待队列中的任务（排队中或已暂停）可以移动，其它任务返回 `DownloadError::NotQueued`。
+    /// aria2_ws 没有导出 `PositionHow`，因此直接调用底层 RPC。
+    async fn change_position(&self, gid: &str, pos: i32, how: &'static str) -> Result<usize> {
+        let state = match self.tell_status(gid).await?.status {
+            TaskStatus::Waiting | TaskStatus::Paused => None,
+            TaskStatus::Active => Some("下载中"),
+            TaskStatus::Complete => Some("已完成"),
+            TaskStatus::Error => Some("已出错"),
+            TaskStatus::Removed => Some("已移除"),
+        };
+        if let Some(state) = state {
+            return Err(DownloadError::NotQueued {
+                gid: gid.to_string(),
+                state: state.to_string(),
+            }
+            .into());
+        }
+
+        let position: i32 = self
+            .engine
+            .call(|c| async move {
+                c.call_and_wait(
+                    "changePosition",
+                    vec![
+                        serde_json::Value::String(gid.to_string()),
+                        serde_json::Value::from(pos),
+                        serde_json::Value::String(how.to_string()),
+                    ],
+                )
+                .await
+            })
+            .await?;
+        log::info!("[aria2] 任务 {} 移到等待队列第 {} 位", gid, position);
+        Ok(position.max(0) as usize)
+    }
+
+    /// 暂停所有任务（活动中和排队中），返回受影响的任务数
+    ///
+    /// 客户端未连接时不做任何事，返回 0
+    pub async fn pause_all(&self) -> Result<usize> {
+        let engine = &self.engine;
+        if engine.current_client().is_none() {
+            return Ok(0);
+        }
+
+        let active = engine.call(|c| async move { c.tell_active().await }).await?.len();
+        let waiting = engine
+            .call(|c| async move { tell_waiting_all(&c).await })
+            .await?
+            .iter()
+            .filter(|s| s.status != TaskStatus::Paused)
+            .count();
+
+        engine.call(|c| async move { c.pause_all().await }).await?;
+        log::info!("[aria2] 已暂停全部任务，数量: {}", active + waiting);
+        Ok(active + waiting)
+    }
+
+    /// 恢复所有已暂停的任务，返回受影响的任务数
+    ///
+    /// 客户端未连接时不做任何事，返回 0
+    pub async fn resume_all(&self) -> Result<usize> {
+        let engine = &self.engine;
+        if engine.current_client().is_none() {
+            return Ok(0);
+        }
+
+        let paused = engine
+            .call(|c| async move { tell_waiting_all(&c).await })
+            .await?
+            .iter()
+            .filter(|s| s.status == TaskStatus::Paused)
+            .count();
+
+        engine.call(|c| async move { c.unpause_all().await }).await?;
+        log::info!("[aria2] 已恢复全部任务，数量: {}", paused);
+        Ok(paused)
+    }
+
+    /// 取消下载
+    pub async fn cancel(&self, gid: &str) -> Result<()> {
+        if self.engine.current_client().is_some() {
+            self.engine.call(|c| async move { c.remove(gid).await }).await?;
+        }
+        self.timings.lock().remove(gid);
+        self.renames.lock().remove(gid);
+        self.watch_cache.lock().remove(gid);
+        self.speeds.lock().remove(gid);
+        self.engine.mirrors.lock().remove(gid);
+        self.engine.size_checks.lock().remove(gid);
+        self.jobs.set_state(gid, JobState::Removed);
+        Ok(())
+    }
+
+    /// 取消下载并删除已下载的部分文件和 `.aria2` 控制文件，返回释放的字节数
+    ///
+    /// 删除前先通过 getFiles 取得文件路径，并等待 aria2 真正停止任务（释放文件句柄）。
+    /// 已结束（出错、已移除）的任务也可以调用，用于清理残留文件。只保留文件的取消请用 `cancel`。
+    pub async fn cancel_and_delete(&self, gid: &str) -> Result<u64> {
+        let status = self.tell_status(gid).await?;
+        let files = self.engine.call(|c| async move { c.get_files(gid).await }).await?;
+
+        if matches!(status.status, TaskStatus::Active | TaskStatus::Waiting | TaskStatus::Paused) {
+            self.cancel(gid).await?;
+            let deadline = Instant::now() + DEFAULT_SHUTDOWN_TIMEOUT;
+            while Instant::now() < deadline {
+                match self.tell_status(gid).await {
+                    Ok(s) if s.status != TaskStatus::Removed => tokio::time::sleep(SHUTDOWN_POLL_INTERVAL).await,
+                    _ => break,
+                }
+            }
+        }
+        // 文件已删除，任务元数据不再有意义
+        self.purge_job(gid).await?;
+
+        let mut reclaimed = 0;
+        for file in files.iter().filter(|f| !f.path.is_empty()) {
+            let path = PathBuf::from(&file.path);
+            let sidecar = is_resumable(&path);
+            if sidecar.control_file_present {
+                log::debug!("[aria2] 删除控制文件，已下载 {} 字节", sidecar.downloaded_bytes());
+            }
+            reclaimed += delete_with_retry(&path).await?;
+            reclaimed += delete_with_retry(&control_file_path(&path)).await?;
+        }
+        log::info!("[aria2] 已取消任务 {} 并删除文件，释放 {} 字节", gid, reclaimed);
+        Ok(reclaimed)
+    }
+
+    /// 获取全局状态（getGlobalStat 加上 getGlobalOption 中的全局限速）
+    ///
+    /// 客户端未连接时返回全 0。
+    pub async fn get_global_stat(&self) -> Result<GlobalStat> {
+        if self.engine.current_client().is_none() {
+            return Ok(GlobalStat::default());
+        }
+        let stat = self.engine.call(|c| async move { c.get_global_stat().await }).await?;
+        Ok(GlobalStat {
+            download_speed: stat.download_speed,
+            upload_speed: stat.upload_speed,
+            num_active: stat.num_active.max(0) as u32,
+            num_waiting: stat.num_waiting.max(0) as u32,
+            num_stopped: stat.num_stopped.max(0) as u32,
+            speed_limit: self.get_global_speed_limit().await?,
+        })
+    }
+
+    /// 设置全局下载限速（字节/秒），对正在下载和之后的任务都生效；0 表示不限速
+    pub async fn set_global_speed_limit(&self, bytes_per_sec: u64) -> Result<()> {
+        self.engine
+            .change_global_options(&[("max-overall-download-limit", bytes_per_sec.to_string())])
+            .await?;
+
+        if bytes_per_sec == 0 {
+            log::info!("[aria2] 已取消全局限速");
+        } else {
+            log::info!("[aria2] 全局限速: {} B/s", bytes_per_sec);
+        }
+        Ok(())
+    }
+
+    /// 取消全局下载限速
+    pub async fn clear_global_speed_limit(&self) -> Result<()> {
+        self.set_global_speed_limit(0).await
+    }
+
+    /// 设置单个任务的下载限速（字节/秒），0 表示不限速
+    ///
+    /// 任务已完成、出错或被移除时返回 `DownloadError::TaskNotActive`。
+    pub async fn set_task_speed_limit(&self, gid: &str, bytes_per_sec: u64) -> Result<()> {
+        let state = match self.tell_status(gid).await {
+            Ok(status) => match status.status {
+                TaskStatus::Active | TaskStatus::Waiting | TaskStatus::Paused => None,
+                TaskStatus::Complete => Some("已完成"),
+                TaskStatus::Error => Some("已出错"),
+                TaskStatus::Removed => Some("已移除"),
+            },
+            Err(e) if matches!(e.downcast_ref(), Some(DownloadError::UnknownGid { .. })) => Some("不存在"),
+            Err(e) => return Err(e),
+        };
+        if let Some(state) = state {
+            return Err(DownloadError::TaskNotActive {
+                gid: gid.to_string(),
+                state: state.to_string(),
+            }
+            .into());
+        }
+
+        let options = aria2_ws::TaskOptions {
+            max_download_limit: Some(bytes_per_sec.to_string()),
+            ..Default::default()
+        };
+        self.engine
+            .call(|c| {
+                let options = options.clone();
+                async move { c.change_option(gid, options).await }
+            })
+            .await?;
+
+        log::info!("[aria2] 任务 {} 限速: {} B/s", gid, bytes_per_sec);
+        Ok(())
+    }
+
+    /// 取消单个任务的下载限速
+    pub async fn clear_task_speed_limit(&self, gid: &str) -> Result<()> {
+        self.set_task_speed_limit(gid, 0).await
+    }
+
+    /// 修改同时进行的最大任务数（1-16），立即生效，aria2c 重启后仍保持
+    pub async fn set_max_concurrent_downloads(&self, n: u32) -> Result<()> {
+        if !(1..=16).contains(&n) {
+            anyhow::bail!("同时下载的任务数必须在 1-16 之间，当前为 {}", n);
+        }
+        self.engine
+            .change_global_options(&[("max-concurrent-downloads", n.to_string())])
+            .await?;
+        log::info!("[aria2] 同时下载的任务数: {}", n);
+        Ok(())
+    }
+
+    /// 当前同时进行的最大任务数（读取 getGlobalOption）
+    pub async fn get_max_concurrent_downloads(&self) -> Result<u32> {
+        let options = self.engine.call(|c| async move { c.get_global_option().await }).await?;
+        options
+            .extra_options
+            .get("max-concurrent-downloads")
+            .and_then(|v| v.as_str())
+            .and_then(|v| v.parse().ok())
+            .ok_or_else(|| anyhow::anyhow!("aria2 未返回 max-concurrent-downloads"))
+    }
+
+    /// 切换下载档位：同时设置并发数、新任务的分片数和全局限速
+    pub async fn apply_profile(&self, profile: DownloadProfile) -> Result<()> {
+        self.set_max_concurrent_downloads(profile.max_concurrent_downloads()).await?;
+        self.engine.split.store(profile.split(), Ordering::SeqCst);
+        self.set_global_speed_limit(profile.overall_speed_limit()).await?;
+        log::info!("[aria2] 已切换下载档位: {:?}", profile);
+        Ok(())
+    }
+
+    /// 当前配置的全局下载限速（字节/秒），0 表示不限速
+    pub async fn get_global_speed_limit(&self) -> Result<u64> {
+        self.engine.global_speed_limit().await
+    }
+
+    /// 替换时段限速规则，立即按当前时间生效
+    ///
+    /// 只影响运行中的管理器；要在重启后保留，还需调用 `AppConfig::set_bandwidth_schedule` 保存。
+    pub fn set_bandwidth_schedule(&self, schedule: BandwidthSchedule) -> Result<()> {
+        schedule.validate()?;
+        log::info!("[aria2] 更新时段限速规则: {} 条", schedule.rules.len());
+        self.schedule.send_replace(schedule);
+        Ok(())
+    }
+
+    /// 以相同配置关闭并重新启动 aria2c
+    ///
+    /// `shutdown` 返回时旧进程已退出且端口已释放（或已超时放弃等待），新的 aria2c 从会话文件恢复未完成的任务。
+    /// 重启后 gid 不变，但本实例在内存中记录的状态（计时、镜像轮换）会重置。
+    pub async fn restart(&mut self) -> Result<()> {
+        let config = self.engine.config.clone();
+        let path = self.shutdown().await?;
+        log::info!("[aria2] 正在重启（旧实例关闭方式: {:?}）", path);
+        *self = Self::start_with(config).await?;
+        Ok(())
+    }
+
+    /// 关闭 aria2c
+    ///
+    /// 返回时 aria2c 已退出且 RPC 端口已释放，可以立即重新启动（等待端口释放最多 `PORT_RELEASE_TIMEOUT`）。
+    pub async fn shutdown(&mut self) -> Result<ShutdownPath> {
+        self.shutdown_with_timeout(DEFAULT_SHUTDOWN_TIMEOUT).await
+    }
+
+    /// 关闭 aria2，返回实际采用的关闭方式
+    ///
+    /// 依次尝试：RPC `shutdown` → 等待 `timeout` → RPC `forceShutdown` → 再等待 `timeout` → 结束进程。
+    /// 直接结束进程可能损坏 aria2 正在写入的会话文件，因此只作为最后手段。
+    pub async fn shutdown_with_timeout(&mut self, timeout: Duration) -> Result<ShutdownPath> {
+        let engine = &self.engine;
+        // 先通知看门狗，避免把主动关闭当作崩溃而重启
+        engine.stopped.store(true, Ordering::SeqCst);
+
+        if !engine.owns_process.load(Ordering::SeqCst) {
+            engine.client.write().take();
+            log::info!("[aria2] aria2c 属于其它实例，仅断开连接");
+            return Ok(ShutdownPath::Detached);
+        }
+
+        // 先删除发现文件，关闭期间启动的其它实例不会再附加到这个 aria2c
+        if let Some(pid) = engine.process.lock().as_ref().map(Child::id) {
+            remove_endpoint(pid);
+        }
+
+        let Some(client) = engine.client.write().take() else {
+            if engine.process.lock().is_none() || engine.exited() {
+                *engine.process.lock() = None;
+                return Ok(ShutdownPath::NotRunning);
+            }
+            engine.kill_process();
+            engine.wait_for_port_release().await;
+            return Ok(ShutdownPath::Killed);
+        };
+
+        // 退出前写入会话，保证未完成的任务在下次启动时可恢复；aria2c 卡死时每个调用最多等待 rpc_call_timeout
+        let rpc_timeout = engine.config.rpc_call_timeout;
+        match tokio::time::timeout(rpc_timeout, client.save_session()).await {
+            Ok(Ok(_)) => {}
+            Ok(Err(e)) => log::warn!("[aria2] 保存会话失败: {}", e),
+            Err(_) => log::warn!("[aria2] 保存会话超时"),
+        }
+
+        let responded = |result: std::result::Result<std::result::Result<_, _>, _>| matches!(result, Ok(Ok(_)));
+        let path = if responded(tokio::time::timeout(rpc_timeout, client.shutdown()).await) && engine.wait_for_exit(timeout).await {
+            ShutdownPath::Graceful
+        } else if responded(tokio::time::timeout(rpc_timeout, client.force_shutdown()).await)
+            && engine.wait_for_exit(timeout).await
+        {
+            ShutdownPath::Forced
+        } else {
+            engine.kill_process();
+            engine.wait_for_port_release().await;
+            ShutdownPath::Killed
+        };
+        *engine.process.lock() = None;
+
+        log::info!("[aria2] 已关闭: {:?}", path);
+        Ok(path)
+    }
+}
+
+// Drop 中无法执行异步 RPC（例如 panic 展开时），只能同步结束进程
+impl Drop for Aria2Manager {
+    fn drop(&mut self) {
+        self.engine.stopped.store(true, Ordering::SeqCst);
+        self.engine.kill_process();
+    }
+}
+
+/// 解析 aria2 的速度选项值（纯数字，或带 K/M 后缀，1K = 1024）
+fn parse_speed_value(value: &str) -> Option<u64> {
+    let value = value.trim();
+    let (number, unit) = match value.chars().last()? {
+        'k' | 'K' => (&value[..value.len() - 1], 1024),
+        'm' | 'M' => (&value[..value.len() - 1], 1024 * 1024),
+        _ => (value, 1),
+    };
+    number.trim().parse::<u64>().ok().map(|n| n * unit)
+}
+
+/// aria2c 可执行文件名（Windows 下带 .exe）
+fn aria2c_file_name() -> String {
+    format!("aria2c{}", std::env::consts::EXE_SUFFIX)
+}
+
+/// 查找 aria2c：配置指定的路径 → bin 目录 → PATH
+///
+/// 都找不到时返回 `DownloadError::Aria2NotFound`，列出查找过的每个位置。
+fn resolve_aria2c(explicit: Option<&Path>) -> Result<PathBuf> {
+    let file_name = aria2c_file_name();
+    let bundled = get_bin_dir().join(&file_name);
+    let mut checked = Vec::new();
+
+    for candidate in explicit.into_iter().chain([bundled.as_path()]) {
+        if candidate.is_file() {
+            return Ok(candidate.to_path_buf());
+        }
+        checked.push(candidate.display().to_string());
+    }
+    if let Some(found) = find_in_path(&file_name) {
+        return Ok(found);
+    }
+    checked.push(format!("PATH 中的 {}", file_name));
+
+    Err(DownloadError::Aria2NotFound { checked }.into())
+}
+
+/// aria2c 命令行参数：RPC 设置、配置项，以及从会话文件恢复未完成的任务
+fn aria2c_args(port: u16, secret: &str, session: &Path, config: &Aria2Config) -> Vec<String> {
+    let mut args = vec![
+        // 不使用 --daemon：守护化后子进程句柄对应的进程立即退出，结束句柄杀不掉真正的 aria2c。
+        // 本程序异常退出（来不及关闭 aria2c）时，aria2c 也随之退出，不会一直占着端口
+        format!("--stop-with-process={}", std::process::id()),
+        "--enable-rpc=true".to_string(),
+        format!("--rpc-listen-port={}", port),
+        format!("--rpc-secret={}", secret),
+        "--rpc-allow-origin-all=true".to_string(),
+        format!("--max-concurrent-downloads={}", config.max_concurrent_downloads),
+        format!("--split={}", config.split),
+        format!("--max-connection-per-server={}", config.max_connection_per_server),
+        format!("--min-split-size={}", config.min_split_size),
+        format!("--file-allocation={}", config.file_allocation.aria2_value()),
+        format!("--continue={}", config.continue_downloads),
+        format!("--user-agent={}", config.user_agent),
+        format!("--check-certificate={}", !config.tls.allow_insecure_tls),
+        // 输出会写入日志，关闭每秒刷新的进度行
+        "--show-console-readout=false".to_string(),
+        "--summary-interval=0".to_string(),
+        "--auto-file-renaming=false".to_string(),
+        "--allow-overwrite=true".to_string(),
+        format!("--input-file={}", session.display()),
+        format!("--save-session={}", session.display()),
+        format!("--save-session-interval={}", SESSION_SAVE_INTERVAL_SECS),
+    ];
+    if let Some(ca) = &config.tls.ca_certificate {
+        args.push(format!("--ca-certificate={}", ca.display()));
+    }
+    args
+}
+
+/// 删除文件，返回其大小；文件不存在时返回 0
+///
+/// aria2 停止任务后可能还会短暂占用文件，删除失败时重试几次。
+async fn delete_with_retry(path: &Path) -> Result<u64> {
+    let display = strip_extended_prefix(path);
+    let path = &to_extended_path(path);
+    let size = match std::fs::metadata(path) {
+        Ok(meta) => meta.len(),
+        Err(_) => return Ok(0),
+    };
+    let mut attempt = 0;
+    loop {
+        match std::fs::remove_file(path) {
+            Ok(()) => return Ok(size),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(e) if attempt + 1 < DELETE_RETRIES => {
+                log::debug!("[aria2] 删除 {} 失败，稍后重试: {}", display.display(), e);
+                attempt += 1;
+                tokio::time::sleep(DELETE_RETRY_INTERVAL).await;
+            }
+            Err(e) => return Err(anyhow::anyhow!("删除 {} 失败: {}", display.display(), e)),
+        }
+    }
+}
+
+/// 任务的分片数：显式设置优先，其次已知大小低于阈值的小文件不分片，否则用全局设置
+fn task_split(explicit: Option<u32>, known_size: Option<u64>, default: u32, small_file_threshold: u64) -> u32 {
+    match explicit {
+        Some(split) => split,
+        None if small_file_threshold > 0 && known_size.is_some_and(|size| size < small_file_threshold) => 1,
+        None => default,
+    }
+}
+
+/// 不带 `force` 时 `retry_download` 是否重试（见 `CHECKSUM_RETRY_LIMIT`）
+fn retry_allowed(kind: &DownloadErrorKind, retries: u32) -> bool {
+    kind.is_retryable() || (*kind == DownloadErrorKind::ChecksumMismatch && retries < CHECKSUM_RETRY_LIMIT)
+}
+
+/// 大小不符的文件改名后的路径：去掉 `.part` 后缀，加上 `.bad`
+fn bad_file_path(path: &Path) -> PathBuf {
+    let path = path.to_string_lossy();
+    let base = path.strip_suffix(PART_SUFFIX).unwrap_or(&path);
+    PathBuf::from(format!("{}.bad", base))
+}
+
+/// 保存位置上已有同名文件的核对结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ExistingFile {
+    /// 文件不存在或大小不符，照常下载
+    Absent,
+    /// 大小相符，已知哈希值时哈希值也相符
+    Complete,
+    /// 大小相符但哈希值不符
+    HashMismatch,
+}
+
+/// 核对保存位置上已有的文件（大文件要计算哈希，会阻塞较长时间）
+fn check_existing_file(path: &Path, expected_size: u64, checksum: Option<&(HashType, String)>) -> Result<ExistingFile> {
+    match std::fs::metadata(to_extended_path(path)) {
+        Ok(meta) if meta.is_file() && meta.len() == expected_size => {}
+        _ => return Ok(ExistingFile::Absent),
+    }
+    let Some((hash_type, expected)) = checksum else {
+        return Ok(ExistingFile::Complete);
+    };
+    let actual = hash_file_blocking(path, *hash_type, |_, _| true)?;
+    Ok(match actual.eq_ignore_ascii_case(expected.trim()) {
+        true => ExistingFile::Complete,
+        false => ExistingFile::HashMismatch,
+    })
+}
+
+/// 把哈希值不符的文件改名为 `<文件名>.bad`（已存在时加序号），返回新路径
+fn set_aside(path: &Path) -> Result<PathBuf> {
+    let bad = bad_file_path(path);
+    let target = match (bad.parent(), bad.file_name()) {
+        (Some(dir), Some(name)) => unique_path(dir, &name.to_string_lossy()),
+        _ => bad,
+    };
+    std::fs::rename(to_extended_path(path), to_extended_path(&target))
+        .map_err(|e| anyhow::anyhow!("将 {} 改名为 {} 失败: {}", path.display(), target.display(), e))?;
+    Ok(target)
+}
+
+/// 已结束任务对应的元数据状态；未结束时返回 None
+fn job_state(status: &DownloadStatus) -> Option<JobState> {
+    match status {
+        DownloadStatus::Complete => Some(JobState::Complete),
+        DownloadStatus::Error(kind) => Some(JobState::Failed(kind.to_string())),
+        DownloadStatus::Removed => Some(JobState::Removed),
+        DownloadStatus::Waiting | DownloadStatus::Active | DownloadStatus::Paused => None,
+    }
+}
+
+/// `watch_progress` 的流状态
+struct WatchState {
+    gid: String,
+    last: Option<DownloadProgress>,
+    finished: bool,
+}
+
+/// 两次进度是否相同（忽略随时间变化的 eta / elapsed）
+fn same_update(a: &DownloadProgress, b: &DownloadProgress) -> bool {
+    a.completed_length == b.completed_length
+        && a.total_length == b.total_length
+        && a.download_speed == b.download_speed
+        && a.status == b.status
+}
+
+/// 尚未得到任何进度时使用的占位值
+fn empty_progress(gid: &str) -> DownloadProgress {
+    DownloadProgress {
+        gid: gid.to_string(),
+        completed_length: 0,
+        total_length: 0,
+        download_speed: 0,
+        download_speed_avg: 0,
+        percentage: None,
+        status: DownloadStatus::Waiting,
+        eta: None,
+        elapsed: Duration::ZERO,
+        file_path: None,
+        file_count: 0,
+        connections: 0,
+        active_uri: None,
+        files: Vec::new(),
+        upload_speed: 0,
+        share_ratio: None,
+    }
+}
+
+/// 完成时的大小核对：先核对调用方给出的预期大小，再确认磁盘上的文件不短于 aria2 的 `total_length`
+///
+/// 磁盘上的长度读不到时用 `total_length` 与预期大小比较；`total_length` 为 0（大小未知）或
+/// `check_length` 为 false 时不判断是否截断。
+fn completed_size_error(
+    expected: Option<u64>,
+    check_length: bool,
+    total_length: u64,
+    on_disk: Option<u64>,
+) -> Option<DownloadErrorKind> {
+    if let Some(expected) = expected {
+        let actual = match on_disk {
+            Some(len) if len != expected => len,
+            _ => total_length,
+        };
+        if actual != expected {
+            return Some(DownloadErrorKind::SizeMismatch { expected, actual });
+        }
+    }
+    match on_disk {
+        Some(len) if check_length && len < total_length => Some(DownloadErrorKind::TruncatedFile {
+            expected: total_length,
+            actual: len,
+        }),
+        _ => None,
+    }
+}
+
+/// 将 `.part` 文件改为最终文件名，并清理残留的 `.aria2` 控制文件
+///
+/// 同一目录内的 rename 在 NTFS 上是原子的，目标要么是旧文件要么是完整的新文件；
+/// 保存在网络共享上时也只是服务器端改名，不会再经网络复制一遍。
+/// 返回实际使用的路径（`OnConflict::Rename` 时可能与 `final_path` 不同）。
+fn finalize_part_file(rename: &PendingRename) -> Result<PathBuf> {
+    let mut target = rename.final_path.clone();
+    if to_extended_path(&target).exists() {
+        match rename.on_conflict {
+            OnConflict::Overwrite => {}
+            OnConflict::Error => {
+                return Err(DownloadError::TargetExists {
+                    path: strip_extended_prefix(&target).display().to_string(),
+                }
+                .into())
+            }
+            OnConflict::Rename => {
+                if let (Some(dir), Some(name)) = (target.parent(), target.file_name()) {
+                    target = unique_path(dir, &name.to_string_lossy());
+                }
+            }
+        }
+    }
+    std::fs::rename(to_extended_path(&rename.part_path), to_extended_path(&target)).map_err(|e| {
+        anyhow::anyhow!(
+            "重命名 {} 为 {} 失败: {}",
+            strip_extended_prefix(&rename.part_path).display(),
+            strip_extended_prefix(&target).display(),
+            e
+        )
+    })?;
+
+    // aria2 正常完成时会自行删除控制文件，这里只处理异常残留
+    let _ = std::fs::remove_file(to_extended_path(&control_file_path(&rename.part_path)));
+    Ok(target)
+}
+
+/// 全局管理器的配置：默认值加上 config.json 中保存的时段限速规则
+fn global_config() -> Aria2Config {
+    Aria2Config {
+        bandwidth_schedule: AppConfig::load().bandwidth_schedule,
+        ..Default::default()
+    }
+}
+
+/// 启动 aria2c 所需、在组装管理器前就已确定的参数
+struct EngineParts {
+    aria2c_path: PathBuf,
+    config: Aria2Config,
+    rpc_secret: String,
+    session_path: PathBuf,
+    port: u16,
+    /// None 表示附加到其它实例的 aria2c
+    process: Option<Child>,
+    output: OutputTail,
+    version: Aria2Version,
+}
+
+/// RPC 端点文件路径（数据目录下）
+fn endpoint_file_path() -> PathBuf {
+    get_data_dir().join(ENDPOINT_FILE_NAME)
+}
+
+/// 写入发现文件（先写临时文件再改名，其它实例不会读到写了一半的内容）
+///
+/// 文件中有明文 RPC 密钥，因此用 `write_private_file` 创建，只有当前用户能读取；
+/// 同一用户的其它实例（包括以管理员身份运行的）仍可读取并附加。改名不会改变文件的 ACL。
+fn write_endpoint(port: u16, secret: &str, pid: u32) {
+    let endpoint = RpcEndpoint {
+        port,
+        secret: secret.to_string(),
+        pid,
+        started_at: JobStore::now(),
+    };
+    let path = endpoint_file_path();
+    let tmp = path.with_extension("rpc.tmp");
+    let result = serde_json::to_string(&endpoint)
+        .map_err(anyhow::Error::from)
+        .and_then(|json| write_private_file(&tmp, json.as_bytes()))
+        .and_then(|_| Ok(std::fs::rename(&tmp, &path)?));
+    if let Err(e) = result {
+        log::warn!("[aria2] 写入 RPC 端点文件失败: {}", e);
+    }
+}
+
+fn read_endpoint() -> Option<RpcEndpoint> {
+    let json = std::fs::read_to_string(endpoint_file_path()).ok()?;
+    serde_json::from_str(&json).ok()
+}
+
+/// 删除发现文件；文件已被接管 aria2c 的其它实例改写（pid 不同）时保留
+fn remove_endpoint(pid: u32) {
+    if read_endpoint().is_some_and(|e| e.pid == pid) {
+        let _ = std::fs::remove_file(endpoint_file_path());
+    }
+}
+
+/// 尝试附加到发现文件记录的 aria2c
+///
+/// 记录的进程已退出、端口已关闭，或连不上、密钥不对（getVersion 失败）时视为过期：
+/// 删除该文件并返回 None，由调用方启动新的 aria2c 并写入新文件。
+async fn attach_existing() -> Option<(RpcEndpoint, aria2_ws::Client)> {
+    let endpoint = read_endpoint()?;
+    if endpoint.is_stale() {
+        log::info!("[aria2] 发现文件记录的 aria2c（pid {}，端口 {}）已不在，忽略", endpoint.pid, endpoint.port);
+        let _ = std::fs::remove_file(endpoint_file_path());
+        return None;
+    }
+
+    let url = format!("ws://127.0.0.1:{}/jsonrpc", endpoint.port);
+    let connected = async {
+        let client = tokio::time::timeout(ATTACH_TIMEOUT, aria2_ws::Client::connect(&url, Some(&endpoint.secret)))
+            .await
+            .ok()?
+            .ok()?;
+        tokio::time::timeout(ATTACH_TIMEOUT, client.get_version())
+            .await
+            .ok()?
+            .ok()?;
+        Some(client)
+    }
+    .await;
+    match connected {
+        Some(client) => Some((endpoint, client)),
+        None => {
+            log::info!("[aria2] 无法连接发现文件记录的端口 {}，忽略", endpoint.port);
+            let _ = std::fs::remove_file(endpoint_file_path());
+            None
+        }
+    }
+}
+
+/// 会话文件路径（数据目录下）
+fn session_file_path() -> std::path::PathBuf {
+    get_data_dir().join(SESSION_FILE_NAME)
+}
+
+/// 确保会话文件存在（--input-file 指向不存在的文件时 aria2c 会启动失败）
+fn prepare_session_file() -> Result<std::path::PathBuf> {
+    let path = session_file_path();
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    if !path.exists() {
+        std::fs::File::create(&path)?;
+    }
+    Ok(path)
+}
+
+/// 列出活动和等待队列中的全部 gid（每次调用最多等待 `timeout`）
+async fn list_unfinished_gids(client: &aria2_ws::Client, timeout: Duration) -> Result<Vec<String>> {
+    let active = bounded_call(timeout, client.tell_active()).await?;
+    let mut gids: Vec<String> = active.into_iter().map(|s| s.gid).collect();
+    gids.extend(bounded_call(timeout, tell_waiting_all(client)).await?.into_iter().map(|s| s.gid));
+    Ok(gids)
+}
+
+/// 在 `timeout` 内等待一次不经过 `Aria2Engine` 的 RPC 调用（启动阶段还没有引擎），超时返回 `DownloadError::RpcTimeout`
+async fn bounded_call<T>(
+    timeout: Duration,
+    call: impl Future<Output = std::result::Result<T, aria2_ws::Error>>,
+) -> Result<T> {
+    match tokio::time::timeout(timeout, call).await {
+        Ok(result) => Ok(result?),
+        Err(_) => Err(DownloadError::RpcTimeout { timeout_secs: timeout.as_secs() }.into()),
+    }
+}
+
+/// 将键值对写入 aria2 选项的 extra_options
+fn apply_extra_options(options: &mut aria2_ws::TaskOptions, pairs: &[(&str, String)]) {
+    for (key, value) in pairs {
+        options
+            .extra_options
+            .insert(key.to_string(), serde_json::Value::String(value.clone()));
+    }
+}
+
+/// 检查 aria2c 是否具备所需的编译特性
+async fn verify_version(client: &aria2_ws::Client, config: &Aria2Config) -> Result<Aria2Version> {
+    let version = bounded_call(config.rpc_call_timeout, client.get_version()).await?;
+    let version = Aria2Version::new(version.version, version.enabled_features);
+    log::info!(
+        "[aria2] aria2c 版本: {}，已启用特性: {}",
+        version.version,
+        version.enabled_features.join(", ")
+    );
+    check_version(&version, config.require_bittorrent)?;
+    Ok(version)
+}
+
+/// 检查版本号和必需特性，不满足时返回 `DownloadError::UnsupportedAria2`
+fn check_version(version: &Aria2Version, require_bittorrent: bool) -> Result<()> {
+    let unsupported = |reason: String| DownloadError::UnsupportedAria2 {
+        version: version.version.clone(),
+        reason,
+    };
+    if version.number < MIN_ARIA2_VERSION {
+        let (major, minor, patch) = MIN_ARIA2_VERSION;
+        return Err(unsupported(format!("版本过旧，至少需要 {}.{}.{}", major, minor, patch)).into());
+    }
+
+    let mut missing: Vec<&str> = REQUIRED_FEATURES
+        .into_iter()
+        .filter(|f| !version.has_feature(f))
+        .collect();
+    if require_bittorrent && !version.has_feature(FEATURE_BITTORRENT) {
+        missing.push(FEATURE_BITTORRENT);
+    }
+    if !missing.is_empty() {
+        return Err(unsupported(format!("缺少特性 {}", missing.join(", "))).into());
+    }
+    Ok(())
+}
+
+/// 构造 aria2 的 checksum 选项值（形如 sha-256=<hex>）
+fn checksum_option(hash_type: HashType, expected: &str) -> Result<String> {
+    let hex = expected.trim().to_lowercase();
+    if hex.len() != hash_type.hex_len() || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        anyhow::bail!(
+            "无效的 {} 校验值: {}（应为 {} 位十六进制）",
+            hash_type.aria2_name(),
+            expected,
+            hash_type.hex_len()
+        );
+    }
+    Ok(format!("{}={}", hash_type.aria2_name(), hex))
+}
+
+/// 将 aria2 任务状态转换为下载状态
+fn map_task_status(status: &Status) -> DownloadStatus {
+    match status.status {
+        TaskStatus::Waiting => DownloadStatus::Waiting,
+        TaskStatus::Active => DownloadStatus::Active,
+        TaskStatus::Paused => DownloadStatus::Paused,
+        TaskStatus::Complete => DownloadStatus::Complete,
+        TaskStatus::Error => DownloadStatus::Error(detect_disk_full(
+            status,
+            DownloadErrorKind::from_aria2(status.error_code.as_deref(), status.error_message.as_deref()),
+        )),
+        TaskStatus::Removed => DownloadStatus::Removed,
+    }
+}
+
+/// 由 tellStatus 结果构造下载进度
+///
+/// 只选择了部分文件的多文件任务按所选文件计算已完成和总大小（aria2 的 totalLength 是整个种子的大小）。
+fn progress_from_status(gid: &str, status: &Status) -> DownloadProgress {
+    let (completed, total) = selected_totals(&status.files).unwrap_or((status.completed_length, status.total_length));
+
+    let mapped = map_task_status(status);
+    let percentage = progress_percentage(completed, total, &mapped);
+    let first_file = status.files.first();
+
+    DownloadProgress {
+        gid: gid.to_string(),
+        completed_length: completed,
+        total_length: total,
+        download_speed: status.download_speed,
+        download_speed_avg: status.download_speed,
+        percentage,
+        status: mapped,
+        eta: estimate_eta(completed, total, status.download_speed),
+        elapsed: Duration::ZERO,
+        file_path: first_file
+            .map(|f| f.path.as_str())
+            .filter(|p| !p.is_empty())
+            .map(PathBuf::from),
+        file_count: status.files.len() as u32,
+        connections: status.connections as u32,
+        active_uri: first_file
+            .and_then(|f| f.uris.iter().find(|u| u.status == UriStatus::Used))
+            .map(|u| u.uri.clone()),
+        files: if status.files.len() > 1 {
+            status.files.iter().map(task_file).collect()
+        } else {
+            Vec::new()
+        },
+        upload_speed: status.upload_speed,
+        share_ratio: status
+            .info_hash
+            .is_some()
+            .then(|| if completed > 0 { status.upload_length as f64 / completed as f64 } else { 0.0 }),
+    }
+}
+
+/// 有未选择的文件时，所选文件的 (已完成, 总大小)
+fn selected_totals(files: &[File]) -> Option<(u64, u64)> {
+    if files.iter().all(|f| f.selected) {
+        return None;
+    }
+    Some(
+        files
+            .iter()
+            .filter(|f| f.selected)
+            .fold((0, 0), |(completed, total), f| (completed + f.completed_length, total + f.length)),
+    )
+}
+
+/// 磁力链接获取元数据期间，aria2 以 `[METADATA]<info hash>` 作为唯一的文件名
+fn is_metadata_file(file: &TaskFile) -> bool {
+    file.path
+        .as_ref()
+        .is_some_and(|p| p.to_string_lossy().starts_with("[METADATA]"))
+}
+
+fn task_file(file: &File) -> TaskFile {
+    TaskFile {
+        index: file.index as u32,
+        path: Some(PathBuf::from(&file.path)).filter(|p| !p.as_os_str().is_empty()),
+        length: file.length,
+        completed_length: file.completed_length,
+        selected: file.selected,
+    }
+}
+
+/// 补全磁盘已满错误的卷和空间信息；文件读写类错误（aria2 错误码 1、16、17）发生时
+/// 剩余空间已不足以放下未下载的部分，也按磁盘已满处理
+fn detect_disk_full(status: &Status, kind: DownloadErrorKind) -> DownloadErrorKind {
+    let io_error = matches!(kind, DownloadErrorKind::Other(1 | 16 | 17, _));
+    if !io_error && !matches!(kind, DownloadErrorKind::DiskFull { .. }) {
+        return kind;
+    }
+    let needed = status.total_length.saturating_sub(status.completed_length);
+    classify_disk_full(kind, needed, preflight::free_space(Path::new(&status.dir)))
+}
+
+/// `free` 为保存位置所在卷的盘符和剩余空间
+fn classify_disk_full(kind: DownloadErrorKind, needed: u64, free: Option<(String, u64)>) -> DownloadErrorKind {
+    let Some((volume, available)) = free else {
+        return kind;
+    };
+    let reported = matches!(kind, DownloadErrorKind::DiskFull { .. });
+    if !reported && (needed == 0 || available >= needed) {
+        return kind;
+    }
+    DownloadErrorKind::DiskFull { volume, needed, available }
+}
+
+/// 下载进度百分比：总大小未知时为 None；已完成的任务（包括空文件）为 100
+fn progress_percentage(completed: u64, total: u64, status: &DownloadStatus) -> Option<f64> {
+    if total > 0 {
+        Some(completed as f64 / total as f64 * 100.0)
+    } else if *status == DownloadStatus::Complete {
+        Some(100.0)
+    } else {
+        None
+    }
+}
+
+/// 按当前速度估算剩余时间；速度为 0 或总大小未知时无法估算
+fn estimate_eta(completed: u64, total: u64, speed: u64) -> Option<Duration> {
+    if speed == 0 || total == 0 {
+        return None;
+    }
+    let remaining = total.saturating_sub(completed);
+    Some(Duration::from_secs(remaining.div_ceil(speed)))
+}
+
+/// 将 aria2 通知转换为 DownloadEvent 并广播
+///
+/// 只持有客户端的弱引用，管理器关闭后通知通道随之关闭，转发任务自动结束。
+/// 每次 tellStatus 最多等待 `rpc_call_timeout`，aria2c 卡死时不会让所有订阅者都收不到事件。
+fn spawn_event_forwarder(
+    client: &Arc<aria2_ws::Client>,
+    events: broadcast::Sender<DownloadEvent>,
+    rpc_call_timeout: Duration,
+) {
+    let mut notifications = client.subscribe_notifications();
+    let weak = Arc::downgrade(client);
+
+    tokio::spawn(async move {
+        loop {
+            let (gid, event) = match notifications.recv().await {
+                Ok(Notification::Aria2 { gid, event }) => (gid, event),
+                Ok(_) => continue,
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    log::warn!("[aria2] 通知处理过慢，丢失 {} 条通知", n);
+                    continue;
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            };
+
+            // 以 tellStatus 的结果为准（可拿到错误信息），查询失败或超时时按事件类型推断
+            let status = match weak.upgrade() {
+                Some(client) => bounded_call(rpc_call_timeout, client.tell_status(&gid))
+                    .await
+                    .ok()
+                    .map(|s| map_task_status(&s)),
+                None => break,
+            };
+            let status = status.unwrap_or_else(|| match event {
+                Event::Start => DownloadStatus::Active,
+                Event::Pause => DownloadStatus::Paused,
+                Event::Complete | Event::BtComplete => DownloadStatus::Complete,
+                Event::Error => DownloadStatus::Error(DownloadErrorKind::Other(1, "下载出错".to_string())),
+                Event::Stop => DownloadStatus::Removed,
+            });
+
+            log::debug!("[aria2] 任务事件: {} {:?} -> {:?}", gid, event, status);
+            // 没有订阅者时发送失败属正常情况
+            let _ = events.send(DownloadEvent { gid, status, detail: None });
+        }
+        log::debug!("[aria2] 通知转发任务结束");
+    });
+}
+
+/// 分页读取整个等待队列（tellWaiting 需要 offset/num 参数）
+async fn tell_waiting_all(client: &aria2_ws::Client) -> std::result::Result<Vec<Status>, aria2_ws::Error> {
+    let mut all = Vec::new();
+    loop {
+        let page = client.tell_waiting(all.len() as i32, TELL_PAGE_SIZE).await?;
+        let done = (page.len() as i32) < TELL_PAGE_SIZE;
+        all.extend(page);
+        if done {
+            return Ok(all);
+        }
+    }
+}
+
+/// 分页读取全部已停止任务（tellStopped 需要 offset/num 参数）
+async fn tell_stopped_all(client: &aria2_ws::Client) -> std::result::Result<Vec<Status>, aria2_ws::Error> {
+    let mut all = Vec::new();
+    loop {
+        let page = client.tell_stopped(all.len() as i32, TELL_PAGE_SIZE).await?;
+        let done = (page.len() as i32) < TELL_PAGE_SIZE;
+        all.extend(page);
+        if done {
+            return Ok(all);
+        }
+    }
+}
+
+/// 比较两个状态是否属于同一类（忽略 Error 中的信息）
+fn status_kind_eq(a: &DownloadStatus, b: &DownloadStatus) -> bool {
+    std::mem::discriminant(a) == std::mem::discriminant(b)
+}
+
+/// 是否为磁力链接
+fn is_magnet_link(s: &str) -> bool {
+    s.trim_start()
+        .get(..7)
+        .is_some_and(|prefix| prefix.eq_ignore_ascii_case("magnet:"))
+}
+
+/// 从下载地址中提取文件名（去掉查询参数和片段）
+fn url_filename(url: &str) -> Option<&str> {
+    let without_fragment = url.split('#').next().unwrap_or(url);
+    let path = without_fragment.split('?').next().unwrap_or(without_fragment);
+    let after_scheme = path.split_once("://").map(|(_, rest)| rest).unwrap_or(path);
+    // 只有主机名没有路径时不算文件名
+    let (_, file_path) = after_scheme.split_once('/')?;
+    file_path.rsplit('/').next().filter(|name| !name.is_empty())
+}
+
+/// 添加前检查网络：任一地址（多镜像时）能连通即可，全部失败时返回第一个地址的错误
+///
+/// 没有联网时 aria2 仍会接受任务并在后台反复重试，用户会误以为下载已经开始。
+async fn preflight_connectivity(uris: &[String], proxy: Option<&ProxyConfig>) -> Result<()> {
+    let mut first_error = None;
+    for uri in uris {
+        match preflight::check_connectivity(uri, proxy).await {
+            Ok(()) => return Ok(()),
+            Err(e) => {
+                log::warn!("[下载预检] 连通性检查失败: {}", e);
+                first_error.get_or_insert(e);
+            }
+        }
+    }
+    first_error.map_or(Ok(()), Err)
+}
+
+/// 校验多镜像地址：至少一个地址，且未显式指定文件名时所有地址必须指向同名文件
+fn validate_mirror_urls(urls: &[String], filename: Option<&str>) -> Result<()> {
+    if urls.is_empty() {
+        anyhow::bail!("至少需要提供一个下载地址");
+    }
+    if filename.is_some() {
+        return Ok(());
+    }
+
+    let first = url_filename(&urls[0]);
+    for url in &urls[1..] {
+        let name = url_filename(url);
+        if name.is_none() || name != first {
+            anyhow::bail!(
+                "镜像地址指向的文件名不一致（{} 与 {}），请显式指定文件名",
+                first.unwrap_or("<无文件名>"),
+                name.unwrap_or("<无文件名>")
+            );
+        }
+    }
+    Ok(())
+}
+
+/// 从系统的密码学随机数生成器读取 `len` 字节，返回十六进制字符串
+#[cfg(windows)]
+fn random_hex(len: usize) -> Result<String> {
+    use windows::Win32::Security::Cryptography::{BCryptGenRandom, BCRYPT_ALG_HANDLE, BCRYPT_USE_SYSTEM_PREFERRED_RNG};
+
+    let mut bytes = vec![0u8; len];
+    unsafe { BCryptGenRandom(BCRYPT_ALG_HANDLE::default(), &mut bytes, BCRYPT_USE_SYSTEM_PREFERRED_RNG) }
+        .ok()
+        .map_err(|e| anyhow::anyhow!("生成随机数失败: {}", e))?;
+    Ok(bytes.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+#[cfg(not(windows))]
+fn random_hex(len: usize) -> Result<String> {
+    use std::io::Read;
+
+    let mut bytes = vec![0u8; len];
+    std::fs::File::open("/dev/urandom")
+        .and_then(|mut f| f.read_exact(&mut bytes))
+        .map_err(|e| anyhow::anyhow!("生成随机数失败: {}", e))?;
+    Ok(bytes.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+/// 生成随机 RPC 密钥（32 位十六进制，128 位随机数）
+fn generate_rpc_secret() -> Result<String> {
+    random_hex(16)
+}
+
+/// 生成任务 gid（aria2 要求 16 位十六进制）
+fn generate_gid() -> Result<String> {
+    random_hex(8)
+}
+
+/// 是否为连接级错误（WebSocket 断开等），区别于 aria2 返回的应用错误
+fn is_connection_error(error: &aria2_ws::Error) -> bool {
+    matches!(
+        error,
+        aria2_ws::Error::WebsocketIo { .. }
+            | aria2_ws::Error::WebsocketClosed { .. }
+            | aria2_ws::Error::ReconnectTaskTimeout { .. }
+    )
+}
+
+/// 端口当前是否可在本机回环地址上绑定
+fn is_port_free(port: u16) -> bool {
+    std::net::TcpListener::bind(("127.0.0.1", port)).is_ok()
+}
+
+/// 在 `timeout` 内等待端口变为可绑定，返回最终是否空闲
+async fn wait_for_port_free(port: u16, timeout: Duration) -> bool {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if is_port_free(port) {
+            return true;
+        }
+        if Instant::now() >= deadline {
+            return false;
+        }
+        tokio::time::sleep(SHUTDOWN_POLL_INTERVAL).await;
+    }
+}
+
+/// 在 [first, last] 范围内查找第一个空闲端口
+fn find_free_port(first: u16, last: u16) -> Option<u16> {
+    (first..=last).find(|&port| is_port_free(port))
+}
+
+/// 清理全局aria2管理器
+pub async fn cleanup_global_aria2() {
+    if let Some(global) = GLOBAL_ARIA2.get() {
+        let mut guard = global.lock().await;
+        if let Some(mut manager) = guard.take() {
+            let _ = manager.shutdown().await;
+        }
+        ARIA2_WARMED_UP.store(false, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_free_port_skips_occupied() {
+        let listener = std::net::TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let busy = listener.local_addr().unwrap().port();
+        assert!(!is_port_free(busy));
+        assert_ne!(find_free_port(busy, busy.saturating_add(10)), Some(busy));
+        assert_eq!(find_free_port(busy, busy), None);
+    }
+
+    #[test]
+    fn test_status_kind_eq() {
+        assert!(status_kind_eq(
+            &DownloadStatus::Error(DownloadErrorKind::Timeout),
+            &DownloadStatus::Error(DownloadErrorKind::NotFound)
+        ));
+        assert!(!status_kind_eq(&DownloadStatus::Removed, &DownloadStatus::Error(DownloadErrorKind::Timeout)));
+        assert!(status_kind_eq(&DownloadStatus::Paused, &DownloadStatus::Paused));
+        assert!(!status_kind_eq(&DownloadStatus::Paused, &DownloadStatus::Active));
+    }
+
+    #[test]
+    fn test_check_existing_file() {
+        let dir = std::env::temp_dir().join(format!("letrecovery_existing_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("install.esd");
+        std::fs::write(&path, vec![7u8; 1024]).unwrap();
+        let md5 = hash_file_blocking(&path, HashType::Md5, |_, _| true).unwrap();
+
+        assert_eq!(check_existing_file(&dir.join("missing.esd"), 1024, None).unwrap(), ExistingFile::Absent);
+        assert_eq!(check_existing_file(&path, 1000, None).unwrap(), ExistingFile::Absent);
+        assert_eq!(check_existing_file(&path, 1024, None).unwrap(), ExistingFile::Complete);
+        let checksum = (HashType::Md5, md5.to_uppercase());
+        assert_eq!(check_existing_file(&path, 1024, Some(&checksum)).unwrap(), ExistingFile::Complete);
+        let checksum = (HashType::Md5, "0".repeat(32));
+        assert_eq!(check_existing_file(&path, 1024, Some(&checksum)).unwrap(), ExistingFile::HashMismatch);
+
+        // 已有 .bad 文件时不覆盖
+        assert_eq!(set_aside(&path).unwrap(), dir.join("install.esd.bad"));
+        std::fs::write(&path, b"again").unwrap();
+        assert_eq!(set_aside(&path).unwrap(), dir.join("install.esd (1).bad"));
+        assert!(!path.exists());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_bad_file_path() {
+        assert_eq!(bad_file_path(Path::new(r"D:\a\install.esd.part")), PathBuf::from(r"D:\a\install.esd.bad"));
+        assert_eq!(bad_file_path(Path::new(r"D:\a\install.esd")), PathBuf::from(r"D:\a\install.esd.bad"));
+    }
+
+    #[test]
+    fn test_task_split() {
+        const MB: u64 = 1024 * 1024;
+        assert_eq!(task_split(None, Some(MB), 32, 8 * MB), 1);
+        assert_eq!(task_split(None, Some(100 * MB), 32, 8 * MB), 32);
+        assert_eq!(task_split(None, None, 32, 8 * MB), 32);
+        assert_eq!(task_split(Some(4), Some(MB), 32, 8 * MB), 4);
+        assert_eq!(task_split(None, Some(MB), 32, 0), 32);
+
+        assert!(DownloadOptions { max_connections: Some(17), ..Default::default() }.validate().is_err());
+        assert!(DownloadOptions { split: Some(0), ..Default::default() }.validate().is_err());
+        assert!(DownloadOptions { split: Some(1), max_connections: Some(16), ..Default::default() }.validate().is_ok());
+    }
+
+    #[test]
+    fn test_torrent_options() {
+        let options = TorrentOptions::default();
+        assert!(options.validate().is_ok());
+        let aria2 = options.aria2_options();
+        assert!(aria2.contains(&("seed-time", "0".to_string())));
+        assert!(aria2.contains(&("seed-ratio", "0.00".to_string())));
+        assert!(!aria2.iter().any(|(key, _)| *key == "max-upload-limit"));
+
+        let capped = TorrentOptions {
+            seed_time: None,
+            seed_ratio: Some(0.5),
+            disable_upload: true,
+            ..Default::default()
+        };
+        let aria2 = capped.aria2_options();
+        assert!(aria2.contains(&("seed-ratio", "0.50".to_string())));
+        assert!(aria2.contains(&("max-upload-limit", "1K".to_string())));
+        assert!(!aria2.iter().any(|(key, _)| *key == "seed-time"));
+
+        assert!(TorrentOptions { seed_time: None, ..Default::default() }.validate().is_err());
+        assert!(TorrentOptions { seed_ratio: Some(-1.0), ..Default::default() }.validate().is_err());
+    }
+
+    #[test]
+    fn test_retry_allowed() {
+        assert!(retry_allowed(&DownloadErrorKind::Timeout, 10));
+        assert!(!retry_allowed(&DownloadErrorKind::NotFound, 0));
+        assert!(retry_allowed(&DownloadErrorKind::ChecksumMismatch, CHECKSUM_RETRY_LIMIT - 1));
+        assert!(!retry_allowed(&DownloadErrorKind::ChecksumMismatch, CHECKSUM_RETRY_LIMIT));
+    }
+
+    #[test]
+    fn test_classify_disk_full() {
+        let write_error = DownloadErrorKind::Other(16, "Failed to write".to_string());
+        let free = |available| Some(("D:".to_string(), available));
+        let disk_full = DownloadErrorKind::DiskFull {
+            volume: "D:".to_string(),
+            needed: 4096,
+            available: 100,
+        };
+        assert_eq!(classify_disk_full(write_error.clone(), 4096, free(100)), disk_full);
+        assert!(!retry_allowed(&disk_full, 0));
+        // 空间足够时是别的写入错误
+        assert_eq!(classify_disk_full(write_error.clone(), 4096, free(1 << 30)), write_error);
+        assert_eq!(classify_disk_full(write_error.clone(), 4096, None), write_error);
+
+        let reported = DownloadErrorKind::from_aria2(Some("9"), None);
+        assert_eq!(classify_disk_full(reported.clone(), 4096, free(100)), disk_full);
+        assert_eq!(classify_disk_full(reported.clone(), 4096, None), reported);
+        assert_eq!(reported.to_string(), "磁盘空间不足");
+        assert!(disk_full.to_string().starts_with("D: 磁盘空间不足"));
+    }
+
+    #[test]
+    fn test_checksum_option() {
+        let hex = "BA7816BF8F01CFEA414140DE5DAE2223B00361A396177A9CB410FF61F20015AD";
+        assert_eq!(
+            checksum_option(HashType::Sha256, hex).unwrap(),
+            format!("sha-256={}", hex.to_lowercase())
+        );
+        assert!(checksum_option(HashType::Md5, hex).is_err());
+        assert!(checksum_option(HashType::Sha1, "not-a-hash").is_err());
+    }
+
+    #[test]
+    fn test_estimate_eta() {
+        assert_eq!(estimate_eta(0, 1000, 100), Some(Duration::from_secs(10)));
+        assert_eq!(estimate_eta(950, 1000, 100), Some(Duration::from_secs(1)));
+        assert_eq!(estimate_eta(1000, 1000, 100), Some(Duration::ZERO));
+        // 速度为 0 或总大小未知
+        assert_eq!(estimate_eta(0, 1000, 0), None);
+        assert_eq!(estimate_eta(500, 0, 100), None);
+    }
+
+    #[test]
+    fn test_progress_percentage() {
+        assert_eq!(progress_percentage(250, 1000, &DownloadStatus::Active), Some(25.0));
+        // 大小未知：下载中为不确定进度，完成后为 100
+        assert_eq!(progress_percentage(4096, 0, &DownloadStatus::Active), None);
+        assert_eq!(progress_percentage(0, 0, &DownloadStatus::Waiting), None);
+        assert_eq!(progress_percentage(0, 0, &DownloadStatus::Complete), Some(100.0));
+    }
+
+    #[test]
+    fn test_aria2c_args_follow_config() {
+        let session = Path::new("aria2.session");
+        let args = aria2c_args(6800, "secret", session, &Aria2Config::default());
+        assert!(args.contains(&"--split=32".to_string()));
+        assert!(args.contains(&"--max-concurrent-downloads=5".to_string()));
+        assert!(args.contains(&"--file-allocation=none".to_string()));
+
+        let config = Aria2Config {
+            split: 8,
+            max_concurrent_downloads: 1,
+            file_allocation: FileAllocation::Falloc,
+            continue_downloads: false,
+            ..Default::default()
+        };
+        let args = aria2c_args(6801, "secret", session, &config);
+        assert!(args.contains(&"--rpc-listen-port=6801".to_string()));
+        assert!(args.contains(&"--split=8".to_string()));
+        assert!(args.contains(&"--max-concurrent-downloads=1".to_string()));
+        assert!(args.contains(&"--file-allocation=falloc".to_string()));
+        assert!(args.contains(&"--continue=false".to_string()));
+        assert!(args.iter().any(|a| a.starts_with("--user-agent=LetRecovery/")));
+        assert!(!args.iter().any(|a| a.starts_with("--daemon")));
+        assert!(args.contains(&format!("--stop-with-process={}", std::process::id())));
+        assert!(args.contains(&"--check-certificate=true".to_string()));
+        assert!(!args.iter().any(|a| a.starts_with("--ca-certificate")));
+
+        let config = Aria2Config {
+            tls: TlsOptions {
+                ca_certificate: Some(PathBuf::from(r"C:\corp\root.pem")),
+                allow_insecure_tls: true,
+            },
+            ..Default::default()
+        };
+        let args = aria2c_args(6802, "secret", session, &config);
+        assert!(args.contains(&"--check-certificate=false".to_string()));
+        assert!(args.contains(&r"--ca-certificate=C:\corp\root.pem".to_string()));
+    }
+
+    #[test]
+    fn test_resolve_aria2c_prefers_explicit_path() {
+        let dir = std::env::temp_dir().join(format!("letrecovery_resolve_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let explicit = dir.join(aria2c_file_name());
+        std::fs::write(&explicit, b"").unwrap();
+        assert_eq!(resolve_aria2c(Some(&explicit)).unwrap(), explicit);
+
+        // 指定路径不存在时继续查找；找不到时错误中列出指定的路径
+        let missing = dir.join("missing").join(aria2c_file_name());
+        if let Err(e) = resolve_aria2c(Some(&missing)) {
+            match e.downcast_ref::<DownloadError>() {
+                Some(DownloadError::Aria2NotFound { checked }) => {
+                    assert_eq!(checked[0], missing.display().to_string());
+                    assert!(checked.len() >= 3);
+                }
+                other => panic!("unexpected error: {:?}", other),
+            }
+        }
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_is_connection_error() {
+        assert!(is_connection_error(&aria2_ws::Error::WebsocketClosed {
+            message: "idle".to_string()
+        }));
+        // 解析失败等应用层错误不应触发重连
+        assert!(!is_connection_error(&aria2_ws::Error::Parse {
+            value: "x".to_string(),
+            to: "u64".to_string()
+        }));
+    }
+
+    #[test]
+    fn test_is_magnet_link() {
+        assert!(is_magnet_link("magnet:?xt=urn:btih:abc"));
+        assert!(is_magnet_link("MAGNET:?xt=urn:btih:abc"));
+        assert!(!is_magnet_link("D:\\images\\win11.torrent"));
+        assert!(!is_magnet_link("mag"));
+    }
+
+    #[test]
+    fn test_parse_speed_value() {
+        assert_eq!(parse_speed_value("0"), Some(0));
+        assert_eq!(parse_speed_value("2097152"), Some(2 * 1024 * 1024));
+        assert_eq!(parse_speed_value("200K"), Some(200 * 1024));
+        assert_eq!(parse_speed_value("2M"), Some(2 * 1024 * 1024));
+        assert_eq!(parse_speed_value(""), None);
+        assert_eq!(parse_speed_value("fast"), None);
+    }
+
+    #[test]
+    fn test_url_filename() {
+        assert_eq!(url_filename("https://a.com/pe/boot.wim"), Some("boot.wim"));
+        assert_eq!(url_filename("https://a.com/pe/boot.wim?sign=1#x"), Some("boot.wim"));
+        assert_eq!(url_filename("https://a.com/"), None);
+        assert_eq!(url_filename("https://a.com"), None);
+    }
+
+    #[test]
+    fn test_progress_from_status_reports_file_and_uri() {
+        let status: Status = serde_json::from_value(serde_json::json!({
+            "gid": "2089b05ecca3d829",
+            "status": "active",
+            "totalLength": "1000",
+            "completedLength": "250",
+            "uploadLength": "0",
+            "downloadSpeed": "100",
+            "uploadSpeed": "0",
+            "pieceLength": "1048576",
+            "numPieces": "1",
+            "connections": "3",
+            "dir": "D:\\LetRecovery",
+            "files": [{
+                "index": "1",
+                "path": "D:\\LetRecovery\\install.esd.part",
+                "length": "1000",
+                "completedLength": "250",
+                "selected": "true",
+                "uris": [
+                    {"status": "waiting", "uri": "https://mirror1.example.com/install.esd"},
+                    {"status": "used", "uri": "https://mirror2.example.com/install.esd"}
+                ]
+            }]
+        }))
+        .unwrap();
+
+        let progress = progress_from_status(&status.gid, &status);
+        assert_eq!(progress.file_path, Some(PathBuf::from("D:\\LetRecovery\\install.esd.part")));
+        assert_eq!(progress.file_count, 1);
+        assert_eq!(progress.connections, 3);
+        assert_eq!(progress.active_uri.as_deref(), Some("https://mirror2.example.com/install.esd"));
+        assert!(progress.files.is_empty());
+        assert_eq!(progress.share_ratio, None);
+        assert!(serde_json::to_value(&progress).is_ok());
+    }
+
+    #[test]
+    fn test_progress_lists_files_of_multi_file_task() {
+        let file = |index: u32, name: &str, completed: u32, selected: bool| {
+            serde_json::json!({
+                "index": index.to_string(),
+                "path": format!("D:\\Drivers\\{}", name),
+                "length": "4096",
+                "completedLength": completed.to_string(),
+                "selected": selected.to_string(),
+                "uris": []
+            })
+        };
+        let status: Status = serde_json::from_value(serde_json::json!({
+            "gid": "2089b05ecca3d830",
+            "status": "active",
+            "totalLength": "8192",
+            "completedLength": "1024",
+            "uploadLength": "0",
+            "downloadSpeed": "0",
+            "uploadSpeed": "0",
+            "pieceLength": "1024",
+            "numPieces": "8",
+            "connections": "0",
+            "dir": "D:\\Drivers",
+            "files": [file(1, "net.zip", 1024, true), file(2, "readme.txt", 0, false)]
+        }))
+        .unwrap();
+
+        let progress = progress_from_status(&status.gid, &status);
+        assert_eq!(progress.file_count, 2);
+        assert_eq!(
+            progress.files,
+            vec![
+                TaskFile {
+                    index: 1,
+                    path: Some(PathBuf::from("D:\\Drivers\\net.zip")),
+                    length: 4096,
+                    completed_length: 1024,
+                    selected: true,
+                },
+                TaskFile {
+                    index: 2,
+                    path: Some(PathBuf::from("D:\\Drivers\\readme.txt")),
+                    length: 4096,
+                    completed_length: 0,
+                    selected: false,
+                },
+            ]
+        );
+        // 只按所选的 net.zip 计算进度
+        assert_eq!((progress.completed_length, progress.total_length), (1024, 4096));
+        assert_eq!(progress.percentage, Some(25.0));
+        assert!(!progress.files.iter().any(is_metadata_file));
+    }
+
+    #[test]
+    fn test_status_is_finished_and_failed() {
+        assert!(DownloadStatus::Removed.is_finished());
+        assert!(!DownloadStatus::Removed.is_failed());
+        assert!(DownloadStatus::Error(DownloadErrorKind::Timeout).is_failed());
+        assert!(DownloadStatus::Complete.is_finished());
+        assert!(!DownloadStatus::Paused.is_finished());
+    }
+
+    #[test]
+    fn test_check_version() {
+        let features = |list: &[&str]| list.iter().map(|f| f.to_string()).collect::<Vec<_>>();
+        let full = features(&["BitTorrent", "HTTPS", "Message Digest", "Metalink", "XML-RPC"]);
+
+        let version = Aria2Version::new("1.37.0".to_string(), full.clone());
+        assert_eq!(version.number, (1, 37, 0));
+        assert!(check_version(&version, true).is_ok());
+
+        let old = Aria2Version::new("1.18.10".to_string(), full);
+        let err = check_version(&old, true).unwrap_err();
+        assert!(matches!(err.downcast_ref::<DownloadError>(), Some(DownloadError::UnsupportedAria2 { .. })));
+
+        let no_bt = Aria2Version::new("1.36.0".to_string(), features(&["HTTPS", "Message Digest", "Metalink"]));
+        assert!(check_version(&no_bt, false).is_ok());
+        assert!(check_version(&no_bt, true).unwrap_err().to_string().contains("BitTorrent"));
+
+        let no_https = Aria2Version::new("1.36.0".to_string(), features(&["Message Digest", "Metalink"]));
+        assert!(check_version(&no_https, false).unwrap_err().to_string().contains("HTTPS"));
+    }
+
+    #[test]
+    fn test_same_update_ignores_timing() {
+        let a = empty_progress("g");
+        let b = DownloadProgress {
+            elapsed: Duration::from_secs(3),
+            eta: Some(Duration::from_secs(9)),
+            ..a.clone()
+        };
+        assert!(same_update(&a, &b));
+        let c = DownloadProgress { completed_length: 1, ..a.clone() };
+        assert!(!same_update(&a, &c));
+    }
+
+    #[test]
+    fn test_finalize_part_file() {
+        let dir = std::env::temp_dir().join(format!("lr_finalize_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let rename = PendingRename {
+            part_path: dir.join("a.iso.part"),
+            final_path: dir.join("a.iso"),
+            on_conflict: OnConflict::Error,
+            finalized: false,
+        };
+        std::fs::write(&rename.part_path, b"new").unwrap();
+        std::fs::write(dir.join("a.iso.part.aria2"), b"").unwrap();
+
+        assert_eq!(finalize_part_file(&rename).unwrap(), rename.final_path);
+        assert_eq!(std::fs::read(&rename.final_path).unwrap(), b"new");
+        assert!(!rename.part_path.exists());
+        assert!(!dir.join("a.iso.part.aria2").exists());
+
+        // 目标已存在：Error 保留两个文件，Overwrite 替换
+        std::fs::write(&rename.part_path, b"newer").unwrap();
+        let err = finalize_part_file(&rename).unwrap_err();
+        assert!(matches!(err.downcast_ref::<DownloadError>(), Some(DownloadError::TargetExists { .. })));
+        assert!(rename.part_path.exists());
+
+        // Rename 保留已有文件，改存为 a (1).iso
+        let rename = PendingRename { on_conflict: OnConflict::Rename, ..rename };
+        assert_eq!(finalize_part_file(&rename).unwrap(), dir.join("a (1).iso"));
+        assert_eq!(std::fs::read(dir.join("a (1).iso")).unwrap(), b"newer");
+        assert_eq!(std::fs::read(&rename.final_path).unwrap(), b"new");
+
+        std::fs::write(&rename.part_path, b"newest").unwrap();
+        let rename = PendingRename { on_conflict: OnConflict::Overwrite, ..rename };
+        finalize_part_file(&rename).unwrap();
+        assert_eq!(std::fs::read(&rename.final_path).unwrap(), b"newest");
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_validate_mirror_urls() {
+        let same = vec![
+            "https://m1.com/img/win11.esd".to_string(),
+            "https://m2.com/mirror/win11.esd?t=1".to_string(),
+        ];
+        assert!(validate_mirror_urls(&same, None).is_ok());
+
+        let different = vec![
+            "https://m1.com/img/win11.esd".to_string(),
+            "https://m2.com/download?id=123".to_string(),
+        ];
+        assert!(validate_mirror_urls(&different, None).is_err());
+        assert!(validate_mirror_urls(&different, Some("win11.esd")).is_ok());
+        assert!(validate_mirror_urls(&[], Some("win11.esd")).is_err());
+    }
+
+    /// 启动独立的 aria2c（不附加到其它测试启动的实例）
+    async fn start_standalone() -> Result<Aria2Manager> {
+        Aria2Manager::start_with(Aria2Config {
+            attach_to_running: false,
+            ..Default::default()
+        })
+        .await
+    }
+
+    /// 只接受连接、从不响应的本地 HTTP 服务，让任务停留在下载中
+    fn stalled_http_server() -> (std::net::TcpListener, String) {
+        let listener = std::net::TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let url = format!("http://{}/file.bin", listener.local_addr().unwrap());
+        (listener, url)
+    }
+
+    /// 本地 HTTP 服务：对每个请求返回 `size` 字节的数据（不限速）
+    fn serving_http_server(size: u64) -> String {
+        use std::io::{Read, Write};
+
+        let listener = std::net::TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let url = format!("http://{}/file.bin", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                std::thread::spawn(move || {
+                    let mut request = [0u8; 4096];
+                    let _ = stream.read(&mut request);
+                    let header = format!(
+                        "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                        size
+                    );
+                    let _ = stream.write_all(header.as_bytes());
+                    let chunk = vec![0u8; 64 * 1024];
+                    let mut sent = 0u64;
+                    while sent < size {
+                        let n = chunk.len().min((size - sent) as usize);
+                        if stream.write_all(&chunk[..n]).is_err() {
+                            break;
+                        }
+                        sent += n as u64;
+                    }
+                });
+            }
+        });
+        url
+    }
+
+    /// 本地 HTTP 服务：返回 `size` 字节的数据，并把收到的每个请求头原文发送到返回的通道
+    fn recording_http_server(size: u64) -> (String, std::sync::mpsc::Receiver<String>) {
+        use std::io::{Read, Write};
+
+        let listener = std::net::TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let url = format!("http://{}/file.bin", listener.local_addr().unwrap());
+        let (tx, rx) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let mut request = [0u8; 4096];
+                let n = stream.read(&mut request).unwrap_or(0);
+                let _ = tx.send(String::from_utf8_lossy(&request[..n]).into_owned());
+                let header = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    size
+                );
+                let _ = stream.write_all(header.as_bytes());
+                let _ = stream.write_all(&vec![0u8; size as usize]);
+            }
+        });
+        (url, rx)
+    }
+
+    #[tokio::test]
+    #[ignore = "需要 bin/aria2c.exe"]
+    async fn test_user_agent_and_referer_are_sent() {
+        let manager = start_standalone().await.unwrap();
+        let (url, requests) = recording_http_server(1024);
+        let dir = std::env::temp_dir();
+        // 添加前的 HEAD 空间预检也会到达服务器，只检查 aria2 发出的 GET
+        let next_get = || loop {
+            let request = requests.recv_timeout(Duration::from_secs(10)).unwrap();
+            if request.starts_with("GET ") {
+                break request;
+            }
+        };
+
+        // 全局默认 User-Agent
+        manager.add_download(&url, dir.to_str().unwrap(), Some("ua_default.bin")).await.unwrap();
+        let request = next_get();
+        assert!(request.contains(&format!("User-Agent: {}", Aria2Config::default().user_agent)), "{}", request);
+
+        // 任务设置优先于全局默认值
+        let options = DownloadOptions {
+            filename: Some("ua_task.bin".to_string()),
+            user_agent: Some("Mozilla/5.0 Test".to_string()),
+            referer: Some("https://mirror.example.com/".to_string()),
+            skip_space_check: true,
+            ..Default::default()
+        };
+        manager.add_download_with_options(&url, dir.to_str().unwrap(), &options).await.unwrap();
+        let request = next_get();
+        assert!(request.contains("User-Agent: Mozilla/5.0 Test"), "{}", request);
+        assert!(request.contains("Referer: https://mirror.example.com/"), "{}", request);
+    }
+
+    #[tokio::test]
+    #[ignore = "需要 bin/aria2c.exe"]
+    async fn test_watch_progress_ends_on_complete() {
+        use futures::StreamExt;
+
+        let manager = start_standalone().await.unwrap();
+        let url = serving_http_server(4 * 1024 * 1024);
+        let options = DownloadOptions {
+            filename: Some("watch.bin".to_string()),
+            skip_space_check: true,
+            ..Default::default()
+        };
+        let gid = manager
+            .add_download_with_options(&url, std::env::temp_dir().to_str().unwrap(), &options)
+            .await
+            .unwrap();
+
+        let interval = Duration::from_millis(200);
+        let (a, b) = tokio::time::timeout(
+            Duration::from_secs(30),
+            futures::future::join(
+                manager.watch_progress(&gid, interval).collect::<Vec<_>>(),
+                manager.watch_progress(&gid, interval).collect::<Vec<_>>(),
+            ),
+        )
+        .await
+        .expect("任务完成后流应当结束");
+
+        for updates in [a, b] {
+            assert_eq!(updates.last().unwrap().status, DownloadStatus::Complete);
+            assert!(updates.windows(2).all(|w| !same_update(&w[0], &w[1])));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_delete_with_retry() {
+        let path = std::env::temp_dir().join(format!("lr_delete_{}.part", std::process::id()));
+        std::fs::write(&path, vec![0u8; 1234]).unwrap();
+        assert_eq!(delete_with_retry(&path).await.unwrap(), 1234);
+        assert!(!path.exists());
+        assert_eq!(delete_with_retry(&path).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    #[ignore = "需要 bin/aria2c.exe"]
+    async fn test_cancel_and_delete_removes_partial_files() {
+        let manager = start_standalone().await.unwrap();
+        let (_listener, url) = stalled_http_server();
+        let dir = std::env::temp_dir().join(format!("lr_cancel_delete_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let options = DownloadOptions {
+            filename: Some("partial.bin".to_string()),
+            skip_space_check: true,
+            ..Default::default()
+        };
+        let gid = manager
+            .add_download_with_options(&url, dir.to_str().unwrap(), &options)
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        // 模拟已下载的部分
+        std::fs::write(dir.join("partial.bin.part"), vec![0u8; 4096]).unwrap();
+
+        let reclaimed = manager.cancel_and_delete(&gid).await.unwrap();
+        assert!(reclaimed >= 4096);
+        assert!(!dir.join("partial.bin.part").exists());
+        assert!(!dir.join("partial.bin.part.aria2").exists());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    #[ignore = "需要 bin/aria2c.exe"]
+    async fn test_task_file_allocation_override() {
+        let manager = start_standalone().await.unwrap();
+        let (_listener, url) = stalled_http_server();
+        let options = DownloadOptions {
+            filename: Some("install.wim".to_string()),
+            file_allocation: Some(FileAllocation::Falloc),
+            skip_space_check: true,
+            ..Default::default()
+        };
+        let gid = manager
+            .add_download_with_options(&url, std::env::temp_dir().to_str().unwrap(), &options)
+            .await
+            .unwrap();
+
+        let client = manager.client().unwrap();
+        let task_options = client.get_option(&gid).await.unwrap();
+        assert_eq!(
+            task_options.extra_options.get("file-allocation"),
+            Some(&serde_json::Value::String("falloc".to_string()))
+        );
+        manager.cancel(&gid).await.unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore = "需要 bin/aria2c.exe"]
+    async fn test_paused_task_starts_on_demand() {
+        let manager = start_standalone().await.unwrap();
+        let (_listener, url) = stalled_http_server();
+        let options = DownloadOptions {
+            filename: Some("paused.bin".to_string()),
+            skip_space_check: true,
+            paused: true,
+            ..Default::default()
+        };
+        let gid = manager
+            .add_download_with_options(&url, std::env::temp_dir().to_str().unwrap(), &options)
+            .await
+            .unwrap();
+        assert_eq!(manager.get_status(&gid).await.unwrap().status, DownloadStatus::Paused);
+
+        manager.start_download(&gid).await.unwrap();
+        let status = manager.get_status(&gid).await.unwrap().status;
+        assert!(matches!(status, DownloadStatus::Active | DownloadStatus::Waiting), "{:?}", status);
+        // 已开始的任务再次 start 不报错
+        manager.start_download(&gid).await.unwrap();
+        manager.cancel(&gid).await.unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore = "需要 bin/aria2c.exe"]
+    async fn test_global_stat_reports_queue_and_limit() {
+        let mut manager = start_standalone().await.unwrap();
+        let (_listener, url) = stalled_http_server();
+        let options = DownloadOptions {
+            filename: Some("stat.bin".to_string()),
+            skip_space_check: true,
+            paused: true,
+            ..Default::default()
+        };
+        let gid = manager
+            .add_download_with_options(&url, std::env::temp_dir().to_str().unwrap(), &options)
+            .await
+            .unwrap();
+        manager.set_global_speed_limit(512 * 1024).await.unwrap();
+
+        let stat = manager.get_global_stat().await.unwrap();
+        assert_eq!(stat.num_waiting, 1);
+        assert_eq!(stat.num_active, 0);
+        assert_eq!(stat.speed_limit, 512 * 1024);
+        manager.cancel(&gid).await.unwrap();
+        manager.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore = "需要 bin/aria2c.exe"]
+    async fn test_apply_profile_changes_concurrency() {
+        let mut manager = start_standalone().await.unwrap();
+        assert_eq!(manager.get_max_concurrent_downloads().await.unwrap(), 5);
+
+        manager.apply_profile(DownloadProfile::Background).await.unwrap();
+        assert_eq!(manager.get_max_concurrent_downloads().await.unwrap(), 2);
+        assert_eq!(
+            manager.get_global_speed_limit().await.unwrap(),
+            DownloadProfile::Background.overall_speed_limit()
+        );
+        assert!(manager.set_max_concurrent_downloads(0).await.is_err());
+        assert!(manager.set_max_concurrent_downloads(17).await.is_err());
+
+        manager.apply_profile(DownloadProfile::Fast).await.unwrap();
+        assert_eq!(manager.get_max_concurrent_downloads().await.unwrap(), 5);
+        assert_eq!(manager.get_global_speed_limit().await.unwrap(), 0);
+        manager.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore = "需要 bin/aria2c.exe"]
+    async fn test_reorder_waiting_queue() {
+        let manager = start_standalone().await.unwrap();
+        let (_listener, url) = stalled_http_server();
+        let save_dir = std::env::temp_dir();
+        let mut gids = Vec::new();
+        for name in ["a.bin", "b.bin", "c.bin"] {
+            let options = DownloadOptions {
+                filename: Some(name.to_string()),
+                skip_space_check: true,
+                paused: true,
+                ..Default::default()
+            };
+            gids.push(
+                manager
+                    .add_download_with_options(&url, save_dir.to_str().unwrap(), &options)
+                    .await
+                    .unwrap(),
+            );
+        }
+        let client = manager.client().unwrap();
+        let order = || async {
+            tell_waiting_all(&client)
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|s| s.gid)
+                .filter(|gid| gids.contains(gid))
+                .collect::<Vec<_>>()
+        };
+
+        manager.move_to_front(&gids[2]).await.unwrap();
+        assert_eq!(order().await, [gids[2].clone(), gids[0].clone(), gids[1].clone()]);
+        manager.move_to_back(&gids[2]).await.unwrap();
+        assert_eq!(order().await, [gids[0].clone(), gids[1].clone(), gids[2].clone()]);
+        manager.move_by(&gids[1], -1).await.unwrap();
+        assert_eq!(order().await, [gids[1].clone(), gids[0].clone(), gids[2].clone()]);
+
+        manager.start_download(&gids[0]).await.unwrap();
+        manager.cancel(&gids[0]).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        let err = manager.set_position(&gids[0], 0).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<DownloadError>(),
+            Some(DownloadError::NotQueued { .. })
+        ));
+        for gid in &gids[1..] {
+            manager.cancel(gid).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    #[ignore = "需要 bin/aria2c.exe"]
+    async fn test_stalled_task_is_restarted() {
+        let manager = Aria2Manager::start_with(Aria2Config {
+            attach_to_running: false,
+            stall_timeout: Some(Duration::from_secs(3)),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        let mut events = manager.subscribe();
+        let (_listener, url) = stalled_http_server();
+        let options = DownloadOptions {
+            filename: Some("stalled.bin".to_string()),
+            skip_space_check: true,
+            ..Default::default()
+        };
+        let gid = manager
+            .add_download_with_options(&url, std::env::temp_dir().to_str().unwrap(), &options)
+            .await
+            .unwrap();
+
+        let stalled = tokio::time::timeout(Duration::from_secs(30), async {
+            loop {
+                let event = events.recv().await.unwrap();
+                if let (true, Some(EventDetail::StallRestarted { stalled_for })) = (event.gid == gid, event.detail) {
+                    break stalled_for;
+                }
+            }
+        })
+        .await
+        .expect("停滞的任务应在超时内被重新开始");
+        assert!(stalled >= Duration::from_secs(3));
+        manager.cancel(&gid).await.unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore = "需要 bin/aria2c.exe"]
+    async fn test_mirror_rotation_skips_missing_mirror() {
+        use std::io::{Read, Write};
+
+        // 第一个镜像对所有请求返回 404
+        let listener = std::net::TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let missing = format!("http://{}/file.bin", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let mut request = [0u8; 4096];
+                let _ = stream.read(&mut request);
+                let _ = stream.write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n");
+            }
+        });
+        let good = serving_http_server(1024 * 1024);
+
+        let manager = start_standalone().await.unwrap();
+        let mut events = manager.subscribe();
+        let save_dir = std::env::temp_dir().join("letrecovery_mirror_rotation");
+        let _ = std::fs::remove_dir_all(&save_dir);
+        let options = DownloadOptions {
+            filename: Some("rotated.bin".to_string()),
+            skip_space_check: true,
+            ..Default::default()
+        };
+        let gid = manager
+            .add_download_with_mirrors(&[missing, good.clone()], save_dir.to_str().unwrap(), &options)
+            .await
+            .unwrap();
+
+        let switched = tokio::time::timeout(Duration::from_secs(30), async {
+            loop {
+                let event = events.recv().await.unwrap();
+                if let (true, Some(detail)) = (event.gid == gid, event.detail) {
+                    break detail;
+                }
+            }
+        })
+        .await
+        .expect("404 的镜像应被切换");
+        assert_eq!(switched, EventDetail::MirrorSwitched { mirror: 1, url: good, attempt: 2 });
+
+        let deadline = Instant::now() + Duration::from_secs(30);
+        loop {
+            let progress = manager.get_status(&gid).await.unwrap();
+            assert!(!progress.status.is_failed(), "切换镜像后不应报告失败: {:?}", progress.status);
+            if progress.status == DownloadStatus::Complete {
+                break;
+            }
+            assert!(Instant::now() < deadline, "切换镜像后应在超时内完成");
+            tokio::time::sleep(Duration::from_millis(200)).await;
+        }
+        assert_eq!(std::fs::metadata(save_dir.join("rotated.bin")).unwrap().len(), 1024 * 1024);
+        let _ = std::fs::remove_dir_all(&save_dir);
+    }
+
+    #[tokio::test]
+    #[ignore = "需要 bin/aria2c.exe"]
+    async fn test_dropped_handle_cancels_task() {
+        let (_listener, url) = stalled_http_server();
+        let mut manager = start_standalone().await.unwrap();
+        let save_dir = std::env::temp_dir().join("letrecovery_handle");
+        let options = DownloadOptions {
+            filename: Some("guarded.bin".to_string()),
+            skip_space_check: true,
+            skip_connectivity_check: true,
+            ..Default::default()
+        };
+        let active_gids = |manager: &Aria2Manager| {
+            let engine = manager.engine.clone();
+            async move {
+                let active = engine.call(|c| async move { c.tell_active().await }).await.unwrap();
+                active.into_iter().map(|s| s.gid).collect::<Vec<_>>()
+            }
+        };
+
+        let dropped = manager
+            .add_download_guarded(&url, save_dir.to_str().unwrap(), &options)
+            .await
+            .unwrap();
+        let dropped_gid = dropped.to_string();
+        let kept_options = DownloadOptions {
+            filename: Some("kept.bin".to_string()),
+            ..options.clone()
+        };
+        let kept = manager
+            .add_download_guarded(&url, save_dir.to_str().unwrap(), &kept_options)
+            .await
+            .unwrap()
+            .detach();
+        assert!(active_gids(&manager).await.contains(&dropped_gid));
+
+        drop(dropped);
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while active_gids(&manager).await.contains(&dropped_gid) {
+            assert!(Instant::now() < deadline, "丢弃句柄后任务应很快被取消");
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        // detach 的任务不受影响
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        assert!(active_gids(&manager).await.contains(&kept));
+
+        manager.shutdown().await.unwrap();
+        let _ = std::fs::remove_dir_all(&save_dir);
+    }
+
+    #[tokio::test]
+    #[ignore = "需要 bin/aria2c.exe"]
+    async fn test_get_statuses_keeps_order_and_isolates_failures() {
+        let url = serving_http_server(4096);
+        let mut manager = start_standalone().await.unwrap();
+        let save_dir = std::env::temp_dir().join("letrecovery_statuses");
+        let options = DownloadOptions {
+            paused: true,
+            skip_space_check: true,
+            ..Default::default()
+        };
+        let first = manager
+            .add_download_with_options(&url, save_dir.to_str().unwrap(), &options)
+            .await
+            .unwrap();
+        let second = manager
+            .add_download_with_options(&url, save_dir.to_str().unwrap(), &options)
+            .await
+            .unwrap();
+
+        let gids = vec![second.clone(), "0000000000000bad".to_string(), first.clone()];
+        let statuses = manager.get_statuses(&gids).await.unwrap();
+        let order: Vec<_> = statuses.iter().map(|p| p.gid.as_str()).collect();
+        assert_eq!(order, [second.as_str(), "0000000000000bad", first.as_str()]);
+        assert_eq!(statuses[0].status, DownloadStatus::Paused);
+        assert!(statuses[1].status.is_failed());
+        assert_eq!(statuses[2].status, DownloadStatus::Paused);
+        manager.shutdown().await.unwrap();
+        let _ = std::fs::remove_dir_all(&save_dir);
+    }
+
+    #[tokio::test]
+    #[ignore = "需要 bin/aria2c.exe"]
+    async fn test_retry_download_reuses_options() {
+        use std::io::{Read, Write};
+
+        // 第一次请求返回 404，之后正常返回数据
+        let listener = std::net::TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let url = format!("http://{}/file.bin", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for (i, mut stream) in listener.incoming().flatten().enumerate() {
+                let mut request = [0u8; 4096];
+                let _ = stream.read(&mut request);
+                let response: &[u8] = if i == 0 {
+                    b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                } else {
+                    b"HTTP/1.1 200 OK\r\nContent-Length: 4\r\nConnection: close\r\n\r\ndata"
+                };
+                let _ = stream.write_all(response);
+            }
+        });
+
+        let manager = start_standalone().await.unwrap();
+        let save_dir = std::env::temp_dir().join("letrecovery_retry");
+        let _ = std::fs::remove_dir_all(&save_dir);
+        let options = DownloadOptions {
+            filename: Some("retried.bin".to_string()),
+            skip_space_check: true,
+            ..Default::default()
+        };
+        let gid = manager
+            .add_download_with_options(&url, save_dir.to_str().unwrap(), &options)
+            .await
+            .unwrap();
+        wait_until_finished(&manager, &gid).await;
+
+        // 404 不会因重试而好转，默认拒绝
+        let refused = manager.retry_download(&gid, false).await.unwrap_err();
+        assert!(matches!(refused.downcast_ref(), Some(DownloadError::NotRetryable { .. })));
+
+        let retried = manager.retry_download(&gid, true).await.unwrap();
+        assert_ne!(retried, gid);
+        assert_eq!(wait_until_finished(&manager, &retried).await, DownloadStatus::Complete);
+        assert_eq!(std::fs::read(save_dir.join("retried.bin")).unwrap(), b"data");
+        let _ = std::fs::remove_dir_all(&save_dir);
+    }
+
+    #[test]
+    fn test_completed_size_error() {
+        assert_eq!(completed_size_error(None, true, 4096, Some(4096)), None);
+        assert_eq!(completed_size_error(None, true, 0, Some(100)), None);
+        assert_eq!(
+            completed_size_error(None, true, 4096, Some(1024)),
+            Some(DownloadErrorKind::TruncatedFile { expected: 4096, actual: 1024 })
+        );
+        assert_eq!(completed_size_error(None, false, 4096, Some(1024)), None);
+        assert_eq!(
+            completed_size_error(Some(2048), true, 1024, Some(1024)),
+            Some(DownloadErrorKind::SizeMismatch { expected: 2048, actual: 1024 })
+        );
+        // 读不到磁盘上的文件时只能与 total_length 比较
+        assert_eq!(completed_size_error(Some(2048), true, 2048, None), None);
+    }
+
+    #[test]
+    fn test_skip_length_check_keeps_expected_size() {
+        assert_eq!(SizeCheck::for_task(None, true), None);
+        assert_eq!(
+            SizeCheck::for_task(None, false),
+            Some(SizeCheck::Pending { expected: None, check_length: true })
+        );
+        let Some(SizeCheck::Pending { expected, check_length }) = SizeCheck::for_task(Some(2048), true) else {
+            panic!("跳过长度核对时仍应核对预期大小");
+        };
+        assert_eq!(
+            completed_size_error(expected, check_length, 4096, Some(1024)),
+            Some(DownloadErrorKind::SizeMismatch { expected: 2048, actual: 1024 })
+        );
+    }
+
+    #[tokio::test]
+    #[ignore = "需要 bin/aria2c.exe"]
+    async fn test_size_mismatch_marks_file_bad() {
+        let url = serving_http_server(1024);
+        let manager = start_standalone().await.unwrap();
+        let save_dir = std::env::temp_dir().join("letrecovery_size_mismatch");
+        let _ = std::fs::remove_dir_all(&save_dir);
+        let options = DownloadOptions {
+            filename: Some("short.bin".to_string()),
+            expected_size: Some(2048),
+            skip_space_check: true,
+            ..Default::default()
+        };
+        let gid = manager
+            .add_download_with_options(&url, save_dir.to_str().unwrap(), &options)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            wait_until_finished(&manager, &gid).await,
+            DownloadStatus::Error(DownloadErrorKind::SizeMismatch { expected: 2048, actual: 1024 })
+        );
+        assert!(!save_dir.join("short.bin").exists());
+        assert_eq!(std::fs::metadata(save_dir.join("short.bin.bad")).unwrap().len(), 1024);
+        let _ = std::fs::remove_dir_all(&save_dir);
+    }
+
+    #[tokio::test]
+    #[ignore = "需要 bin/aria2c.exe"]
+    async fn test_skip_length_check_still_checks_expected_size() {
+        let url = serving_http_server(1024);
+        let manager = start_standalone().await.unwrap();
+        let save_dir = std::env::temp_dir().join("letrecovery_skip_length_check");
+        let _ = std::fs::remove_dir_all(&save_dir);
+        let options = DownloadOptions {
+            filename: Some("short.bin".to_string()),
+            expected_size: Some(2048),
+            skip_space_check: true,
+            skip_length_check: true,
+            ..Default::default()
+        };
+        let gid = manager
+            .add_download_with_options(&url, save_dir.to_str().unwrap(), &options)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            wait_until_finished(&manager, &gid).await,
+            DownloadStatus::Error(DownloadErrorKind::SizeMismatch { expected: 2048, actual: 1024 })
+        );
+        let _ = std::fs::remove_dir_all(&save_dir);
+    }
+
+    #[tokio::test]
+    #[ignore = "需要 bin/aria2c.exe"]
+    async fn test_content_disposition_names_file() {
+        use std::io::{Read, Write};
+
+        let listener = std::net::TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let url = format!("http://{}/download?id=123", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let mut request = [0u8; 4096];
+                let _ = stream.read(&mut request);
+                let _ = stream.write_all(
+                    b"HTTP/1.1 200 OK\r\nContent-Length: 4\r\n\
+                      Content-Disposition: attachment; filename*=UTF-8''%E9%A9%B1%E5%8A%A8%3F.zip\r\n\
+                      Connection: close\r\n\r\ndata",
+                );
+            }
+        });
+
+        let manager = start_standalone().await.unwrap();
+        let save_dir = std::env::temp_dir().join("letrecovery_content_disposition");
+        let _ = std::fs::remove_dir_all(&save_dir);
+        let gid = manager.add_download(&url, save_dir.to_str().unwrap(), None).await.unwrap();
+        assert_eq!(wait_until_finished(&manager, &gid).await, DownloadStatus::Complete);
+
+        let expected = save_dir.join("驱动_.zip");
+        assert_eq!(manager.get_status(&gid).await.unwrap().file_path, Some(expected.clone()));
+        assert_eq!(manager.job(&gid).unwrap().file_name.as_deref(), Some("驱动_.zip"));
+        assert_eq!(std::fs::read(&expected).unwrap(), b"data");
+        let _ = std::fs::remove_dir_all(&save_dir);
+    }
+
+    async fn wait_until_finished(manager: &Aria2Manager, gid: &str) -> DownloadStatus {
+        let deadline = Instant::now() + Duration::from_secs(30);
+        loop {
+            let status = manager.get_status(gid).await.unwrap().status;
+            if status.is_finished() {
+                return status;
+            }
+            assert!(Instant::now() < deadline, "任务应在超时内结束");
+            tokio::time::sleep(Duration::from_millis(200)).await;
+        }
+    }
+
+    #[tokio::test]
+    #[ignore = "需要 bin/aria2c.exe"]
+    async fn test_chunked_download_reports_indeterminate_progress() {
+        use std::io::{Read, Write};
+
+        // 分块传输、不给出 Content-Length，约 3 秒内发完 30 块
+        let listener = std::net::TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let url = format!("http://{}/stream.bin", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let mut request = [0u8; 4096];
+                let _ = stream.read(&mut request);
+                let _ = stream.write_all(b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\nConnection: close\r\n\r\n");
+                for _ in 0..30 {
+                    let _ = stream.write_all(format!("{:x}\r\n", 1024).as_bytes());
+                    let _ = stream.write_all(&[0u8; 1024]);
+                    let _ = stream.write_all(b"\r\n");
+                    std::thread::sleep(Duration::from_millis(100));
+                }
+                let _ = stream.write_all(b"0\r\n\r\n");
+            }
+        });
+
+        let mut manager = start_standalone().await.unwrap();
+        let save_dir = std::env::temp_dir().join("letrecovery_chunked");
+        let _ = std::fs::remove_dir_all(&save_dir);
+        let options = DownloadOptions {
+            filename: Some("stream.bin".to_string()),
+            skip_space_check: true,
+            ..Default::default()
+        };
+        let gid = manager
+            .add_download_with_options(&url, save_dir.to_str().unwrap(), &options)
+            .await
+            .unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let mut saw_bytes = false;
+        while Instant::now() < deadline {
+            let progress = manager.get_status(&gid).await.unwrap();
+            if progress.status.is_finished() {
+                break;
+            }
+            if progress.completed_length > 0 {
+                assert_eq!(progress.percentage, None);
+                assert_eq!(progress.eta, None);
+                saw_bytes = true;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        assert!(saw_bytes, "下载过程中应能看到已下载的字节数");
+
+        assert_eq!(wait_until_finished(&manager, &gid).await, DownloadStatus::Complete);
+        let progress = manager.get_status(&gid).await.unwrap();
+        assert_eq!(progress.percentage, Some(100.0));
+        assert_eq!(std::fs::metadata(save_dir.join("stream.bin")).unwrap().len(), 30 * 1024);
+        manager.shutdown().await.unwrap();
+        let _ = std::fs::remove_dir_all(&save_dir);
+    }
+
+    #[tokio::test]
+    #[ignore = "需要 bin/aria2c.exe"]
+    async fn test_task_speed_limit_caps_speed() {
+        const CAP: u64 = 256 * 1024;
+        let url = serving_http_server(1 << 30);
+        let save_dir = std::env::temp_dir().join("letrecovery_task_limit");
+        let save_dir = save_dir.to_string_lossy();
+
+        let mut manager = start_standalone().await.unwrap();
+        let gid = manager.add_download(&url, &save_dir, Some("limited.bin")).await.unwrap();
+        manager.set_task_speed_limit(&gid, CAP).await.unwrap();
+
+        // 等待限速稳定后采样
+        tokio::time::sleep(std::time::Duration::from_secs(4)).await;
+        let speed = manager.get_status(&gid).await.unwrap().download_speed;
+        assert!(speed <= CAP + CAP / 5, "speed {} exceeds cap {}", speed, CAP);
+
+        manager.cancel(&gid).await.unwrap();
+        let err = manager.set_task_speed_limit("ffffffffffffffff", CAP).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<DownloadError>(),
+            Some(DownloadError::TaskNotActive { .. })
+        ));
+        manager.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore = "需要 bin/aria2c.exe"]
+    async fn test_pause_all_pauses_every_task() {
+        let (_server, url) = stalled_http_server();
+        let save_dir = std::env::temp_dir().join("letrecovery_pause_all");
+        let save_dir = save_dir.to_string_lossy();
+
+        let mut manager = start_standalone().await.unwrap();
+        let a = manager.add_download(&url, &save_dir, Some("a.bin")).await.unwrap();
+        let b = manager.add_download(&url, &save_dir, Some("b.bin")).await.unwrap();
+
+        assert_eq!(manager.pause_all().await.unwrap(), 2);
+        for gid in [&a, &b] {
+            let mut status = manager.get_status(gid).await.unwrap().status;
+            for _ in 0..20 {
+                if status == DownloadStatus::Paused {
+                    break;
+                }
+                tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+                status = manager.get_status(gid).await.unwrap().status;
+            }
+            assert_eq!(status, DownloadStatus::Paused);
+        }
+        assert_eq!(manager.resume_all().await.unwrap(), 2);
+
+        manager.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore = "需要 bin/aria2c.exe"]
+    async fn test_shutdown_is_graceful_and_releases_port() {
+        let mut manager = start_standalone().await.unwrap();
+        let port = manager.rpc_port();
+        let pid = manager.engine.process.lock().as_ref().map(Child::id).unwrap();
+
+        assert_eq!(manager.shutdown().await.unwrap(), ShutdownPath::Graceful);
+        assert!(is_port_free(port));
+        assert!(!is_pid_alive(pid), "shutdown 后 aria2c 进程 {} 仍在运行", pid);
+        // 重复关闭不应出错
+        assert_eq!(manager.shutdown().await.unwrap(), ShutdownPath::NotRunning);
+    }
+
+    #[test]
+    fn test_endpoint_staleness() {
+        let listener = std::net::TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let endpoint = |pid| RpcEndpoint {
+            port,
+            secret: "secret".to_string(),
+            pid,
+            started_at: 0,
+        };
+        assert!(!endpoint(std::process::id()).is_stale());
+        // 进程在文件写入之后才启动：PID 被复用
+        let reused = RpcEndpoint {
+            started_at: 1,
+            ..endpoint(std::process::id())
+        };
+        assert!(reused.is_stale());
+        let current = RpcEndpoint {
+            started_at: JobStore::now() + 1,
+            ..endpoint(std::process::id())
+        };
+        assert!(!current.is_stale());
+        // 旧版本的文件没有 pid，只按端口判断
+        assert!(!endpoint(0).is_stale());
+        assert!(endpoint(u32::MAX - 1).is_stale());
+        drop(listener);
+        assert!(endpoint(std::process::id()).is_stale());
+
+        let old: RpcEndpoint = serde_json::from_str(r#"{"port":6800,"secret":"s"}"#).unwrap();
+        assert_eq!((old.pid, old.started_at), (0, 0));
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_port_free() {
+        let listener = std::net::TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        assert!(!wait_for_port_free(port, Duration::from_millis(300)).await);
+
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(200));
+            drop(listener);
+        });
+        assert!(wait_for_port_free(port, Duration::from_secs(3)).await);
+    }
+
+    #[tokio::test]
+    #[ignore = "需要 bin/aria2c.exe"]
+    async fn test_restart_reuses_port() {
+        let mut manager = Aria2Manager::start_with(Aria2Config {
+            attach_to_running: false,
+            rpc_port: Some(6899),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        for _ in 0..3 {
+            manager.restart().await.unwrap();
+            assert_eq!(manager.rpc_port(), 6899);
+            assert!(manager.get_global_stat().await.is_ok());
+        }
+        manager.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore = "需要 bin/aria2c.exe"]
+    async fn test_drop_kills_aria2c() {
+        let manager = start_standalone().await.unwrap();
+        let port = manager.rpc_port();
+        let pid = manager.engine.process.lock().as_ref().map(Child::id).unwrap();
+
+        drop(manager);
+        assert!(is_port_free(port));
+        assert!(!is_pid_alive(pid), "管理器释放后 aria2c 进程 {} 仍在运行", pid);
+    }
+
+    #[tokio::test]
+    #[ignore = "需要 bin/aria2c.exe"]
+    async fn test_watchdog_restarts_crashed_aria2() {
+        let (_server, url) = stalled_http_server();
+        let save_dir = std::env::temp_dir().join("letrecovery_watchdog");
+        let save_dir = save_dir.to_string_lossy();
+
+        let mut manager = start_standalone().await.unwrap();
+        let gid = manager.add_download(&url, &save_dir, Some("crash.bin")).await.unwrap();
+        manager.client().unwrap().save_session().await.unwrap();
+
+        // 模拟 aria2c 崩溃
+        manager.engine.kill_process();
+        for _ in 0..50 {
+            if manager.restart_count() > 0 {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+        }
+        assert_eq!(manager.restart_count(), 1);
+        assert!(manager.get_status(&gid).await.is_ok());
+
+        manager.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore = "需要 bin/aria2c.exe"]
+    async fn test_failed_batch_leaves_nothing_behind() {
+        let url = serving_http_server(64 * 1024);
+        let save_dir = std::env::temp_dir().join("letrecovery_batch_rollback");
+        let _ = std::fs::remove_dir_all(&save_dir);
+        std::fs::create_dir_all(&save_dir).unwrap();
+        // 保存目录是一个普通文件，第二项添加时无法写入
+        let not_a_dir = save_dir.join("not_a_dir");
+        std::fs::write(&not_a_dir, b"x").unwrap();
+
+        let manager = start_standalone().await.unwrap();
+        let jobs_before = manager.jobs().len();
+        let item = |dir: &Path, name: &str| DownloadRequest {
+            url: url.clone(),
+            save_dir: dir.to_string_lossy().to_string(),
+            filename: Some(name.to_string()),
+            paused: true,
+            expected_size: None,
+        };
+        let items = [item(&save_dir, "first.bin"), item(&not_a_dir, "second.bin")];
+        assert!(manager.add_downloads(&items).await.is_err());
+
+        assert_eq!(manager.jobs().len(), jobs_before);
+        assert!(manager.requests.lock().is_empty());
+        assert!(manager.renames.lock().is_empty());
+        assert!(manager.timings.lock().is_empty());
+        assert!(manager.engine.size_checks.lock().is_empty());
+        assert!(manager.engine.mirrors.lock().is_empty());
+        let _ = std::fs::remove_dir_all(&save_dir);
+    }
+
+    #[tokio::test]
+    #[ignore = "需要 bin/aria2c.exe"]
+    async fn test_purged_gid_is_unknown() {
+        let url = serving_http_server(64 * 1024);
+        let save_dir = std::env::temp_dir().join("letrecovery_purge");
+        let save_dir = save_dir.to_string_lossy();
+
+        let mut manager = Aria2Manager::start_with(Aria2Config {
+            attach_to_running: false,
+            auto_remove_results: true,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        let gid = manager.add_download(&url, &save_dir, Some("small.bin")).await.unwrap();
+        let mut status = manager.get_status(&gid).await.unwrap().status;
+        for _ in 0..50 {
+            if status == DownloadStatus::Complete {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+            status = manager.get_status(&gid).await.unwrap().status;
+        }
+        assert_eq!(status, DownloadStatus::Complete);
+
+        // 观察到完成后结果已被自动清理，任务元数据保留
+        let err = manager.get_status(&gid).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<DownloadError>(),
+            Some(DownloadError::UnknownGid { .. })
+        ));
+        assert_eq!(manager.job(&gid).unwrap().state, JobState::Complete);
+        manager.purge_results().await.unwrap();
+        assert!(manager.job(&gid).is_some());
+
+        manager.purge_job(&gid).await.unwrap();
+        assert!(manager.job(&gid).is_none());
+        manager.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore = "需要 bin/aria2c.exe"]
+    async fn test_second_instance_attaches_without_killing() {
+        let mut first = start_standalone().await.unwrap();
+        let mut second = Aria2Manager::start().await.unwrap();
+        assert!(first.owns_process());
+        assert!(!second.owns_process());
+        assert_eq!(second.rpc_port(), first.rpc_port());
+
+        // 附加方关闭只断开连接，不影响第一个实例
+        assert_eq!(second.shutdown().await.unwrap(), ShutdownPath::Detached);
+        assert!(first.get_global_stat().await.is_ok());
+        first.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore = "需要 bin/aria2c.exe"]
+    async fn test_session_restores_unfinished_tasks() {
+        let (_server, url) = stalled_http_server();
+        let save_dir = std::env::temp_dir().join("letrecovery_session");
+        let save_dir = save_dir.to_string_lossy();
+
+        let mut manager = start_standalone().await.unwrap();
+        let gid = manager.add_download(&url, &save_dir, Some("resume.bin")).await.unwrap();
+        manager.shutdown().await.unwrap();
+
+        // 重启后任务从会话文件恢复，gid 不变
+        let mut manager = start_standalone().await.unwrap();
+        assert!(manager.restored_tasks().contains(&gid));
+        for gid in manager.restored_tasks().to_vec() {
+            manager.cancel(&gid).await.unwrap();
+        }
+        manager.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore = "需要 bin/aria2c.exe"]
+    async fn test_metalink_creates_gid_per_file() {
+        let (_server, url) = stalled_http_server();
+        let base = url.trim_end_matches("/file.bin");
+
+        let dir = std::env::temp_dir().join("letrecovery_metalink");
+        std::fs::create_dir_all(&dir).unwrap();
+        let metalink = dir.join("two_files.meta4");
+        std::fs::write(
+            &metalink,
+            format!(
+                r#"<?xml version="1.0" encoding="UTF-8"?>
+<metalink xmlns="urn:ietf:params:xml:ns:metalink">
+  <file name="boot.wim"><url>{base}/boot.wim</url></file>
+  <file name="install.swm"><url>{base}/install.swm</url></file>
+</metalink>"#
+            ),
+        )
+        .unwrap();
+
+        let mut manager = start_standalone().await.unwrap();
+        let gids = manager
+            .add_metalink(&metalink, &dir.to_string_lossy())
+            .await
+            .unwrap();
+        assert_eq!(gids.len(), 2);
+        for gid in &gids {
+            assert_eq!(manager.get_status(gid).await.unwrap().gid, *gid);
+        }
+
+        manager.shutdown().await.unwrap();
+    }
+
+    #[test]
+    fn test_generate_rpc_secret() {
+        let a = generate_rpc_secret().unwrap();
+        let b = generate_rpc_secret().unwrap();
+        assert_eq!(a.len(), 32);
+        assert!(a.chars().all(|c| c.is_ascii_hexdigit()));
+        assert_ne!(a, b);
+        assert_eq!(generate_gid().unwrap().len(), 16);
     }
 }
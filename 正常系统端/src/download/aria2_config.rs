@@ -0,0 +1,309 @@
+//! aria2c 启动参数配置
+//!
+//! 未设置的项使用与之前硬编码参数相同的默认值，例如：
+//!
+//! ```ignore
+//! let config = Aria2Config {
+//!     split: 8,                    // 机械硬盘上减少分片
+//!     max_concurrent_downloads: 1, // 按流量计费的网络
+//!     ..Default::default()
+//! };
+//! let manager = Aria2Manager::start_with(config).await?;
+//! ```
+
+use anyhow::Result;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use super::schedule::BandwidthSchedule;
+
+/// 文件预分配方式（aria2 `--file-allocation`）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FileAllocation {
+    /// 不预分配（默认，启动最快）
+    #[default]
+    None,
+    /// 写满零预分配，慢但兼容所有文件系统
+    Prealloc,
+    /// 截断到目标大小
+    Trunc,
+    /// 调用文件系统的快速预分配（NTFS/ext4 等）
+    Falloc,
+}
+
+impl FileAllocation {
+    /// aria2 选项值
+    pub fn aria2_value(&self) -> &'static str {
+        match self {
+            FileAllocation::None => "none",
+            FileAllocation::Prealloc => "prealloc",
+            FileAllocation::Trunc => "trunc",
+            FileAllocation::Falloc => "falloc",
+        }
+    }
+}
+
+/// 运行时切换的下载档位（`Aria2Manager::apply_profile`），一次设置并发数、分片数和全局限速
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DownloadProfile {
+    /// 尽快下载：与默认配置相同，不限速
+    Fast,
+    /// 后台下载：少量并发和分片，并限制总速度，不影响用户正常上网
+    Background,
+}
+
+impl DownloadProfile {
+    /// 同时进行的最大任务数
+    pub fn max_concurrent_downloads(&self) -> u32 {
+        match self {
+            DownloadProfile::Fast => 5,
+            DownloadProfile::Background => 2,
+        }
+    }
+
+    /// 新任务的分片数（已开始的任务不变）
+    pub fn split(&self) -> u32 {
+        match self {
+            DownloadProfile::Fast => 32,
+            DownloadProfile::Background => 4,
+        }
+    }
+
+    /// 全局下载限速（字节/秒），0 表示不限速
+    pub fn overall_speed_limit(&self) -> u64 {
+        match self {
+            DownloadProfile::Fast => 0,
+            DownloadProfile::Background => 2 * 1024 * 1024,
+        }
+    }
+}
+
+/// HTTPS 证书校验设置（全局见 `Aria2Config::tls`，单个任务见 `DownloadOptions::tls`）
+///
+/// 公司网络中做 TLS 解密的代理会用自己的根证书重新签发，此时应通过 `ca_certificate` 提供该根证书；
+/// `allow_insecure_tls` 完全关闭校验，下载内容可能被篡改，只作为最后手段。
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TlsOptions {
+    /// 额外信任的 CA 证书文件（PEM），对应 aria2 `--ca-certificate`
+    pub ca_certificate: Option<PathBuf>,
+    /// 不校验服务器证书（`--check-certificate=false`），每次使用都会记录警告
+    pub allow_insecure_tls: bool,
+}
+
+impl TlsOptions {
+    /// 对应的 aria2 选项（总是包含 `check-certificate`，使附加的 aria2c 行为一致）
+    pub fn aria2_options(&self) -> Vec<(&'static str, String)> {
+        let mut options = vec![("check-certificate", (!self.allow_insecure_tls).to_string())];
+        if let Some(ca) = &self.ca_certificate {
+            options.push(("ca-certificate", ca.display().to_string()));
+        }
+        options
+    }
+
+    /// 关闭校验时记录警告，`context` 说明在哪里使用
+    pub fn warn_if_insecure(&self, context: &str) {
+        if self.allow_insecure_tls {
+            log::warn!("[aria2] ⚠ 已关闭 HTTPS 证书校验（{}），下载内容可能被中间人篡改", context);
+        }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if let Some(ca) = self.ca_certificate.as_ref().filter(|ca| !ca.is_file()) {
+            anyhow::bail!("CA 证书文件不存在: {}", ca.display());
+        }
+        Ok(())
+    }
+}
+
+/// aria2c 启动配置
+#[derive(Debug, Clone)]
+pub struct Aria2Config {
+    /// aria2c 可执行文件路径；None 时依次查找 bin 目录和 PATH
+    pub aria2c_path: Option<PathBuf>,
+    /// RPC 端口；None 时在 6800-6900 中自动选择第一个空闲端口
+    pub rpc_port: Option<u16>,
+    /// RPC 密钥；None 时随机生成
+    pub rpc_secret: Option<String>,
+    /// 单个文件的分片数（`--split`）
+    pub split: u32,
+    /// 每个服务器的最大连接数（`--max-connection-per-server`，aria2 上限 16）
+    pub max_connection_per_server: u32,
+    /// 已知大小（`DownloadOptions::expected_size` 或 HEAD 探测）小于此值的任务不分片（split=1），
+    /// 避免大量小文件（如驱动）反复建立连接被服务器封禁；0 表示不按大小调整
+    pub small_file_threshold: u64,
+    /// 同时进行的最大任务数（`--max-concurrent-downloads`）
+    pub max_concurrent_downloads: u32,
+    /// 最小分片大小（`--min-split-size`，如 "1M"）
+    pub min_split_size: String,
+    /// 文件预分配方式
+    pub file_allocation: FileAllocation,
+    /// 断点续传（`--continue`）
+    pub continue_downloads: bool,
+    /// 默认 User-Agent（`--user-agent`），任务未单独设置时使用
+    pub user_agent: String,
+    /// 速度不高于该值（字节/秒）时 aria2 断开连接换源重试（`lowest-speed-limit`），0 表示不限制
+    pub lowest_speed_limit: u64,
+    /// 网络读写超时（`timeout`）
+    pub timeout: Duration,
+    /// 建立连接超时（`connect-timeout`）
+    pub connect_timeout: Duration,
+    /// 活动任务的已下载量持续这么久没有增长时，由管理器暂停并恢复该任务；None 表示不检测
+    pub stall_timeout: Option<Duration>,
+    /// 镜像轮换任务（`add_download_with_mirrors`）中每个镜像最多尝试的次数
+    pub mirror_attempts_per_url: u32,
+    /// 镜像轮换任务所有镜像合计最多尝试的次数
+    pub mirror_max_attempts: u32,
+    /// `get_status` 观察到任务完成后自动清理其在 aria2 中的结果（默认关闭，保持原有行为）
+    pub auto_remove_results: bool,
+    /// 启动前先尝试附加到本程序另一个实例正在使用的 aria2c
+    pub attach_to_running: bool,
+    /// 要求 aria2c 支持 BitTorrent（`add_torrent` / 磁力链接），不支持时启动失败
+    pub require_bittorrent: bool,
+    /// 添加任务前检查磁盘空间时，在文件大小之外额外要求的剩余空间（字节）
+    pub disk_space_margin: u64,
+    /// 等待 RPC 服务就绪的总时长
+    pub rpc_connect_timeout: Duration,
+    /// 等待 RPC 服务就绪时的重试间隔
+    pub rpc_connect_interval: Duration,
+    /// HTTPS 证书校验设置，可被单个任务的 `DownloadOptions::tls` 覆盖
+    pub tls: TlsOptions,
+    /// 单次 RPC 调用的超时（aria2c 卡死时调用方不会一直等待）；连续多次超时后看门狗会重启 aria2c
+    pub rpc_call_timeout: Duration,
+    /// 按时段限速或暂停的规则，运行中可用 `Aria2Manager::set_bandwidth_schedule` 替换
+    pub bandwidth_schedule: BandwidthSchedule,
+}
+
+impl Default for Aria2Config {
+    fn default() -> Self {
+        Self {
+            aria2c_path: None,
+            rpc_port: None,
+            rpc_secret: None,
+            split: 32,
+            max_connection_per_server: 16,
+            small_file_threshold: 8 * 1024 * 1024,
+            max_concurrent_downloads: 5,
+            min_split_size: "1M".to_string(),
+            file_allocation: FileAllocation::None,
+            continue_downloads: true,
+            user_agent: format!("LetRecovery/{}", env!("CARGO_PKG_VERSION")),
+            lowest_speed_limit: 1024,
+            timeout: Duration::from_secs(60),
+            connect_timeout: Duration::from_secs(30),
+            stall_timeout: Some(Duration::from_secs(120)),
+            mirror_attempts_per_url: 2,
+            mirror_max_attempts: 8,
+            auto_remove_results: false,
+            attach_to_running: true,
+            require_bittorrent: true,
+            disk_space_margin: 500 * 1024 * 1024,
+            rpc_connect_timeout: Duration::from_secs(6),
+            rpc_connect_interval: Duration::from_millis(200),
+            // 磁盘繁忙时 aria2 响应会变慢，留足余量
+            rpc_call_timeout: Duration::from_secs(15),
+            tls: TlsOptions::default(),
+            bandwidth_schedule: BandwidthSchedule::default(),
+        }
+    }
+}
+
+impl Aria2Config {
+    /// 检查取值是否在 aria2 接受的范围内，避免 aria2c 启动即退出时只能看到连接超时
+    pub fn validate(&self) -> Result<()> {
+        if self.split == 0 {
+            anyhow::bail!("split 不能为 0");
+        }
+        if !(1..=16).contains(&self.max_connection_per_server) {
+            anyhow::bail!(
+                "max_connection_per_server 必须在 1-16 之间，当前为 {}",
+                self.max_connection_per_server
+            );
+        }
+        if self.max_concurrent_downloads == 0 {
+            anyhow::bail!("max_concurrent_downloads 不能为 0");
+        }
+        if self.timeout.as_secs() == 0 || self.connect_timeout.as_secs() == 0 {
+            anyhow::bail!("timeout 和 connect_timeout 至少为 1 秒");
+        }
+        if self.stall_timeout.is_some_and(|t| t.is_zero()) {
+            anyhow::bail!("stall_timeout 不能为 0，不检测请设为 None");
+        }
+        if self.mirror_attempts_per_url == 0 || self.mirror_max_attempts == 0 {
+            anyhow::bail!("mirror_attempts_per_url 和 mirror_max_attempts 不能为 0");
+        }
+        if self.rpc_connect_interval.is_zero() || self.rpc_call_timeout.is_zero() {
+            anyhow::bail!("rpc_connect_interval 和 rpc_call_timeout 不能为 0");
+        }
+        self.tls.validate()?;
+        self.bandwidth_schedule.validate()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        assert!(Aria2Config::default().validate().is_ok());
+
+        let config = Aria2Config {
+            max_connection_per_server: 32,
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+
+        let config = Aria2Config {
+            split: 0,
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+
+        let config = Aria2Config {
+            stall_timeout: Some(Duration::ZERO),
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+
+        let config = Aria2Config {
+            rpc_call_timeout: Duration::ZERO,
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+
+        let config = Aria2Config {
+            tls: TlsOptions {
+                ca_certificate: Some(PathBuf::from("no_such_root_ca.pem")),
+                allow_insecure_tls: false,
+            },
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_tls_aria2_options() {
+        assert_eq!(TlsOptions::default().aria2_options(), [("check-certificate", "true".to_string())]);
+        let tls = TlsOptions {
+            ca_certificate: Some(PathBuf::from("root.pem")),
+            allow_insecure_tls: true,
+        };
+        assert_eq!(
+            tls.aria2_options(),
+            [("check-certificate", "false".to_string()), ("ca-certificate", "root.pem".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_fast_profile_matches_defaults() {
+        let config = Aria2Config::default();
+        let fast = DownloadProfile::Fast;
+        assert_eq!(fast.max_concurrent_downloads(), config.max_concurrent_downloads);
+        assert_eq!(fast.split(), config.split);
+        assert_eq!(fast.overall_speed_limit(), 0);
+
+        let background = DownloadProfile::Background;
+        assert!(background.max_concurrent_downloads() < fast.max_concurrent_downloads());
+        assert!(background.overall_speed_limit() > 0);
+    }
+}
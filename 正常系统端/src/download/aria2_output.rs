@@ -0,0 +1,131 @@
+//! aria2c 进程输出的采集
+//!
+//! aria2c 启动失败（参数错误、端口冲突、缺少 DLL）时只能从它自己的输出看出原因。
+//! 这里把 stdout/stderr 逐行写入日志（前缀 `[aria2c]`），并保留最近的若干行，
+//! 供启动失败时附在错误信息中。
+
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Read};
+use std::process::Child;
+use std::sync::Arc;
+
+use crate::utils::encoding::gbk_to_utf8;
+
+/// 保留的最近输出行数
+const TAIL_LINES: usize = 200;
+
+/// aria2c 最近的输出（重启后的新进程继续写入同一个缓冲）
+#[derive(Debug, Clone, Default)]
+pub struct OutputTail {
+    lines: Arc<parking_lot::Mutex<VecDeque<String>>>,
+}
+
+impl OutputTail {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 取走子进程的 stdout/stderr，在后台线程逐行读取
+    ///
+    /// 读取线程在进程退出（管道关闭）后自动结束，不需要也不会阻塞关闭流程。
+    pub fn capture(&self, child: &mut Child) {
+        if let Some(stdout) = child.stdout.take() {
+            self.spawn_reader("aria2c-stdout", stdout, false);
+        }
+        if let Some(stderr) = child.stderr.take() {
+            self.spawn_reader("aria2c-stderr", stderr, true);
+        }
+    }
+
+    fn spawn_reader<R: Read + Send + 'static>(&self, name: &str, reader: R, is_stderr: bool) {
+        let tail = self.clone();
+        let spawned = std::thread::Builder::new()
+            .name(name.to_string())
+            .spawn(move || tail.read_lines(reader, is_stderr));
+        if let Err(e) = spawned {
+            log::warn!("[aria2] 无法启动输出读取线程: {}", e);
+        }
+    }
+
+    /// 读取到 EOF 为止；stderr 的内容按警告记录
+    fn read_lines<R: Read>(&self, reader: R, is_stderr: bool) {
+        let mut reader = BufReader::new(reader);
+        let mut buf = Vec::new();
+        loop {
+            buf.clear();
+            match reader.read_until(b'\n', &mut buf) {
+                Ok(0) | Err(_) => break,
+                Ok(_) => {}
+            }
+            let line = decode_line(&buf);
+            if line.is_empty() {
+                continue;
+            }
+            if is_stderr {
+                log::warn!("[aria2c] {}", line);
+            } else {
+                log::debug!("[aria2c] {}", line);
+            }
+            self.push(line);
+        }
+    }
+
+    fn push(&self, line: String) {
+        let mut lines = self.lines.lock();
+        if lines.len() == TAIL_LINES {
+            lines.pop_front();
+        }
+        lines.push_back(line);
+    }
+
+    /// 最近的输出（最多 200 行），按时间先后排列
+    pub fn recent(&self) -> Vec<String> {
+        self.lines.lock().iter().cloned().collect()
+    }
+
+    /// 在错误上附加最近的输出；没有输出时原样返回
+    pub fn attach_to(&self, error: anyhow::Error) -> anyhow::Error {
+        let lines = self.recent();
+        if lines.is_empty() {
+            return error;
+        }
+        error.context(format!("aria2c 启动失败，最近的输出:\n{}", lines.join("\n")))
+    }
+}
+
+/// aria2c 在中文系统上按控制台代码页（GBK）输出非 ASCII 的文件名，不是合法 UTF-8 时按 GBK 解码
+fn decode_line(bytes: &[u8]) -> String {
+    let text = match std::str::from_utf8(bytes) {
+        Ok(text) => text.to_string(),
+        Err(_) => gbk_to_utf8(bytes),
+    };
+    text.trim_end_matches(['\r', '\n']).trim_end().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_lines_keeps_tail() {
+        let tail = OutputTail::new();
+        let output: String = (0..250).map(|i| format!("line {}\r\n", i)).collect();
+        tail.read_lines(output.as_bytes(), false);
+
+        let lines = tail.recent();
+        assert_eq!(lines.len(), TAIL_LINES);
+        assert_eq!(lines[0], "line 50");
+        assert_eq!(lines.last().unwrap(), "line 249");
+    }
+
+    #[test]
+    fn test_read_lines_skips_blank_and_decodes_gbk() {
+        let tail = OutputTail::new();
+        // "下载" 的 GBK 编码
+        let mut output = b"\r\n\nException: ".to_vec();
+        output.extend_from_slice(&[0xCF, 0xC2, 0xD4, 0xD8]);
+        output.extend_from_slice(b"\n");
+        tail.read_lines(output.as_slice(), true);
+        assert_eq!(tail.recent(), ["Exception: 下载"]);
+    }
+}
@@ -0,0 +1,232 @@
+//! 下载模块的类型化错误
+//!
+//! 与 `core::ghost::GhostError` 相同的用法：函数仍返回 `anyhow::Result`，
+//! 需要区分错误类型的调用方通过 `downcast_ref::<DownloadError>()` 判断。
+
+/// 下载操作错误
+#[derive(Debug, thiserror::Error)]
+pub enum DownloadError {
+    #[error("任务 {gid} 当前不在下载中（{state}），无法修改")]
+    TaskNotActive { gid: String, state: String },
+
+    /// 调整队列顺序时任务不在等待队列中（下载中或已结束的任务不能移动）
+    #[error("任务 {gid} 不在等待队列中（{state}），无法调整顺序")]
+    NotQueued { gid: String, state: String },
+
+    /// aria2 中没有该 gid（从未添加，或结果已被 `remove_result`/`purge_results` 清理）
+    #[error("未知的下载任务: {gid}")]
+    UnknownGid { gid: String },
+
+    /// 找不到 aria2c 可执行文件，`checked` 为依次查找过的位置
+    #[error("未找到 aria2c，已查找: {}", format_checked(checked))]
+    Aria2NotFound { checked: Vec<String> },
+
+    /// 自定义请求头无效（只包含名称，不包含可能是凭据的值）
+    #[error("请求头 {name} 无效: {reason}")]
+    InvalidHeader { name: String, reason: String },
+
+    /// 保存目录所在卷的剩余空间不足（`required` 已包含安全余量），`volume` 为盘符如 "C:" 或共享根目录
+    #[error(
+        "{volume} 剩余空间不足：需要 {} MB，可用 {} MB",
+        .required / (1024 * 1024),
+        .available / (1024 * 1024)
+    )]
+    InsufficientDiskSpace { volume: String, required: u64, available: u64 },
+
+    /// 保存目录是网络共享，但当前用户无权写入（未登录该共享或凭据错误）
+    #[error("拒绝访问网络路径 {path}，请确认已登录该共享并有写入权限")]
+    NetworkPathAccessDenied { path: String },
+
+    /// 保存目录是网络共享，但服务器连不上或共享不存在
+    #[error("无法访问网络路径 {path}: {reason}")]
+    NetworkPathUnavailable { path: String, reason: String },
+
+    /// 下载完成后改名时目标文件已存在（`OnConflict::Error`）
+    #[error("目标文件已存在: {path}")]
+    TargetExists { path: String },
+
+    /// 批量操作（如下载组的暂停/取消）中部分任务失败，其余任务已正常处理
+    #[error("{}/{total} 个任务操作失败: {}", failed.len(), format_failed(failed))]
+    PartialFailure { failed: Vec<(String, String)>, total: usize },
+
+    /// aria2c 版本过旧或缺少必需的编译特性（常见于用户替换了自带的 aria2c.exe）
+    #[error("aria2c {version} 不受支持：{reason}，请使用程序自带的 aria2c.exe")]
+    UnsupportedAria2 { version: String, reason: String },
+
+    /// 添加任务前的连通性检查：无法解析主机名，通常是没有联网（`host` 为下载服务器或代理服务器）
+    #[error("无法解析 {host}，请检查网络连接")]
+    Offline { host: String },
+
+    /// 添加任务前的连通性检查：主机名可以解析，但在超时内连不上
+    #[error("无法连接到 {host}: {reason}")]
+    HostUnreachable { host: String, reason: String },
+
+    /// `retry_download` 拒绝重试：任务未失败、没有原始参数，或失败原因重试也无法解决
+    #[error("任务 {gid} 无法重试: {reason}")]
+    NotRetryable { gid: String, reason: String },
+
+    /// RPC 调用在 `Aria2Config::rpc_call_timeout` 内没有返回（aria2c 卡死或磁盘长时间繁忙）
+    ///
+    /// 调用可能已经被 aria2 执行，不会自动重放。
+    #[error("aria2 在 {timeout_secs} 秒内没有响应")]
+    RpcTimeout { timeout_secs: u64 },
+
+    /// 与 aria2 的 RPC 连接断开，重连并重放一次后仍失败（不是任务本身的错误）
+    #[error("与 aria2 的连接已断开，重连后仍失败: {reason}")]
+    ConnectionLost { reason: String },
+}
+
+fn disk_full_message(volume: &str, needed: u64, available: u64) -> String {
+    if volume.is_empty() {
+        return "磁盘空间不足".to_string();
+    }
+    format!(
+        "{} 磁盘空间不足：还需要 {} MB，可用 {} MB，请清理空间或换一个保存位置",
+        volume,
+        needed / (1024 * 1024),
+        available / (1024 * 1024)
+    )
+}
+
+fn format_checked(checked: &[String]) -> String {
+    checked.join("; ")
+}
+
+fn format_failed(failed: &[(String, String)]) -> String {
+    failed
+        .iter()
+        .map(|(gid, reason)| format!("{}: {}", gid, reason))
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// 下载失败原因（由 aria2 的数字 errorCode 映射，不依赖可能被本地化的错误文本）
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, thiserror::Error)]
+pub enum DownloadErrorKind {
+    /// 网络错误或域名解析失败
+    #[error("网络不可达")]
+    NetworkUnreachable,
+
+    /// 连接超时或速度长时间低于下限
+    #[error("连接超时")]
+    Timeout,
+
+    /// 服务器返回资源不存在
+    #[error("资源不存在")]
+    NotFound,
+
+    /// 下载过程中保存位置所在的卷已满：`volume` 为盘符（如 "D:"），`needed` 为剩余未下载的字节数，
+    /// `available` 为出错时的剩余空间；无法读取时 `volume` 为空、数值为 0
+    #[error("{}", disk_full_message(volume, *needed, *available))]
+    DiskFull { volume: String, needed: u64, available: u64 },
+
+    #[error("文件校验失败：哈希值与预期不匹配")]
+    ChecksumMismatch,
+
+    /// aria2 报告完成，但文件大小与预期不符（镜像提供了截断或错误的文件），文件已改名为 `.bad`
+    #[error("文件大小与预期不符：预期 {expected} 字节，实际 {actual} 字节")]
+    SizeMismatch { expected: u64, actual: u64 },
+
+    /// aria2 报告完成，但磁盘上的文件比它记录的总大小短（通常是异常中断后续传出错），不会改名为最终文件
+    #[error("文件不完整：应有 {expected} 字节，磁盘上只有 {actual} 字节")]
+    TruncatedFile { expected: u64, actual: u64 },
+
+    /// 无法创建/打开本地文件或目录
+    #[error("没有写入权限，无法创建文件或目录")]
+    InsufficientPermissions,
+
+    /// 服务器要求鉴权或拒绝访问（HTTP 401/403），通常是 Cookie 或令牌已过期
+    #[error("服务器拒绝访问，登录状态可能已过期，请重新登录后再下载")]
+    Unauthorized,
+
+    /// 其它错误：(aria2 错误码, 错误信息)；错误码 0 表示不是 aria2 报告的错误
+    #[error("{1}")]
+    Other(u32, String),
+}
+
+impl DownloadErrorKind {
+    /// 由 aria2 `tellStatus` 的 errorCode / errorMessage 映射
+    ///
+    /// 错误码含义见 aria2 文档 EXIT STATUS 一节。
+    pub fn from_aria2(code: Option<&str>, message: Option<&str>) -> Self {
+        let code: u32 = code.and_then(|c| c.trim().parse().ok()).unwrap_or(1);
+        match code {
+            2 | 5 => DownloadErrorKind::Timeout,
+            3 | 4 => DownloadErrorKind::NotFound,
+            6 | 19 => DownloadErrorKind::NetworkUnreachable,
+            9 => DownloadErrorKind::DiskFull {
+                volume: String::new(),
+                needed: 0,
+                available: 0,
+            },
+            15 | 16 | 18 => DownloadErrorKind::InsufficientPermissions,
+            24 => DownloadErrorKind::Unauthorized,
+            32 => DownloadErrorKind::ChecksumMismatch,
+            // 22: 响应状态异常，403 也归在这里，只能从信息中的状态码区分
+            22 if message.is_some_and(|m| m.contains("status=401") || m.contains("status=403")) => {
+                DownloadErrorKind::Unauthorized
+            }
+            _ => {
+                let message = match message.map(str::trim) {
+                    Some(m) if !m.is_empty() => m.to_string(),
+                    _ => format!("aria2 错误码 {}", code),
+                };
+                DownloadErrorKind::Other(code, message)
+            }
+        }
+    }
+
+    /// 重试是否可能成功（网络类的临时故障）
+    ///
+    /// 资源不存在、磁盘已满、校验失败等重试也不会改变结果，返回 false。
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            DownloadErrorKind::NetworkUnreachable | DownloadErrorKind::Timeout => true,
+            // 换个镜像或重新下载通常就能得到完整的文件
+            DownloadErrorKind::SizeMismatch { .. } => true,
+            // 29: 服务器过载（HTTP 503）
+            DownloadErrorKind::Other(code, _) => *code == 29,
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_aria2_error_code() {
+        assert_eq!(DownloadErrorKind::from_aria2(Some("6"), None), DownloadErrorKind::NetworkUnreachable);
+        assert_eq!(DownloadErrorKind::from_aria2(Some("3"), Some("x")), DownloadErrorKind::NotFound);
+        assert!(matches!(DownloadErrorKind::from_aria2(Some("9"), None), DownloadErrorKind::DiskFull { .. }));
+        assert_eq!(DownloadErrorKind::from_aria2(Some("32"), None), DownloadErrorKind::ChecksumMismatch);
+        assert_eq!(
+            DownloadErrorKind::from_aria2(Some("24"), Some("Authorization failed.")),
+            DownloadErrorKind::Unauthorized
+        );
+        assert_eq!(
+            DownloadErrorKind::from_aria2(Some("22"), Some("The response status is not successful. status=403")),
+            DownloadErrorKind::Unauthorized
+        );
+        assert_eq!(
+            DownloadErrorKind::from_aria2(Some("22"), Some("status=500")),
+            DownloadErrorKind::Other(22, "status=500".to_string())
+        );
+        // 缺少错误码按 aria2 的「未知错误」(1) 处理
+        assert_eq!(
+            DownloadErrorKind::from_aria2(None, None),
+            DownloadErrorKind::Other(1, "aria2 错误码 1".to_string())
+        );
+    }
+
+    #[test]
+    fn test_is_retryable() {
+        assert!(DownloadErrorKind::Timeout.is_retryable());
+        assert!(DownloadErrorKind::NetworkUnreachable.is_retryable());
+        assert!(DownloadErrorKind::Other(29, String::new()).is_retryable());
+        assert!(DownloadErrorKind::SizeMismatch { expected: 2, actual: 1 }.is_retryable());
+        assert!(!DownloadErrorKind::NotFound.is_retryable());
+        assert!(!DownloadErrorKind::ChecksumMismatch.is_retryable());
+    }
+}
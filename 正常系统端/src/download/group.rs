@@ -0,0 +1,192 @@
+//! 下载组：把多个 gid 作为一个整体查询进度和控制
+//!
+//! 例如安装某个 Windows 版本需要同时下载 boot.wim、若干 .swm 分卷和驱动包，
+//! 界面上只显示一个总进度。组只保存 gid，每次操作时传入管理器。
+
+use anyhow::Result;
+use std::future::Future;
+
+use super::aria2::{Aria2Manager, DownloadProgress, DownloadStatus};
+use super::error::DownloadError;
+
+/// 一组下载任务
+#[derive(Debug, Clone)]
+pub struct DownloadGroup {
+    gids: Vec<String>,
+}
+
+/// 下载组的汇总进度
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct GroupProgress {
+    pub completed_length: u64,
+    /// 所有成员的总大小；有成员大小未知时只计已知部分
+    pub total_length: u64,
+    pub download_speed: u64,
+    /// 各成员平滑速度之和
+    pub download_speed_avg: u64,
+    /// 按字节加权的总百分比
+    pub percentage: f64,
+    /// 汇总状态，见 `DownloadGroup::progress`
+    pub status: DownloadStatus,
+    /// 各成员的进度，顺序与 gid 相同
+    pub members: Vec<DownloadProgress>,
+}
+
+impl DownloadGroup {
+    pub fn new(gids: Vec<String>) -> Self {
+        Self { gids }
+    }
+
+    pub fn gids(&self) -> &[String] {
+        &self.gids
+    }
+
+    /// 汇总所有成员的进度
+    ///
+    /// 状态取最差的成员：任一出错则为 `Error`（取第一个出错成员的原因），否则依次为
+    /// `Removed`（有成员被取消）、`Paused`、`Active`、`Waiting`，全部完成才是 `Complete`。
+    /// 所有成员同时查询（`Aria2Manager::get_statuses`），查询失败的成员按出错计入。
+    pub async fn progress(&self, manager: &Aria2Manager) -> Result<GroupProgress> {
+        Ok(summarize(manager.get_statuses(&self.gids).await?))
+    }
+
+    /// 开始所有以暂停状态添加的成员（见 `Aria2Manager::start_download`）
+    pub async fn start(&self, manager: &Aria2Manager) -> Result<()> {
+        self.for_each(|gid| manager.start_download(gid)).await
+    }
+
+    /// 暂停所有成员
+    pub async fn pause(&self, manager: &Aria2Manager) -> Result<()> {
+        self.for_each(|gid| manager.pause(gid)).await
+    }
+
+    /// 恢复所有成员
+    pub async fn resume(&self, manager: &Aria2Manager) -> Result<()> {
+        self.for_each(|gid| manager.resume(gid)).await
+    }
+
+    /// 取消所有成员
+    pub async fn cancel(&self, manager: &Aria2Manager) -> Result<()> {
+        self.for_each(|gid| manager.cancel(gid)).await
+    }
+
+    /// 对每个成员执行操作；某个成员失败不影响其它成员，最后以 `DownloadError::PartialFailure` 汇报
+    async fn for_each<'a, F, Fut>(&'a self, op: F) -> Result<()>
+    where
+        F: Fn(&'a str) -> Fut,
+        Fut: Future<Output = Result<()>>,
+    {
+        let mut failed = Vec::new();
+        for gid in &self.gids {
+            if let Err(e) = op(gid).await {
+                log::warn!("[下载组] 任务 {} 操作失败: {}", gid, e);
+                failed.push((gid.clone(), e.to_string()));
+            }
+        }
+        if failed.is_empty() {
+            Ok(())
+        } else {
+            Err(DownloadError::PartialFailure {
+                failed,
+                total: self.gids.len(),
+            }
+            .into())
+        }
+    }
+}
+
+/// 由各成员进度计算汇总进度
+fn summarize(members: Vec<DownloadProgress>) -> GroupProgress {
+    let completed_length = members.iter().map(|m| m.completed_length).sum::<u64>();
+    let total_length = members.iter().map(|m| m.total_length).sum::<u64>();
+    let download_speed = members.iter().map(|m| m.download_speed).sum();
+    let download_speed_avg = members.iter().map(|m| m.download_speed_avg).sum();
+    let percentage = if total_length > 0 {
+        completed_length as f64 / total_length as f64 * 100.0
+    } else {
+        0.0
+    };
+
+    let statuses = || members.iter().map(|m| &m.status);
+    let status = if let Some(error) = statuses().find(|s| matches!(s, DownloadStatus::Error(_))) {
+        error.clone()
+    } else if statuses().any(|s| *s == DownloadStatus::Removed) {
+        DownloadStatus::Removed
+    } else if statuses().any(|s| *s == DownloadStatus::Paused) {
+        DownloadStatus::Paused
+    } else if statuses().any(|s| *s == DownloadStatus::Active) {
+        DownloadStatus::Active
+    } else if statuses().any(|s| *s == DownloadStatus::Waiting) {
+        DownloadStatus::Waiting
+    } else {
+        DownloadStatus::Complete
+    };
+
+    GroupProgress {
+        completed_length,
+        total_length,
+        download_speed,
+        download_speed_avg,
+        percentage,
+        status,
+        members,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::download::aria2::DownloadErrorKind;
+    use std::time::Duration;
+
+    fn member(completed: u64, total: u64, speed: u64, status: DownloadStatus) -> DownloadProgress {
+        DownloadProgress {
+            gid: String::new(),
+            completed_length: completed,
+            total_length: total,
+            download_speed: speed,
+            download_speed_avg: speed,
+            percentage: None,
+            status,
+            eta: None,
+            elapsed: Duration::ZERO,
+            file_path: None,
+            file_count: 1,
+            connections: 0,
+            active_uri: None,
+            files: Vec::new(),
+            upload_speed: 0,
+            share_ratio: None,
+        }
+    }
+
+    #[test]
+    fn test_summarize_weights_by_bytes() {
+        let group = summarize(vec![
+            member(900, 900, 0, DownloadStatus::Complete),
+            member(100, 9100, 50, DownloadStatus::Active),
+        ]);
+        assert_eq!(group.completed_length, 1000);
+        assert_eq!(group.total_length, 10000);
+        assert_eq!(group.download_speed, 50);
+        assert!((group.percentage - 10.0).abs() < 1e-9);
+        assert_eq!(group.status, DownloadStatus::Active);
+    }
+
+    #[test]
+    fn test_summarize_worst_status() {
+        let error = DownloadStatus::Error(DownloadErrorKind::NotFound);
+        let group = summarize(vec![
+            member(0, 0, 0, DownloadStatus::Paused),
+            member(0, 0, 0, error.clone()),
+        ]);
+        assert_eq!(group.status, error);
+
+        let group = summarize(vec![
+            member(1, 1, 0, DownloadStatus::Complete),
+            member(1, 1, 0, DownloadStatus::Complete),
+        ]);
+        assert_eq!(group.status, DownloadStatus::Complete);
+        assert_eq!(summarize(Vec::new()).status, DownloadStatus::Complete);
+    }
+}
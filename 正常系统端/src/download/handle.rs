@@ -0,0 +1,72 @@
+//! 任务句柄：丢弃时自动取消下载
+//!
+//! 向导等上层流程添加下载后可能被用户中途退出，只保存 gid 的话任务会留在 aria2 中继续占用带宽。
+//! `Aria2Manager::add_download_guarded` 返回的句柄在被丢弃时通知管理器取消任务；
+//! Drop 中不能 await，取消请求经通道交给管理器的后台任务执行，尽力而为，不保证一定成功。
+
+use std::ops::Deref;
+use tokio::sync::mpsc::UnboundedSender;
+
+/// 下载任务句柄，可当作 gid（`&str`）使用
+///
+/// 丢弃时取消任务（已完成的任务不受影响）；需要让任务在句柄释放后继续下载时调用 `detach`。
+#[derive(Debug)]
+pub struct DownloadHandle {
+    gid: String,
+    /// 取消请求的发送端；`detach` 后为 None
+    cancel: Option<UnboundedSender<String>>,
+}
+
+impl DownloadHandle {
+    pub(super) fn new(gid: String, cancel: UnboundedSender<String>) -> Self {
+        Self {
+            gid,
+            cancel: Some(cancel),
+        }
+    }
+
+    pub fn gid(&self) -> &str {
+        &self.gid
+    }
+
+    /// 放弃自动取消，返回 gid；任务此后由调用方自行管理
+    pub fn detach(mut self) -> String {
+        self.cancel = None;
+        std::mem::take(&mut self.gid)
+    }
+}
+
+impl Deref for DownloadHandle {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.gid
+    }
+}
+
+impl Drop for DownloadHandle {
+    fn drop(&mut self) {
+        if let Some(cancel) = self.cancel.take() {
+            // 管理器已关闭时通道已断开，任务也已随 aria2c 停止
+            let _ = cancel.send(std::mem::take(&mut self.gid));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_drop_sends_cancel_unless_detached() {
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let handle = DownloadHandle::new("2089b05ecca3d829".to_string(), tx.clone());
+        assert_eq!(&*handle, "2089b05ecca3d829");
+        drop(handle);
+        assert_eq!(rx.try_recv().unwrap(), "2089b05ecca3d829");
+
+        let handle = DownloadHandle::new("2089b05ecca3d830".to_string(), tx);
+        assert_eq!(handle.detach(), "2089b05ecca3d830");
+        assert!(rx.try_recv().is_err());
+    }
+}
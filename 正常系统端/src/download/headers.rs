@@ -0,0 +1,259 @@
+//! 下载任务的自定义请求头与 Cookie，以及响应头 `Content-Disposition` 中文件名的解析
+//!
+//! 请求头和 Cookie 常用于鉴权（Bearer 令牌、登录会话），其值等同于凭据：
+//! 校验失败的错误信息只包含名称，`Cookie` 的 `Debug` 输出隐藏值，也不会写入日志。
+
+use anyhow::Result;
+use std::fmt;
+
+use super::error::DownloadError;
+use crate::utils::path::sanitize_filename;
+
+/// 检查请求头名称和值，并转换为 aria2 `header` 选项使用的 "Name: Value" 形式
+///
+/// 名称必须是 HTTP token；值不能包含换行等控制字符（否则可能注入额外的请求头）。
+/// 错误信息只包含名称，不包含值。
+pub fn header_lines(headers: &[(String, String)]) -> Result<Vec<String>> {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            let invalid = |reason: &str| DownloadError::InvalidHeader {
+                name: name.clone(),
+                reason: reason.to_string(),
+            };
+            if name.is_empty() {
+                return Err(invalid("名称为空"));
+            }
+            if !name.bytes().all(is_token_byte) {
+                return Err(invalid("名称包含非法字符"));
+            }
+            if value.chars().any(|c| c.is_control() && c != '\t') {
+                return Err(invalid("值包含换行或控制字符"));
+            }
+            Ok(format!("{}: {}", name, value.trim()))
+        })
+        .collect::<std::result::Result<_, _>>()
+        .map_err(Into::into)
+}
+
+/// 解析 "Name: Value" 形式的请求头
+pub fn parse_header_line(line: &str) -> Result<(String, String)> {
+    match line.split_once(':') {
+        Some((name, value)) => Ok((name.trim().to_string(), value.trim().to_string())),
+        None => Err(DownloadError::InvalidHeader {
+            name: line.chars().take(32).collect(),
+            reason: "缺少冒号".to_string(),
+        }
+        .into()),
+    }
+}
+
+/// 单个 Cookie（名称=值）
+#[derive(Clone, PartialEq, Eq)]
+pub struct Cookie {
+    pub name: String,
+    pub value: String,
+}
+
+impl Cookie {
+    pub fn new(name: impl Into<String>, value: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            value: value.into(),
+        }
+    }
+}
+
+impl fmt::Debug for Cookie {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Cookie")
+            .field("name", &self.name)
+            .field("value", &"***")
+            .finish()
+    }
+}
+
+/// 拼接为 `Cookie` 请求头的值（"a=1; b=2"），没有 Cookie 时返回 None
+///
+/// 名称必须是 HTTP token，值不能包含分号、逗号、引号、空白或控制字符（RFC 6265 cookie-octet），
+/// 避免拼接后被解析成额外的 Cookie 或请求头。
+pub fn cookie_header_value(cookies: &[Cookie]) -> Result<Option<String>> {
+    if cookies.is_empty() {
+        return Ok(None);
+    }
+
+    let mut pairs = Vec::with_capacity(cookies.len());
+    for cookie in cookies {
+        let invalid = |reason: String| DownloadError::InvalidHeader {
+            name: "Cookie".to_string(),
+            reason,
+        };
+        if cookie.name.is_empty() || !cookie.name.bytes().all(is_token_byte) {
+            return Err(invalid(format!("名称 {:?} 包含非法字符", cookie.name)).into());
+        }
+        if !cookie.value.bytes().all(is_cookie_octet) {
+            return Err(invalid(format!("{} 的值包含非法字符", cookie.name)).into());
+        }
+        pairs.push(format!("{}={}", cookie.name, cookie.value));
+    }
+    Ok(Some(pairs.join("; ")))
+}
+
+/// 从 `Content-Disposition` 响应头取出文件名，并处理为 Windows 可用的文件名
+///
+/// 优先使用 RFC 5987 的 `filename*`（如 `UTF-8''%E4%B8%8B.iso`），其次是 `filename`。
+/// 服务器直接发送 UTF-8 字节的 `filename` 也能识别。
+pub fn content_disposition_filename(value: &[u8]) -> Option<String> {
+    let value = String::from_utf8_lossy(value);
+    let mut plain = None;
+    let mut extended = None;
+    for param in split_params(&value).into_iter().skip(1) {
+        let Some((key, raw)) = param.split_once('=') else {
+            continue;
+        };
+        match key.trim().to_ascii_lowercase().as_str() {
+            "filename*" => extended = decode_ext_value(raw.trim()),
+            "filename" => plain = Some(unquote(raw.trim())),
+            _ => {}
+        }
+    }
+    extended
+        .or(plain)
+        .map(|name| sanitize_filename(&name))
+        .filter(|name| !name.is_empty())
+}
+
+/// 按不在引号内的 `;` 拆分参数
+fn split_params(value: &str) -> Vec<&str> {
+    let mut params = Vec::new();
+    let (mut start, mut quoted, mut escaped) = (0, false, false);
+    for (i, c) in value.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            ';' if !quoted => {
+                params.push(&value[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    params.push(&value[start..]);
+    params
+}
+
+/// 去掉引号并处理 `\"` 转义
+fn unquote(raw: &str) -> String {
+    let Some(inner) = raw.strip_prefix('"').and_then(|r| r.strip_suffix('"')) else {
+        return raw.to_string();
+    };
+    let mut out = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => out.extend(chars.next()),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// 解码 RFC 5987 ext-value：`charset'language'percent-encoded`，支持 UTF-8 和 ISO-8859-1
+fn decode_ext_value(raw: &str) -> Option<String> {
+    let mut parts = raw.splitn(3, '\'');
+    let charset = parts.next()?.to_ascii_lowercase();
+    let _language = parts.next()?;
+    let encoded = parts.next()?.as_bytes();
+
+    let mut bytes = Vec::with_capacity(encoded.len());
+    let mut i = 0;
+    while i < encoded.len() {
+        if encoded[i] == b'%' {
+            let hex = std::str::from_utf8(encoded.get(i + 1..i + 3)?).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            bytes.push(encoded[i]);
+            i += 1;
+        }
+    }
+    match charset.as_str() {
+        "utf-8" => String::from_utf8(bytes).ok(),
+        "iso-8859-1" => Some(bytes.iter().map(|&b| b as char).collect()),
+        _ => None,
+    }
+}
+
+/// HTTP token 字符（RFC 9110 tchar）
+fn is_token_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b)
+}
+
+/// RFC 6265 cookie-octet
+fn is_cookie_octet(b: u8) -> bool {
+    matches!(b, 0x21 | 0x23..=0x2B | 0x2D..=0x3A | 0x3C..=0x5B | 0x5D..=0x7E)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_header_lines() {
+        let headers = vec![
+            ("Authorization".to_string(), "Bearer abc".to_string()),
+            ("X-Client-Version".to_string(), " 2026.2 ".to_string()),
+        ];
+        assert_eq!(
+            header_lines(&headers).unwrap(),
+            vec!["Authorization: Bearer abc", "X-Client-Version: 2026.2"]
+        );
+
+        for (name, value) in [("", "x"), ("Bad Name", "x"), ("X-Inject", "a\r\nHost: evil")] {
+            let err = header_lines(&[(name.to_string(), value.to_string())]).unwrap_err();
+            assert!(matches!(err.downcast_ref::<DownloadError>(), Some(DownloadError::InvalidHeader { .. })));
+            assert!(!err.to_string().contains("evil"));
+        }
+
+        assert_eq!(
+            parse_header_line("Referer: https://example.com/a").unwrap(),
+            ("Referer".to_string(), "https://example.com/a".to_string())
+        );
+        assert!(parse_header_line("no colon").is_err());
+    }
+
+    #[test]
+    fn test_cookie_header_value() {
+        assert_eq!(cookie_header_value(&[]).unwrap(), None);
+        assert_eq!(
+            cookie_header_value(&[Cookie::new("session", "abc123"), Cookie::new("lang", "zh-CN")]).unwrap(),
+            Some("session=abc123; lang=zh-CN".to_string())
+        );
+
+        let err = cookie_header_value(&[Cookie::new("session", "a; admin=1")]).unwrap_err();
+        assert!(!err.to_string().contains("admin"));
+        assert!(cookie_header_value(&[Cookie::new("bad name", "x")]).is_err());
+        assert!(cookie_header_value(&[Cookie::new("s", "a\r\nHost: x")]).is_err());
+    }
+
+    #[test]
+    fn test_debug_hides_value() {
+        let debug = format!("{:?}", Cookie::new("session", "secret-token"));
+        assert!(debug.contains("session"));
+        assert!(!debug.contains("secret-token"));
+    }
+
+    #[test]
+    fn test_content_disposition_filename() {
+        let parse = |v: &str| content_disposition_filename(v.as_bytes());
+        assert_eq!(parse(r#"attachment; filename="Win11 24H2.iso""#).as_deref(), Some("Win11 24H2.iso"));
+        assert_eq!(
+            parse(r#"attachment; filename="fallback.iso"; filename*=UTF-8''%E7%B3%BB%E7%BB%9F.iso"#).as_deref(),
+            Some("系统.iso")
+        );
+        assert_eq!(parse(r#"attachment; filename="a;\"b\".iso""#).as_deref(), Some("a;_b_.iso"));
+        assert_eq!(parse("attachment; filename=../../evil.exe").as_deref(), Some("evil.exe"));
+        assert_eq!(parse("inline"), None);
+    }
+}
@@ -0,0 +1,274 @@
+//! 下载任务元数据的持久化存储（data/download_jobs.json）
+//!
+//! gid 本身没有含义，程序重启并从会话文件恢复任务后，需要靠这里记录的信息
+//! 知道某个 gid 是「Windows 11 install.esd」还是「网卡驱动」，以及下载到哪里、期望的哈希值等。
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::utils::path::get_data_dir;
+
+/// 存储文件名（数据目录下）
+const JOBS_FILE_NAME: &str = "download_jobs.json";
+
+/// 任务记录的状态
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum JobState {
+    /// 已添加，尚未结束
+    Pending,
+    Complete,
+    /// 失败，附带失败原因
+    Failed(String),
+    /// 被用户取消
+    Removed,
+}
+
+/// 单个下载任务的元数据
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JobRecord {
+    pub gid: String,
+    /// 显示名称（未指定时为文件名或地址）
+    pub display_name: String,
+    /// 下载地址（多镜像任务为全部地址）
+    pub urls: Vec<String>,
+    /// 保存目录（实际下载到的目录）
+    pub save_dir: PathBuf,
+    /// 调用方原本指定的保存目录；空间不足自动改存到其它卷时才有值
+    #[serde(default)]
+    pub requested_save_dir: Option<PathBuf>,
+    /// 最终文件名（未指定时由 aria2 推断，为 None）
+    pub file_name: Option<String>,
+    /// 是否先写入 `.part` 临时文件，完成后再改名
+    pub uses_part_file: bool,
+    /// 期望的哈希值（"算法=十六进制"，如 "sha-256=..."）
+    pub expected_hash: Option<String>,
+    /// 期望的文件大小（字节），完成时核对（旧版本写入的记录没有此项）
+    #[serde(default)]
+    pub expected_size: Option<u64>,
+    /// 完成时不核对文件是否短于 aria2 记录的总大小（流式内容的地址）
+    #[serde(default)]
+    pub skip_length_check: bool,
+    /// 调用方自定义的分组标签（如某次安装计划的 ID）
+    pub tag: Option<String>,
+    /// 添加时间（Unix 时间戳，秒）
+    pub created_at: u64,
+    pub state: JobState,
+    /// 最近一次按校验文件核对通过的记录
+    #[serde(default)]
+    pub verified: Option<VerifiedFile>,
+}
+
+/// 按校验文件核对通过时文件的状态；文件大小或修改时间变化后不再有效
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VerifiedFile {
+    /// 核对使用的哈希值（"算法=十六进制"，与 `expected_hash` 格式相同）
+    pub checksum: String,
+    pub size: u64,
+    /// 文件修改时间（Unix 时间戳，秒）
+    pub modified: u64,
+    /// 核对时间（Unix 时间戳，秒）
+    pub verified_at: u64,
+}
+
+impl VerifiedFile {
+    /// 文件当前的大小和修改时间（Unix 时间戳，秒）
+    pub fn stamp(path: &Path) -> std::io::Result<(u64, u64)> {
+        let meta = std::fs::metadata(crate::utils::path::to_extended_path(path))?;
+        let modified = meta
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        Ok((meta.len(), modified))
+    }
+
+    /// 文件自核对以来是否没有变化
+    pub fn still_valid(&self, stamp: (u64, u64)) -> bool {
+        (self.size, self.modified) == stamp
+    }
+}
+
+/// 任务元数据存储，每次修改后立即写回文件
+pub struct JobStore {
+    path: PathBuf,
+    jobs: parking_lot::Mutex<HashMap<String, JobRecord>>,
+}
+
+impl JobStore {
+    /// 打开数据目录下的默认存储
+    pub fn open_default() -> Self {
+        Self::open(get_data_dir().join(JOBS_FILE_NAME))
+    }
+
+    /// 打开指定路径的存储；文件不存在或损坏时从空存储开始
+    pub fn open(path: PathBuf) -> Self {
+        let jobs = match std::fs::read_to_string(&path) {
+            Ok(json) => serde_json::from_str::<Vec<JobRecord>>(&json).unwrap_or_else(|e| {
+                log::warn!("[下载记录] {} 解析失败，已忽略: {}", path.display(), e);
+                Vec::new()
+            }),
+            Err(_) => Vec::new(),
+        };
+        Self {
+            path,
+            jobs: parking_lot::Mutex::new(jobs.into_iter().map(|j| (j.gid.clone(), j)).collect()),
+        }
+    }
+
+    /// 当前时间（Unix 时间戳，秒）
+    pub fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0)
+    }
+
+    pub fn insert(&self, record: JobRecord) {
+        let mut jobs = self.jobs.lock();
+        jobs.insert(record.gid.clone(), record);
+        self.save(&jobs);
+    }
+
+    /// 更新任务状态；状态未变化时不写文件
+    pub fn set_state(&self, gid: &str, state: JobState) {
+        let mut jobs = self.jobs.lock();
+        match jobs.get_mut(gid) {
+            Some(job) if job.state != state => job.state = state,
+            _ => return,
+        }
+        self.save(&jobs);
+    }
+
+    /// 记录实际的最终文件名：添加时未能确定、由 aria2 推断的文件名，或目标已存在时改用的新文件名
+    pub fn set_file_name(&self, gid: &str, file_name: &str) {
+        let mut jobs = self.jobs.lock();
+        match jobs.get_mut(gid) {
+            Some(job) if job.file_name.as_deref() != Some(file_name) => job.file_name = Some(file_name.to_string()),
+            _ => return,
+        }
+        self.save(&jobs);
+    }
+
+    /// 记录按校验文件核对通过
+    pub fn set_verified(&self, gid: &str, verified: VerifiedFile) {
+        let mut jobs = self.jobs.lock();
+        match jobs.get_mut(gid) {
+            Some(job) => job.verified = Some(verified),
+            None => return,
+        }
+        self.save(&jobs);
+    }
+
+    pub fn get(&self, gid: &str) -> Option<JobRecord> {
+        self.jobs.lock().get(gid).cloned()
+    }
+
+    /// 全部记录，按添加时间排序
+    pub fn list(&self) -> Vec<JobRecord> {
+        let mut jobs: Vec<_> = self.jobs.lock().values().cloned().collect();
+        jobs.sort_by_key(|j| j.created_at);
+        jobs
+    }
+
+    pub fn remove(&self, gid: &str) {
+        let mut jobs = self.jobs.lock();
+        if jobs.remove(gid).is_some() {
+            self.save(&jobs);
+        }
+    }
+
+    /// 只保留满足条件的记录
+    pub fn retain(&self, keep: impl Fn(&JobRecord) -> bool) {
+        let mut jobs = self.jobs.lock();
+        let before = jobs.len();
+        jobs.retain(|_, job| keep(job));
+        if jobs.len() != before {
+            self.save(&jobs);
+        }
+    }
+
+    /// 写回文件：先写临时文件再改名，避免写到一半时程序退出留下损坏的文件
+    fn save(&self, jobs: &HashMap<String, JobRecord>) {
+        let mut list: Vec<_> = jobs.values().collect();
+        list.sort_by_key(|j| j.created_at);
+        if let Err(e) = write_atomically(&self.path, &list) {
+            log::warn!("[下载记录] 写入 {} 失败: {}", self.path.display(), e);
+        }
+    }
+}
+
+fn write_atomically(path: &Path, jobs: &[&JobRecord]) -> anyhow::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    std::fs::write(&tmp, serde_json::to_string_pretty(jobs)?)?;
+    std::fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(gid: &str, created_at: u64) -> JobRecord {
+        JobRecord {
+            gid: gid.to_string(),
+            display_name: "install.esd".to_string(),
+            urls: vec!["https://example.com/install.esd".to_string()],
+            save_dir: PathBuf::from(r"D:\LetRecovery"),
+            requested_save_dir: None,
+            file_name: Some("install.esd".to_string()),
+            uses_part_file: true,
+            expected_hash: None,
+            expected_size: Some(4096),
+            skip_length_check: false,
+            tag: Some("win11".to_string()),
+            created_at,
+            state: JobState::Pending,
+            verified: None,
+        }
+    }
+
+    #[test]
+    fn test_job_store_persists() {
+        let path = std::env::temp_dir().join(format!("lr_jobs_{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let store = JobStore::open(path.clone());
+        store.insert(record("b", 2));
+        store.insert(record("a", 1));
+        store.set_state("a", JobState::Failed("连接超时".to_string()));
+        // 目标已存在时改用的新文件名
+        store.set_file_name("b", "install (1).esd");
+        let verified = VerifiedFile {
+            checksum: "sha-256=ab".to_string(),
+            size: 4096,
+            modified: 1_700_000_000,
+            verified_at: 1_700_000_100,
+        };
+        store.set_verified("b", verified.clone());
+
+        let reopened = JobStore::open(path.clone());
+        let gids: Vec<_> = reopened.list().into_iter().map(|j| j.gid).collect();
+        assert_eq!(gids, ["a", "b"]);
+        assert_eq!(reopened.get("a").unwrap().state, JobState::Failed("连接超时".to_string()));
+        assert_eq!(reopened.get("b").unwrap().file_name.as_deref(), Some("install (1).esd"));
+        assert_eq!(reopened.get("b").unwrap().verified, Some(verified));
+
+        reopened.retain(|j| j.state == JobState::Pending);
+        assert!(JobStore::open(path.clone()).get("a").is_none());
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_corrupt_file_starts_empty() {
+        let path = std::env::temp_dir().join(format!("lr_jobs_corrupt_{}.json", std::process::id()));
+        std::fs::write(&path, "{not json").unwrap();
+        assert!(JobStore::open(path.clone()).list().is_empty());
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,169 @@
+//! 自动选择保存位置：首选目录所在卷空间不足时，换到剩余空间最多的其它本地卷
+//!
+//! 多数电脑的 C 盘空间紧张而 D 盘宽裕，与其让下载直接失败，不如把镜像存到
+//! `D:\LetRecovery\downloads`。默认只考虑本机固定磁盘，排除可移动磁盘、网络驱动器、
+//! 系统保留/恢复分区、PE 环境的 X 盘以及调用方指定的卷（通常是即将重装的目标分区，
+//! 重装时会被格式化）。
+
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+
+use crate::core::disk::DiskManager;
+use crate::utils::path::{list_fixed_volumes, to_extended_path, volume_space};
+
+/// 自动选择的保存目录（卷根目录下）
+const FALLBACK_DIR: &str = r"LetRecovery\downloads";
+
+/// 视为系统保留分区的卷标（不区分大小写）
+const RESERVED_LABELS: [&str; 6] = ["System Reserved", "系统保留", "Recovery", "恢复", "WinRE", "ESP"];
+
+/// 卷的类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VolumeKind {
+    Fixed,
+    Removable,
+    Network,
+}
+
+/// 一个可以用作保存位置的带盘符的卷及其剩余空间
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CandidateVolume {
+    /// 盘符（如 "D:"）
+    pub volume: String,
+    pub kind: VolumeKind,
+    pub label: String,
+    /// 当前用户可用的剩余空间（字节）
+    pub free_bytes: u64,
+}
+
+impl CandidateVolume {
+    fn is_reserved(&self) -> bool {
+        RESERVED_LABELS.iter().any(|l| self.label.eq_ignore_ascii_case(l))
+    }
+}
+
+/// 候选卷的筛选条件
+#[derive(Debug, Clone, Default)]
+pub struct LocationFilter {
+    /// 是否考虑可移动磁盘
+    pub include_removable: bool,
+    /// 是否考虑映射的网络驱动器
+    pub include_network: bool,
+    /// 不使用的卷（盘符，如 "C:"），通常是即将重装的目标分区
+    pub exclude: Vec<String>,
+}
+
+impl LocationFilter {
+    fn accepts(&self, volume: &CandidateVolume) -> bool {
+        let kind_allowed = match volume.kind {
+            VolumeKind::Fixed => true,
+            VolumeKind::Removable => self.include_removable,
+            VolumeKind::Network => self.include_network,
+        };
+        kind_allowed
+            && !volume.is_reserved()
+            && !self.exclude.iter().any(|v| v.trim_end_matches('\\').eq_ignore_ascii_case(&volume.volume))
+    }
+}
+
+/// 列出所有带盘符、能读取剩余空间的卷（不含光驱），按盘符排序
+pub fn list_volumes() -> Vec<CandidateVolume> {
+    let fixed = list_fixed_volumes().into_iter().map(|v| CandidateVolume {
+        volume: format!("{}:", v.letter),
+        kind: VolumeKind::Fixed,
+        label: v.label,
+        free_bytes: v.space.free,
+    });
+    let others = (b'A'..=b'Z').map(char::from).filter_map(|letter| {
+        let kind = if DiskManager::is_removable_drive(letter) {
+            VolumeKind::Removable
+        } else if DiskManager::is_network_drive(letter) {
+            VolumeKind::Network
+        } else {
+            return None;
+        };
+        let volume = format!("{}:", letter);
+        Some(CandidateVolume {
+            free_bytes: volume_space(Path::new(&format!("{}\\", volume))).ok()?.free,
+            label: DiskManager::get_volume_label(&volume).unwrap_or_default(),
+            volume,
+            kind,
+        })
+    });
+
+    let is_pe = DiskManager::is_pe_environment();
+    let mut volumes: Vec<_> = fixed
+        .chain(others)
+        // PE 环境的 X 盘是内存中的系统盘，重启即丢失
+        .filter(|v| !(is_pe && v.volume == "X:"))
+        .collect();
+    volumes.sort_by(|a, b| a.volume.cmp(&b.volume));
+    volumes
+}
+
+/// 从 `volumes` 中选出能放下 `required` 字节的卷：系统盘排在其它卷之后，其余按剩余空间从大到小
+fn pick_volume<'a>(volumes: &'a [CandidateVolume], required: u64, filter: &LocationFilter, system_drive: &str) -> Option<&'a CandidateVolume> {
+    volumes
+        .iter()
+        .filter(|v| v.free_bytes >= required && filter.accepts(v))
+        .min_by_key(|v| (v.volume.eq_ignore_ascii_case(system_drive), std::cmp::Reverse(v.free_bytes)))
+}
+
+/// 选出能放下 `required` 字节的最佳保存目录（`<卷>\LetRecovery\downloads`）并创建它
+///
+/// 没有合适的卷时返回 None；目录创建失败时返回错误。
+pub fn best_save_dir(required: u64, filter: &LocationFilter) -> Result<Option<PathBuf>> {
+    let system_drive = std::env::var("SystemDrive").unwrap_or_else(|_| "C:".to_string());
+    let volumes = list_volumes();
+    let Some(volume) = pick_volume(&volumes, required, filter, &system_drive) else {
+        log::info!("[保存位置] 没有剩余空间不少于 {} MB 的可用卷", required / (1024 * 1024));
+        return Ok(None);
+    };
+
+    let dir = PathBuf::from(format!(r"{}\{}", volume.volume, FALLBACK_DIR));
+    std::fs::create_dir_all(to_extended_path(&dir))
+        .map_err(|e| anyhow::anyhow!("创建保存目录 {} 失败: {}", dir.display(), e))?;
+    log::info!(
+        "[保存位置] 选择 {}（剩余 {} MB）",
+        dir.display(),
+        volume.free_bytes / (1024 * 1024)
+    );
+    Ok(Some(dir))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn volume(letter: &str, kind: VolumeKind, label: &str, free_gb: u64) -> CandidateVolume {
+        CandidateVolume {
+            volume: letter.to_string(),
+            kind,
+            label: label.to_string(),
+            free_bytes: free_gb << 30,
+        }
+    }
+
+    #[test]
+    fn test_pick_volume() {
+        let volumes = vec![
+            volume("C:", VolumeKind::Fixed, "", 80),
+            volume("D:", VolumeKind::Fixed, "Data", 40),
+            volume("E:", VolumeKind::Fixed, "系统保留", 90),
+            volume("F:", VolumeKind::Removable, "U盘", 60),
+            volume("G:", VolumeKind::Fixed, "", 10),
+        ];
+        let filter = LocationFilter::default();
+        // 系统盘空间更大，但其它卷放得下时优先其它卷
+        assert_eq!(pick_volume(&volumes, 20 << 30, &filter, "C:").unwrap().volume, "D:");
+        assert_eq!(pick_volume(&volumes, 50 << 30, &filter, "C:").unwrap().volume, "C:");
+
+        let filter = LocationFilter {
+            include_removable: true,
+            exclude: vec!["d:\\".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(pick_volume(&volumes, 20 << 30, &filter, "C:").unwrap().volume, "F:");
+        assert_eq!(pick_volume(&volumes, 100 << 30, &filter, "C:"), None);
+    }
+}
@@ -60,8 +60,18 @@ impl DownloadManager {
                 completed_length: 0,
                 total_length: 0,
                 download_speed: 0,
-                percentage: 0.0,
+                download_speed_avg: 0,
+                percentage: None,
                 status: DownloadStatus::Waiting,
+                eta: None,
+                elapsed: std::time::Duration::ZERO,
+                file_path: None,
+                file_count: 0,
+                connections: 0,
+                active_uri: None,
+                files: Vec::new(),
+                upload_speed: 0,
+                share_ratio: None,
             },
         };
 
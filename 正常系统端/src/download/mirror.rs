@@ -0,0 +1,275 @@
+//! 多镜像轮换：当前镜像出错时换下一个镜像重新添加任务
+//!
+//! 与 `add_download_multi`（所有地址交给同一个 aria2 任务）不同，轮换模式下 aria2 每次只拿到一个地址，
+//! 出错后由管理器移除任务并用下一个镜像以相同的 gid、目录和文件名重新添加，
+//! 已下载的部分由 `.aria2` 控制文件续传。
+//!
+//! `rank_mirrors` 对各镜像做一次小范围测速，可在添加任务前把最快的镜像排在前面。
+
+use anyhow::Result;
+use std::time::{Duration, Instant};
+
+use super::aria2_config::TlsOptions;
+use super::error::DownloadErrorKind;
+use super::preflight::http_client;
+use super::proxy::ProxyConfig;
+
+/// 测速时从每个镜像下载的字节数
+const PROBE_BYTES: u64 = 1024 * 1024;
+
+/// 单个镜像测速的总超时
+const PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 单个镜像的测速结果
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct MirrorScore {
+    pub url: String,
+    /// 从发出请求到收到响应头的时间；镜像不可用时为 None
+    pub latency: Option<Duration>,
+    /// 收到响应头之后的下载速度（字节/秒）；镜像不可用时为 0
+    pub throughput: u64,
+}
+
+impl MirrorScore {
+    fn unreachable(url: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            latency: None,
+            throughput: 0,
+        }
+    }
+}
+
+/// 同时对所有镜像测速，按速度从快到慢排序（速度相同时延迟低的在前）
+///
+/// 每个镜像只请求开头的 1 MB（`Range` 头；服务器不支持时读到 1 MB 即断开）。
+/// 不可用的镜像速度记为 0 排在最后，不会让整个测速失败。
+pub async fn rank_mirrors(
+    urls: &[String],
+    proxy: Option<&ProxyConfig>,
+    tls: &TlsOptions,
+    user_agent: &str,
+) -> Result<Vec<MirrorScore>> {
+    let client = http_client(PROBE_TIMEOUT, user_agent, proxy, tls)?;
+    let mut scores = futures::future::join_all(urls.iter().map(|url| measure(&client, url))).await;
+    sort_scores(&mut scores);
+    Ok(scores)
+}
+
+async fn measure(client: &reqwest::Client, url: &str) -> MirrorScore {
+    let start = Instant::now();
+    let response = client
+        .get(url)
+        .header(reqwest::header::RANGE, format!("bytes=0-{}", PROBE_BYTES - 1))
+        .send()
+        .await;
+    let mut response = match response {
+        Ok(response) if response.status().is_success() => response,
+        Ok(response) => {
+            log::debug!("[镜像测速] {} 返回 {}", url, response.status());
+            return MirrorScore::unreachable(url);
+        }
+        Err(e) => {
+            log::debug!("[镜像测速] {} 不可用: {}", url, e);
+            return MirrorScore::unreachable(url);
+        }
+    };
+
+    let latency = start.elapsed();
+    let body_start = Instant::now();
+    let mut received = 0u64;
+    while received < PROBE_BYTES {
+        match response.chunk().await {
+            Ok(Some(chunk)) => received += chunk.len() as u64,
+            Ok(None) => break,
+            Err(e) => {
+                log::debug!("[镜像测速] {} 读取中断: {}", url, e);
+                break;
+            }
+        }
+    }
+    let elapsed = body_start.elapsed().max(Duration::from_millis(1));
+    MirrorScore {
+        url: url.to_string(),
+        latency: Some(latency),
+        throughput: (received as f64 / elapsed.as_secs_f64()) as u64,
+    }
+}
+
+fn sort_scores(scores: &mut [MirrorScore]) {
+    scores.sort_by(|a, b| {
+        b.throughput
+            .cmp(&a.throughput)
+            .then_with(|| a.latency.unwrap_or(Duration::MAX).cmp(&b.latency.unwrap_or(Duration::MAX)))
+    });
+}
+
+/// 一个任务的镜像轮换状态
+#[derive(Debug, Clone)]
+pub struct MirrorRotation {
+    mirrors: Vec<String>,
+    current: usize,
+    /// 每个镜像已尝试的次数（含第一次添加）
+    attempts: Vec<u32>,
+    per_mirror: u32,
+    max_total: u32,
+}
+
+impl MirrorRotation {
+    /// 从第一个镜像开始；`per_mirror` 为每个镜像最多尝试的次数，`max_total` 为所有镜像合计的上限
+    pub fn new(mirrors: Vec<String>, per_mirror: u32, max_total: u32) -> Self {
+        let mut attempts = vec![0; mirrors.len()];
+        if let Some(first) = attempts.first_mut() {
+            *first = 1;
+        }
+        Self {
+            mirrors,
+            current: 0,
+            attempts,
+            per_mirror,
+            max_total,
+        }
+    }
+
+    /// 当前使用的镜像序号（从 0 开始）
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    pub fn current_url(&self) -> &str {
+        &self.mirrors[self.current]
+    }
+
+    pub fn mirrors(&self) -> &[String] {
+        &self.mirrors
+    }
+
+    /// 已尝试的总次数
+    pub fn total_attempts(&self) -> u32 {
+        self.attempts.iter().sum()
+    }
+
+    /// 该错误是否值得换镜像：可重试的网络错误，或当前镜像上没有这个文件
+    ///
+    /// 校验失败、磁盘已满、权限不足等换镜像也不会好转，直接报告给调用方。
+    pub fn should_rotate(kind: &DownloadErrorKind) -> bool {
+        kind.is_retryable() || *kind == DownloadErrorKind::NotFound
+    }
+
+    /// 当前镜像以 `kind` 失败后切换到下一个镜像，返回其序号；放弃时返回 None
+    ///
+    /// 优先尝试其它镜像，全部轮过一遍后才会回到同一个镜像重试；404 的镜像不再重试。
+    pub fn advance(&mut self, kind: &DownloadErrorKind) -> Option<usize> {
+        if !Self::should_rotate(kind) || self.mirrors.is_empty() {
+            return None;
+        }
+        if *kind == DownloadErrorKind::NotFound {
+            self.attempts[self.current] = self.attempts[self.current].max(self.per_mirror);
+        }
+        if self.total_attempts() >= self.max_total {
+            return None;
+        }
+
+        let count = self.mirrors.len();
+        let next = (1..=count)
+            .map(|offset| (self.current + offset) % count)
+            .find(|&i| self.attempts[i] < self.per_mirror)?;
+        self.current = next;
+        self.attempts[next] += 1;
+        Some(next)
+    }
+
+    /// `advance` 是否会继续尝试（不修改状态）
+    pub fn can_advance(&self, kind: &DownloadErrorKind) -> bool {
+        self.clone().advance(kind).is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mirrors(n: usize) -> Vec<String> {
+        (0..n).map(|i| format!("https://mirror{}.example.com/a.iso", i)).collect()
+    }
+
+    #[test]
+    fn test_advance_prefers_other_mirrors() {
+        let mut rotation = MirrorRotation::new(mirrors(3), 2, 10);
+        let timeout = DownloadErrorKind::Timeout;
+        assert_eq!(rotation.advance(&timeout), Some(1));
+        assert_eq!(rotation.advance(&timeout), Some(2));
+        assert_eq!(rotation.advance(&timeout), Some(0));
+        assert_eq!(rotation.advance(&timeout), Some(1));
+        assert_eq!(rotation.advance(&timeout), Some(2));
+        // 每个镜像都已尝试 2 次
+        assert_eq!(rotation.advance(&timeout), None);
+        assert_eq!(rotation.total_attempts(), 6);
+    }
+
+    #[test]
+    fn test_sort_scores() {
+        let score = |url: &str, latency_ms: Option<u64>, throughput| MirrorScore {
+            url: url.to_string(),
+            latency: latency_ms.map(Duration::from_millis),
+            throughput,
+        };
+        let mut scores = vec![
+            score("dead", None, 0),
+            score("slow", Some(20), 100),
+            score("fast-far", Some(300), 5000),
+            score("fast-near", Some(10), 5000),
+        ];
+        sort_scores(&mut scores);
+        let order: Vec<_> = scores.iter().map(|s| s.url.as_str()).collect();
+        assert_eq!(order, ["fast-near", "fast-far", "slow", "dead"]);
+    }
+
+    #[tokio::test]
+    async fn test_rank_mirrors_scores_unreachable_as_zero() {
+        use std::io::{Read, Write};
+
+        let listener = std::net::TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let good = format!("http://{}/a.iso", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let mut request = [0u8; 4096];
+                let _ = stream.read(&mut request);
+                let body = vec![0u8; PROBE_BYTES as usize];
+                let header = format!("HTTP/1.1 206 Partial Content\r\nContent-Length: {}\r\n\r\n", body.len());
+                let _ = stream.write_all(header.as_bytes());
+                let _ = stream.write_all(&body);
+            }
+        });
+        let closed = std::net::TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let dead = format!("http://{}/a.iso", closed.local_addr().unwrap());
+        drop(closed);
+
+        let scores = rank_mirrors(&[dead.clone(), good.clone()], None, &TlsOptions::default(), "LetRecovery-test").await.unwrap();
+        assert_eq!(scores[0].url, good);
+        assert!(scores[0].throughput > 0 && scores[0].latency.is_some());
+        assert_eq!(scores[1], MirrorScore::unreachable(&dead));
+    }
+
+    #[test]
+    fn test_advance_caps_and_skips_not_found() {
+        let mut rotation = MirrorRotation::new(mirrors(2), 3, 10);
+        assert_eq!(rotation.advance(&DownloadErrorKind::NotFound), Some(1));
+        // 镜像 0 返回过 404，只剩镜像 1 可以重试
+        assert_eq!(rotation.advance(&DownloadErrorKind::Timeout), Some(1));
+        assert_eq!(rotation.current_url(), "https://mirror1.example.com/a.iso");
+
+        let mut rotation = MirrorRotation::new(mirrors(5), 2, 3);
+        assert!(rotation.advance(&DownloadErrorKind::Timeout).is_some());
+        assert!(rotation.advance(&DownloadErrorKind::Timeout).is_some());
+        assert!(!rotation.can_advance(&DownloadErrorKind::Timeout));
+
+        let rotation = MirrorRotation::new(mirrors(2), 2, 10);
+        let disk_full = DownloadErrorKind::DiskFull {
+            volume: "D:".to_string(),
+            needed: 1,
+            available: 0,
+        };
+        assert!(!rotation.can_advance(&disk_full));
+    }
+}
@@ -1,5 +1,20 @@
 pub mod aria2;
+pub mod aria2_config;
+pub mod aria2_output;
 pub mod config;
+pub mod error;
+pub mod group;
+pub mod handle;
+pub mod headers;
+pub mod job_store;
+pub mod location;
 pub mod manager;
+pub mod mirror;
 pub mod pe_url_resolver;
+pub mod preflight;
+pub mod proxy;
+pub mod resume;
+pub mod schedule;
 pub mod server_config;
+pub mod speed;
+pub mod verify;
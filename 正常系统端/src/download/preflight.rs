@@ -0,0 +1,339 @@
+//! 添加下载任务前的预检
+//!
+//! 在交给 aria2 之前发现问题：没有联网时直接报错（而不是让任务在后台反复重试），
+//! 磁盘空间不足时也不会等大文件下载到一半才失败。
+//!
+//! 保存目录可以是网络共享（`\\fileserver\images`）：空间检查以共享根目录作为「卷」，
+//! 添加前先确认能在共享上写入文件，凭据错误时报告为拒绝访问，而不是 aria2 的文件读写错误。
+
+use anyhow::Result;
+use std::path::Path;
+use std::time::Duration;
+
+use super::aria2_config::TlsOptions;
+use super::error::DownloadError;
+use super::headers::content_disposition_filename;
+use super::proxy::ProxyConfig;
+use crate::utils::path::{to_extended_path, volume_space};
+
+/// 探测文件大小和文件名的 HEAD 请求超时
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 连通性检查中 DNS 解析和 TCP 连接各自的超时
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Windows 错误码：拒绝访问、密码错误、登录失败、账户已禁用
+const ACCESS_DENIED_CODES: [i32; 4] = [5, 86, 1326, 1331];
+
+/// Windows 错误码：找不到网络路径、网络名、网络名已删除、网络不可用
+const NETWORK_UNAVAILABLE_CODES: [i32; 4] = [53, 64, 67, 1222];
+
+/// 写入检查时在网络共享上临时创建的文件名
+const WRITE_PROBE_NAME: &str = ".letrecovery-write-test";
+
+/// 路径所在卷：本地路径为盘符（如 "C:"），UNC 路径为共享根目录（如 `\\server\share`）；
+/// 相对路径等返回 None
+pub fn volume_of(path: &Path) -> Option<String> {
+    let s = path.to_str()?;
+    if let Some(rest) = unc_rest(s) {
+        let mut parts = rest.split('\\').filter(|p| !p.is_empty());
+        let (server, share) = (parts.next()?, parts.next()?);
+        return Some(format!(r"\\{}\{}", server, share));
+    }
+    let s = s.strip_prefix(r"\\?\").unwrap_or(s);
+    let mut chars = s.chars();
+    match (chars.next(), chars.next()) {
+        (Some(letter), Some(':')) if letter.is_ascii_alphabetic() => {
+            Some(format!("{}:", letter.to_ascii_uppercase()))
+        }
+        _ => None,
+    }
+}
+
+/// UNC 路径去掉开头 `\\`（或 `\\?\UNC\`）后的部分（`server\share\...`）
+fn unc_rest(s: &str) -> Option<&str> {
+    if let Some(rest) = s.strip_prefix(r"\\?\UNC\") {
+        return Some(rest);
+    }
+    if s.starts_with(r"\\?\") || s.starts_with(r"\\.\") {
+        return None;
+    }
+    s.strip_prefix(r"\\")
+}
+
+/// 是否为网络共享路径（`\\server\share\...`）
+pub fn is_unc(path: &Path) -> bool {
+    path.to_str().and_then(unc_rest).is_some()
+}
+
+/// 确认当前用户可以在网络共享上的 `save_dir` 中写入文件（本地目录不检查，见 `utils::path::ensure_writable`）
+///
+/// 目录不存在时先创建；用试写一个临时文件的方式检查，未登录共享或凭据错误时返回
+/// `DownloadError::NetworkPathAccessDenied`，服务器或共享不存在时返回 `DownloadError::NetworkPathUnavailable`。
+pub fn check_network_dir(save_dir: &Path) -> Result<()> {
+    if !is_unc(save_dir) {
+        return Ok(());
+    }
+    let dir = to_extended_path(save_dir);
+    let probe = dir.join(WRITE_PROBE_NAME);
+    std::fs::create_dir_all(&dir)
+        .and_then(|_| std::fs::write(&probe, b""))
+        .map_err(|e| network_path_error(save_dir, e))?;
+    let _ = std::fs::remove_file(&probe);
+    Ok(())
+}
+
+fn network_path_error(path: &Path, error: std::io::Error) -> anyhow::Error {
+    let path = path.display().to_string();
+    match error.raw_os_error() {
+        Some(code) if ACCESS_DENIED_CODES.contains(&code) => DownloadError::NetworkPathAccessDenied { path }.into(),
+        Some(code) if NETWORK_UNAVAILABLE_CODES.contains(&code) => DownloadError::NetworkPathUnavailable {
+            path,
+            reason: error.to_string(),
+        }
+        .into(),
+        _ if error.kind() == std::io::ErrorKind::PermissionDenied => {
+            DownloadError::NetworkPathAccessDenied { path }.into()
+        }
+        _ => anyhow::anyhow!("无法写入网络路径 {}: {}", path, error),
+    }
+}
+
+/// 路径所在卷（见 `volume_of`）及其剩余空间（字节）；UNC 路径读取共享的剩余空间，
+/// 无法确定卷或读取失败时返回 None
+pub fn free_space(path: &Path) -> Option<(String, u64)> {
+    let volume = volume_of(path)?;
+    let space = volume_space(path).ok()?;
+    Some((volume, space.free))
+}
+
+/// 从地址中取出主机和端口，未写端口时使用协议的默认端口
+///
+/// 不是 http/https/ftp 地址（如 magnet: 链接、本地路径）时返回 None。
+pub fn host_port(url: &str) -> Option<(String, u16)> {
+    let (scheme, rest) = url.split_once("://")?;
+    let default_port = match scheme.to_ascii_lowercase().as_str() {
+        "http" => 80,
+        "https" => 443,
+        "ftp" => 21,
+        _ => return None,
+    };
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let authority = authority.rsplit_once('@').map_or(authority, |(_, host)| host);
+
+    // IPv6 地址写在方括号中：[::1]:8080
+    let (host, port) = if let Some(rest) = authority.strip_prefix('[') {
+        let (host, after) = rest.split_once(']')?;
+        (host, after.strip_prefix(':'))
+    } else {
+        match authority.rsplit_once(':') {
+            Some((host, port)) => (host, Some(port)),
+            None => (authority, None),
+        }
+    };
+    if host.is_empty() {
+        return None;
+    }
+    let port = match port {
+        Some(port) => port.parse().ok()?,
+        None => default_port,
+    };
+    Some((host.to_string(), port))
+}
+
+/// 检查能否连到下载服务器；访问该主机需要走代理时检查代理服务器
+///
+/// 只做 DNS 解析和 TCP 连接，不发送请求。无法解析主机名返回 `DownloadError::Offline`，
+/// 解析成功但连不上返回 `DownloadError::HostUnreachable`。无法从地址取出主机时不检查。
+pub async fn check_connectivity(url: &str, proxy: Option<&ProxyConfig>) -> Result<()> {
+    let Some((host, port)) = host_port(url) else {
+        return Ok(());
+    };
+    let (host, port) = match proxy.filter(|p| !p.bypasses(&host)) {
+        Some(proxy) => match host_port(&proxy.url) {
+            Some(target) => target,
+            None => return Ok(()),
+        },
+        None => (host, port),
+    };
+
+    let addrs: Vec<_> = match tokio::time::timeout(CONNECT_TIMEOUT, tokio::net::lookup_host((host.as_str(), port))).await {
+        Ok(Ok(addrs)) => addrs.collect(),
+        Ok(Err(_)) | Err(_) => Vec::new(),
+    };
+    if addrs.is_empty() {
+        return Err(DownloadError::Offline { host }.into());
+    }
+
+    let mut reason = String::new();
+    for addr in addrs {
+        match tokio::time::timeout(CONNECT_TIMEOUT, tokio::net::TcpStream::connect(addr)).await {
+            Ok(Ok(_)) => return Ok(()),
+            Ok(Err(e)) => reason = e.to_string(),
+            Err(_) => reason = format!("{} 秒内没有响应", CONNECT_TIMEOUT.as_secs()),
+        }
+    }
+    Err(DownloadError::HostUnreachable {
+        host: format!("{}:{}", host, port),
+        reason,
+    }
+    .into())
+}
+
+/// 创建与 aria2 走相同代理、相同证书校验设置的 HTTP 客户端（未配置代理时不使用任何代理，与 aria2 一致）
+pub fn http_client(
+    timeout: Duration,
+    user_agent: &str,
+    proxy: Option<&ProxyConfig>,
+    tls: &TlsOptions,
+) -> Result<reqwest::Client> {
+    let mut builder = reqwest::Client::builder().timeout(timeout).user_agent(user_agent);
+    if let Some(path) = &tls.ca_certificate {
+        let bytes = std::fs::read(path).map_err(|e| anyhow::anyhow!("读取 CA 证书 {} 失败: {}", path.display(), e))?;
+        let certificate = reqwest::Certificate::from_pem(&bytes).or_else(|_| reqwest::Certificate::from_der(&bytes))?;
+        builder = builder.add_root_certificate(certificate);
+    }
+    if tls.allow_insecure_tls {
+        builder = builder.danger_accept_invalid_certs(true);
+    }
+    let builder = match proxy {
+        Some(proxy) => {
+            let mut reqwest_proxy = reqwest::Proxy::all(&proxy.url)?
+                .no_proxy(reqwest::NoProxy::from_string(&proxy.no_proxy.join(",")));
+            if let Some(user) = &proxy.user {
+                reqwest_proxy = reqwest_proxy.basic_auth(user, proxy.password.as_deref().unwrap_or(""));
+            }
+            builder.proxy(reqwest_proxy)
+        }
+        None => builder.no_proxy(),
+    };
+    Ok(builder.build()?)
+}
+
+/// HEAD 请求得到的文件信息
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HeadInfo {
+    /// Content-Length
+    pub content_length: Option<u64>,
+    /// `Content-Disposition` 中的文件名（已处理为 Windows 可用的文件名）
+    pub file_name: Option<String>,
+}
+
+/// 通过 HEAD 请求获取文件大小（Content-Length）和服务器建议的文件名（Content-Disposition）
+///
+/// `headers` 与下载时使用的请求头相同（部分镜像需要鉴权），`proxy` 为 aria2 使用的代理。
+/// 服务器不支持 HEAD 时返回 None，调用方应跳过空间检查、由 aria2 决定文件名，而不是报错。
+pub async fn probe_head(
+    url: &str,
+    headers: &[(String, String)],
+    proxy: Option<&ProxyConfig>,
+    tls: &TlsOptions,
+) -> Option<HeadInfo> {
+    let client = http_client(PROBE_TIMEOUT, "LetRecovery/2026.1", proxy, tls).ok()?;
+
+    let mut request = client.head(url);
+    for (name, value) in headers {
+        request = request.header(name, value);
+    }
+
+    let response = request.send().await.ok()?;
+    if !response.status().is_success() {
+        log::debug!("[下载预检] HEAD 返回 {}，跳过探测", response.status());
+        return None;
+    }
+    let headers = response.headers();
+    Some(HeadInfo {
+        content_length: headers
+            .get(reqwest::header::CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse().ok()),
+        file_name: headers
+            .get(reqwest::header::CONTENT_DISPOSITION)
+            .and_then(|v| content_disposition_filename(v.as_bytes())),
+    })
+}
+
+/// 检查 `save_dir` 所在卷是否有足够空间保存 `size` 字节（另加 `margin` 余量）
+///
+/// 断点续传时已存在的 `.part` 文件不扣除：多分片下载的临时文件长度很快接近完整大小，
+/// 不能代表已下载的量。无法确定盘符或剩余空间时不做限制。
+pub fn check_disk_space(save_dir: &Path, size: u64, margin: u64) -> Result<()> {
+    let Some((volume, available)) = free_space(save_dir) else {
+        log::debug!("[下载预检] 无法读取 {} 所在卷的剩余空间，跳过空间检查", save_dir.display());
+        return Ok(());
+    };
+
+    let required = size.saturating_add(margin);
+    if available < required {
+        return Err(DownloadError::InsufficientDiskSpace {
+            volume,
+            required,
+            available,
+        }
+        .into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_volume_of() {
+        assert_eq!(volume_of(Path::new(r"d:\Downloads\a")), Some("D:".to_string()));
+        assert_eq!(volume_of(Path::new(r"\\?\C:\data")), Some("C:".to_string()));
+        assert_eq!(volume_of(Path::new(r"\\server\share\images\a.wim")), Some(r"\\server\share".to_string()));
+        assert_eq!(volume_of(Path::new(r"\\?\UNC\server\share")), Some(r"\\server\share".to_string()));
+        assert_eq!(volume_of(Path::new(r"\\server")), None);
+        assert_eq!(volume_of(Path::new("relative")), None);
+    }
+
+    #[test]
+    fn test_is_unc() {
+        assert!(is_unc(Path::new(r"\\fileserver\images")));
+        assert!(!is_unc(Path::new(r"\\?\C:\data")));
+        assert!(is_unc(Path::new(r"\\?\UNC\fileserver\images")));
+    }
+
+    #[test]
+    fn test_network_path_error() {
+        let path = Path::new(r"\\fileserver\images");
+        let denied = network_path_error(path, std::io::Error::from_raw_os_error(1326));
+        assert!(matches!(denied.downcast_ref(), Some(DownloadError::NetworkPathAccessDenied { .. })));
+        let missing = network_path_error(path, std::io::Error::from_raw_os_error(53));
+        assert!(matches!(missing.downcast_ref(), Some(DownloadError::NetworkPathUnavailable { .. })));
+        let other = network_path_error(path, std::io::Error::other("boom"));
+        assert!(other.downcast_ref::<DownloadError>().is_none());
+    }
+
+    #[test]
+    fn test_host_port() {
+        let hp = host_port;
+        assert_eq!(hp("https://dl.example.com/a.iso"), Some(("dl.example.com".to_string(), 443)));
+        assert_eq!(hp("http://user:pw@10.0.0.1:8080/x?y"), Some(("10.0.0.1".to_string(), 8080)));
+        assert_eq!(hp("http://[::1]:6800/jsonrpc"), Some(("::1".to_string(), 6800)));
+        assert_eq!(hp("ftp://mirror.example.com"), Some(("mirror.example.com".to_string(), 21)));
+        assert_eq!(hp("magnet:?xt=urn:btih:abc"), None);
+        assert_eq!(hp("http://:80/"), None);
+    }
+
+    #[tokio::test]
+    async fn test_check_connectivity() {
+        let listener = std::net::TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let url = format!("http://127.0.0.1:{}/file.bin", port);
+        assert!(check_connectivity(&url, None).await.is_ok());
+
+        drop(listener);
+        let err = check_connectivity(&url, None).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<DownloadError>(),
+            Some(DownloadError::HostUnreachable { .. })
+        ));
+
+        let err = check_connectivity("https://no-such-host.invalid/a.iso", None).await.unwrap_err();
+        assert!(matches!(err.downcast_ref::<DownloadError>(), Some(DownloadError::Offline { .. })));
+        assert!(check_connectivity("magnet:?xt=urn:btih:abc", None).await.is_ok());
+    }
+}
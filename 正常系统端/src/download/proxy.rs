@@ -0,0 +1,224 @@
+//! 下载代理配置
+//!
+//! - 显式配置：调用 `Aria2Manager::set_proxy`
+//! - 未显式配置时回退到 Windows 系统代理（注册表 Internet Settings）
+//!
+//! 注意：aria2 只支持 HTTP 代理（包括通过 HTTP 代理访问 HTTPS），不支持 SOCKS，
+//! 因此 socks5:// 地址会在配置时直接报错，而不是等到下载失败。
+
+use anyhow::Result;
+
+/// 代理配置
+#[derive(Clone, Default, PartialEq)]
+pub struct ProxyConfig {
+    /// 代理地址，如 `http://proxy.corp.com:8080`
+    pub url: String,
+    pub user: Option<String>,
+    pub password: Option<String>,
+    /// 不走代理的主机（域名或 IP，支持 `.corp.com` 后缀形式）
+    pub no_proxy: Vec<String>,
+}
+
+// 手写 Debug，避免密码出现在日志中
+impl std::fmt::Debug for ProxyConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ProxyConfig")
+            .field("url", &self.url)
+            .field("user", &self.user)
+            .field("password", &self.password.as_ref().map(|_| "***"))
+            .field("no_proxy", &self.no_proxy)
+            .finish()
+    }
+}
+
+impl ProxyConfig {
+    /// 创建代理配置并校验地址
+    pub fn new(url: &str, user: Option<&str>, password: Option<&str>) -> Result<Self> {
+        Ok(Self {
+            url: normalize_proxy_url(url)?,
+            user: user.map(str::to_string),
+            password: password.map(str::to_string),
+            no_proxy: Vec::new(),
+        })
+    }
+
+    /// 设置不走代理的主机列表
+    pub fn with_no_proxy(mut self, hosts: Vec<String>) -> Self {
+        self.no_proxy = hosts;
+        self
+    }
+
+    /// 访问 `host` 时是否不走代理（与 aria2 `no-proxy` 的匹配方式相同：完全相同，或以 `.` 开头的后缀）
+    pub fn bypasses(&self, host: &str) -> bool {
+        self.no_proxy.iter().any(|entry| {
+            if entry.starts_with('.') {
+                host.len() > entry.len() && host[host.len() - entry.len()..].eq_ignore_ascii_case(entry)
+            } else {
+                host.eq_ignore_ascii_case(entry)
+            }
+        })
+    }
+
+    /// 转换为 aria2 选项（键值对），全局选项和任务选项通用
+    pub fn to_aria2_options(&self) -> Vec<(&'static str, String)> {
+        let mut options = vec![("all-proxy", self.url.clone())];
+        if let Some(user) = &self.user {
+            options.push(("all-proxy-user", user.clone()));
+        }
+        if let Some(password) = &self.password {
+            options.push(("all-proxy-passwd", password.clone()));
+        }
+        if !self.no_proxy.is_empty() {
+            options.push(("no-proxy", self.no_proxy.join(",")));
+        }
+        options
+    }
+}
+
+/// 校验并规范化代理地址；未写协议时按 http:// 处理
+pub fn normalize_proxy_url(url: &str) -> Result<String> {
+    let url = url.trim();
+    if url.is_empty() {
+        anyhow::bail!("代理地址为空");
+    }
+
+    let Some((scheme, rest)) = url.split_once("://") else {
+        return Ok(format!("http://{}", url));
+    };
+    if rest.is_empty() {
+        anyhow::bail!("代理地址缺少主机名: {}", url);
+    }
+
+    match scheme.to_ascii_lowercase().as_str() {
+        "http" | "https" => Ok(url.to_string()),
+        "socks" | "socks4" | "socks5" | "socks5h" => {
+            anyhow::bail!("aria2 不支持 SOCKS 代理（{}），请改用 HTTP 代理", url)
+        }
+        other => anyhow::bail!("不支持的代理协议: {}", other),
+    }
+}
+
+/// 解析注册表 ProxyServer 值
+///
+/// 可能是 `host:port`，也可能按协议分别设置：`http=h1:80;https=h2:443;socks=h3:1080`。
+/// 分协议时优先 https，其次 http；只配置了 socks 时返回 None（aria2 不支持）。
+pub fn parse_proxy_server(value: &str) -> Option<String> {
+    let value = value.trim();
+    if value.is_empty() {
+        return None;
+    }
+    if !value.contains('=') {
+        return Some(value.to_string());
+    }
+
+    let entries: Vec<(&str, &str)> = value
+        .split(';')
+        .filter_map(|e| e.split_once('='))
+        .map(|(k, v)| (k.trim(), v.trim()))
+        .collect();
+    ["https", "http"].iter().find_map(|wanted| {
+        entries
+            .iter()
+            .find(|(k, v)| k.eq_ignore_ascii_case(wanted) && !v.is_empty())
+            .map(|(_, v)| v.to_string())
+    })
+}
+
+/// 解析注册表 ProxyOverride 值为 no-proxy 列表
+///
+/// `<local>` 表示本地地址，转换为 localhost/127.0.0.1；aria2 不支持通配符，
+/// `*.corp.com` 转为 `.corp.com`，其它带通配符的条目（如 `10.*`）无法表达，直接忽略。
+pub fn parse_proxy_override(value: &str) -> Vec<String> {
+    let mut hosts = Vec::new();
+    for entry in value.split(';').map(str::trim).filter(|e| !e.is_empty()) {
+        if entry.eq_ignore_ascii_case("<local>") {
+            hosts.push("localhost".to_string());
+            hosts.push("127.0.0.1".to_string());
+        } else {
+            let host = entry.trim_start_matches('*');
+            if !host.is_empty() && !host.contains('*') {
+                hosts.push(host.to_string());
+            }
+        }
+    }
+    hosts
+}
+
+/// 读取 Windows 系统代理（当前用户的 Internet Settings）
+#[cfg(windows)]
+pub fn read_system_proxy() -> Option<ProxyConfig> {
+    use winreg::enums::HKEY_CURRENT_USER;
+    use winreg::RegKey;
+
+    let key = RegKey::predef(HKEY_CURRENT_USER)
+        .open_subkey(r"Software\Microsoft\Windows\CurrentVersion\Internet Settings")
+        .ok()?;
+    let enabled: u32 = key.get_value("ProxyEnable").unwrap_or(0);
+    if enabled == 0 {
+        return None;
+    }
+
+    let server: String = key.get_value("ProxyServer").ok()?;
+    let url = normalize_proxy_url(&parse_proxy_server(&server)?).ok()?;
+    let overrides: String = key.get_value("ProxyOverride").unwrap_or_default();
+
+    Some(ProxyConfig {
+        url,
+        no_proxy: parse_proxy_override(&overrides),
+        ..Default::default()
+    })
+}
+
+/// 非 Windows 平台没有注册表代理设置
+#[cfg(not(windows))]
+pub fn read_system_proxy() -> Option<ProxyConfig> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_proxy_url() {
+        assert_eq!(normalize_proxy_url("proxy:8080").unwrap(), "http://proxy:8080");
+        assert_eq!(normalize_proxy_url("https://p:443").unwrap(), "https://p:443");
+        assert!(normalize_proxy_url("socks5://p:1080").is_err());
+        assert!(normalize_proxy_url("ftp://p").is_err());
+        assert!(normalize_proxy_url(" ").is_err());
+    }
+
+    #[test]
+    fn test_parse_system_proxy_values() {
+        assert_eq!(parse_proxy_server("10.0.0.1:3128").as_deref(), Some("10.0.0.1:3128"));
+        assert_eq!(
+            parse_proxy_server("http=h1:80;https=h2:443;socks=h3:1080").as_deref(),
+            Some("h2:443")
+        );
+        assert_eq!(parse_proxy_server("socks=h3:1080"), None);
+        assert_eq!(
+            parse_proxy_override("<local>;*.corp.com;10.*"),
+            vec!["localhost", "127.0.0.1", ".corp.com"]
+        );
+    }
+
+    #[test]
+    fn test_bypasses() {
+        let proxy = ProxyConfig::new("proxy:8080", None, None)
+            .unwrap()
+            .with_no_proxy(vec!["localhost".to_string(), ".corp.com".to_string()]);
+        assert!(proxy.bypasses("LOCALHOST"));
+        assert!(proxy.bypasses("files.corp.com"));
+        assert!(!proxy.bypasses("corp.com"));
+        assert!(!proxy.bypasses("example.com"));
+    }
+
+    #[test]
+    fn test_password_not_in_debug_output() {
+        let proxy = ProxyConfig::new("proxy:8080", Some("user"), Some("s3cret")).unwrap();
+        assert!(!format!("{:?}", proxy).contains("s3cret"));
+        assert!(proxy
+            .to_aria2_options()
+            .contains(&("all-proxy-passwd", "s3cret".to_string())));
+    }
+}
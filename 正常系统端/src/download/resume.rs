@@ -0,0 +1,156 @@
+//! 未完成下载的续传信息
+//!
+//! aria2 在下载过程中把分片完成情况写入与文件同目录的 `<文件>.aria2` 控制文件，
+//! 重新添加同一路径的任务时据此续传。这里只读取控制文件头部，得到文件总大小和已完成的分片，
+//! 用于在添加前告诉用户「已下载 3.2 GB，继续下载」还是「从头开始」。
+
+use std::path::{Path, PathBuf};
+
+use crate::utils::path::to_extended_path;
+
+/// aria2 控制文件的后缀
+const CONTROL_SUFFIX: &str = ".aria2";
+
+/// 目标文件的续传状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ResumeInfo {
+    /// 磁盘上已有的部分文件大小（预分配时即为完整大小，不代表已下载的量）
+    pub partial_bytes: u64,
+    pub control_file_present: bool,
+    /// 控制文件记录的文件总大小（控制文件不存在或无法解析时为 None）
+    pub total_length: Option<u64>,
+    /// 控制文件记录的已完成分片的字节数
+    pub completed_bytes: Option<u64>,
+}
+
+impl ResumeInfo {
+    /// 重新添加时 aria2 会续传：部分文件和控制文件都在
+    ///
+    /// 只有部分文件没有控制文件时，aria2 无法得知哪些分片已完成，会从头下载。
+    pub fn can_resume(&self) -> bool {
+        self.control_file_present && self.partial_bytes > 0
+    }
+
+    /// 已下载的字节数：优先用控制文件记录的已完成分片，否则用部分文件大小
+    pub fn downloaded_bytes(&self) -> u64 {
+        self.completed_bytes.unwrap_or(self.partial_bytes)
+    }
+}
+
+/// 文件对应的 aria2 控制文件路径（`<文件>.aria2`）
+pub fn control_file_path(path: &Path) -> PathBuf {
+    let mut control = path.as_os_str().to_owned();
+    control.push(CONTROL_SUFFIX);
+    PathBuf::from(control)
+}
+
+/// 检查 `path`（aria2 写入的文件路径，使用临时名时为 `.part` 文件）的续传状态
+pub fn is_resumable(path: &Path) -> ResumeInfo {
+    let path = &to_extended_path(path);
+    let partial_bytes = std::fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+    let control = std::fs::read(control_file_path(path)).ok();
+    let parsed = control.as_deref().and_then(parse_control_file);
+    ResumeInfo {
+        partial_bytes,
+        control_file_present: control.is_some(),
+        total_length: parsed.map(|(total, _)| total),
+        completed_bytes: parsed.map(|(_, completed)| completed),
+    }
+}
+
+/// 解析控制文件头部，返回 (总大小, 已完成字节数)
+///
+/// 格式见 aria2 文档「CONTROL FILE」一节：版本 1 为大端序，版本 0 为写入时的主机字节序（Windows 上为小端序）。
+fn parse_control_file(bytes: &[u8]) -> Option<(u64, u64)> {
+    let mut reader = Reader {
+        bytes,
+        pos: 0,
+        big_endian: true,
+    };
+    let version = u16::from_be_bytes(reader.take(2)?.try_into().ok()?);
+    reader.big_endian = match version {
+        1 => true,
+        0 => false,
+        _ => return None,
+    };
+    reader.take(4)?; // 扩展标志
+    let info_hash_len = reader.u32()? as usize;
+    reader.take(info_hash_len)?;
+    let piece_length = reader.u32()? as u64;
+    let total_length = reader.u64()?;
+    reader.take(8)?; // 已上传字节数
+    let bitfield_len = reader.u32()? as usize;
+    let bitfield = reader.take(bitfield_len)?;
+
+    let pieces: u64 = bitfield.iter().map(|b| b.count_ones() as u64).sum();
+    Some((total_length, (pieces * piece_length).min(total_length)))
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    big_endian: bool,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        let slice = self.bytes.get(self.pos..self.pos.checked_add(n)?)?;
+        self.pos += n;
+        Some(slice)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        let bytes = self.take(4)?.try_into().ok()?;
+        Some(if self.big_endian { u32::from_be_bytes(bytes) } else { u32::from_le_bytes(bytes) })
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        let bytes = self.take(8)?.try_into().ok()?;
+        Some(if self.big_endian { u64::from_be_bytes(bytes) } else { u64::from_le_bytes(bytes) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 版本 1 的控制文件：分片 1 MiB，总大小 2.5 MiB，已完成第 1、3 片
+    fn control_v1() -> Vec<u8> {
+        let mut bytes = vec![0x00, 0x01, 0, 0, 0, 0];
+        bytes.extend_from_slice(&0u32.to_be_bytes());
+        bytes.extend_from_slice(&(1024 * 1024u32).to_be_bytes());
+        bytes.extend_from_slice(&(5 * 512 * 1024u64).to_be_bytes());
+        bytes.extend_from_slice(&0u64.to_be_bytes());
+        bytes.extend_from_slice(&1u32.to_be_bytes());
+        bytes.push(0b1010_0000);
+        bytes
+    }
+
+    #[test]
+    fn test_parse_control_file() {
+        assert_eq!(parse_control_file(&control_v1()), Some((5 * 512 * 1024, 2 * 1024 * 1024)));
+        assert_eq!(parse_control_file(&control_v1()[..20]), None);
+        assert_eq!(parse_control_file(&[0x00, 0x07]), None);
+    }
+
+    #[test]
+    fn test_is_resumable() {
+        let dir = std::env::temp_dir().join(format!("lr_resume_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("install.esd.part");
+
+        assert_eq!(is_resumable(&path), ResumeInfo::default());
+
+        std::fs::write(&path, vec![0u8; 4096]).unwrap();
+        let info = is_resumable(&path);
+        assert!(!info.can_resume());
+        assert_eq!(info.downloaded_bytes(), 4096);
+
+        std::fs::write(control_file_path(&path), control_v1()).unwrap();
+        let info = is_resumable(&path);
+        assert!(info.can_resume());
+        assert_eq!(info.total_length, Some(5 * 512 * 1024));
+        assert_eq!(info.downloaded_bytes(), 2 * 1024 * 1024);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,178 @@
+//! 按时段限速：例如工作时间限速 1 MB/s、夜间全速，或在某些时段完全暂停下载
+//!
+//! 规则随 config.json 保存（`AppConfig::bandwidth_schedule`），启动全局管理器时读入。
+//! 管理器的后台任务定期按本地时间计算当前生效的限制，跨过时段边界时才调用全局限速或暂停/恢复任务。
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// 一天中的时刻（本地时间，精确到分钟），在配置文件中写作 "HH:MM"
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct TimeOfDay {
+    minutes: u16,
+}
+
+impl TimeOfDay {
+    pub fn new(hour: u16, minute: u16) -> Option<Self> {
+        (hour < 24 && minute < 60).then_some(Self {
+            minutes: hour * 60 + minute,
+        })
+    }
+
+    /// 当前本地时间
+    pub fn now() -> Self {
+        use chrono::Timelike;
+        let now = chrono::Local::now();
+        Self {
+            minutes: (now.hour() * 60 + now.minute()) as u16,
+        }
+    }
+}
+
+impl fmt::Display for TimeOfDay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:02}:{:02}", self.minutes / 60, self.minutes % 60)
+    }
+}
+
+impl TryFrom<String> for TimeOfDay {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        let parsed = s
+            .trim()
+            .split_once(':')
+            .and_then(|(h, m)| Some((h.parse().ok()?, m.parse().ok()?)))
+            .and_then(|(h, m)| Self::new(h, m));
+        parsed.ok_or_else(|| format!("时间格式应为 HH:MM: {}", s))
+    }
+}
+
+impl From<TimeOfDay> for String {
+    fn from(time: TimeOfDay) -> Self {
+        time.to_string()
+    }
+}
+
+/// 时段内的动作
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScheduleAction {
+    /// 全局限速（字节/秒）
+    Limit(u64),
+    /// 暂停全部任务，离开时段后恢复
+    Pause,
+}
+
+/// 一条时段规则：`start` 到 `end` 之间（不含 `end`）生效
+///
+/// `start` 晚于 `end` 表示跨过午夜（如 22:00-06:00），两者相同表示全天。
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScheduleRule {
+    pub start: TimeOfDay,
+    pub end: TimeOfDay,
+    pub action: ScheduleAction,
+}
+
+impl ScheduleRule {
+    pub fn contains(&self, time: TimeOfDay) -> bool {
+        match self.start.cmp(&self.end) {
+            std::cmp::Ordering::Less => self.start <= time && time < self.end,
+            std::cmp::Ordering::Greater => time >= self.start || time < self.end,
+            std::cmp::Ordering::Equal => true,
+        }
+    }
+}
+
+/// 某一时刻生效的限制
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ScheduleEffect {
+    /// 全局限速（字节/秒），None 表示没有限速规则生效（恢复规则之外的限速）
+    pub limit: Option<u64>,
+    pub paused: bool,
+}
+
+/// 全部时段规则
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BandwidthSchedule {
+    #[serde(default)]
+    pub rules: Vec<ScheduleRule>,
+}
+
+impl BandwidthSchedule {
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// 限速为 0 的规则在 aria2 中表示不限速，与规则的本意相反，视为错误（要停止下载请用 `Pause`）
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if let Some(rule) = self.rules.iter().find(|r| r.action == ScheduleAction::Limit(0)) {
+            anyhow::bail!("{}-{} 的限速不能为 0，暂停下载请使用暂停规则", rule.start, rule.end);
+        }
+        Ok(())
+    }
+
+    /// `time` 时生效的限制：多条规则重叠时取最严格的（任一暂停即暂停，限速取最小值）
+    pub fn effect_at(&self, time: TimeOfDay) -> ScheduleEffect {
+        let active = || self.rules.iter().filter(|r| r.contains(time));
+        ScheduleEffect {
+            limit: active()
+                .filter_map(|r| match r.action {
+                    ScheduleAction::Limit(limit) => Some(limit),
+                    ScheduleAction::Pause => None,
+                })
+                .min(),
+            paused: active().any(|r| r.action == ScheduleAction::Pause),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(hour: u16, minute: u16) -> TimeOfDay {
+        TimeOfDay::new(hour, minute).unwrap()
+    }
+
+    fn rule(start: TimeOfDay, end: TimeOfDay, action: ScheduleAction) -> ScheduleRule {
+        ScheduleRule { start, end, action }
+    }
+
+    #[test]
+    fn test_effect_takes_most_restrictive() {
+        let schedule = BandwidthSchedule {
+            rules: vec![
+                rule(at(9, 0), at(18, 0), ScheduleAction::Limit(1024 * 1024)),
+                rule(at(12, 0), at(13, 0), ScheduleAction::Limit(256 * 1024)),
+                rule(at(23, 0), at(6, 0), ScheduleAction::Pause),
+            ],
+        };
+        assert_eq!(schedule.effect_at(at(8, 59)), ScheduleEffect::default());
+        assert_eq!(schedule.effect_at(at(9, 0)).limit, Some(1024 * 1024));
+        assert_eq!(schedule.effect_at(at(12, 30)).limit, Some(256 * 1024));
+        assert_eq!(schedule.effect_at(at(18, 0)).limit, None);
+        // 跨午夜的暂停时段
+        assert!(schedule.effect_at(at(23, 30)).paused);
+        assert!(schedule.effect_at(at(5, 59)).paused);
+        assert!(!schedule.effect_at(at(6, 0)).paused);
+    }
+
+    #[test]
+    fn test_schedule_round_trips_through_json() {
+        let schedule = BandwidthSchedule {
+            rules: vec![rule(at(22, 0), at(7, 30), ScheduleAction::Pause)],
+        };
+        let json = serde_json::to_value(&schedule).unwrap();
+        assert_eq!(json["rules"][0]["start"], "22:00");
+        assert_eq!(serde_json::from_value::<BandwidthSchedule>(json).unwrap(), schedule);
+
+        assert!(TimeOfDay::try_from("24:00".to_string()).is_err());
+        assert!(TimeOfDay::try_from("7:5".to_string()).is_ok());
+        let zero = BandwidthSchedule {
+            rules: vec![rule(at(9, 0), at(17, 0), ScheduleAction::Limit(0))],
+        };
+        assert!(zero.validate().is_err());
+    }
+}
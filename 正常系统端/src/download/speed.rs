@@ -0,0 +1,70 @@
+//! 下载速度平滑
+//!
+//! aria2 报告的瞬时速度在两次查询之间可能相差一个数量级，直接显示会让速度和剩余时间不停跳动。
+//! 这里对每个任务保留最近若干次的速度，取加权平均（越新的样本权重越大）。
+
+use std::collections::VecDeque;
+
+/// 保留的样本数
+const SAMPLES: usize = 10;
+
+/// 单个任务最近的速度样本
+#[derive(Debug, Clone, Default)]
+pub struct SpeedHistory {
+    samples: VecDeque<u64>,
+}
+
+impl SpeedHistory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 记录一次瞬时速度，返回平滑后的速度
+    pub fn push(&mut self, speed: u64) -> u64 {
+        if self.samples.len() == SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(speed);
+        self.average()
+    }
+
+    /// 线性加权平均：第 i 个样本（从旧到新，从 1 开始）的权重为 i；没有样本时为 0
+    pub fn average(&self) -> u64 {
+        let (sum, weights) = self
+            .samples
+            .iter()
+            .zip(1u128..)
+            .fold((0u128, 0u128), |(sum, weights), (&speed, w)| (sum + speed as u128 * w, weights + w));
+        sum.checked_div(weights).unwrap_or(0) as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_average_smooths_spikes() {
+        let mut history = SpeedHistory::new();
+        assert_eq!(history.average(), 0);
+        assert_eq!(history.push(1000), 1000);
+        for _ in 0..8 {
+            history.push(1000);
+        }
+        // 一次尖峰只拉高一部分
+        let avg = history.push(10_000);
+        assert!(avg > 1000 && avg < 3000, "{}", avg);
+    }
+
+    #[test]
+    fn test_old_samples_drop_out() {
+        let mut history = SpeedHistory::new();
+        for _ in 0..SAMPLES {
+            history.push(5000);
+        }
+        for _ in 0..SAMPLES {
+            history.push(100);
+        }
+        assert_eq!(history.average(), 100);
+    }
+}
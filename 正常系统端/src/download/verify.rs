@@ -0,0 +1,329 @@
+//! 按镜像站发布的校验文件（SHA256SUMS、MD5SUMS 等）核对下载的文件
+//!
+//! 镜像站通常在镜像旁边放一个校验文件，不必把哈希值写进我们自己的清单。支持两种格式：
+//! - GNU coreutils：`<哈希>  <文件名>`（二进制模式为 `<哈希> *<文件名>`），算法按哈希长度判断
+//! - BSD：`SHA256 (<文件名>) = <哈希>`
+//!
+//! 带 PGP 签名的校验文件只读取签名内的正文，不验证签名本身。
+
+use anyhow::Result;
+use std::path::Path;
+use std::time::Duration;
+
+use super::aria2_config::{Aria2Config, TlsOptions};
+use super::preflight;
+use super::proxy::ProxyConfig;
+use crate::utils::hash::{hash_file, HashType};
+use crate::utils::path::to_extended_path;
+
+/// 校验文件的大小上限，防止把地址误填成镜像本身时下载数 GB 的内容
+const MAX_CHECKSUM_FILE_SIZE: u64 = 1 << 20;
+
+/// 下载校验文件的超时
+const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// 校验文件中的一条记录
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChecksumEntry {
+    pub algo: HashType,
+    /// 小写十六进制
+    pub hash: String,
+    /// 记录中的文件名（可能带有相对路径，如 `./images/win11.iso`）
+    pub file_name: String,
+}
+
+/// 按校验文件核对失败的原因（下载或读取校验文件失败、计算哈希失败以普通错误返回）
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ChecksumFileError {
+    #[error("校验文件 {source_name} 格式无法识别：{reason}")]
+    Malformed { source_name: String, reason: String },
+
+    #[error("校验文件 {source_name} 中没有 {file_name} 的记录")]
+    MissingEntry { source_name: String, file_name: String },
+
+    #[error("{file_name} 的 {algo} 哈希值与校验文件不一致：应为 {expected}，实际为 {actual}")]
+    Mismatch {
+        file_name: String,
+        algo: &'static str,
+        expected: String,
+        actual: String,
+    },
+}
+
+/// 下载（地址以 http:// 或 https:// 开头）或读取校验文件，找到 `file_path` 对应的记录并计算哈希核对
+///
+/// 本地校验文件的相对路径基于 `file_path` 所在目录（如直接写 `SHA256SUMS`）。
+/// 通过时返回使用的记录；失败时的错误可 downcast 为 `ChecksumFileError`。
+pub async fn verify_with_checksum_file(file_path: &Path, checksum_url_or_path: &str) -> Result<ChecksumEntry> {
+    let user_agent = Aria2Config::default().user_agent;
+    verify_against(file_path, checksum_url_or_path, None, &TlsOptions::default(), &user_agent).await
+}
+
+/// 同 `verify_with_checksum_file`，下载校验文件时使用下载管理器的代理、证书设置和 User-Agent
+pub(super) async fn verify_against(
+    file_path: &Path,
+    source: &str,
+    proxy: Option<&ProxyConfig>,
+    tls: &TlsOptions,
+    user_agent: &str,
+) -> Result<ChecksumEntry> {
+    let file_name = file_path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .ok_or_else(|| anyhow::anyhow!("路径中没有文件名: {}", file_path.display()))?;
+    let text = load_checksum_file(file_path, source, proxy, tls, user_agent).await?;
+    let entries = parse_checksum_file(&text, source)?;
+    let entry = find_entry(&entries, &file_name)
+        .cloned()
+        .ok_or_else(|| ChecksumFileError::MissingEntry {
+            source_name: source.to_string(),
+            file_name: file_name.clone(),
+        })?;
+
+    log::info!("[校验] 按 {} 核对 {} 的 {} 哈希值", source, file_name, entry.algo.aria2_name());
+    let actual = hash_file(file_path.to_path_buf(), entry.algo, |_, _| true).await?;
+    if actual != entry.hash {
+        return Err(ChecksumFileError::Mismatch {
+            file_name,
+            algo: entry.algo.aria2_name(),
+            expected: entry.hash,
+            actual,
+        }
+        .into());
+    }
+    log::info!("[校验] {} 校验通过", file_name);
+    Ok(entry)
+}
+
+async fn load_checksum_file(
+    file_path: &Path,
+    source: &str,
+    proxy: Option<&ProxyConfig>,
+    tls: &TlsOptions,
+    user_agent: &str,
+) -> Result<String> {
+    let is_url = ["http://", "https://"]
+        .iter()
+        .any(|scheme| source.get(..scheme.len()).is_some_and(|s| s.eq_ignore_ascii_case(scheme)));
+    let bytes = if is_url {
+        let client = preflight::http_client(FETCH_TIMEOUT, user_agent, proxy, tls)?;
+        let mut response = client.get(source).send().await?;
+        if !response.status().is_success() {
+            anyhow::bail!("下载校验文件 {} 失败: HTTP {}", source, response.status());
+        }
+        if response.content_length().is_some_and(|len| len > MAX_CHECKSUM_FILE_SIZE) {
+            anyhow::bail!("{} 超过 {} KB，不像是校验文件", source, MAX_CHECKSUM_FILE_SIZE / 1024);
+        }
+        // 服务器可能不给 Content-Length（分块传输）或给错，边读边检查
+        let mut bytes = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            if (bytes.len() + chunk.len()) as u64 > MAX_CHECKSUM_FILE_SIZE {
+                anyhow::bail!("{} 超过 {} KB，不像是校验文件", source, MAX_CHECKSUM_FILE_SIZE / 1024);
+            }
+            bytes.extend_from_slice(&chunk);
+        }
+        bytes
+    } else {
+        let path = match Path::new(source) {
+            p if p.is_relative() => file_path.parent().map_or_else(|| p.to_path_buf(), |dir| dir.join(p)),
+            p => p.to_path_buf(),
+        };
+        read_local(&path)?
+    };
+    if bytes.len() as u64 > MAX_CHECKSUM_FILE_SIZE {
+        anyhow::bail!("{} 超过 {} KB，不像是校验文件", source, MAX_CHECKSUM_FILE_SIZE / 1024);
+    }
+    let text = String::from_utf8_lossy(&bytes);
+    Ok(text.strip_prefix('\u{feff}').unwrap_or(&text).to_string())
+}
+
+fn read_local(path: &Path) -> Result<Vec<u8>> {
+    let extended = to_extended_path(path);
+    let len = std::fs::metadata(&extended)
+        .map_err(|e| anyhow::anyhow!("读取校验文件 {} 失败: {}", path.display(), e))?
+        .len();
+    if len > MAX_CHECKSUM_FILE_SIZE {
+        anyhow::bail!("{} 超过 {} KB，不像是校验文件", path.display(), MAX_CHECKSUM_FILE_SIZE / 1024);
+    }
+    Ok(std::fs::read(&extended)?)
+}
+
+/// 解析校验文件；`source_name` 只用于错误信息
+///
+/// 空行、`#` 注释和 PGP 签名的包装行被忽略，其它无法识别的行视为格式错误，而不是跳过，
+/// 以免把数字签名或网页错误页当作「没有该文件的记录」。
+pub fn parse_checksum_file(text: &str, source_name: &str) -> std::result::Result<Vec<ChecksumEntry>, ChecksumFileError> {
+    let malformed = |reason: String| ChecksumFileError::Malformed {
+        source_name: source_name.to_string(),
+        reason,
+    };
+    let mut entries = Vec::new();
+    let mut in_signature = false;
+    for (index, raw) in text.lines().enumerate() {
+        let line = raw.trim();
+        if line.starts_with("-----BEGIN PGP SIGNATURE") {
+            in_signature = true;
+        } else if line.starts_with("-----END PGP SIGNATURE") {
+            in_signature = false;
+        }
+        if in_signature || line.is_empty() || line.starts_with('#') || line.starts_with("-----") || line.starts_with("Hash:") {
+            continue;
+        }
+        let entry = parse_bsd_line(line)
+            .or_else(|| parse_gnu_line(line))
+            .ok_or_else(|| malformed(format!("第 {} 行不是「哈希值  文件名」或「SHA256 (文件名) = 哈希值」格式", index + 1)))?;
+        entries.push(entry.map_err(|algo| malformed(format!("第 {} 行的算法 {} 不受支持", index + 1, algo)))?);
+    }
+    if entries.is_empty() {
+        return Err(malformed("没有任何校验记录".to_string()));
+    }
+    Ok(entries)
+}
+
+/// `SHA256 (win11.iso) = <哈希>`；格式不符时为 None，算法不受支持时为 `Some(Err(算法名))`
+fn parse_bsd_line(line: &str) -> Option<std::result::Result<ChecksumEntry, String>> {
+    let (tag, rest) = line.split_once(" (")?;
+    let (file_name, hash) = rest.rsplit_once(") = ")?;
+    let algo = match tag.trim().replace('-', "").to_ascii_uppercase().as_str() {
+        "SHA256" => HashType::Sha256,
+        "SHA1" => HashType::Sha1,
+        "MD5" => HashType::Md5,
+        _ => return Some(Err(tag.trim().to_string())),
+    };
+    let hash = hash.trim();
+    (hash.len() == algo.hex_len() && is_hex(hash)).then(|| {
+        Ok(ChecksumEntry {
+            algo,
+            hash: hash.to_ascii_lowercase(),
+            file_name: file_name.to_string(),
+        })
+    })
+}
+
+/// `<哈希>  win11.iso` 或 `<哈希> *win11.iso`；算法按哈希长度判断
+fn parse_gnu_line(line: &str) -> Option<std::result::Result<ChecksumEntry, String>> {
+    let (hash, rest) = line.split_once(char::is_whitespace)?;
+    let file_name = rest.trim_start();
+    let file_name = file_name.strip_prefix('*').unwrap_or(file_name);
+    if file_name.is_empty() || !is_hex(hash) {
+        return None;
+    }
+    let algo = [HashType::Sha256, HashType::Sha1, HashType::Md5]
+        .into_iter()
+        .find(|algo| algo.hex_len() == hash.len());
+    Some(match algo {
+        Some(algo) => Ok(ChecksumEntry {
+            algo,
+            hash: hash.to_ascii_lowercase(),
+            file_name: file_name.to_string(),
+        }),
+        None => Err(format!("{} 位哈希", hash.len() * 4)),
+    })
+}
+
+fn is_hex(s: &str) -> bool {
+    !s.is_empty() && s.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// 找到 `file_name` 的记录：只比较最后一段文件名（不区分大小写），同一文件有多种算法时取最强的
+pub fn find_entry<'a>(entries: &'a [ChecksumEntry], file_name: &str) -> Option<&'a ChecksumEntry> {
+    let strength = |algo: HashType| match algo {
+        HashType::Sha256 => 2,
+        HashType::Sha1 => 1,
+        HashType::Md5 => 0,
+    };
+    entries
+        .iter()
+        .filter(|entry| {
+            let name = entry.file_name.rsplit(['/', '\\']).next().unwrap_or(&entry.file_name);
+            name.to_lowercase() == file_name.to_lowercase()
+        })
+        .max_by_key(|entry| strength(entry.algo))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ABC_SHA256: &str = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+    const ABC_MD5: &str = "900150983cd24fb0d6963f7d28e17f72";
+
+    #[test]
+    fn test_parse_both_formats() {
+        let text = format!(
+            "-----BEGIN PGP SIGNED MESSAGE-----\nHash: SHA512\n\n# 镜像校验\n{}  ./images/Win11.iso\n{} *boot.wim\nMD5 (Win11.iso) = {}\n-----BEGIN PGP SIGNATURE-----\niQIzBAEBCgAdFiEE\n-----END PGP SIGNATURE-----\n",
+            ABC_SHA256.to_ascii_uppercase(),
+            ABC_MD5,
+            ABC_MD5
+        );
+        let entries = parse_checksum_file(&text, "SHA256SUMS").unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[1].algo, HashType::Md5);
+        assert_eq!(entries[1].file_name, "boot.wim");
+
+        // 同一文件有 SHA-256 和 MD5 两条记录时取 SHA-256，文件名不区分大小写
+        let entry = find_entry(&entries, "win11.iso").unwrap();
+        assert_eq!((entry.algo, entry.hash.as_str()), (HashType::Sha256, ABC_SHA256));
+        assert!(find_entry(&entries, "install.esd").is_none());
+    }
+
+    #[test]
+    fn test_malformed_checksum_file() {
+        let err = parse_checksum_file("<html>404 Not Found</html>\n", "SHA256SUMS").unwrap_err();
+        assert!(matches!(err, ChecksumFileError::Malformed { ref reason, .. } if reason.starts_with("第 1 行")));
+        assert!(parse_checksum_file("\n# 空\n", "SHA256SUMS").is_err());
+        let sha512 = format!("{}  a.iso\n", "a".repeat(128));
+        let err = parse_checksum_file(&sha512, "SHA512SUMS").unwrap_err();
+        assert!(err.to_string().contains("512 位哈希"));
+    }
+
+    #[tokio::test]
+    async fn test_verify_with_local_checksum_file() {
+        let dir = std::env::temp_dir().join(format!("lr_verify_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let image = dir.join("win11.iso");
+        std::fs::write(&image, b"abc").unwrap();
+        std::fs::write(dir.join("SHA256SUMS"), format!("{}  win11.iso\n", ABC_SHA256)).unwrap();
+        std::fs::write(dir.join("BAD"), format!("{}  win11.iso\n", "0".repeat(64))).unwrap();
+        std::fs::write(dir.join("OTHER"), format!("{}  boot.wim\n", ABC_SHA256)).unwrap();
+
+        // 相对路径基于镜像所在目录
+        let entry = verify_with_checksum_file(&image, "SHA256SUMS").await.unwrap();
+        assert_eq!(entry.algo, HashType::Sha256);
+
+        let err = verify_with_checksum_file(&image, &dir.join("BAD").to_string_lossy()).await.unwrap_err();
+        assert!(matches!(err.downcast_ref::<ChecksumFileError>(), Some(ChecksumFileError::Mismatch { .. })));
+        let err = verify_with_checksum_file(&image, "OTHER").await.unwrap_err();
+        assert!(matches!(err.downcast_ref::<ChecksumFileError>(), Some(ChecksumFileError::MissingEntry { .. })));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_remote_checksum_file_is_capped_while_streaming() {
+        use std::io::{Read, Write};
+
+        // 不带 Content-Length、以关闭连接结束的响应体，超过上限
+        let listener = std::net::TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let url = format!("http://{}/SHA256SUMS", listener.local_addr().unwrap());
+        let (tx, rx) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            if let Some(mut stream) = listener.incoming().flatten().next() {
+                let mut request = [0u8; 4096];
+                let n = stream.read(&mut request).unwrap_or(0);
+                let _ = tx.send(String::from_utf8_lossy(&request[..n]).to_string());
+                let _ = stream.write_all(b"HTTP/1.1 200 OK\r\nConnection: close\r\n\r\n");
+                let line = format!("{}  win11.iso\n", ABC_SHA256);
+                for _ in 0..=(MAX_CHECKSUM_FILE_SIZE as usize / line.len()) {
+                    if stream.write_all(line.as_bytes()).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+
+        let image = std::env::temp_dir().join("win11.iso");
+        let err = verify_against(&image, &url, None, &TlsOptions::default(), "LetRecovery-test").await.unwrap_err();
+        assert!(err.to_string().contains("不像是校验文件"), "{}", err);
+        assert!(rx.recv().unwrap().to_ascii_lowercase().contains("user-agent: letrecovery-test"));
+    }
+}
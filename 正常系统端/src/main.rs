@@ -18,6 +18,18 @@ pub struct PreloadedConfig {
     pub partitions: Vec<core::disk::Partition>,
 }
 
+/// 以前的运行留下的临时目录超过这个时间后在启动时删除
+const STALE_TEMP_AGE: std::time::Duration = std::time::Duration::from_secs(3 * 24 * 60 * 60);
+
+/// 丢弃时删除本次运行的临时目录
+struct SessionTempCleanup;
+
+impl Drop for SessionTempCleanup {
+    fn drop(&mut self) {
+        utils::path::cleanup_session_temp();
+    }
+}
+
 fn main() -> eframe::Result<()> {
     // 加载应用配置（用于获取日志设置）
     let app_config = core::app_config::AppConfig::load();
@@ -35,11 +47,29 @@ fn main() -> eframe::Result<()> {
         }
     }
 
+    // 退出 main 时（包括 PE 安装等提前返回的路径）删除本次运行的临时目录，并清理以前崩溃留下的
+    let _session_temp = SessionTempCleanup;
+    utils::path::cleanup_stale_session_temps(STALE_TEMP_AGE);
+
     // 初始化国际化系统
     utils::i18n::init(&app_config.language);
 
+    // 确定外部工具所在的 bin 目录（配置文件 > 环境变量 > 程序所在目录）
+    utils::path::init_bin_dir(app_config.bin_dir.as_deref());
+    log::info!(
+        "{}，数据目录: {}（程序所在目录下放置 {} 可切换为便携模式）",
+        utils::path::path_mode(),
+        utils::path::get_app_data_root().display(),
+        utils::path::PORTABLE_FLAG
+    );
+
     log::info!("LetRecovery 启动中...");
 
+    // 本程序崩溃或被强制关闭时，让 7z、DISM 等子进程一起结束，避免它们继续锁定文件
+    if let Err(e) = utils::cmd::init_process_job() {
+        log::warn!("{}，子进程不会随本程序结束", e);
+    }
+
     // 检查命令行参数，处理PE环境下的自动安装/备份
     let args: Vec<String> = std::env::args().collect();
     
@@ -155,9 +155,10 @@ impl App {
                         if log_dir.exists() {
                             #[cfg(windows)]
                             {
-                                let _ = std::process::Command::new("explorer")
-                                    .arg(&log_dir)
-                                    .spawn();
+                                let _ = crate::utils::cmd::spawn_detached(
+                                    std::path::Path::new("explorer"),
+                                    &[&log_dir.to_string_lossy()],
+                                );
                             }
                         }
                     }
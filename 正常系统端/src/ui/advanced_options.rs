@@ -74,28 +74,15 @@ impl AdvancedOptions {
     /// 脚本目录名称（统一路径）
     const SCRIPTS_DIR: &'static str = "LetRecovery_Scripts";
 
-    /// 获取程序运行目录（exe 所在目录）
-    fn get_program_dir() -> Option<PathBuf> {
-        std::env::current_exe()
-            .ok()
-            .and_then(|p| p.parent().map(|d| d.to_path_buf()))
-    }
-
     /// 获取 Win7 驱动目录（bin\drivers\{usb3|nvme}）
     fn get_win7_driver_dirs() -> (Option<PathBuf>, Option<PathBuf>) {
-        let base = Self::get_program_dir();
-        let usb3 = base
-            .as_ref()
-            .map(|b| b.join("bin").join("drivers").join("usb3"));
-        let nvme = base
-            .as_ref()
-            .map(|b| b.join("bin").join("drivers").join("nvme"));
-        (usb3, nvme)
+        let drivers = crate::utils::path::get_drivers_dir();
+        (Some(drivers.join("usb3")), Some(drivers.join("nvme")))
     }
     
     /// 获取 UefiSeven 目录（bin\uefiseven）
     fn get_uefiseven_dir() -> Option<PathBuf> {
-        Self::get_program_dir().map(|b| b.join("bin").join("uefiseven"))
+        Some(crate::utils::path::get_uefiseven_dir())
     }
     
     /// 显示依赖无人值守的复选框
@@ -310,7 +297,7 @@ log=0
                     disk_num, part_num, letter
                 );
                 
-                let temp_script = std::env::temp_dir().join("efi_mount.txt");
+                let temp_script = crate::utils::path::temp_file("efi_mount", "txt")?;
                 std::fs::write(&temp_script, &diskpart_script)?;
                 
                 let diskpart_result = Command::new("diskpart")
@@ -2,7 +2,7 @@ use egui;
 use std::sync::mpsc;
 
 use crate::app::App;
-use crate::download::aria2::{Aria2Manager, DownloadProgress, DownloadStatus};
+use crate::download::aria2::{Aria2Manager, DownloadErrorKind, DownloadProgress, DownloadStatus};
 
 /// 下载控制命令
 #[derive(Debug, Clone)]
@@ -48,8 +48,7 @@ impl App {
         if let Some(url) = self.pending_download_url.take() {
             let filename = self.pending_download_filename.take();
             let save_path = if self.download_save_path.is_empty() {
-                crate::utils::path::get_exe_dir()
-                    .join("downloads")
+                crate::utils::path::get_downloads_dir()
                     .to_string_lossy()
                     .to_string()
             } else {
@@ -114,34 +113,45 @@ impl App {
                 ui.label(format!("文件: {}", filename));
             }
 
-            // 进度条
-            ui.add(
-                egui::ProgressBar::new(progress.percentage as f32 / 100.0)
-                    .show_percentage()
-                    .animate(progress.status == DownloadStatus::Active),
-            );
+            // 进度条：服务器未给出文件大小时无法计算百分比，只显示已下载的量
+            let bar = match progress.percentage {
+                Some(percentage) => egui::ProgressBar::new(percentage as f32 / 100.0).show_percentage(),
+                None => egui::ProgressBar::new(0.0).text("文件大小未知"),
+            };
+            ui.add(bar.animate(progress.status == DownloadStatus::Active));
 
             // 详细信息
             ui.horizontal(|ui| {
-                ui.label(format!(
-                    "已下载: {} / {}",
-                    Self::format_bytes(progress.completed_length),
-                    Self::format_bytes(progress.total_length)
-                ));
+                if progress.percentage.is_some() {
+                    ui.label(format!(
+                        "已下载: {} / {}",
+                        Self::format_bytes(progress.completed_length),
+                        Self::format_bytes(progress.total_length)
+                    ));
+                } else {
+                    ui.label(format!("已下载: {}", Self::format_bytes(progress.completed_length)));
+                }
                 ui.separator();
                 ui.label(format!(
                     "速度: {}/s",
-                    Self::format_bytes(progress.download_speed)
+                    Self::format_bytes(progress.download_speed_avg)
                 ));
+                if let Some(eta) = progress.eta {
+                    ui.separator();
+                    ui.label(format!("剩余: {}", Self::format_duration(eta)));
+                }
+                ui.separator();
+                ui.label(format!("已用: {}", Self::format_duration(progress.elapsed)));
             });
 
             // 状态
             let status_text = match &progress.status {
-                DownloadStatus::Waiting => "等待中...",
-                DownloadStatus::Active => "下载中...",
-                DownloadStatus::Paused => "已暂停",
-                DownloadStatus::Complete => "下载完成",
-                DownloadStatus::Error(msg) => msg.as_str(),
+                DownloadStatus::Waiting => "等待中...".to_string(),
+                DownloadStatus::Active => "下载中...".to_string(),
+                DownloadStatus::Paused => "已暂停".to_string(),
+                DownloadStatus::Complete => "下载完成".to_string(),
+                DownloadStatus::Error(kind) => kind.to_string(),
+                DownloadStatus::Removed => "已取消".to_string(),
             };
             ui.label(format!("状态: {}", status_text));
 
@@ -149,8 +159,7 @@ impl App {
 
             // 控制按钮 - 使用克隆的状态来判断
             let status = progress.status.clone();
-            let is_complete = status == DownloadStatus::Complete;
-            let is_error = matches!(status, DownloadStatus::Error(_));
+            let is_finished = status.is_finished();
 
             ui.horizontal(|ui| {
                 match status {
@@ -260,7 +269,7 @@ impl App {
                                         self.cleanup_download();
                                         
                                         // 运行软件
-                                        if let Err(e) = std::process::Command::new(&path).spawn() {
+                                        if let Err(e) = crate::utils::cmd::spawn_detached(std::path::Path::new(&path), &[]) {
                                             log::warn!("启动软件失败: {}", e);
                                         }
                                         
@@ -366,7 +375,7 @@ impl App {
                             }
                         }
                     }
-                    DownloadStatus::Error(_) => {
+                    DownloadStatus::Error(_) | DownloadStatus::Removed => {
                         if ui.button("返回").clicked() {
                             // 先获取待执行操作
                             let action = self.pe_download_then_action.take();
@@ -388,10 +397,8 @@ impl App {
                     _ => {}
                 }
 
-                if !is_complete && !is_error {
-                    if ui.button("取消").clicked() {
-                        self.cancel_current_download();
-                    }
+                if !is_finished && ui.button("取消").clicked() {
+                    self.cancel_current_download();
                 }
             });
         } else {
@@ -424,7 +431,7 @@ impl App {
             log::info!("[MD5] 开始计算文件MD5: {}", file_path);
             let start_time = std::time::Instant::now();
             
-            match md5::calculate_file_md5(&file_path) {
+            match crate::utils::md5::calculate_file_md5(&file_path) {
                 Ok(actual_md5) => {
                     let elapsed = start_time.elapsed();
                     log::info!("[MD5] 计算完成，耗时: {:?}, 实际MD5: {}", elapsed, actual_md5);
@@ -526,8 +533,18 @@ impl App {
                         completed_length: 0,
                         total_length: 0,
                         download_speed: 0,
-                        percentage: 0.0,
-                        status: DownloadStatus::Error(format!("创建运行时失败: {}", e)),
+                        download_speed_avg: 0,
+                        percentage: None,
+                        status: DownloadStatus::Error(DownloadErrorKind::Other(0, format!("创建运行时失败: {}", e))),
+                        eta: None,
+                        elapsed: std::time::Duration::ZERO,
+                        file_path: None,
+                        file_count: 0,
+                        connections: 0,
+                        active_uri: None,
+                        files: Vec::new(),
+                        upload_speed: 0,
+                        share_ratio: None,
                     });
                     return;
                 }
@@ -588,8 +605,18 @@ impl App {
                             completed_length: 0,
                             total_length: 0,
                             download_speed: 0,
-                            percentage: 0.0,
-                            status: DownloadStatus::Error(format!("初始化aria2失败: {}", e)),
+                            download_speed_avg: 0,
+                            percentage: None,
+                            status: DownloadStatus::Error(DownloadErrorKind::Other(0, format!("初始化aria2失败: {}", e))),
+                            eta: None,
+                            elapsed: std::time::Duration::ZERO,
+                            file_path: None,
+                            file_count: 0,
+                            connections: 0,
+                            active_uri: None,
+                            files: Vec::new(),
+                            upload_speed: 0,
+                            share_ratio: None,
                         });
                         return;
                     }
@@ -624,8 +651,18 @@ impl App {
                             completed_length: 0,
                             total_length: 0,
                             download_speed: 0,
-                            percentage: 0.0,
-                            status: DownloadStatus::Error(format!("添加任务失败: {}", e)),
+                            download_speed_avg: 0,
+                            percentage: None,
+                            status: DownloadStatus::Error(DownloadErrorKind::Other(0, format!("添加任务失败: {}", e))),
+                            eta: None,
+                            elapsed: std::time::Duration::ZERO,
+                            file_path: None,
+                            file_count: 0,
+                            connections: 0,
+                            active_uri: None,
+                            files: Vec::new(),
+                            upload_speed: 0,
+                            share_ratio: None,
                         });
                         return;
                     }
@@ -654,14 +691,13 @@ impl App {
 
                     match aria2.get_status(&gid).await {
                         Ok(progress) => {
-                            let is_complete = progress.status == DownloadStatus::Complete;
-                            let is_error = matches!(progress.status, DownloadStatus::Error(_));
+                            let is_finished = progress.status.is_finished();
 
                             if progress_tx.send(progress).is_err() {
                                 break; // 接收端已关闭
                             }
 
-                            if is_complete || is_error {
+                            if is_finished {
                                 break;
                             }
                         }
@@ -671,8 +707,18 @@ impl App {
                                 completed_length: 0,
                                 total_length: 0,
                                 download_speed: 0,
-                                percentage: 0.0,
-                                status: DownloadStatus::Error(format!("获取状态失败: {}", e)),
+                                download_speed_avg: 0,
+                                percentage: None,
+                                status: DownloadStatus::Error(DownloadErrorKind::Other(0, format!("获取状态失败: {}", e))),
+                                eta: None,
+                                elapsed: std::time::Duration::ZERO,
+                                file_path: None,
+                                file_count: 0,
+                                connections: 0,
+                                active_uri: None,
+                                files: Vec::new(),
+                                upload_speed: 0,
+                                share_ratio: None,
                             });
                             break;
                         }
@@ -779,235 +825,14 @@ impl App {
             format!("{} B", bytes)
         }
     }
-}
 
-/// MD5计算模块（纯Rust实现，无外部依赖）
-mod md5 {
-    use std::io::Read;
-    use std::path::Path;
-    
-    /// MD5上下文
-    pub struct Md5Context {
-        state: [u32; 4],
-        count: [u32; 2],
-        buffer: [u8; 64],
-    }
-    
-    impl Md5Context {
-        pub fn new() -> Self {
-            Self {
-                state: [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476],
-                count: [0, 0],
-                buffer: [0u8; 64],
-            }
-        }
-        
-        pub fn update(&mut self, input: &[u8]) {
-            let input_len = input.len();
-            let mut index = ((self.count[0] >> 3) & 0x3F) as usize;
-            
-            self.count[0] = self.count[0].wrapping_add((input_len as u32) << 3);
-            if self.count[0] < (input_len as u32) << 3 {
-                self.count[1] = self.count[1].wrapping_add(1);
-            }
-            self.count[1] = self.count[1].wrapping_add((input_len as u32) >> 29);
-            
-            let part_len = 64 - index;
-            let mut i = 0;
-            
-            if input_len >= part_len {
-                self.buffer[index..64].copy_from_slice(&input[..part_len]);
-                let block: [u8; 64] = self.buffer;
-                self.transform(&block);
-                
-                i = part_len;
-                while i + 63 < input_len {
-                    let block: [u8; 64] = input[i..i + 64].try_into().unwrap();
-                    self.transform(&block);
-                    i += 64;
-                }
-                index = 0;
-            }
-            
-            self.buffer[index..index + (input_len - i)].copy_from_slice(&input[i..]);
-        }
-        
-        pub fn finalize(mut self) -> [u8; 16] {
-            let bits: [u8; 8] = [
-                self.count[0] as u8,
-                (self.count[0] >> 8) as u8,
-                (self.count[0] >> 16) as u8,
-                (self.count[0] >> 24) as u8,
-                self.count[1] as u8,
-                (self.count[1] >> 8) as u8,
-                (self.count[1] >> 16) as u8,
-                (self.count[1] >> 24) as u8,
-            ];
-            
-            let index = ((self.count[0] >> 3) & 0x3F) as usize;
-            let pad_len = if index < 56 { 56 - index } else { 120 - index };
-            
-            let mut padding = [0u8; 64];
-            padding[0] = 0x80;
-            self.update(&padding[..pad_len]);
-            self.update(&bits);
-            
-            let mut digest = [0u8; 16];
-            for (i, &s) in self.state.iter().enumerate() {
-                digest[i * 4] = s as u8;
-                digest[i * 4 + 1] = (s >> 8) as u8;
-                digest[i * 4 + 2] = (s >> 16) as u8;
-                digest[i * 4 + 3] = (s >> 24) as u8;
-            }
-            digest
-        }
-        
-        fn transform(&mut self, block: &[u8; 64]) {
-            let mut a = self.state[0];
-            let mut b = self.state[1];
-            let mut c = self.state[2];
-            let mut d = self.state[3];
-            
-            let mut x = [0u32; 16];
-            for i in 0..16 {
-                x[i] = u32::from_le_bytes([
-                    block[i * 4],
-                    block[i * 4 + 1],
-                    block[i * 4 + 2],
-                    block[i * 4 + 3],
-                ]);
-            }
-            
-            // Round 1
-            macro_rules! ff {
-                ($a:expr, $b:expr, $c:expr, $d:expr, $x:expr, $s:expr, $ac:expr) => {
-                    $a = $a.wrapping_add(($b & $c) | (!$b & $d))
-                        .wrapping_add($x)
-                        .wrapping_add($ac);
-                    $a = $a.rotate_left($s).wrapping_add($b);
-                };
-            }
-            
-            ff!(a, b, c, d, x[0], 7, 0xd76aa478);
-            ff!(d, a, b, c, x[1], 12, 0xe8c7b756);
-            ff!(c, d, a, b, x[2], 17, 0x242070db);
-            ff!(b, c, d, a, x[3], 22, 0xc1bdceee);
-            ff!(a, b, c, d, x[4], 7, 0xf57c0faf);
-            ff!(d, a, b, c, x[5], 12, 0x4787c62a);
-            ff!(c, d, a, b, x[6], 17, 0xa8304613);
-            ff!(b, c, d, a, x[7], 22, 0xfd469501);
-            ff!(a, b, c, d, x[8], 7, 0x698098d8);
-            ff!(d, a, b, c, x[9], 12, 0x8b44f7af);
-            ff!(c, d, a, b, x[10], 17, 0xffff5bb1);
-            ff!(b, c, d, a, x[11], 22, 0x895cd7be);
-            ff!(a, b, c, d, x[12], 7, 0x6b901122);
-            ff!(d, a, b, c, x[13], 12, 0xfd987193);
-            ff!(c, d, a, b, x[14], 17, 0xa679438e);
-            ff!(b, c, d, a, x[15], 22, 0x49b40821);
-            
-            // Round 2
-            macro_rules! gg {
-                ($a:expr, $b:expr, $c:expr, $d:expr, $x:expr, $s:expr, $ac:expr) => {
-                    $a = $a.wrapping_add(($b & $d) | ($c & !$d))
-                        .wrapping_add($x)
-                        .wrapping_add($ac);
-                    $a = $a.rotate_left($s).wrapping_add($b);
-                };
-            }
-            
-            gg!(a, b, c, d, x[1], 5, 0xf61e2562);
-            gg!(d, a, b, c, x[6], 9, 0xc040b340);
-            gg!(c, d, a, b, x[11], 14, 0x265e5a51);
-            gg!(b, c, d, a, x[0], 20, 0xe9b6c7aa);
-            gg!(a, b, c, d, x[5], 5, 0xd62f105d);
-            gg!(d, a, b, c, x[10], 9, 0x02441453);
-            gg!(c, d, a, b, x[15], 14, 0xd8a1e681);
-            gg!(b, c, d, a, x[4], 20, 0xe7d3fbc8);
-            gg!(a, b, c, d, x[9], 5, 0x21e1cde6);
-            gg!(d, a, b, c, x[14], 9, 0xc33707d6);
-            gg!(c, d, a, b, x[3], 14, 0xf4d50d87);
-            gg!(b, c, d, a, x[8], 20, 0x455a14ed);
-            gg!(a, b, c, d, x[13], 5, 0xa9e3e905);
-            gg!(d, a, b, c, x[2], 9, 0xfcefa3f8);
-            gg!(c, d, a, b, x[7], 14, 0x676f02d9);
-            gg!(b, c, d, a, x[12], 20, 0x8d2a4c8a);
-            
-            // Round 3
-            macro_rules! hh {
-                ($a:expr, $b:expr, $c:expr, $d:expr, $x:expr, $s:expr, $ac:expr) => {
-                    $a = $a.wrapping_add($b ^ $c ^ $d)
-                        .wrapping_add($x)
-                        .wrapping_add($ac);
-                    $a = $a.rotate_left($s).wrapping_add($b);
-                };
-            }
-            
-            hh!(a, b, c, d, x[5], 4, 0xfffa3942);
-            hh!(d, a, b, c, x[8], 11, 0x8771f681);
-            hh!(c, d, a, b, x[11], 16, 0x6d9d6122);
-            hh!(b, c, d, a, x[14], 23, 0xfde5380c);
-            hh!(a, b, c, d, x[1], 4, 0xa4beea44);
-            hh!(d, a, b, c, x[4], 11, 0x4bdecfa9);
-            hh!(c, d, a, b, x[7], 16, 0xf6bb4b60);
-            hh!(b, c, d, a, x[10], 23, 0xbebfbc70);
-            hh!(a, b, c, d, x[13], 4, 0x289b7ec6);
-            hh!(d, a, b, c, x[0], 11, 0xeaa127fa);
-            hh!(c, d, a, b, x[3], 16, 0xd4ef3085);
-            hh!(b, c, d, a, x[6], 23, 0x04881d05);
-            hh!(a, b, c, d, x[9], 4, 0xd9d4d039);
-            hh!(d, a, b, c, x[12], 11, 0xe6db99e5);
-            hh!(c, d, a, b, x[15], 16, 0x1fa27cf8);
-            hh!(b, c, d, a, x[2], 23, 0xc4ac5665);
-            
-            // Round 4
-            macro_rules! ii {
-                ($a:expr, $b:expr, $c:expr, $d:expr, $x:expr, $s:expr, $ac:expr) => {
-                    $a = $a.wrapping_add($c ^ ($b | !$d))
-                        .wrapping_add($x)
-                        .wrapping_add($ac);
-                    $a = $a.rotate_left($s).wrapping_add($b);
-                };
-            }
-            
-            ii!(a, b, c, d, x[0], 6, 0xf4292244);
-            ii!(d, a, b, c, x[7], 10, 0x432aff97);
-            ii!(c, d, a, b, x[14], 15, 0xab9423a7);
-            ii!(b, c, d, a, x[5], 21, 0xfc93a039);
-            ii!(a, b, c, d, x[12], 6, 0x655b59c3);
-            ii!(d, a, b, c, x[3], 10, 0x8f0ccc92);
-            ii!(c, d, a, b, x[10], 15, 0xffeff47d);
-            ii!(b, c, d, a, x[1], 21, 0x85845dd1);
-            ii!(a, b, c, d, x[8], 6, 0x6fa87e4f);
-            ii!(d, a, b, c, x[15], 10, 0xfe2ce6e0);
-            ii!(c, d, a, b, x[6], 15, 0xa3014314);
-            ii!(b, c, d, a, x[13], 21, 0x4e0811a1);
-            ii!(a, b, c, d, x[4], 6, 0xf7537e82);
-            ii!(d, a, b, c, x[11], 10, 0xbd3af235);
-            ii!(c, d, a, b, x[2], 15, 0x2ad7d2bb);
-            ii!(b, c, d, a, x[9], 21, 0xeb86d391);
-            
-            self.state[0] = self.state[0].wrapping_add(a);
-            self.state[1] = self.state[1].wrapping_add(b);
-            self.state[2] = self.state[2].wrapping_add(c);
-            self.state[3] = self.state[3].wrapping_add(d);
-        }
-    }
-    
-    /// 计算文件的MD5值
-    pub fn calculate_file_md5<P: AsRef<Path>>(path: P) -> std::io::Result<String> {
-        let mut file = std::fs::File::open(path)?;
-        let mut context = Md5Context::new();
-        let mut buffer = [0u8; 65536];  // 64KB缓冲区，提高大文件读取速度
-        
-        loop {
-            let bytes_read = file.read(&mut buffer)?;
-            if bytes_read == 0 {
-                break;
-            }
-            context.update(&buffer[..bytes_read]);
+    /// 格式化时长（时:分:秒）
+    fn format_duration(duration: std::time::Duration) -> String {
+        let secs = duration.as_secs();
+        if secs >= 3600 {
+            format!("{}:{:02}:{:02}", secs / 3600, secs % 3600 / 60, secs % 60)
+        } else {
+            format!("{:02}:{:02}", secs / 60, secs % 60)
         }
-        
-        let digest = context.finalize();
-        Ok(digest.iter().map(|b| format!("{:02X}", b)).collect())
     }
 }
@@ -583,8 +583,7 @@ impl App {
         self.install_volume_index = volume_number;
         
         // 开始下载系统镜像
-        let pe_dir = crate::utils::path::get_exe_dir()
-            .join("downloads")
+        let pe_dir = crate::utils::path::get_downloads_dir()
             .to_string_lossy()
             .to_string();
         let _ = std::fs::create_dir_all(&pe_dir);
@@ -586,7 +586,14 @@ impl App {
             };
             
             let data_dir = ConfigFileManager::get_data_dir(&data_partition);
-            std::fs::create_dir_all(&data_dir).ok();
+            if let Err(e) = crate::utils::path::ensure_writable(std::path::Path::new(&data_dir)) {
+                println!("[INSTALL PE STEP 3] 数据目录不可写: {}", e);
+                let _ = progress_tx.send(DismProgress {
+                    percentage: 0,
+                    status: format!("ERROR:{}", e),
+                });
+                return;
+            }
             
             // 根据driver_action决定是否导出驱动
             let should_export = matches!(
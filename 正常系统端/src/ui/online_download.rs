@@ -174,8 +174,7 @@ impl App {
                 
                 // 设置下载路径
                 let save_path = if self.download_save_path.is_empty() {
-                    crate::utils::path::get_exe_dir()
-                        .join("downloads")
+                    crate::utils::path::get_downloads_dir()
                         .to_string_lossy()
                         .to_string()
                 } else {
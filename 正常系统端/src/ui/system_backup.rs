@@ -412,6 +412,15 @@ impl App {
             return;
         }
 
+        // 先确认保存位置可以写入，避免 DISM 运行到一半才失败
+        if let Some(save_dir) = Path::new(&self.backup_save_path).parent() {
+            if let Err(e) = crate::utils::path::ensure_writable(save_dir) {
+                self.backup_error = Some(e.to_string());
+                return;
+            }
+        }
+        self.backup_error = None;
+
         // 检查BitLocker锁定的分区
         let locked_partitions = self.check_bitlocker_for_backup();
         if !locked_partitions.is_empty() {
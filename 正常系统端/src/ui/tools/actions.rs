@@ -2,7 +2,8 @@
 //!
 //! 提供各种工具的启动和操作功能
 
-use std::process::Command;
+use std::path::Path;
+use crate::utils::cmd::spawn_detached;
 use crate::utils::path::{get_bin_dir, get_tools_dir};
 
 /// 启动指定工具
@@ -11,10 +12,11 @@ pub fn launch_tool(tool_name: &str) -> Result<(), String> {
     let tool_path = tools_dir.join(tool_name);
 
     if tool_path.exists() {
+        // 工具在本程序退出后继续运行
         let result = if tool_name.to_lowercase().ends_with(".cpl") {
-            Command::new("control.exe").arg(&tool_path).spawn()
+            spawn_detached(Path::new("control.exe"), &[&tool_path.to_string_lossy()])
         } else {
-            Command::new(&tool_path).spawn()
+            spawn_detached(&tool_path, &[])
         };
 
         match result {
@@ -32,7 +34,7 @@ pub fn launch_ghost() -> Result<(), String> {
     let ghost_path = bin_dir.join("ghost").join("Ghost64.exe");
 
     if ghost_path.exists() {
-        match Command::new(&ghost_path).spawn() {
+        match spawn_detached(&ghost_path, &[]) {
             Ok(_) => Ok(()),
             Err(e) => Err(format!("启动失败: Ghost64.exe - {}", e)),
         }
@@ -47,7 +49,7 @@ pub fn launch_space_sniffer() -> Result<(), String> {
     let space_sniffer_path = tools_dir.join("SpaceSniffer.exe");
 
     if space_sniffer_path.exists() {
-        match Command::new(&space_sniffer_path).spawn() {
+        match spawn_detached(&space_sniffer_path, &[]) {
             Ok(_) => Ok(()),
             Err(e) => Err(format!("启动失败: SpaceSniffer.exe - {}", e)),
         }
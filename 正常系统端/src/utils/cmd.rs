@@ -1,20 +1,314 @@
 use std::process::{Command, Output, Child, Stdio};
 use std::ffi::OsStr;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
-use crate::utils::encoding::gbk_to_utf8;
 
-/// Windows CREATE_NO_WINDOW 标志
+/// 非 Windows 平台（测试）假定的代码页：简体中文 GBK
+#[cfg(not(windows))]
+const DEFAULT_CODEPAGE: u32 = 936;
+
+/// PowerShell 脚本的默认超时
+const POWERSHELL_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// 等待子进程退出时的轮询间隔
+const WAIT_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// 当前系统的 ANSI 代码页（简体中文 Windows 为 936）
 #[cfg(windows)]
+pub fn active_codepage() -> u32 {
+    unsafe { windows::Win32::Globalization::GetACP() }
+}
+
+#[cfg(not(windows))]
+pub fn active_codepage() -> u32 {
+    DEFAULT_CODEPAGE
+}
+
+/// 代码页对应的编码；不认识的代码页返回 None
+fn encoding_for_codepage(codepage: u32) -> Option<&'static encoding_rs::Encoding> {
+    use encoding_rs::*;
+    Some(match codepage {
+        936 => GBK,
+        54936 => GB18030,
+        950 => BIG5,
+        932 => SHIFT_JIS,
+        949 => EUC_KR,
+        874 => WINDOWS_874,
+        1250 => WINDOWS_1250,
+        1251 => WINDOWS_1251,
+        1252 => WINDOWS_1252,
+        1253 => WINDOWS_1253,
+        1254 => WINDOWS_1254,
+        1255 => WINDOWS_1255,
+        1256 => WINDOWS_1256,
+        1257 => WINDOWS_1257,
+        1258 => WINDOWS_1258,
+        65001 => UTF_8,
+        _ => return None,
+    })
+}
+
+/// 解码命令输出：diskpart、bcdedit 等系统工具按系统代码页输出（中文系统为 GBK），
+/// 部分 PowerShell 调用输出 UTF-16LE
+///
+/// 依次尝试：UTF-16LE（有 BOM，或无 BOM 但形如 UTF-16 的 ASCII 文本）、UTF-8 BOM、系统代码页、UTF-8；
+/// 都不能无损解码时按系统代码页解码，无法识别的字节替换为 U+FFFD。
+pub fn decode_output(bytes: &[u8]) -> String {
+    decode_with_codepage(bytes, active_codepage())
+}
+
+fn decode_with_codepage(bytes: &[u8], codepage: u32) -> String {
+    use encoding_rs::{UTF_16LE, UTF_8};
+
+    if let Some(rest) = bytes.strip_prefix(&[0xFF, 0xFE]) {
+        return UTF_16LE.decode_without_bom_handling(rest).0.into_owned();
+    }
+    if looks_like_utf16le(bytes) {
+        return UTF_16LE.decode_without_bom_handling(bytes).0.into_owned();
+    }
+    if let Some(rest) = bytes.strip_prefix(&[0xEF, 0xBB, 0xBF]) {
+        return UTF_8.decode_without_bom_handling(rest).0.into_owned();
+    }
+
+    let encoding = encoding_for_codepage(codepage).unwrap_or(encoding_rs::GBK);
+    if let Some(text) = encoding.decode_without_bom_handling_and_without_replacement(bytes) {
+        return text.into_owned();
+    }
+    match std::str::from_utf8(bytes) {
+        Ok(text) => text.to_string(),
+        Err(_) => encoding.decode_without_bom_handling(bytes).0.into_owned(),
+    }
+}
+
+/// 没有 BOM 的 UTF-16LE：长度为偶数，且大部分奇数位置的字节为 0（ASCII 字符的高字节）
+fn looks_like_utf16le(bytes: &[u8]) -> bool {
+    if bytes.len() < 2 || !bytes.len().is_multiple_of(2) {
+        return false;
+    }
+    let zeros = bytes.iter().skip(1).step_by(2).filter(|&&b| b == 0).count();
+    zeros * 4 >= bytes.len() / 2 * 3
+}
+
+/// 命令执行结果
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CmdOutput {
+    pub stdout: String,
+    pub stderr: String,
+    /// 退出码；超时被结束或被信号终止时为 None
+    pub exit_code: Option<i32>,
+    /// 从启动到退出（或超时被结束）所用的时间
+    pub duration: Duration,
+}
+
+impl CmdOutput {
+    /// 是否以退出码 0 结束
+    pub fn success(&self) -> bool {
+        self.exit_code == Some(0)
+    }
+}
+
+/// 命令执行错误
+#[derive(Debug, thiserror::Error)]
+pub enum CmdError {
+    /// 超过时限仍未退出，进程已被结束；`output` 为此前已读到的输出
+    #[error("{program} 执行超时（{} 秒），已结束进程", timeout.as_secs())]
+    CommandTimeout {
+        program: String,
+        timeout: Duration,
+        output: CmdOutput,
+    },
+
+    /// `run_with_retry` 的命令最终仍失败（重试次数用完，或失败不值得重试）；`output` 为最后一次的输出
+    #[error(
+        "{program} 执行失败（共尝试 {attempts} 次，最后退出码 {:?}）: {}",
+        output.exit_code,
+        output.stderr.trim()
+    )]
+    CommandFailed {
+        program: String,
+        attempts: u32,
+        output: CmdOutput,
+    },
+
+    /// PowerShell 脚本执行失败（退出码非 0 或有错误输出），`stderr` 为错误输出的文本
+    #[error("PowerShell 脚本执行失败（退出码 {exit_code:?}）: {stderr}")]
+    PowerShellFailed { exit_code: Option<i32>, stderr: String },
+
+    /// 用户在 UAC 提示中选择了「否」
+    #[error("用户取消了管理员权限请求，无法执行 {program}")]
+    ElevationCancelled { program: String },
+}
+
+/// Windows CREATE_NO_WINDOW 标志
 const CREATE_NO_WINDOW: u32 = 0x08000000;
 
+/// 不继承控制台、新进程组（`spawn_detached` 使用）
+#[cfg(windows)]
+const DETACHED_PROCESS: u32 = 0x0000_0008;
+#[cfg(windows)]
+const CREATE_NEW_PROCESS_GROUP: u32 = 0x0000_0200;
+/// 脱离所在的作业对象（作业需设置 `JOB_OBJECT_LIMIT_BREAKAWAY_OK`）
+const CREATE_BREAKAWAY_FROM_JOB: u32 = 0x0100_0000;
+
+/// `init_process_job` 创建的作业对象句柄，0 表示未创建
+static PROCESS_JOB: AtomicUsize = AtomicUsize::new(0);
+
+/// 把本程序放入一个设置了 `JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE` 的作业对象，启动时调用一次
+///
+/// 子进程创建时自动加入父进程所在的作业，因此之后 `create_command` 等启动的 7z、DISM 等辅助程序都在作业中。
+/// 句柄有意不关闭：本程序无论正常退出、崩溃还是被强制结束，系统关闭最后一个句柄时都会结束作业中的全部进程，
+/// 不会留下继续锁定文件的子进程。
+///
+/// 需要在本程序退出后继续运行的进程用 `CommandOptions::kill_with_app = false` 或 `spawn_detached` 启动，
+/// 创建时脱离作业（作业设置了 `JOB_OBJECT_LIMIT_BREAKAWAY_OK`）。`run_elevated` 经 UAC 启动的进程不继承作业，见该函数说明。
+///
+/// 本程序已在不允许嵌套或加入的作业中时返回错误，此时子进程的行为与未调用时相同。
+#[cfg(windows)]
+pub fn init_process_job() -> anyhow::Result<()> {
+    use windows::core::PCWSTR;
+    use windows::Win32::Foundation::CloseHandle;
+    use windows::Win32::System::JobObjects::{
+        AssignProcessToJobObject, CreateJobObjectW, JobObjectExtendedLimitInformation, SetInformationJobObject,
+        JOBOBJECT_EXTENDED_LIMIT_INFORMATION, JOB_OBJECT_LIMIT_BREAKAWAY_OK, JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE,
+    };
+    use windows::Win32::System::Threading::GetCurrentProcess;
+
+    if process_job_active() {
+        return Ok(());
+    }
+    unsafe {
+        let job = CreateJobObjectW(None, PCWSTR::null()).map_err(|e| anyhow::anyhow!("创建作业对象失败: {}", e))?;
+        let mut info = JOBOBJECT_EXTENDED_LIMIT_INFORMATION::default();
+        info.BasicLimitInformation.LimitFlags = JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE | JOB_OBJECT_LIMIT_BREAKAWAY_OK;
+        let result = SetInformationJobObject(
+            job,
+            JobObjectExtendedLimitInformation,
+            &info as *const _ as *const std::ffi::c_void,
+            std::mem::size_of::<JOBOBJECT_EXTENDED_LIMIT_INFORMATION>() as u32,
+        )
+        .and_then(|_| AssignProcessToJobObject(job, GetCurrentProcess()));
+        if let Err(e) = result {
+            let _ = CloseHandle(job);
+            anyhow::bail!("把本程序加入作业对象失败: {}", e);
+        }
+        PROCESS_JOB.store(job.0 as usize, AtomicOrdering::SeqCst);
+    }
+    log::info!("[CMD] 已创建作业对象，子进程将随本程序一起结束");
+    Ok(())
+}
+
+#[cfg(not(windows))]
+pub fn init_process_job() -> anyhow::Result<()> {
+    Ok(())
+}
+
+/// `init_process_job` 是否已成功创建作业对象
+pub fn process_job_active() -> bool {
+    PROCESS_JOB.load(AtomicOrdering::SeqCst) != 0
+}
+
+/// 尽力把不是由本程序直接创建的进程（经 UAC 提权启动）加入作业对象
+///
+/// 未提权的本程序通常得不到提权进程的 `PROCESS_SET_QUOTA` 权限，失败时只记录日志。
+#[cfg(windows)]
+fn assign_to_process_job(process: windows::Win32::Foundation::HANDLE, program: &str) {
+    use windows::Win32::Foundation::HANDLE;
+    use windows::Win32::System::JobObjects::AssignProcessToJobObject;
+
+    let job = PROCESS_JOB.load(AtomicOrdering::SeqCst);
+    if job == 0 {
+        return;
+    }
+    if let Err(e) = unsafe { AssignProcessToJobObject(HANDLE(job as *mut std::ffi::c_void), process) } {
+        log::info!("[CMD] 提权进程 {} 无法加入作业对象，本程序退出后它会继续运行到结束: {}", program, e);
+    }
+}
+
+/// 进程优先级
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PriorityClass {
+    Idle,
+    BelowNormal,
+    Normal,
+    AboveNormal,
+    High,
+}
+
+impl PriorityClass {
+    /// 对应的 Windows 进程创建标志（`*_PRIORITY_CLASS`）
+    fn creation_flag(self) -> u32 {
+        match self {
+            PriorityClass::Idle => 0x0000_0040,
+            PriorityClass::BelowNormal => 0x0000_4000,
+            PriorityClass::Normal => 0x0000_0020,
+            PriorityClass::AboveNormal => 0x0000_8000,
+            PriorityClass::High => 0x0000_0080,
+        }
+    }
+}
+
+/// `create_command_with` 的选项，默认与 `create_command` 相同：隐藏控制台窗口、继承标准输入输出、普通优先级
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandOptions {
+    /// 是否显示控制台窗口（调试时查看工具输出，或隐藏窗口后行为异常的交互式工具）
+    pub show_window: bool,
+    /// 额外的进程创建标志（如 `CREATE_NEW_CONSOLE`），与窗口和优先级标志合并
+    pub creation_flags: u32,
+    /// 是否继承本进程的标准输入输出；false 时都设为空设备（之后仍可用 `Command::stdout` 等覆盖）
+    pub inherit_stdio: bool,
+    /// 进程优先级，None 时与本进程相同；后台解压等耗时操作建议 `BelowNormal`，避免系统卡顿
+    pub priority: Option<PriorityClass>,
+    /// 是否随本程序一起结束（留在 `init_process_job` 的作业中）；false 时创建时脱离作业，
+    /// 用于自行处理退出的进程（如带 `--stop-with-process` 的 aria2c，需正常退出以保存会话）
+    pub kill_with_app: bool,
+}
+
+impl Default for CommandOptions {
+    fn default() -> Self {
+        Self {
+            show_window: false,
+            creation_flags: 0,
+            inherit_stdio: true,
+            priority: None,
+            kill_with_app: true,
+        }
+    }
+}
+
+impl CommandOptions {
+    /// 合并后的进程创建标志
+    #[cfg_attr(not(windows), allow(dead_code))]
+    fn flags(&self) -> u32 {
+        let window = if self.show_window { 0 } else { CREATE_NO_WINDOW };
+        let priority = self.priority.map_or(0, PriorityClass::creation_flag);
+        // 只脱离自己创建的作业：本程序所在的其它作业可能不允许脱离，此时 CreateProcess 会失败
+        let breakaway = if !self.kill_with_app && process_job_active() { CREATE_BREAKAWAY_FROM_JOB } else { 0 };
+        window | priority | breakaway | self.creation_flags
+    }
+}
+
 /// 创建一个配置好的 Command，在 Windows 上隐藏控制台窗口
 pub fn create_command<S: AsRef<OsStr>>(program: S) -> Command {
+    create_command_with(program, &CommandOptions::default())
+}
+
+/// 按 `options` 创建 Command（窗口、创建标志、标准输入输出和优先级）
+///
+/// 非 Windows 平台上只有 `inherit_stdio` 生效。
+pub fn create_command_with<S: AsRef<OsStr>>(program: S, options: &CommandOptions) -> Command {
     let mut cmd = Command::new(program);
 
     #[cfg(windows)]
     {
         use std::os::windows::process::CommandExt;
-        cmd.creation_flags(CREATE_NO_WINDOW);
+        cmd.creation_flags(options.flags());
+    }
+    if !options.inherit_stdio {
+        cmd.stdin(Stdio::null()).stdout(Stdio::null()).stderr(Stdio::null());
     }
 
     cmd
@@ -33,8 +327,8 @@ pub fn run_command<S: AsRef<OsStr>>(program: S, args: &[&str]) -> std::io::Resul
 
     #[cfg(debug_assertions)]
     {
-        let stdout = gbk_to_utf8(&output.stdout);
-        let stderr = gbk_to_utf8(&output.stderr);
+        let stdout = decode_output(&output.stdout);
+        let stderr = decode_output(&output.stderr);
 
         if !stdout.trim().is_empty() {
             println!("[STDOUT] {}", stdout.trim());
@@ -49,6 +343,750 @@ pub fn run_command<S: AsRef<OsStr>>(program: S, args: &[&str]) -> std::io::Resul
     Ok(output)
 }
 
+/// 执行命令，等待其退出并返回输出、退出码和耗时
+///
+/// 标准输入为空，等待用户输入的工具会立即读到 EOF 而不是一直卡住。超过 `timeout` 仍未退出时
+/// 结束进程，返回 `CmdError::CommandTimeout`（附带已读到的输出）。退出码非 0 不算错误，由调用方判断。
+pub fn run_with_output(cmd: &Path, args: &[&str], timeout: Duration) -> anyhow::Result<CmdOutput> {
+    run_decoded(cmd, args, timeout, decode_output)
+}
+
+/// `run_with_output` 的实现，输出按 `decode` 解码（已知编码的命令不必猜测）
+fn run_decoded(cmd: &Path, args: &[&str], timeout: Duration, decode: fn(&[u8]) -> String) -> anyhow::Result<CmdOutput> {
+    log::debug!("[CMD] {} {}", cmd.display(), args.join(" "));
+    let mut command = create_command(cmd);
+    command.args(args);
+    run_prepared(command, None, timeout, decode)
+}
+
+/// 执行已配置好的命令：`stdin` 为 None 时标准输入为空，否则在后台线程中写入后关闭
+fn run_prepared(
+    mut command: Command,
+    stdin: Option<Vec<u8>>,
+    timeout: Duration,
+    decode: fn(&[u8]) -> String,
+) -> anyhow::Result<CmdOutput> {
+    let program = Path::new(command.get_program()).display().to_string();
+    let start = Instant::now();
+    let mut child = command
+        .stdin(if stdin.is_some() { Stdio::piped() } else { Stdio::null() })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| anyhow::anyhow!("启动 {} 失败: {}", program, e))?;
+    // 输入和输出同时进行：子进程输出填满管道缓冲区之前可能不再读取输入，先写完输入再读输出会互相等待
+    if let (Some(mut pipe), Some(bytes)) = (child.stdin.take(), stdin) {
+        std::thread::spawn(move || {
+            use std::io::Write;
+            // 子进程不读取全部输入就退出时写入失败，属正常情况
+            let _ = pipe.write_all(&bytes);
+        });
+    }
+    let stdout = read_in_background(child.stdout.take());
+    let stderr = read_in_background(child.stderr.take());
+
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break Some(status);
+        }
+        if start.elapsed() >= timeout {
+            if let Err(e) = kill_process_tree(child.id()) {
+                log::warn!("[CMD] {}", e);
+            }
+            let _ = child.kill();
+            let _ = child.wait();
+            break None;
+        }
+        std::thread::sleep(WAIT_POLL_INTERVAL);
+    };
+
+    let duration = start.elapsed();
+    let Some(status) = status else {
+        // 子进程派生的进程可能仍持有管道，不等待读取线程结束，只取已读到的部分
+        let output = CmdOutput {
+            stdout: decode(&stdout.snapshot()),
+            stderr: decode(&stderr.snapshot()),
+            exit_code: None,
+            duration,
+        };
+        log::warn!("[CMD] {} 超过 {} 秒未退出，已结束", program, timeout.as_secs());
+        return Err(CmdError::CommandTimeout { program, timeout, output }.into());
+    };
+
+    let output = CmdOutput {
+        stdout: decode(&stdout.finish()),
+        stderr: decode(&stderr.finish()),
+        exit_code: status.code(),
+        duration,
+    };
+    log::debug!("[CMD] {} 退出: {:?}，耗时 {} ms", program, output.exit_code, duration.as_millis());
+    Ok(output)
+}
+
+/// 重试之间的等待方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backoff {
+    /// 每次等待相同时间
+    Fixed(Duration),
+    /// 从 `initial` 开始每次翻倍，不超过 `max`；`jitter` 时在 [一半, 全部] 之间随机取值，避免多个调用同时重试
+    Exponential { initial: Duration, max: Duration, jitter: bool },
+}
+
+impl Backoff {
+    /// 第 `attempt` 次（从 1 开始）失败后的等待时间
+    fn delay(&self, attempt: u32) -> Duration {
+        match *self {
+            Backoff::Fixed(delay) => delay,
+            Backoff::Exponential { initial, max, jitter } => {
+                let delay = initial.saturating_mul(1u32 << attempt.saturating_sub(1).min(16)).min(max);
+                if jitter {
+                    delay / 2 + delay.mul_f64(random_fraction() / 2.0)
+                } else {
+                    delay
+                }
+            }
+        }
+    }
+}
+
+/// [0, 1) 之间的随机数（只用于退避抖动，不需要密码学强度）
+fn random_fraction() -> f64 {
+    use std::collections::hash_map::RandomState;
+    use std::hash::BuildHasher;
+
+    (RandomState::new().hash_one(Instant::now()) >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// 判断一次失败是否值得重试
+pub type RetryPredicate = Arc<dyn Fn(&CmdOutput) -> bool + Send + Sync>;
+
+/// `run_with_retry` 的重试策略
+#[derive(Clone)]
+pub struct RetryPolicy {
+    /// 最多执行的次数（含第一次）
+    pub max_attempts: u32,
+    pub backoff: Backoff,
+    /// 判断失败（退出码非 0，或超时被结束，此时 `exit_code` 为 None）是否值得重试；None 时所有失败都重试
+    pub retryable: Option<RetryPredicate>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            backoff: Backoff::Exponential {
+                initial: Duration::from_secs(1),
+                max: Duration::from_secs(10),
+                jitter: true,
+            },
+            retryable: None,
+        }
+    }
+}
+
+impl std::fmt::Debug for RetryPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RetryPolicy")
+            .field("max_attempts", &self.max_attempts)
+            .field("backoff", &self.backoff)
+            .field("retryable", &self.retryable.is_some())
+            .finish()
+    }
+}
+
+impl RetryPolicy {
+    /// 只在错误输出包含 `patterns` 之一（不区分大小写）时重试，如 "正在使用"、"0x80070020"
+    pub fn retry_on_stderr(mut self, patterns: &[&str]) -> Self {
+        let patterns: Vec<String> = patterns.iter().map(|p| p.to_lowercase()).collect();
+        self.retryable = Some(Arc::new(move |output: &CmdOutput| {
+            let stderr = output.stderr.to_lowercase();
+            patterns.iter().any(|p| stderr.contains(p.as_str()))
+        }));
+        self
+    }
+}
+
+/// 执行命令，失败时按 `policy` 重试，返回第一次成功（退出码 0）的输出
+///
+/// 用于 `net use`、组件存储繁忙时的 DISM、刚挂载的卷等偶尔失败、再试一次就能成功的操作。每次尝试都记录日志；
+/// 最终失败时返回 `CmdError::CommandFailed`，包含最后一次的输出和实际尝试的次数。无法启动程序时直接返回错误。
+pub fn run_with_retry(cmd: &Path, args: &[&str], timeout: Duration, policy: &RetryPolicy) -> anyhow::Result<CmdOutput> {
+    let program = cmd.display().to_string();
+    let max_attempts = policy.max_attempts.max(1);
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        log::info!("[CMD] 执行 {}（第 {}/{} 次）", program, attempt, max_attempts);
+        let output = match run_with_output(cmd, args, timeout) {
+            Ok(output) if output.success() => return Ok(output),
+            Ok(output) => output,
+            Err(e) => match e.downcast::<CmdError>() {
+                Ok(CmdError::CommandTimeout { output, .. }) => output,
+                Ok(other) => return Err(other.into()),
+                Err(e) => return Err(e),
+            },
+        };
+
+        let retryable = policy.retryable.as_ref().is_none_or(|retryable| retryable(&output));
+        if !retryable || attempt >= max_attempts {
+            log::warn!(
+                "[CMD] {} 第 {} 次执行失败（退出码 {:?}），{}",
+                program,
+                attempt,
+                output.exit_code,
+                if retryable { "已达到重试上限" } else { "不再重试" }
+            );
+            return Err(CmdError::CommandFailed {
+                program,
+                attempts: attempt,
+                output,
+            }
+            .into());
+        }
+        let delay = policy.backoff.delay(attempt);
+        log::warn!(
+            "[CMD] {} 第 {} 次执行失败（退出码 {:?}），{} ms 后重试: {}",
+            program,
+            attempt,
+            output.exit_code,
+            delay.as_millis(),
+            output.stderr.trim()
+        );
+        std::thread::sleep(delay);
+    }
+}
+
+/// 执行 PowerShell 脚本，把结果（`& { <script> } | ConvertTo-Json -Depth 4`）反序列化为 `T`
+///
+/// 解析 JSON 而不是表格文本，不受系统语言影响。脚本以 `-EncodedCommand` 传递，不需要处理引号转义；
+/// 输出编码固定为 UTF-8。`ConvertTo-Json` 对单个对象输出对象而不是数组、没有结果时没有输出，
+/// `T` 为 `Vec<_>` 时这两种情况分别按一个元素和空数组处理。
+/// 退出码非 0 或有错误输出时返回 `CmdError::PowerShellFailed`，附带错误文本。
+pub fn run_powershell_json<T: serde::de::DeserializeOwned>(script: &str) -> anyhow::Result<T> {
+    use base64::Engine;
+
+    let full = format!(
+        "[Console]::OutputEncoding = [System.Text.Encoding]::UTF8; $ErrorActionPreference = 'Stop'; \
+         $ProgressPreference = 'SilentlyContinue'; & {{ {} }} | ConvertTo-Json -Depth 4",
+        script
+    );
+    let utf16: Vec<u8> = full.encode_utf16().flat_map(u16::to_le_bytes).collect();
+    let encoded = base64::engine::general_purpose::STANDARD.encode(utf16);
+    let output = run_decoded(
+        Path::new("powershell"),
+        &["-NoProfile", "-NonInteractive", "-EncodedCommand", &encoded],
+        POWERSHELL_TIMEOUT,
+        |bytes| String::from_utf8_lossy(bytes).into_owned(),
+    )?;
+
+    let stderr = powershell_error_text(&output.stderr);
+    if !output.success() || !stderr.is_empty() {
+        return Err(CmdError::PowerShellFailed {
+            exit_code: output.exit_code,
+            stderr,
+        }
+        .into());
+    }
+    parse_powershell_json(&output.stdout)
+}
+
+/// 解析 `ConvertTo-Json` 的输出：`T` 需要数组而输出是单个对象或为空时，包装成数组再试
+fn parse_powershell_json<T: serde::de::DeserializeOwned>(stdout: &str) -> anyhow::Result<T> {
+    use serde_json::Value;
+
+    let text = stdout.trim().trim_start_matches('\u{feff}');
+    let value: Value = if text.is_empty() {
+        Value::Null
+    } else {
+        serde_json::from_str(text).map_err(|e| anyhow::anyhow!("PowerShell 输出不是有效的 JSON: {}", e))?
+    };
+    let error = match serde_json::from_value(value.clone()) {
+        Ok(parsed) => return Ok(parsed),
+        Err(e) => e,
+    };
+    let wrapped = match value {
+        Value::Array(_) => None,
+        Value::Null => Some(Value::Array(Vec::new())),
+        other => Some(Value::Array(vec![other])),
+    };
+    wrapped
+        .and_then(|wrapped| serde_json::from_value(wrapped).ok())
+        .ok_or_else(|| anyhow::anyhow!("无法解析 PowerShell 输出: {}", error))
+}
+
+/// PowerShell 的错误输出；以 `-EncodedCommand` 启动时可能是 CLIXML，只取其中的错误文本
+fn powershell_error_text(stderr: &str) -> String {
+    let stderr = stderr.trim();
+    let Some(xml) = stderr.strip_prefix("#< CLIXML") else {
+        return stderr.to_string();
+    };
+    let mut text = String::new();
+    for part in xml.split(r#"<S S="Error">"#).skip(1) {
+        if let Some((message, _)) = part.split_once("</S>") {
+            text.push_str(&message.replace("_x000D_", "").replace("_x000A_", "\n"));
+        }
+    }
+    text.trim().to_string()
+}
+
+/// 当前进程是否以管理员权限运行（已提权）
+#[cfg(windows)]
+pub fn is_elevated() -> bool {
+    crate::utils::privilege::is_admin()
+}
+
+#[cfg(not(windows))]
+pub fn is_elevated() -> bool {
+    false
+}
+
+/// 以管理员权限执行命令，等待其退出并返回输出、退出码和耗时
+///
+/// 当前进程已提权时等同于 `run_with_output`。否则通过 ShellExecuteExW 的 `runas` 弹出 UAC 提示，
+/// 提权后的 cmd.exe 把输出重定向到临时文件，进程退出后读回。用户拒绝时返回 `CmdError::ElevationCancelled`；
+/// 超时的处理与 `run_with_output` 相同（输出为临时文件中已写入的部分）。
+///
+/// 经 UAC 启动的 cmd.exe 由系统服务创建，不继承 `init_process_job` 的作业；启动后会尝试把它加入作业，
+/// 但通常因权限不足而失败，此时本程序崩溃或被结束后它仍会运行到命令结束。
+pub fn run_elevated(cmd: &Path, args: &[&str], timeout: Duration) -> anyhow::Result<CmdOutput> {
+    if is_elevated() {
+        return run_with_output(cmd, args, timeout);
+    }
+    run_with_runas(cmd, args, timeout)
+}
+
+#[cfg(not(windows))]
+fn run_with_runas(cmd: &Path, args: &[&str], timeout: Duration) -> anyhow::Result<CmdOutput> {
+    run_with_output(cmd, args, timeout)
+}
+
+#[cfg(windows)]
+fn run_with_runas(cmd: &Path, args: &[&str], timeout: Duration) -> anyhow::Result<CmdOutput> {
+    use windows::core::PCWSTR;
+    use windows::Win32::Foundation::{CloseHandle, ERROR_CANCELLED, WAIT_OBJECT_0};
+    use windows::Win32::System::Threading::{GetExitCodeProcess, GetProcessId, TerminateProcess, WaitForSingleObject};
+    use windows::Win32::UI::Shell::{ShellExecuteExW, SEE_MASK_NOASYNC, SEE_MASK_NOCLOSEPROCESS, SHELLEXECUTEINFOW};
+    use windows::Win32::UI::WindowsAndMessaging::SW_HIDE;
+
+    let program = cmd.display().to_string();
+    let stdout_path = crate::utils::path::temp_file("runas", "out")?;
+    let stderr_path = crate::utils::path::temp_file("runas", "err")?;
+    let command_line = std::iter::once(program.as_str())
+        .chain(args.iter().copied())
+        .map(quote_cmd_arg)
+        .collect::<anyhow::Result<Vec<_>>>()?
+        .join(" ");
+    // /S：去掉最外层引号后原样执行，命令中的其它引号不受影响
+    let parameters = format!(
+        "/S /C \"{} > {} 2> {}\"",
+        command_line,
+        quote_cmd_arg(&stdout_path.to_string_lossy())?,
+        quote_cmd_arg(&stderr_path.to_string_lossy())?
+    );
+    log::info!("[CMD] 以管理员权限执行: {} {}", program, args.join(" "));
+
+    let wide = |s: &str| s.encode_utf16().chain(std::iter::once(0)).collect::<Vec<u16>>();
+    let verb = wide("runas");
+    let file = wide("cmd.exe");
+    let parameters = wide(&parameters);
+    let mut info = SHELLEXECUTEINFOW {
+        cbSize: std::mem::size_of::<SHELLEXECUTEINFOW>() as u32,
+        fMask: SEE_MASK_NOCLOSEPROCESS | SEE_MASK_NOASYNC,
+        lpVerb: PCWSTR(verb.as_ptr()),
+        lpFile: PCWSTR(file.as_ptr()),
+        lpParameters: PCWSTR(parameters.as_ptr()),
+        nShow: SW_HIDE.0,
+        ..Default::default()
+    };
+
+    let start = Instant::now();
+    if let Err(e) = unsafe { ShellExecuteExW(&mut info) } {
+        if e.code() == ERROR_CANCELLED.to_hresult() {
+            return Err(CmdError::ElevationCancelled { program }.into());
+        }
+        anyhow::bail!("以管理员权限启动 {} 失败: {}", program, e);
+    }
+    if info.hProcess.is_invalid() {
+        anyhow::bail!("以管理员权限启动 {} 失败: 未取得进程句柄", program);
+    }
+    assign_to_process_job(info.hProcess, &program);
+
+    let mut timed_out = false;
+    unsafe {
+        while WaitForSingleObject(info.hProcess, WAIT_POLL_INTERVAL.as_millis() as u32) != WAIT_OBJECT_0 {
+            if start.elapsed() >= timeout {
+                // 连同 cmd.exe 启动的命令一起结束；提权后的后代可能无权结束，至少结束 cmd.exe
+                if let Err(e) = kill_process_tree(GetProcessId(info.hProcess)) {
+                    log::warn!("[CMD] 结束 {} 的进程树失败: {}", program, e);
+                    let _ = TerminateProcess(info.hProcess, 1);
+                }
+                timed_out = true;
+                break;
+            }
+        }
+    }
+    let mut exit_code = 0u32;
+    let exit_code = unsafe { GetExitCodeProcess(info.hProcess, &mut exit_code) }
+        .ok()
+        .filter(|_| !timed_out)
+        .map(|_| exit_code as i32);
+    unsafe {
+        let _ = CloseHandle(info.hProcess);
+    }
+
+    let read = |path: &Path| {
+        let bytes = std::fs::read(path).unwrap_or_default();
+        let _ = std::fs::remove_file(path);
+        decode_output(&bytes)
+    };
+    let output = CmdOutput {
+        stdout: read(&stdout_path),
+        stderr: read(&stderr_path),
+        exit_code,
+        duration: start.elapsed(),
+    };
+    if timed_out {
+        log::warn!("[CMD] {} 超过 {} 秒未退出，已结束", program, timeout.as_secs());
+        return Err(CmdError::CommandTimeout { program, timeout, output }.into());
+    }
+    Ok(output)
+}
+
+/// 为 cmd.exe 命令行中的一个参数加引号（含空格或 cmd 特殊字符时）
+///
+/// 参数中的双引号在 cmd.exe 与目标程序的两层解析下无法可靠转义，直接报错。
+#[cfg_attr(not(windows), allow(dead_code))]
+fn quote_cmd_arg(arg: &str) -> anyhow::Result<String> {
+    if arg.contains('"') {
+        anyhow::bail!("参数中不能包含双引号: {}", arg);
+    }
+    let needs_quotes = arg.is_empty() || arg.chars().any(|c| c.is_whitespace() || "&|<>^()%!,;=".contains(c));
+    Ok(if needs_quotes { format!("\"{}\"", arg) } else { arg.to_string() })
+}
+
+/// 在后台线程中读取管道的全部内容
+struct PipeReader {
+    buffer: Arc<Mutex<Vec<u8>>>,
+    thread: Option<std::thread::JoinHandle<()>>,
+}
+
+impl PipeReader {
+    /// 目前已读到的内容
+    fn snapshot(&self) -> Vec<u8> {
+        self.buffer.lock().map(|b| b.clone()).unwrap_or_default()
+    }
+
+    /// 等待管道关闭后返回全部内容
+    fn finish(mut self) -> Vec<u8> {
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        self.snapshot()
+    }
+}
+
+fn read_in_background<R: Read + Send + 'static>(pipe: Option<R>) -> PipeReader {
+    let buffer = Arc::new(Mutex::new(Vec::new()));
+    let thread = pipe.map(|mut pipe| {
+        let buffer = buffer.clone();
+        std::thread::spawn(move || {
+            let mut chunk = [0u8; 4096];
+            while let Ok(n) = pipe.read(&mut chunk) {
+                if n == 0 {
+                    break;
+                }
+                if let Ok(mut buffer) = buffer.lock() {
+                    buffer.extend_from_slice(&chunk[..n]);
+                }
+            }
+        })
+    });
+    PipeReader { buffer, thread }
+}
+
+/// 流式读取到的一行输出
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OutputLine {
+    Stdout(String),
+    Stderr(String),
+}
+
+/// 正在运行、逐行读取输出的子进程（`spawn_streaming` 返回）
+///
+/// 丢弃时结束子进程。
+pub struct StreamingChild {
+    lines: tokio::sync::mpsc::UnboundedReceiver<OutputLine>,
+    child: tokio::process::Child,
+}
+
+impl StreamingChild {
+    /// 子进程的 PID（已退出时为 None）
+    pub fn pid(&self) -> Option<u32> {
+        self.child.id()
+    }
+
+    /// 下一行输出；标准输出和标准错误都已关闭后返回 None
+    pub async fn next_line(&mut self) -> Option<OutputLine> {
+        self.lines.recv().await
+    }
+
+    /// 等待子进程退出，返回退出码（被信号终止时为 None）；尚未读取的输出被丢弃
+    pub async fn wait(mut self) -> anyhow::Result<Option<i32>> {
+        let status = self.child.wait().await?;
+        Ok(status.code())
+    }
+}
+
+/// 启动命令并逐行读取其输出（DISM、wimlib 等长时间运行、边运行边输出进度的工具）
+///
+/// 行按 `\n`、单独的 `\r` 和退格符分割：DISM 用 `\r`、7-Zip 用退格符反复改写同一行进度，每次改写都作为一行产出。
+/// 每行按 `decode_output` 解码；输出 UTF-16 的命令不能按字节分行，请改用 `run_with_output`。
+pub fn spawn_streaming(cmd: &Path, args: &[&str]) -> anyhow::Result<StreamingChild> {
+    log::debug!("[SPAWN STREAM] {} {}", cmd.display(), args.join(" "));
+    let mut command = create_command(cmd);
+    command.args(args).stdin(Stdio::null()).stdout(Stdio::piped()).stderr(Stdio::piped());
+    let mut child = tokio::process::Command::from(command)
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| anyhow::anyhow!("启动 {} 失败: {}", cmd.display(), e))?;
+
+    let (tx, lines) = tokio::sync::mpsc::unbounded_channel();
+    if let Some(stdout) = child.stdout.take() {
+        tokio::spawn(forward_lines(stdout, tx.clone(), OutputLine::Stdout));
+    }
+    if let Some(stderr) = child.stderr.take() {
+        tokio::spawn(forward_lines(stderr, tx, OutputLine::Stderr));
+    }
+    Ok(StreamingChild { lines, child })
+}
+
+async fn forward_lines<R>(
+    mut pipe: R,
+    tx: tokio::sync::mpsc::UnboundedSender<OutputLine>,
+    tag: fn(String) -> OutputLine,
+) where
+    R: tokio::io::AsyncRead + Unpin,
+{
+    use tokio::io::AsyncReadExt;
+
+    let mut splitter = LineSplitter::default();
+    let mut chunk = [0u8; 4096];
+    loop {
+        let n = match pipe.read(&mut chunk).await {
+            Ok(0) | Err(_) => break,
+            Ok(n) => n,
+        };
+        for line in splitter.push(&chunk[..n]) {
+            if tx.send(tag(decode_output(&line))).is_err() {
+                return;
+            }
+        }
+    }
+    if let Some(line) = splitter.finish() {
+        let _ = tx.send(tag(decode_output(&line)));
+    }
+}
+
+/// 按 `\n`、`\r\n`、单独的 `\r` 和退格符把字节流分成行（跨块的 `\r\n` 也只算一次换行）
+///
+/// 连续的退格符只算一次分割，退格符之前没有内容时不产出空行。
+#[derive(Debug, Default)]
+struct LineSplitter {
+    pending: Vec<u8>,
+    /// 上一块以 `\r` 结束，下一块开头的 `\n` 属于同一个换行
+    after_cr: bool,
+}
+
+impl LineSplitter {
+    fn push(&mut self, bytes: &[u8]) -> Vec<Vec<u8>> {
+        let mut lines = Vec::new();
+        for &byte in bytes {
+            let after_cr = std::mem::take(&mut self.after_cr);
+            match byte {
+                b'\n' if after_cr => {}
+                b'\n' => lines.push(std::mem::take(&mut self.pending)),
+                b'\r' => {
+                    lines.push(std::mem::take(&mut self.pending));
+                    self.after_cr = true;
+                }
+                b'\x08' if self.pending.is_empty() => {}
+                b'\x08' => lines.push(std::mem::take(&mut self.pending)),
+                _ => self.pending.push(byte),
+            }
+        }
+        lines
+    }
+
+    /// 输出结束时剩下的不完整一行
+    fn finish(self) -> Option<Vec<u8>> {
+        (!self.pending.is_empty()).then_some(self.pending)
+    }
+}
+
+/// 命令构建器：在 `create_command_with` 的基础上设置工作目录、环境变量和标准输入
+///
+/// ```ignore
+/// let output = CmdBuilder::new(&diskpart)
+///     .stdin_bytes(b"list disk\r\nexit\r\n".to_vec())
+///     .timeout(Duration::from_secs(60))
+///     .run()?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct CmdBuilder {
+    program: std::path::PathBuf,
+    args: Vec<std::ffi::OsString>,
+    options: CommandOptions,
+    current_dir: Option<std::path::PathBuf>,
+    env_clear: bool,
+    /// 按顺序应用的环境变量修改，值为 None 表示删除
+    env: Vec<(std::ffi::OsString, Option<std::ffi::OsString>)>,
+    stdin: Option<Vec<u8>>,
+    timeout: Duration,
+}
+
+impl CmdBuilder {
+    /// 默认超时
+    pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(300);
+
+    pub fn new(program: impl AsRef<Path>) -> Self {
+        Self {
+            program: program.as_ref().to_path_buf(),
+            args: Vec::new(),
+            options: CommandOptions::default(),
+            current_dir: None,
+            env_clear: false,
+            env: Vec::new(),
+            stdin: None,
+            timeout: Self::DEFAULT_TIMEOUT,
+        }
+    }
+
+    pub fn arg(mut self, arg: impl AsRef<OsStr>) -> Self {
+        self.args.push(arg.as_ref().to_owned());
+        self
+    }
+
+    pub fn args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        self.args.extend(args.into_iter().map(|a| a.as_ref().to_owned()));
+        self
+    }
+
+    /// 窗口、创建标志和优先级（见 `CommandOptions`）
+    pub fn options(mut self, options: CommandOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// 工作目录（wimlib 等使用相对路径时需要）
+    pub fn current_dir(mut self, dir: impl AsRef<Path>) -> Self {
+        self.current_dir = Some(dir.as_ref().to_path_buf());
+        self
+    }
+
+    pub fn env(mut self, key: impl AsRef<OsStr>, value: impl AsRef<OsStr>) -> Self {
+        self.env.push((key.as_ref().to_owned(), Some(value.as_ref().to_owned())));
+        self
+    }
+
+    pub fn env_remove(mut self, key: impl AsRef<OsStr>) -> Self {
+        self.env.push((key.as_ref().to_owned(), None));
+        self
+    }
+
+    /// 不继承本进程的环境变量，只使用之后通过 `env` 设置的
+    pub fn env_clear(mut self) -> Self {
+        self.env_clear = true;
+        self.env.clear();
+        self
+    }
+
+    /// 写入子进程标准输入的内容（如 diskpart 脚本），写完后关闭标准输入；只用于 `run`
+    pub fn stdin_bytes(mut self, bytes: Vec<u8>) -> Self {
+        self.stdin = Some(bytes);
+        self
+    }
+
+    /// `run` 的超时，默认 `DEFAULT_TIMEOUT`
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// 配置好的 Command（不含标准输入内容），用于需要自行处理进程的场景
+    pub fn build(&self) -> Command {
+        let mut command = create_command_with(&self.program, &self.options);
+        command.args(&self.args);
+        if let Some(dir) = &self.current_dir {
+            command.current_dir(dir);
+        }
+        if self.env_clear {
+            command.env_clear();
+        }
+        for (key, value) in &self.env {
+            match value {
+                Some(value) => command.env(key, value),
+                None => command.env_remove(key),
+            };
+        }
+        command
+    }
+
+    /// 执行并等待退出，行为与 `run_with_output` 相同（输出解码、超时结束进程）
+    pub fn run(&self) -> anyhow::Result<CmdOutput> {
+        log::debug!("[CMD] {} {:?}", self.program.display(), self.args);
+        run_prepared(self.build(), self.stdin.clone(), self.timeout, decode_output)
+    }
+}
+
+/// 启动一个在本程序退出后继续运行的独立进程，只返回其 PID
+///
+/// 用于流程的最后一步（重启助手、已安装系统的 OOBE 工具等）以及用户从界面打开的工具。
+/// 与 `create_command` 启动的子进程不同：
+/// - 不属于本程序的控制台和进程组（`DETACHED_PROCESS | CREATE_NEW_PROCESS_GROUP`），不会收到本程序的 Ctrl+C；
+/// - 尝试脱离本程序所在的作业对象（`CREATE_BREAKAWAY_FROM_JOB`），`init_process_job` 的作业关闭时不会结束它；
+///   作业不允许脱离时退回为普通启动并记录警告，此时它仍会随作业一起结束；
+/// - 不保留进程句柄和标准输入输出管道，无法等待它退出或读取输出，超时、重试等辅助函数都不适用。
+///
+/// 之后对本程序调用 `kill_process_tree` 仍会按父进程 ID 找到并结束它，不要对本程序自身调用。
+pub fn spawn_detached(cmd: &Path, args: &[&str]) -> anyhow::Result<u32> {
+    log::info!("[SPAWN DETACHED] {} {}", cmd.display(), args.join(" "));
+    let spawn = |extra_flags: u32| {
+        let options = CommandOptions {
+            // DETACHED_PROCESS 与 CREATE_NO_WINDOW 不能同时使用，不带控制台的进程本来就没有窗口
+            show_window: true,
+            creation_flags: extra_flags,
+            inherit_stdio: false,
+            priority: None,
+            // 脱离作业由 extra_flags 控制，以便退回时不再尝试
+            kill_with_app: true,
+        };
+        create_command_with(cmd, &options).args(args).spawn()
+    };
+
+    #[cfg(windows)]
+    let child = {
+        let detached = DETACHED_PROCESS | CREATE_NEW_PROCESS_GROUP;
+        match spawn(detached | CREATE_BREAKAWAY_FROM_JOB) {
+            // 所在作业没有 JOB_OBJECT_LIMIT_BREAKAWAY_OK 时 CreateProcess 返回拒绝访问
+            Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => {
+                log::warn!("[SPAWN DETACHED] 无法脱离作业对象，{} 将随本程序的作业一起结束", cmd.display());
+                spawn(detached)
+            }
+            result => result,
+        }
+    };
+    #[cfg(not(windows))]
+    let child = spawn(0);
+
+    let child = child.map_err(|e| anyhow::anyhow!("启动 {} 失败: {}", cmd.display(), e))?;
+    // 丢弃 Child 只关闭句柄，不会结束进程
+    Ok(child.id())
+}
+
 /// 执行命令并spawn（不等待结果）
 pub fn spawn_command<S: AsRef<OsStr>>(program: S, args: &[&str]) -> std::io::Result<Child> {
     let program_str = program.as_ref().to_string_lossy();
@@ -64,7 +1102,7 @@ pub fn spawn_command<S: AsRef<OsStr>>(program: S, args: &[&str]) -> std::io::Res
 /// 执行命令并返回 stdout 字符串
 pub fn run_command_string<S: AsRef<OsStr>>(program: S, args: &[&str]) -> std::io::Result<String> {
     let output = run_command(program, args)?;
-    Ok(gbk_to_utf8(&output.stdout))
+    Ok(decode_output(&output.stdout))
 }
 
 /// 执行命令并返回 stdout 字符串（带自定义参数的版本）
@@ -80,8 +1118,8 @@ pub fn run_command_with_args<S: AsRef<OsStr>>(program: S, args: Vec<String>) ->
 
     #[cfg(debug_assertions)]
     {
-        let stdout = gbk_to_utf8(&output.stdout);
-        let stderr = gbk_to_utf8(&output.stderr);
+        let stdout = decode_output(&output.stdout);
+        let stderr = decode_output(&output.stderr);
 
         if !stdout.trim().is_empty() {
             println!("[STDOUT] {}", stdout.trim());
@@ -111,3 +1149,558 @@ pub fn spawn_command_piped<S: AsRef<OsStr>>(program: S, args: &[&str]) -> std::i
         .stderr(Stdio::piped())
         .spawn()
 }
+
+/// 指定 PID 的进程是否仍在运行
+///
+/// 无权打开的进程（如其它用户或更高权限的进程）视为仍在运行。
+#[cfg(windows)]
+pub fn is_pid_alive(pid: u32) -> bool {
+    use windows::Win32::Foundation::{CloseHandle, E_ACCESSDENIED, STILL_ACTIVE};
+    use windows::Win32::System::Threading::{GetExitCodeProcess, OpenProcess, PROCESS_QUERY_LIMITED_INFORMATION};
+
+    unsafe {
+        let handle = match OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, pid) {
+            Ok(handle) => handle,
+            Err(e) => return e.code() == E_ACCESSDENIED,
+        };
+        let mut exit_code = 0u32;
+        let queried = GetExitCodeProcess(handle, &mut exit_code).is_ok();
+        let _ = CloseHandle(handle);
+        queried && exit_code == STILL_ACTIVE.0 as u32
+    }
+}
+
+/// 指定 PID 的进程是否仍在运行
+#[cfg(not(windows))]
+pub fn is_pid_alive(pid: u32) -> bool {
+    std::path::Path::new(&format!("/proc/{}", pid)).exists()
+}
+
+/// 正在运行的进程的信息
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProcessInfo {
+    pub pid: u32,
+    pub parent_pid: u32,
+    /// 可执行文件名（如 "aria2c.exe"）
+    pub name: String,
+    /// 可执行文件的完整路径；无权查询的进程（系统进程、更高权限的进程）为 None
+    pub exe_path: Option<PathBuf>,
+    /// 启动时间（Unix 时间戳，秒）；无权查询时为 None
+    pub started_at: Option<u64>,
+}
+
+impl ProcessInfo {
+    fn from_entry(entry: ProcessEntry) -> Self {
+        Self {
+            exe_path: process_image_path(entry.pid),
+            started_at: process_start_time(entry.pid).and_then(start_time_to_unix),
+            pid: entry.pid,
+            parent_pid: entry.parent,
+            name: entry.name,
+        }
+    }
+}
+
+/// 查找可执行文件名为 `name` 的所有进程（不区分大小写，`name` 可省略 ".exe"）
+///
+/// 通过进程快照查询，不依赖 tasklist 的本地化输出；快照失败时记录警告并返回空列表。
+pub fn find_processes_by_name(name: &str) -> Vec<ProcessInfo> {
+    let entries = match process_snapshot() {
+        Ok(entries) => entries,
+        Err(e) => {
+            log::warn!("[CMD] 获取进程列表失败: {}", e);
+            return Vec::new();
+        }
+    };
+    entries
+        .into_iter()
+        .filter(|entry| process_name_matches(&entry.name, name))
+        .map(ProcessInfo::from_entry)
+        .collect()
+}
+
+/// 指定 PID 的进程信息，进程不存在时为 None
+pub fn process_info(pid: u32) -> Option<ProcessInfo> {
+    process_snapshot()
+        .ok()?
+        .into_iter()
+        .find(|entry| entry.pid == pid)
+        .map(ProcessInfo::from_entry)
+}
+
+fn process_name_matches(exe_name: &str, name: &str) -> bool {
+    exe_name.eq_ignore_ascii_case(name) || exe_name.eq_ignore_ascii_case(&format!("{}.exe", name))
+}
+
+/// 进程快照中的一项
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ProcessEntry {
+    pid: u32,
+    parent: u32,
+    /// 可执行文件名（如 "ping.exe"）
+    name: String,
+}
+
+/// 结束进程及其所有后代进程（子进程先于父进程结束）
+///
+/// 结束 cmd.exe、7z 等包装进程时，由它启动的子进程不会随之退出，仍会占用文件。
+/// 后代按进程快照中的父进程 ID 查找，创建时间早于父进程的视为 PID 被复用的无关进程，不会结束。
+/// 进程已经退出时返回 Ok；无法结束 `pid` 本身时返回错误，个别后代结束失败只记录日志。
+pub fn kill_process_tree(pid: u32) -> anyhow::Result<()> {
+    let entries = process_snapshot()?;
+    for child in descendants_bottom_up(pid, &entries, process_start_time) {
+        if let Err(e) = terminate_process(child) {
+            log::debug!("[CMD] 结束子进程 {} 失败: {}", child, e);
+        }
+    }
+    match terminate_process(pid) {
+        Err(_) if !is_pid_alive(pid) => Ok(()),
+        result => result.map_err(|e| anyhow::anyhow!("结束进程 {} 失败: {}", pid, e)),
+    }
+}
+
+/// `root` 的所有后代（不含 `root`），子进程排在父进程之前
+///
+/// `started_at` 返回进程的创建时间（单位不限，只比较先后），取不到时不做 PID 复用检查。
+fn descendants_bottom_up(root: u32, entries: &[ProcessEntry], started_at: impl Fn(u32) -> Option<u64>) -> Vec<u32> {
+    let mut order = Vec::new();
+    let mut visited = std::collections::HashSet::from([root]);
+    // 后序遍历：节点第二次出栈（子进程都已处理）时才加入结果
+    let mut stack = vec![(root, false)];
+    while let Some((pid, children_done)) = stack.pop() {
+        if children_done {
+            if pid != root {
+                order.push(pid);
+            }
+            continue;
+        }
+        stack.push((pid, true));
+        let parent_start = started_at(pid);
+        for entry in entries.iter().filter(|e| e.parent == pid) {
+            let reused = matches!((parent_start, started_at(entry.pid)), (Some(parent), Some(child)) if child < parent);
+            if !reused && visited.insert(entry.pid) {
+                stack.push((entry.pid, false));
+            }
+        }
+    }
+    order
+}
+
+#[cfg(windows)]
+fn process_snapshot() -> anyhow::Result<Vec<ProcessEntry>> {
+    use windows::Win32::Foundation::CloseHandle;
+    use windows::Win32::System::Diagnostics::ToolHelp::{
+        CreateToolhelp32Snapshot, Process32FirstW, Process32NextW, PROCESSENTRY32W, TH32CS_SNAPPROCESS,
+    };
+
+    let mut entries = Vec::new();
+    unsafe {
+        let snapshot = CreateToolhelp32Snapshot(TH32CS_SNAPPROCESS, 0)?;
+        let mut entry = PROCESSENTRY32W {
+            dwSize: std::mem::size_of::<PROCESSENTRY32W>() as u32,
+            ..Default::default()
+        };
+        let mut next = Process32FirstW(snapshot, &mut entry);
+        while next.is_ok() {
+            let len = entry.szExeFile.iter().position(|&c| c == 0).unwrap_or(entry.szExeFile.len());
+            entries.push(ProcessEntry {
+                pid: entry.th32ProcessID,
+                parent: entry.th32ParentProcessID,
+                name: String::from_utf16_lossy(&entry.szExeFile[..len]),
+            });
+            next = Process32NextW(snapshot, &mut entry);
+        }
+        let _ = CloseHandle(snapshot);
+    }
+    Ok(entries)
+}
+
+#[cfg(not(windows))]
+fn process_snapshot() -> anyhow::Result<Vec<ProcessEntry>> {
+    let entries = std::fs::read_dir("/proc")?
+        .flatten()
+        .filter_map(|dir| {
+            let pid = dir.file_name().to_str()?.parse().ok()?;
+            let parent = proc_stat_field(pid, 1)?.parse().ok()?;
+            let name = std::fs::read_to_string(format!("/proc/{}/comm", pid)).ok()?.trim_end().to_string();
+            Some(ProcessEntry { pid, parent, name })
+        })
+        .collect();
+    Ok(entries)
+}
+
+/// /proc/<pid>/stat 中进程名之后的第 `index` 个字段（0 为状态，1 为父进程 ID）
+#[cfg(not(windows))]
+fn proc_stat_field(pid: u32, index: usize) -> Option<String> {
+    let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    let rest = &stat[stat.rfind(')')? + 1..];
+    rest.split_whitespace().nth(index).map(str::to_string)
+}
+
+/// 进程的创建时间（FILETIME，100 纳秒为单位）
+#[cfg(windows)]
+fn process_start_time(pid: u32) -> Option<u64> {
+    use windows::Win32::Foundation::{CloseHandle, FILETIME};
+    use windows::Win32::System::Threading::{GetProcessTimes, OpenProcess, PROCESS_QUERY_LIMITED_INFORMATION};
+
+    unsafe {
+        let handle = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, pid).ok()?;
+        let (mut created, mut exited, mut kernel, mut user) =
+            (FILETIME::default(), FILETIME::default(), FILETIME::default(), FILETIME::default());
+        let result = GetProcessTimes(handle, &mut created, &mut exited, &mut kernel, &mut user);
+        let _ = CloseHandle(handle);
+        result.ok()?;
+        Some(((created.dwHighDateTime as u64) << 32) | created.dwLowDateTime as u64)
+    }
+}
+
+/// 进程的启动时间（系统启动后的时钟节拍数）
+#[cfg(not(windows))]
+fn process_start_time(pid: u32) -> Option<u64> {
+    proc_stat_field(pid, 19)?.parse().ok()
+}
+
+/// 把 `process_start_time` 的返回值换算为 Unix 时间戳（秒）
+#[cfg(windows)]
+fn start_time_to_unix(filetime: u64) -> Option<u64> {
+    /// 1601-01-01 到 1970-01-01 的 100 纳秒数
+    const UNIX_EPOCH_FILETIME: u64 = 116_444_736_000_000_000;
+    Some(filetime.checked_sub(UNIX_EPOCH_FILETIME)? / 10_000_000)
+}
+
+#[cfg(not(windows))]
+fn start_time_to_unix(ticks: u64) -> Option<u64> {
+    // 开机时刻加上启动时的时钟节拍数（USER_HZ 在 Linux 上固定为 100）
+    let stat = std::fs::read_to_string("/proc/stat").ok()?;
+    let boot_time: u64 = stat.lines().find_map(|line| line.strip_prefix("btime "))?.trim().parse().ok()?;
+    Some(boot_time + ticks / 100)
+}
+
+/// 进程可执行文件的完整路径
+#[cfg(windows)]
+fn process_image_path(pid: u32) -> Option<PathBuf> {
+    use windows::core::PWSTR;
+    use windows::Win32::Foundation::CloseHandle;
+    use windows::Win32::System::Threading::{
+        OpenProcess, QueryFullProcessImageNameW, PROCESS_NAME_WIN32, PROCESS_QUERY_LIMITED_INFORMATION,
+    };
+
+    unsafe {
+        let handle = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, pid).ok()?;
+        let mut buffer = vec![0u16; 32768];
+        let mut len = buffer.len() as u32;
+        let result = QueryFullProcessImageNameW(handle, PROCESS_NAME_WIN32, PWSTR(buffer.as_mut_ptr()), &mut len);
+        let _ = CloseHandle(handle);
+        result.ok()?;
+        Some(PathBuf::from(String::from_utf16_lossy(&buffer[..len as usize])))
+    }
+}
+
+#[cfg(not(windows))]
+fn process_image_path(pid: u32) -> Option<PathBuf> {
+    std::fs::read_link(format!("/proc/{}/exe", pid)).ok()
+}
+
+#[cfg(windows)]
+fn terminate_process(pid: u32) -> anyhow::Result<()> {
+    use windows::Win32::Foundation::CloseHandle;
+    use windows::Win32::System::Threading::{OpenProcess, TerminateProcess, PROCESS_TERMINATE};
+
+    unsafe {
+        let handle = OpenProcess(PROCESS_TERMINATE, false, pid)?;
+        let result = TerminateProcess(handle, 1);
+        let _ = CloseHandle(handle);
+        result?;
+    }
+    Ok(())
+}
+
+#[cfg(not(windows))]
+fn terminate_process(pid: u32) -> anyhow::Result<()> {
+    let status = Command::new("kill").args(["-KILL", &pid.to_string()]).status()?;
+    if !status.success() {
+        anyhow::bail!("kill 退出码 {:?}", status.code());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 用系统 shell 执行脚本的程序和参数
+    fn shell(windows: &'static str, unix: &'static str) -> (&'static Path, Vec<&'static str>) {
+        if cfg!(windows) {
+            (Path::new("cmd.exe"), vec!["/C", windows])
+        } else {
+            (Path::new("sh"), vec!["-c", unix])
+        }
+    }
+
+    #[test]
+    fn test_decode_output() {
+        // diskpart 在简体中文系统上的输出（GBK）
+        let gbk = encoding_rs::GBK.encode("磁盘 0    联机").0.into_owned();
+        assert_eq!(decode_with_codepage(&gbk, 936), "磁盘 0    联机");
+
+        // PowerShell 重定向得到的 UTF-16LE，有 BOM 和无 BOM
+        let utf16: Vec<u8> = "卷 C 已就绪\r\n".encode_utf16().flat_map(u16::to_le_bytes).collect();
+        let with_bom = [&[0xFF, 0xFE][..], &utf16].concat();
+        assert_eq!(decode_with_codepage(&with_bom, 936), "卷 C 已就绪\r\n");
+        let ascii: Vec<u8> = "Ready".encode_utf16().flat_map(u16::to_le_bytes).collect();
+        assert_eq!(decode_with_codepage(&ascii, 936), "Ready");
+
+        // 不是合法 GBK 的 UTF-8 输出（如自带工具）按 UTF-8 解码
+        assert_eq!(decode_with_codepage("完成 ✓".as_bytes(), 936), "完成 ✓");
+        assert_eq!(decode_with_codepage(&[0xEF, 0xBB, 0xBF, b'o', b'k'], 936), "ok");
+        assert_eq!(decode_with_codepage(&[0xE9], 1252), "é");
+    }
+
+    #[test]
+    fn test_quote_cmd_arg() {
+        assert_eq!(quote_cmd_arg("/enum").unwrap(), "/enum");
+        assert_eq!(
+            quote_cmd_arg(r"C:\Program Files\LetRecovery\bin\bcdedit.exe").unwrap(),
+            r#""C:\Program Files\LetRecovery\bin\bcdedit.exe""#
+        );
+        assert_eq!(quote_cmd_arg("a&b").unwrap(), "\"a&b\"");
+        assert_eq!(quote_cmd_arg("").unwrap(), "\"\"");
+        assert!(quote_cmd_arg("say \"hi\"").is_err());
+    }
+
+    #[test]
+    fn test_descendants_bottom_up() {
+        let entry = |pid, parent| ProcessEntry {
+            pid,
+            parent,
+            name: format!("{}.exe", pid),
+        };
+        // 10 -> 11 -> 13, 10 -> 12；20 的父进程 ID 也是 10，但创建得比 10 早（PID 被复用）
+        let entries = [entry(10, 1), entry(11, 10), entry(12, 10), entry(13, 11), entry(20, 10), entry(30, 1)];
+        let started = |pid: u32| Some(if pid == 20 { 0 } else { pid as u64 });
+        let order = descendants_bottom_up(10, &entries, started);
+        assert_eq!(order.len(), 3);
+        let position = |pid| order.iter().position(|&p| p == pid).unwrap();
+        assert!(position(13) < position(11));
+        assert!(order.contains(&12));
+        assert!(descendants_bottom_up(30, &entries, |_| None).is_empty());
+    }
+
+    #[test]
+    fn test_find_processes_by_name() {
+        assert!(process_name_matches("aria2c.exe", "ARIA2C"));
+        assert!(process_name_matches("Dism.exe", "dism.exe"));
+        assert!(!process_name_matches("aria2c.exe", "aria2"));
+
+        let me = process_info(std::process::id()).unwrap();
+        assert_eq!(me.exe_path, std::env::current_exe().ok());
+        let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs();
+        assert!(me.started_at.is_some_and(|t| t <= now + 1 && t + 3600 > now));
+        assert!(find_processes_by_name(&me.name).iter().any(|p| p.pid == me.pid));
+        assert!(process_info(u32::MAX - 1).is_none());
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_kill_process_tree_kills_children() {
+        let mut wrapper = create_command("cmd.exe")
+            .args(["/c", "ping -n 30 127.0.0.1"])
+            .stdout(Stdio::null())
+            .spawn()
+            .unwrap();
+        // 等待 cmd.exe 启动 ping
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let ping = loop {
+            let snapshot = process_snapshot().unwrap();
+            let child = snapshot
+                .iter()
+                .find(|e| e.parent == wrapper.id() && e.name.eq_ignore_ascii_case("PING.EXE"));
+            if let Some(child) = child {
+                break child.pid;
+            }
+            assert!(Instant::now() < deadline, "ping 未启动");
+            std::thread::sleep(Duration::from_millis(100));
+        };
+
+        kill_process_tree(wrapper.id()).unwrap();
+        let _ = wrapper.wait();
+        std::thread::sleep(Duration::from_millis(200));
+        assert!(!is_pid_alive(ping));
+    }
+
+    #[test]
+    fn test_line_splitter() {
+        let mut splitter = LineSplitter::default();
+        let mut lines = splitter.push(b"[==   10.0%   ]\r[=====  50.0%  ]\r");
+        lines.extend(splitter.push(b"\ndone\r\nerr\n\ntail"));
+        assert_eq!(lines, [&b"[==   10.0%   ]"[..], b"[=====  50.0%  ]", b"done", b"err", b""]);
+        assert_eq!(splitter.finish(), Some(b"tail".to_vec()));
+
+        // 7-Zip 用退格符改写进度
+        let mut splitter = LineSplitter::default();
+        let lines = splitter.push(b"  5% 3\x08\x08\x08\x08\x08\x08      \x08\x08\x08\x08\x08\x08 42% 7\x08\x08\x08\x08\x08\x08");
+        assert_eq!(lines, [&b"  5% 3"[..], b"      ", b" 42% 7"]);
+    }
+
+    #[tokio::test]
+    async fn test_spawn_streaming() {
+        let (program, args) = shell("echo out && echo err 1>&2 && exit 2", "echo out; echo err >&2; exit 2");
+        let mut child = spawn_streaming(program, &args).unwrap();
+        let mut lines = Vec::new();
+        while let Some(line) = child.next_line().await {
+            lines.push(line);
+        }
+        // cmd.exe 的 echo 会保留 && 前的空格
+        assert!(lines.iter().any(|l| matches!(l, OutputLine::Stdout(s) if s.trim() == "out")));
+        assert!(lines.iter().any(|l| matches!(l, OutputLine::Stderr(s) if s.trim() == "err")));
+        assert_eq!(child.wait().await.unwrap(), Some(2));
+    }
+
+    #[test]
+    fn test_parse_powershell_json() {
+        #[derive(Debug, serde::Deserialize, PartialEq)]
+        struct Disk {
+            #[serde(rename = "Number")]
+            number: u32,
+        }
+        let many: Vec<Disk> = parse_powershell_json(r#"[{"Number":0},{"Number":1}]"#).unwrap();
+        assert_eq!(many.len(), 2);
+        // 只有一个结果时 ConvertTo-Json 输出对象
+        let one: Vec<Disk> = parse_powershell_json("{\r\n  \"Number\": 0\r\n}\r\n").unwrap();
+        assert_eq!(one, [Disk { number: 0 }]);
+        let none: Vec<Disk> = parse_powershell_json("").unwrap();
+        assert!(none.is_empty());
+        let single: Disk = parse_powershell_json(r#"{"Number":3}"#).unwrap();
+        assert_eq!(single.number, 3);
+        assert!(parse_powershell_json::<Disk>("").is_err());
+    }
+
+    #[test]
+    fn test_powershell_error_text() {
+        assert_eq!(powershell_error_text("Get-Disk : 拒绝访问。\r\n"), "Get-Disk : 拒绝访问。");
+        let clixml = r#"#< CLIXML
+<Objs Version="1.1.0.1"><Obj S="progress" RefId="0"><TN RefId="0"><T>System.Management.Automation.PSCustomObject</T></TN></Obj><S S="Error">找不到磁盘_x000D__x000A_</S><S S="Error">行 1_x000D__x000A_</S></Objs>"#;
+        assert_eq!(powershell_error_text(clixml), "找不到磁盘\n行 1");
+        assert_eq!(powershell_error_text(r#"#< CLIXML
+<Objs Version="1.1.0.1"><Obj S="progress" RefId="0"></Obj></Objs>"#), "");
+    }
+
+    #[test]
+    fn test_command_options_flags() {
+        assert_eq!(CommandOptions::default().flags(), CREATE_NO_WINDOW);
+        let options = CommandOptions {
+            show_window: true,
+            creation_flags: 0x10,
+            priority: Some(PriorityClass::BelowNormal),
+            ..Default::default()
+        };
+        assert_eq!(options.flags(), 0x10 | 0x4000);
+        // 没有创建作业对象时不脱离作业
+        let options = CommandOptions {
+            kill_with_app: false,
+            ..Default::default()
+        };
+        assert_eq!(options.flags(), CREATE_NO_WINDOW);
+    }
+
+    #[test]
+    fn test_backoff_delay() {
+        let exponential = Backoff::Exponential {
+            initial: Duration::from_millis(100),
+            max: Duration::from_secs(1),
+            jitter: false,
+        };
+        let delays: Vec<_> = (1..=6).map(|n| exponential.delay(n).as_millis()).collect();
+        assert_eq!(delays, [100, 200, 400, 800, 1000, 1000]);
+        assert_eq!(Backoff::Fixed(Duration::from_secs(2)).delay(5), Duration::from_secs(2));
+
+        let jittered = Backoff::Exponential {
+            initial: Duration::from_millis(400),
+            max: Duration::from_secs(10),
+            jitter: true,
+        };
+        for _ in 0..20 {
+            let delay = jittered.delay(1);
+            assert!(delay >= Duration::from_millis(200) && delay <= Duration::from_millis(400));
+        }
+    }
+
+    #[test]
+    fn test_run_with_retry_reports_attempts() {
+        let (program, args) = shell("echo busy 1>&2 && exit 5", "echo busy >&2; exit 5");
+        let policy = RetryPolicy {
+            max_attempts: 3,
+            backoff: Backoff::Fixed(Duration::from_millis(10)),
+            retryable: None,
+        };
+        let err = run_with_retry(program, &args, Duration::from_secs(10), &policy).unwrap_err();
+        match err.downcast_ref::<CmdError>() {
+            Some(CmdError::CommandFailed { attempts, output, .. }) => {
+                assert_eq!(*attempts, 3);
+                assert_eq!(output.exit_code, Some(5));
+                assert_eq!(output.stderr.trim(), "busy");
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+
+        // 错误输出不匹配时不重试
+        let policy = policy.retry_on_stderr(&["locked"]);
+        let err = run_with_retry(program, &args, Duration::from_secs(10), &policy).unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(CmdError::CommandFailed { attempts: 1, .. })));
+    }
+
+    #[test]
+    fn test_spawn_detached_returns_running_pid() {
+        let (program, args) = shell("ping -n 3 127.0.0.1 > nul", "sleep 2");
+        let pid = spawn_detached(program, &args).unwrap();
+        assert!(is_pid_alive(pid));
+        let _ = kill_process_tree(pid);
+    }
+
+    #[test]
+    fn test_cmd_builder() {
+        let dir = std::env::temp_dir();
+        let (program, args) = shell("cd && echo %LR_TEST_VAR% && sort", r#"pwd; echo "$LR_TEST_VAR"; cat"#);
+        // 输入大于管道缓冲区，子进程同时回显输出：不能先写完输入再读输出
+        let line = "x".repeat(63);
+        let input = format!("{}\n", line).repeat(4096);
+        let output = CmdBuilder::new(program)
+            .args(&args)
+            .current_dir(&dir)
+            .env("LR_TEST_VAR", "hello")
+            .stdin_bytes(input.clone().into_bytes())
+            .timeout(Duration::from_secs(30))
+            .run()
+            .unwrap();
+        assert!(output.success(), "{:?}", output.stderr);
+        let mut lines = output.stdout.lines();
+        let cwd = std::fs::canonicalize(lines.next().unwrap().trim()).unwrap();
+        assert_eq!(cwd, std::fs::canonicalize(&dir).unwrap());
+        assert_eq!(lines.next().unwrap().trim(), "hello");
+        assert_eq!(lines.filter(|l| l.trim() == line).count(), 4096);
+    }
+
+    #[test]
+    fn test_run_with_output() {
+        let (program, args) = shell("echo out && echo err 1>&2 && exit 3", "echo out; echo err >&2; exit 3");
+        let output = run_with_output(program, &args, Duration::from_secs(10)).unwrap();
+        assert_eq!(output.stdout.trim(), "out");
+        assert_eq!(output.stderr.trim(), "err");
+        assert_eq!(output.exit_code, Some(3));
+        assert!(!output.success());
+    }
+
+    #[test]
+    fn test_run_with_output_kills_on_timeout() {
+        let (program, args) = shell("echo partial && ping -n 30 127.0.0.1 > nul", "echo partial; sleep 30");
+        let err = run_with_output(program, &args, Duration::from_millis(500)).unwrap_err();
+        match err.downcast_ref::<CmdError>() {
+            Some(CmdError::CommandTimeout { output, .. }) => {
+                assert_eq!(output.stdout.trim(), "partial");
+                assert_eq!(output.exit_code, None);
+                assert!(output.duration < Duration::from_secs(10));
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,455 @@
+//! 文件哈希计算（流式、可报告进度、可取消）
+//!
+//! 用于下载完成后校验数 GB 的 ISO/WIM 文件：
+//! - 在阻塞线程池中分块读取，不卡住异步运行时和界面
+//! - 每读取一块回调一次「已处理字节 / 总字节」，回调返回 false 即取消
+//! - 支持 SHA-256、SHA-1、MD5
+//! - 多个文件可用 `hash_files` 并行计算，汇总报告进度
+//!
+//! 另有 CRC32 快速检查（`quick_verify`），只用于「续传后已有数据是否完好」这类粗略判断：
+//! CRC32 不抗篡改，抽样模式还会漏掉未抽到的区域，结果类型为 `QuickCheck::QuickCheckPassed`
+//! 而不是「已校验」。应用镜像前仍须用 SHA-256 完整校验。
+
+use anyhow::Result;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use super::md5::Md5Context;
+
+/// 每次读取的块大小
+const CHUNK_SIZE: usize = 1 << 20; // 1 MiB
+
+/// `hash_files` 默认同时计算的文件数：机械硬盘上更多的并发只会增加寻道
+pub const DEFAULT_HASH_CONCURRENCY: usize = 3;
+
+/// 哈希算法
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashType {
+    Sha256,
+    Sha1,
+    Md5,
+}
+
+impl HashType {
+    /// aria2 `checksum` 选项中的算法名
+    pub fn aria2_name(&self) -> &'static str {
+        match self {
+            HashType::Sha256 => "sha-256",
+            HashType::Sha1 => "sha-1",
+            HashType::Md5 => "md5",
+        }
+    }
+
+    /// 十六进制摘要的长度
+    pub fn hex_len(&self) -> usize {
+        match self {
+            HashType::Sha256 => 64,
+            HashType::Sha1 => 40,
+            HashType::Md5 => 32,
+        }
+    }
+}
+
+/// 快速检查读取哪些数据
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SampleStrategy {
+    /// 读取整个文件
+    Full,
+    /// 只读取开头和结尾各 `edge_bytes` 字节，以及中间 `random_blocks` 个 1 MiB 的块
+    ///
+    /// 块的位置由 `seed` 和文件大小决定，同一文件用相同参数计算的结果可以比较。
+    /// 抽样覆盖整个文件时与 `Full` 结果相同。
+    Sampled { edge_bytes: u64, random_blocks: u32, seed: u64 },
+}
+
+/// CRC32 快速检查的结果（不等同于 SHA-256 校验通过）
+#[must_use]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuickCheck {
+    /// 读取的数据与预期 CRC32 一致；`sampled_bytes` 为实际读取的字节数
+    QuickCheckPassed { sampled_bytes: u64, total_bytes: u64 },
+    Mismatch { expected: u32, actual: u32 },
+}
+
+impl QuickCheck {
+    pub fn passed(&self) -> bool {
+        matches!(self, QuickCheck::QuickCheckPassed { .. })
+    }
+}
+
+/// 哈希计算错误
+#[derive(Debug, thiserror::Error)]
+pub enum HashError {
+    #[error("哈希计算已取消")]
+    Cancelled,
+
+    #[error("读取文件失败: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// 统一三种算法的增量计算接口
+enum Hasher {
+    Sha256(Sha256),
+    Sha1(Sha1),
+    Md5(Md5Context),
+}
+
+impl Hasher {
+    fn new(algo: HashType) -> Self {
+        match algo {
+            HashType::Sha256 => Hasher::Sha256(Sha256::new()),
+            HashType::Sha1 => Hasher::Sha1(Sha1::new()),
+            HashType::Md5 => Hasher::Md5(Md5Context::new()),
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Sha256(h) => h.update(data),
+            Hasher::Sha1(h) => h.update(data),
+            Hasher::Md5(h) => h.update(data),
+        }
+    }
+
+    fn finalize_hex(self) -> String {
+        let digest: Vec<u8> = match self {
+            Hasher::Sha256(h) => h.finalize().to_vec(),
+            Hasher::Sha1(h) => h.finalize().to_vec(),
+            Hasher::Md5(h) => h.finalize().to_vec(),
+        };
+        digest.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+}
+
+/// 从 reader 流式计算哈希（小写十六进制）
+///
+/// `total` 仅用于回调报告；`progress_cb(已处理, 总数)` 返回 false 时中止并返回 `HashError::Cancelled`。
+pub fn hash_reader<R: Read>(
+    mut reader: R,
+    algo: HashType,
+    total: u64,
+    mut progress_cb: impl FnMut(u64, u64) -> bool,
+) -> std::result::Result<String, HashError> {
+    let mut hasher = Hasher::new(algo);
+    let mut buffer = vec![0u8; CHUNK_SIZE];
+    let mut processed = 0u64;
+
+    loop {
+        let n = reader.read(&mut buffer)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buffer[..n]);
+        processed += n as u64;
+        if !progress_cb(processed, total) {
+            return Err(HashError::Cancelled);
+        }
+    }
+
+    Ok(hasher.finalize_hex())
+}
+
+/// 同步计算文件哈希（会阻塞当前线程，适合已在后台线程中的调用方）
+pub fn hash_file_blocking(
+    path: &Path,
+    algo: HashType,
+    progress_cb: impl FnMut(u64, u64) -> bool,
+) -> std::result::Result<String, HashError> {
+    let file = File::open(super::path::to_extended_path(path))?;
+    let total = file.metadata()?.len();
+    hash_reader(file, algo, total, progress_cb)
+}
+
+/// 并行计算多个文件的哈希，按 `paths` 的顺序返回每个文件的结果
+///
+/// 最多同时计算 `concurrency` 个文件（None 时为 `DEFAULT_HASH_CONCURRENCY`）。
+/// 单个文件读取失败只体现在该文件的结果中，不影响其它文件；
+/// `progress_cb(全部已处理, 全部总字节)` 返回 false 时整批取消，错误可 downcast 为 `HashError::Cancelled`。
+pub async fn hash_files<F>(
+    paths: &[PathBuf],
+    algo: HashType,
+    concurrency: Option<usize>,
+    progress_cb: F,
+) -> Result<Vec<(PathBuf, std::result::Result<String, HashError>)>>
+where
+    F: FnMut(u64, u64) -> bool + Send + 'static,
+{
+    let paths: Arc<Vec<PathBuf>> = Arc::new(paths.to_vec());
+    // 打不开的文件按 0 字节计入总数，错误在计算时报告
+    let total: u64 = paths
+        .iter()
+        .filter_map(|p| std::fs::metadata(super::path::to_extended_path(p)).ok())
+        .map(|m| m.len())
+        .sum();
+    let next = Arc::new(AtomicUsize::new(0));
+    let processed = Arc::new(AtomicU64::new(0));
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let progress_cb = Arc::new(parking_lot::Mutex::new(progress_cb));
+
+    let workers = concurrency.unwrap_or(DEFAULT_HASH_CONCURRENCY).clamp(1, paths.len().max(1));
+    let handles: Vec<_> = (0..workers)
+        .map(|_| {
+            let (paths, next, processed, cancelled, progress_cb) =
+                (paths.clone(), next.clone(), processed.clone(), cancelled.clone(), progress_cb.clone());
+            tokio::task::spawn_blocking(move || {
+                let mut results = Vec::new();
+                loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    if index >= paths.len() || cancelled.load(Ordering::Relaxed) {
+                        return results;
+                    }
+                    let mut reported = 0u64;
+                    let result = hash_file_blocking(&paths[index], algo, |done, _| {
+                        let all = processed.fetch_add(done - reported, Ordering::Relaxed) + (done - reported);
+                        reported = done;
+                        if cancelled.load(Ordering::Relaxed) || !(progress_cb.lock())(all, total) {
+                            cancelled.store(true, Ordering::Relaxed);
+                            return false;
+                        }
+                        true
+                    });
+                    results.push((index, result));
+                }
+            })
+        })
+        .collect();
+
+    let mut results = Vec::with_capacity(paths.len());
+    for handle in handles {
+        results.extend(handle.await?);
+    }
+    if cancelled.load(Ordering::Relaxed) {
+        return Err(HashError::Cancelled.into());
+    }
+    results.sort_by_key(|(index, _)| *index);
+    Ok(results.into_iter().map(|(index, result)| (paths[index].clone(), result)).collect())
+}
+
+/// 读取 `strategy` 选中的区域并计算 CRC32（支持时使用 CPU 的 CRC 指令），返回 (CRC32, 读取的字节数)
+///
+/// 用于记录预期值，之后用相同的 `strategy` 调用 `quick_verify` 比较。
+pub fn quick_crc32_blocking(
+    path: &Path,
+    strategy: SampleStrategy,
+    mut progress_cb: impl FnMut(u64, u64) -> bool,
+) -> std::result::Result<(u32, u64), HashError> {
+    let mut file = File::open(super::path::to_extended_path(path))?;
+    let len = file.metadata()?.len();
+    let ranges = sample_ranges(len, strategy);
+    let sampled: u64 = ranges.iter().map(|(_, n)| n).sum();
+
+    let mut hasher = crc32fast::Hasher::new();
+    let mut buffer = vec![0u8; CHUNK_SIZE];
+    let mut processed = 0u64;
+    for (offset, size) in ranges {
+        file.seek(SeekFrom::Start(offset))?;
+        let mut remaining = size;
+        while remaining > 0 {
+            let want = remaining.min(CHUNK_SIZE as u64) as usize;
+            file.read_exact(&mut buffer[..want])?;
+            hasher.update(&buffer[..want]);
+            remaining -= want as u64;
+            processed += want as u64;
+            if !progress_cb(processed, sampled) {
+                return Err(HashError::Cancelled);
+            }
+        }
+    }
+    Ok((hasher.finalize(), sampled))
+}
+
+/// 用 CRC32 快速检查文件（在阻塞线程池中读取），比 SHA-256 弱得多，见模块说明
+///
+/// `expected_crc` 须是用相同 `strategy` 由 `quick_crc32_blocking` 算出的值。
+/// 取消时返回的错误可 downcast 为 `HashError::Cancelled`。
+pub async fn quick_verify<F>(path: impl Into<PathBuf>, expected_crc: u32, strategy: SampleStrategy, progress_cb: F) -> Result<QuickCheck>
+where
+    F: FnMut(u64, u64) -> bool + Send + 'static,
+{
+    let path = path.into();
+    let total_bytes = std::fs::metadata(super::path::to_extended_path(&path))?.len();
+    let (actual, sampled_bytes) =
+        tokio::task::spawn_blocking(move || quick_crc32_blocking(&path, strategy, progress_cb)).await??;
+    Ok(if actual == expected_crc {
+        QuickCheck::QuickCheckPassed { sampled_bytes, total_bytes }
+    } else {
+        QuickCheck::Mismatch { expected: expected_crc, actual }
+    })
+}
+
+/// `strategy` 在长度为 `len` 的文件中选中的区域 (偏移, 长度)：按偏移排序，重叠的区域已合并
+fn sample_ranges(len: u64, strategy: SampleStrategy) -> Vec<(u64, u64)> {
+    let SampleStrategy::Sampled { edge_bytes, random_blocks, seed } = strategy else {
+        return vec![(0, len)];
+    };
+    let block = CHUNK_SIZE as u64;
+    let edge = edge_bytes.min(len);
+    let mut ranges = vec![(0, edge), (len - edge, edge)];
+
+    // xorshift64，种子混入文件大小，使不同大小的文件抽到不同位置；种子为 0 时 xorshift 会一直输出 0
+    let mut state = (seed ^ len.rotate_left(32)) | 1;
+    let middle = len.saturating_sub(2 * edge);
+    for _ in 0..random_blocks {
+        if middle == 0 {
+            break;
+        }
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        let offset = edge + state % middle;
+        ranges.push((offset, block.min(len - offset)));
+    }
+
+    ranges.sort_unstable();
+    let mut merged: Vec<(u64, u64)> = Vec::with_capacity(ranges.len());
+    for (offset, size) in ranges.into_iter().filter(|&(_, size)| size > 0) {
+        match merged.last_mut() {
+            Some((start, n)) if offset <= *start + *n => *n = (*n).max(offset + size - *start),
+            _ => merged.push((offset, size)),
+        }
+    }
+    merged
+}
+
+/// 异步计算文件哈希（在阻塞线程池中分块读取）
+///
+/// 取消时返回的错误可 downcast 为 `HashError::Cancelled`。
+pub async fn hash_file<F>(path: impl Into<PathBuf>, algo: HashType, progress_cb: F) -> Result<String>
+where
+    F: FnMut(u64, u64) -> bool + Send + 'static,
+{
+    let path = path.into();
+    let hash = tokio::task::spawn_blocking(move || hash_file_blocking(&path, algo, progress_cb))
+        .await??;
+    Ok(hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hash_bytes(data: &[u8], algo: HashType) -> String {
+        hash_reader(data, algo, data.len() as u64, |_, _| true).unwrap()
+    }
+
+    #[test]
+    fn test_known_vectors() {
+        assert_eq!(
+            hash_bytes(b"abc", HashType::Sha256),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            hash_bytes(b"abc", HashType::Sha1),
+            "a9993e364706816aba3e25717850c26c9cd0d89d"
+        );
+        assert_eq!(hash_bytes(b"abc", HashType::Md5), "900150983cd24fb0d6963f7d28e17f72");
+        assert_eq!(hash_bytes(b"", HashType::Md5), "d41d8cd98f00b204e9800998ecf8427e");
+    }
+
+    #[tokio::test]
+    async fn test_hash_files_isolates_failures() {
+        let dir = std::env::temp_dir().join(format!("letrecovery_hash_files_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut paths = Vec::new();
+        for i in 0..5u8 {
+            let path = dir.join(format!("{}.bin", i));
+            std::fs::write(&path, vec![i; CHUNK_SIZE + i as usize]).unwrap();
+            paths.push(path);
+        }
+        paths.insert(2, dir.join("missing.bin"));
+        let total: u64 = (0..5).map(|i| (CHUNK_SIZE + i) as u64).sum();
+
+        let last = Arc::new(parking_lot::Mutex::new(0u64));
+        let last_cb = last.clone();
+        let results = hash_files(&paths, HashType::Md5, Some(2), move |done, all| {
+            assert_eq!(all, total);
+            *last_cb.lock() = done;
+            true
+        })
+        .await
+        .unwrap();
+        assert_eq!(*last.lock(), total);
+        assert_eq!(results.len(), 6);
+        assert!(matches!(results[2], (ref p, Err(HashError::Io(_))) if p == &paths[2]));
+        let expected = hash_bytes(&vec![4u8; CHUNK_SIZE + 4], HashType::Md5);
+        assert_eq!(results[5].1.as_ref().unwrap(), &expected);
+
+        let err = hash_files(&paths, HashType::Md5, None, |_, _| false).await.unwrap_err();
+        assert!(matches!(err.downcast_ref::<HashError>(), Some(HashError::Cancelled)));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_sample_ranges() {
+        let mib = CHUNK_SIZE as u64;
+        let strategy = SampleStrategy::Sampled {
+            edge_bytes: 4 * mib,
+            random_blocks: 3,
+            seed: 42,
+        };
+        let ranges = sample_ranges(100 * mib, strategy);
+        assert_eq!(ranges.first(), Some(&(0, 4 * mib)));
+        assert_eq!(ranges.last().map(|(offset, n)| offset + n), Some(100 * mib));
+        assert!(ranges.iter().map(|(_, n)| n).sum::<u64>() <= 11 * mib);
+        assert!(ranges.windows(2).all(|w| w[0].0 + w[0].1 < w[1].0));
+        // 相同参数抽到相同位置
+        assert_eq!(ranges, sample_ranges(100 * mib, strategy));
+
+        // 抽样覆盖整个文件时与 Full 相同
+        assert_eq!(sample_ranges(6 * mib, strategy), vec![(0, 6 * mib)]);
+        assert_eq!(sample_ranges(0, strategy), Vec::new());
+    }
+
+    #[tokio::test]
+    async fn test_quick_verify() {
+        let path = std::env::temp_dir().join(format!("letrecovery_crc_{}.bin", std::process::id()));
+        std::fs::write(&path, b"123456789").unwrap();
+        // CRC-32/ISO-HDLC 的标准测试向量
+        let (crc, sampled) = quick_crc32_blocking(&path, SampleStrategy::Full, |_, _| true).unwrap();
+        assert_eq!((crc, sampled), (0xCBF4_3926, 9));
+
+        let sampled = SampleStrategy::Sampled {
+            edge_bytes: 1 << 20,
+            random_blocks: 2,
+            seed: 7,
+        };
+        let outcome = quick_verify(&path, crc, sampled, |_, _| true).await.unwrap();
+        assert_eq!(outcome, QuickCheck::QuickCheckPassed { sampled_bytes: 9, total_bytes: 9 });
+        let outcome = quick_verify(&path, crc ^ 1, SampleStrategy::Full, |_, _| true).await.unwrap();
+        assert!(!outcome.passed());
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn test_large_file_progress_and_cancel() {
+        let path = std::env::temp_dir().join(format!("letrecovery_hash_{}.bin", std::process::id()));
+        let data = vec![0x5au8; 5 * CHUNK_SIZE + 123];
+        std::fs::write(&path, &data).unwrap();
+
+        let last = std::sync::Arc::new(std::sync::Mutex::new((0u64, 0u64)));
+        let last_cb = last.clone();
+        let hash = hash_file(&path, HashType::Sha256, move |done, total| {
+            *last_cb.lock().unwrap() = (done, total);
+            true
+        })
+        .await
+        .unwrap();
+        assert_eq!(hash, hash_bytes(&data, HashType::Sha256));
+        assert_eq!(*last.lock().unwrap(), (data.len() as u64, data.len() as u64));
+
+        // 第二块之后取消
+        let err = hash_file(&path, HashType::Md5, |done, _| done < 2 * CHUNK_SIZE as u64)
+            .await
+            .unwrap_err();
+        assert!(matches!(err.downcast_ref::<HashError>(), Some(HashError::Cancelled)));
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -16,8 +16,6 @@ use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
 use tracing_subscriber::{fmt, EnvFilter, Layer};
 
-use super::path::get_exe_dir;
-
 /// 全局日志启用状态
 static LOG_ENABLED: AtomicBool = AtomicBool::new(true);
 
@@ -30,7 +28,7 @@ pub struct LogManager;
 impl LogManager {
     /// 获取日志目录路径
     pub fn get_log_dir() -> PathBuf {
-        get_exe_dir().join("log")
+        super::path::get_log_dir()
     }
 
     /// 初始化日志系统
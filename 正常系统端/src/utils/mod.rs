@@ -1,7 +1,10 @@
 pub mod cmd;
 pub mod command;
 pub mod encoding;
+pub mod hash;
 pub mod i18n;
 pub mod logger;
+pub mod md5;
 pub mod path;
 pub mod privilege;
+pub mod tool_error;
@@ -1,4 +1,31 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, OnceLock, RwLock};
+use std::time::{Duration, SystemTime};
+
+/// 覆盖 bin 目录的环境变量（`cargo run` 时工作区中没有 bin 目录，或测试时指向存放测试用程序的目录）
+pub const BIN_DIR_ENV: &str = "LETRECOVERY_BIN_DIR";
+
+/// 已确定的 bin 目录，首次使用或 `init_bin_dir` 时确定
+static BIN_DIR: RwLock<Option<PathBuf>> = RwLock::new(None);
+
+/// 便携模式的标记文件：放在程序所在目录下时，数据、日志和缓存都保存在程序所在目录
+pub const PORTABLE_FLAG: &str = "portable.flag";
+
+/// 安装模式下的程序数据目录名（在 %LOCALAPPDATA% 下）
+const APP_DIR_NAME: &str = "LetRecovery";
+
+/// 数据目录的存放方式，首次使用时确定
+static PATH_MODE: OnceLock<PathMode> = OnceLock::new();
+
+/// 本次运行的临时目录（`%TEMP%\LetRecovery\<运行 ID>`），首次使用时创建
+static SESSION_TEMP_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// `temp_file` 的序号
+static TEMP_FILE_COUNTER: AtomicU32 = AtomicU32::new(0);
+
+/// 各次运行的临时目录的上级目录名（在系统临时目录下）
+const SESSION_TEMP_ROOT: &str = "LetRecovery";
 
 /// 获取程序所在目录
 pub fn get_exe_dir() -> PathBuf {
@@ -9,8 +36,120 @@ pub fn get_exe_dir() -> PathBuf {
 }
 
 /// 获取 bin 目录路径
+///
+/// 依次使用配置文件的 `bin_dir`（需先调用 `init_bin_dir`）、环境变量 `LETRECOVERY_BIN_DIR`、程序所在目录下的 bin，
+/// 不存在的目录跳过。结果在首次调用时确定并记录到日志。
 pub fn get_bin_dir() -> PathBuf {
-    get_exe_dir().join("bin")
+    if let Some(dir) = BIN_DIR.read().ok().and_then(|dir| dir.clone()) {
+        return dir;
+    }
+    init_bin_dir(None)
+}
+
+/// 按配置文件中的 `bin_dir` 重新确定 bin 目录并返回，启动时在读取配置后调用
+pub fn init_bin_dir(configured: Option<&Path>) -> PathBuf {
+    let from_env = std::env::var_os(BIN_DIR_ENV).filter(|v| !v.is_empty()).map(PathBuf::from);
+    let candidates = [
+        (configured.map(Path::to_path_buf), "配置文件"),
+        (from_env, "环境变量 LETRECOVERY_BIN_DIR"),
+    ];
+    let (dir, source) = pick_bin_dir(candidates, get_exe_dir().join("bin"));
+    log::info!("bin 目录: {}（来自{}）", dir.display(), source);
+    if let Ok(mut cached) = BIN_DIR.write() {
+        *cached = Some(dir.clone());
+    }
+    dir
+}
+
+/// 从候选目录中选出第一个存在的，都不存在时为 `default`；相对路径基于程序所在目录
+fn pick_bin_dir(candidates: [(Option<PathBuf>, &'static str); 2], default: PathBuf) -> (PathBuf, &'static str) {
+    for (dir, source) in candidates {
+        let Some(dir) = dir else { continue };
+        let dir = if dir.is_relative() { get_exe_dir().join(dir) } else { dir };
+        if dir.is_dir() {
+            return (dir, source);
+        }
+        log::warn!("{}指定的 bin 目录 {} 不存在，忽略", source, dir.display());
+    }
+    if !default.is_dir() {
+        log::warn!("bin 目录 {} 不存在，外部工具将无法使用", default.display());
+    }
+    (default, "程序所在目录")
+}
+
+/// 数据、日志和缓存目录的存放方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathMode {
+    /// 放在程序所在目录，从 U 盘运行时随程序一起带走
+    Portable,
+    /// 放在当前用户的 %LOCALAPPDATA%\LetRecovery 下
+    Installed,
+}
+
+impl std::fmt::Display for PathMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            PathMode::Portable => "便携模式",
+            PathMode::Installed => "安装模式",
+        })
+    }
+}
+
+/// 当前的存放方式
+///
+/// 程序所在目录下有 `portable.flag`，或有早期版本留下的 data 目录时为便携模式；
+/// 但程序所在目录不可写（光盘、写保护的 U 盘、Program Files）或取不到 %LOCALAPPDATA%（如 PE 环境）时
+/// 分别退回安装模式和便携模式。
+pub fn path_mode() -> PathMode {
+    *PATH_MODE.get_or_init(|| {
+        let local_app_data = dirs::data_local_dir();
+        detect_path_mode(&get_exe_dir(), local_app_data.is_some())
+    })
+}
+
+fn detect_path_mode(exe_dir: &Path, has_local_app_data: bool) -> PathMode {
+    if !has_local_app_data {
+        return PathMode::Portable;
+    }
+    let wants_portable = exe_dir.join(PORTABLE_FLAG).is_file() || exe_dir.join("data").is_dir();
+    if wants_portable && ensure_writable(exe_dir).is_ok() {
+        PathMode::Portable
+    } else {
+        PathMode::Installed
+    }
+}
+
+/// 数据、日志和缓存目录的上级目录：便携模式为程序所在目录，安装模式为 %LOCALAPPDATA%\LetRecovery
+pub fn get_app_data_root() -> PathBuf {
+    match path_mode() {
+        PathMode::Portable => get_exe_dir(),
+        PathMode::Installed => dirs::data_local_dir()
+            .map(|dir| dir.join(APP_DIR_NAME))
+            .unwrap_or_else(get_exe_dir),
+    }
+}
+
+/// 获取数据目录路径（存放 aria2 会话、下载任务记录等运行数据）
+pub fn get_data_dir() -> PathBuf {
+    get_app_data_root().join("data")
+}
+
+/// 获取日志目录路径
+pub fn get_log_dir() -> PathBuf {
+    get_app_data_root().join("log")
+}
+
+/// 获取缓存目录路径（可随时删除、删除后能重新生成的文件）
+pub fn get_cache_dir() -> PathBuf {
+    get_app_data_root().join("cache")
+}
+
+/// 默认的下载保存目录：便携模式为程序所在目录下的 downloads，安装模式为用户「下载」文件夹下的 LetRecovery
+pub fn get_downloads_dir() -> PathBuf {
+    match (path_mode(), dirs::download_dir()) {
+        (PathMode::Installed, Some(dir)) => dir.join(APP_DIR_NAME),
+        _ => get_app_data_root().join("downloads"),
+    }
 }
 
 /// 获取 PE 目录路径（统一放在 bin/pe，注意小写）
@@ -35,7 +174,1118 @@ pub fn get_uefiseven_dir() -> PathBuf {
     get_bin_dir().join("uefiseven")
 }
 
-/// 获取临时目录
+/// 在 PATH 环境变量的目录中查找可执行文件（类似 `which`）
+pub fn find_in_path(file_name: &str) -> Option<PathBuf> {
+    let paths = std::env::var_os("PATH")?;
+    std::env::split_paths(&paths)
+        .map(|dir| dir.join(file_name))
+        .find(|p| p.is_file())
+}
+
+/// 获取程序的临时目录（数据目录同级的 temp）
 pub fn get_temp_dir() -> PathBuf {
-    get_exe_dir().join("temp")
+    get_app_data_root().join("temp")
+}
+
+/// 本次运行专用的临时目录（`%TEMP%\LetRecovery\<运行 ID>`），首次调用时创建
+///
+/// diskpart 脚本、下载的校验文件等临时文件都放在这里，正常退出时由 `cleanup_session_temp` 整个删除；
+/// 崩溃留下的目录在之后启动时由 `cleanup_stale_session_temps` 清理。
+pub fn session_temp_dir() -> anyhow::Result<PathBuf> {
+    let dir = SESSION_TEMP_DIR.get_or_init(|| {
+        let run_id = format!("{}-{}", chrono::Local::now().format("%Y%m%d-%H%M%S"), std::process::id());
+        std::env::temp_dir().join(SESSION_TEMP_ROOT).join(run_id)
+    });
+    std::fs::create_dir_all(dir).map_err(|e| anyhow::anyhow!("创建临时目录 {} 失败: {}", dir.display(), e))?;
+    Ok(dir.clone())
+}
+
+/// 在本次运行的临时目录中分配一个不重复的文件路径（`<prefix>_<序号>.<ext>`），只返回路径，不创建文件
+pub fn temp_file(prefix: &str, ext: &str) -> anyhow::Result<PathBuf> {
+    let n = TEMP_FILE_COUNTER.fetch_add(1, Ordering::SeqCst);
+    Ok(session_temp_dir()?.join(format!("{}_{}.{}", prefix, n, ext)))
+}
+
+/// 删除本次运行的临时目录，程序正常退出时调用；没有用过临时目录时什么也不做
+pub fn cleanup_session_temp() {
+    if let Some(dir) = SESSION_TEMP_DIR.get() {
+        remove_dir_best_effort(dir);
+    }
+}
+
+/// 删除之前的运行留下的、修改时间早于 `max_age` 的临时目录（本次运行的目录除外），启动时调用
+///
+/// 只按时间判断，另一个正在运行的实例的目录在 `max_age` 内不会被删除。
+pub fn cleanup_stale_session_temps(max_age: Duration) {
+    let root = std::env::temp_dir().join(SESSION_TEMP_ROOT);
+    let Ok(entries) = std::fs::read_dir(&root) else {
+        return;
+    };
+    let now = SystemTime::now();
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if SESSION_TEMP_DIR.get() == Some(&path) || !path.is_dir() {
+            continue;
+        }
+        let age = entry
+            .metadata()
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|modified| now.duration_since(modified).ok());
+        if age.is_some_and(|age| age >= max_age) {
+            log::info!("清理过期的临时目录: {}", path.display());
+            remove_dir_best_effort(&path);
+        }
+    }
+}
+
+/// 尽力删除目录及其内容：删除失败的文件（如仍被占用）记录警告后跳过，不中断清理，返回跳过的数量
+fn remove_dir_best_effort(dir: &Path) -> usize {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return 0;
+    };
+    let mut skipped = 0;
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if entry.file_type().is_ok_and(|t| t.is_dir()) {
+            skipped += remove_dir_best_effort(&path);
+        } else if let Err(e) = std::fs::remove_file(&path) {
+            log::warn!("无法删除临时文件 {}: {}", path.display(), e);
+            skipped += 1;
+        }
+    }
+    // 有文件被跳过时目录非空，删除失败是预期的
+    if skipped == 0 {
+        if let Err(e) = std::fs::remove_dir(dir) {
+            log::warn!("无法删除临时目录 {}: {}", dir.display(), e);
+        }
+    }
+    skipped
+}
+
+/// Windows 传统路径长度上限（MAX_PATH，含结尾的 NUL）
+pub const MAX_PATH: usize = 260;
+
+/// 规范化为不带 `\\?\` 前缀的绝对路径：相对路径基于当前目录，`/` 统一为 `\`，
+/// 去掉 `.`、`..` 和重复的分隔符；`\\?\C:\...`、`\\?\UNC\server\share\...` 还原为普通形式
+///
+/// 结果用于交给 aria2 等外部程序和显示；本程序自己的文件操作再经 `to_extended_path` 加上前缀。
+/// 非 Windows 平台上不是 Windows 绝对路径的输入原样返回。
+pub fn normalize_path(path: &str) -> String {
+    let path = path.trim();
+    let unified = strip_prefix_str(&path.replace('/', "\\"));
+
+    let (root, rest) = if let Some(rest) = unified.strip_prefix(r"\\") {
+        let mut parts = rest.splitn(3, '\\');
+        let server = parts.next().unwrap_or_default();
+        let share = parts.next().unwrap_or_default();
+        (format!(r"\\{}\{}", server, share), parts.next().unwrap_or_default().to_string())
+    } else if unified.len() >= 2 && unified.as_bytes()[1] == b':' && unified.as_bytes()[0].is_ascii_alphabetic() {
+        (format!("{}\\", &unified[..2].to_ascii_uppercase()), unified[2..].to_string())
+    } else if cfg!(windows) {
+        let cwd = std::env::current_dir().unwrap_or_default();
+        return normalize_path(&format!(r"{}\{}", cwd.display(), unified));
+    } else {
+        return path.to_string();
+    };
+
+    let mut components: Vec<&str> = Vec::new();
+    for part in rest.split('\\') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                components.pop();
+            }
+            _ => components.push(part),
+        }
+    }
+    if components.is_empty() {
+        return root;
+    }
+    let separator = if root.ends_with('\\') { "" } else { "\\" };
+    format!("{}{}{}", root, separator, components.join("\\"))
+}
+
+/// 转为 `\\?\` 前缀的绝对路径（UNC 为 `\\?\UNC\server\share\...`），不受 MAX_PATH 限制
+///
+/// 用于本程序自己的 std::fs 操作（改名、删除、读取、计算哈希）；带前缀的路径不会再被系统规范化，
+/// 因此先经过 `normalize_path`。非 Windows 平台上原样返回。
+pub fn to_extended_path(path: &Path) -> PathBuf {
+    if !cfg!(windows) {
+        return path.to_path_buf();
+    }
+    PathBuf::from(extended_form(&normalize_path(&path.to_string_lossy())))
+}
+
+/// 去掉 `to_extended_path` 加上的 `\\?\` / `\\?\UNC\` 前缀，用于在日志和界面中显示；其它路径原样返回
+pub fn strip_extended_prefix(path: &Path) -> PathBuf {
+    match path.to_str() {
+        Some(s) => PathBuf::from(strip_prefix_str(s)),
+        None => path.to_path_buf(),
+    }
+}
+
+fn strip_prefix_str(s: &str) -> String {
+    match s.strip_prefix(r"\\?\UNC\") {
+        Some(rest) => format!(r"\\{}", rest),
+        None => s.strip_prefix(r"\\?\").unwrap_or(s).to_string(),
+    }
+}
+
+/// 给已规范化的绝对路径加上 `\\?\` 前缀
+fn extended_form(normalized: &str) -> String {
+    match normalized.strip_prefix(r"\\") {
+        Some(unc) => format!(r"\\?\UNC\{}", unc),
+        None => format!(r"\\?\{}", normalized),
+    }
+}
+
+/// 路径长度（UTF-16 码元数）是否超过 MAX_PATH，超过时不支持长路径的程序（如 aria2c）可能无法打开
+pub fn exceeds_max_path(path: &Path) -> bool {
+    path.as_os_str().to_string_lossy().encode_utf16().count() >= MAX_PATH
+}
+
+/// 写保护的磁盘（Windows 错误码 ERROR_WRITE_PROTECT）
+const ERROR_WRITE_PROTECT: i32 = 19;
+
+/// 文件名或路径过长（ERROR_FILENAME_EXCED_RANGE）
+const ERROR_FILENAME_EXCED_RANGE: i32 = 206;
+
+/// 云文件（OneDrive 等按需下载的占位文件夹）相关的错误码，ERROR_CLOUD_FILE_PROVIDER_NOT_RUNNING 起
+const CLOUD_FILE_ERRORS: std::ops::RangeInclusive<i32> = 362..=400;
+
+/// 目录无法写入的原因，由 `ensure_writable` 返回
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum DirNotWritable {
+    #[error("无法写入 {path}：拒绝访问，请换一个目录，或在 Windows 安全中心的「受控文件夹访问」中允许本程序")]
+    AccessDenied { path: String },
+
+    #[error("无法写入 {path}：磁盘是只读的或已写保护")]
+    ReadOnlyVolume { path: String },
+
+    #[error("无法写入 {path}：路径过长，请换一个较短的目录")]
+    PathTooLong { path: String },
+
+    #[error("无法写入 {path}：这是云盘（如 OneDrive）中尚未同步到本地的文件夹，请确认云盘客户端已登录并联网，或换一个本地目录")]
+    CloudUnavailable { path: String },
+
+    #[error("无法写入 {path}: {reason}")]
+    Other { path: String, reason: String },
+}
+
+impl DirNotWritable {
+    fn from_io(path: &Path, error: &std::io::Error) -> Self {
+        let path = strip_extended_prefix(path).display().to_string();
+        match error.raw_os_error() {
+            Some(ERROR_WRITE_PROTECT) if cfg!(windows) => DirNotWritable::ReadOnlyVolume { path },
+            Some(ERROR_FILENAME_EXCED_RANGE) if cfg!(windows) => DirNotWritable::PathTooLong { path },
+            Some(code) if cfg!(windows) && CLOUD_FILE_ERRORS.contains(&code) => DirNotWritable::CloudUnavailable { path },
+            _ => match error.kind() {
+                std::io::ErrorKind::PermissionDenied => DirNotWritable::AccessDenied { path },
+                std::io::ErrorKind::ReadOnlyFilesystem => DirNotWritable::ReadOnlyVolume { path },
+                std::io::ErrorKind::InvalidFilename => DirNotWritable::PathTooLong { path },
+                _ => DirNotWritable::Other {
+                    path,
+                    reason: error.to_string(),
+                },
+            },
+        }
+    }
+}
+
+/// 确认可以在 `dir` 中创建文件：目录不存在时先创建，再写入并删除一个探测文件
+///
+/// 在下载、备份等耗时操作开始前调用，让用户立即看到「无法写入 D:\...」，而不是等到 aria2 或 DISM 中途失败。
+/// 失败时返回 `DirNotWritable`，调用方可以 downcast 区分原因。
+pub fn ensure_writable(dir: &Path) -> anyhow::Result<()> {
+    let extended = to_extended_path(dir);
+    let probe = extended.join(format!(".letrecovery-write-test-{}", std::process::id()));
+    std::fs::create_dir_all(&extended)
+        .and_then(|_| std::fs::write(&probe, b"LetRecovery"))
+        .map_err(|e| DirNotWritable::from_io(dir, &e))?;
+    let _ = std::fs::remove_file(&probe);
+    Ok(())
+}
+
+/// 创建只有当前用户能访问的文件并写入 `contents`（已存在时先删除再创建）
+///
+/// Windows 上以受保护的 DACL（只允许当前用户完全控制，不继承上级目录的 ACL）创建文件，
+/// 用于保存密钥等敏感内容。先删除旧文件是因为覆盖已有文件时不会应用新的安全描述符。
+#[cfg(windows)]
+pub fn write_private_file(path: &Path, contents: &[u8]) -> anyhow::Result<()> {
+    use std::io::Write;
+    use std::os::windows::ffi::OsStrExt;
+    use std::os::windows::io::FromRawHandle;
+    use windows::core::{PCWSTR, PWSTR};
+    use windows::Win32::Foundation::{CloseHandle, LocalFree, GENERIC_WRITE, HANDLE, HLOCAL};
+    use windows::Win32::Security::Authorization::{
+        ConvertSidToStringSidW, ConvertStringSecurityDescriptorToSecurityDescriptorW, SDDL_REVISION_1,
+    };
+    use windows::Win32::Security::{GetTokenInformation, TokenUser, PSECURITY_DESCRIPTOR, SECURITY_ATTRIBUTES, TOKEN_QUERY, TOKEN_USER};
+    use windows::Win32::Storage::FileSystem::{CreateFileW, CREATE_NEW, FILE_ATTRIBUTE_NORMAL, FILE_SHARE_READ};
+    use windows::Win32::System::Threading::{GetCurrentProcess, OpenProcessToken};
+
+    // 当前用户的 SID（字符串形式，用于拼 SDDL）
+    let sid = unsafe {
+        let mut token = HANDLE::default();
+        OpenProcessToken(GetCurrentProcess(), TOKEN_QUERY, &mut token)
+            .map_err(|e| anyhow::anyhow!("读取当前用户失败: {}", e))?;
+        let mut len = 0u32;
+        let _ = GetTokenInformation(token, TokenUser, None, 0, &mut len);
+        // 按 8 字节对齐分配，TOKEN_USER 中含指针
+        let mut buf = vec![0u64; (len as usize).div_ceil(8)];
+        let result = GetTokenInformation(token, TokenUser, Some(buf.as_mut_ptr().cast()), len, &mut len);
+        let _ = CloseHandle(token);
+        result.map_err(|e| anyhow::anyhow!("读取当前用户失败: {}", e))?;
+
+        let user = &*(buf.as_ptr() as *const TOKEN_USER);
+        let mut sid = PWSTR::null();
+        ConvertSidToStringSidW(user.User.Sid, &mut sid).map_err(|e| anyhow::anyhow!("读取当前用户失败: {}", e))?;
+        let text = sid.to_string();
+        let _ = LocalFree(HLOCAL(sid.0.cast()));
+        text?
+    };
+
+    let sddl: Vec<u16> = format!("D:P(A;;FA;;;{})", sid).encode_utf16().chain(std::iter::once(0)).collect();
+    let mut descriptor = PSECURITY_DESCRIPTOR::default();
+    unsafe { ConvertStringSecurityDescriptorToSecurityDescriptorW(PCWSTR(sddl.as_ptr()), SDDL_REVISION_1, &mut descriptor, None) }
+        .map_err(|e| anyhow::anyhow!("创建安全描述符失败: {}", e))?;
+    let attributes = SECURITY_ATTRIBUTES {
+        nLength: std::mem::size_of::<SECURITY_ATTRIBUTES>() as u32,
+        lpSecurityDescriptor: descriptor.0,
+        bInheritHandle: false.into(),
+    };
+
+    let extended = to_extended_path(path);
+    match std::fs::remove_file(&extended) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+            unsafe { LocalFree(HLOCAL(descriptor.0)) };
+            anyhow::bail!("删除旧文件 {} 失败: {}", path.display(), e);
+        }
+        _ => {}
+    }
+    let wide: Vec<u16> = extended.as_os_str().encode_wide().chain(std::iter::once(0)).collect();
+    let handle = unsafe {
+        CreateFileW(
+            PCWSTR(wide.as_ptr()),
+            GENERIC_WRITE.0,
+            FILE_SHARE_READ,
+            Some(&attributes),
+            CREATE_NEW,
+            FILE_ATTRIBUTE_NORMAL,
+            HANDLE::default(),
+        )
+    };
+    unsafe { LocalFree(HLOCAL(descriptor.0)) };
+    let handle = handle.map_err(|e| anyhow::anyhow!("创建 {} 失败: {}", path.display(), e))?;
+
+    let mut file = unsafe { std::fs::File::from_raw_handle(handle.0) };
+    file.write_all(contents)
+        .map_err(|e| anyhow::anyhow!("写入 {} 失败: {}", path.display(), e))
+}
+
+#[cfg(not(windows))]
+pub fn write_private_file(path: &Path, contents: &[u8]) -> anyhow::Result<()> {
+    std::fs::write(path, contents).map_err(|e| anyhow::anyhow!("写入 {} 失败: {}", path.display(), e))
+}
+
+/// 文件名长度上限（UTF-16 码元数）：NTFS 允许 255，留出 `.part.aria2` 等临时后缀的余量
+pub const MAX_FILE_NAME_LEN: usize = 240;
+
+/// 截断过长的文件名时保留的扩展名最大长度，更长的视为文件名的一部分
+const MAX_EXTENSION_LEN: usize = 16;
+
+/// 把从 URL、Content-Disposition 或清单中得到的文件名处理为 Windows 可用的形式
+///
+/// 去掉路径部分（只保留最后一段），把 `<>:"/\|?*` 和控制字符替换为 `_`，去掉末尾的点和空格，
+/// CON、NUL、COM1 等保留名加上 `_` 前缀，超过 `MAX_FILE_NAME_LEN` 时截断主文件名并保留扩展名。
+/// 没有可用字符时返回空字符串，由调用方决定默认名。
+pub fn sanitize_filename(raw: &str) -> String {
+    let name = raw.rsplit(['/', '\\']).next().unwrap_or(raw);
+    let cleaned: String = name
+        .chars()
+        .map(|c| if c.is_control() || r#"<>:"/\|?*"#.contains(c) { '_' } else { c })
+        .collect();
+    let cleaned = cleaned.trim().trim_end_matches(['.', ' ']);
+    if cleaned.chars().all(|c| c == '.') {
+        return String::new();
+    }
+    let stem = cleaned.split('.').next().unwrap_or(cleaned).trim_end().to_ascii_uppercase();
+    let reserved = matches!(stem.as_str(), "CON" | "PRN" | "AUX" | "NUL")
+        || (stem.len() == 4
+            && (stem.starts_with("COM") || stem.starts_with("LPT"))
+            && stem.as_bytes()[3].is_ascii_digit()
+            && stem.as_bytes()[3] != b'0');
+    let name = if reserved { format!("_{}", cleaned) } else { cleaned.to_string() };
+    truncate_file_name(&name)
+}
+
+/// 截断到 `MAX_FILE_NAME_LEN` 以内，按字符截断以免拆开代理对
+fn truncate_file_name(name: &str) -> String {
+    let units = |s: &str| s.encode_utf16().count();
+    if units(name) <= MAX_FILE_NAME_LEN {
+        return name.to_string();
+    }
+    let (stem, ext) = match name.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() && units(ext) < MAX_EXTENSION_LEN => (stem, Some(ext)),
+        _ => (name, None),
+    };
+    let budget = MAX_FILE_NAME_LEN - ext.map_or(0, |ext| units(ext) + 1);
+    let mut used = 0;
+    let stem: String = stem
+        .chars()
+        .take_while(|c| {
+            used += c.len_utf16();
+            used <= budget
+        })
+        .collect();
+    let stem = stem.trim_end_matches(['.', ' ']);
+    match ext {
+        Some(ext) => format!("{}.{}", stem, ext),
+        None => stem.to_string(),
+    }
+}
+
+/// `dir` 中不与已有文件重名的路径：`filename` 不存在时原样使用，否则依次尝试 `名称 (1).扩展名`、`名称 (2).扩展名`……
+pub fn unique_path(dir: &Path, filename: &str) -> PathBuf {
+    let candidate = dir.join(filename);
+    if !to_extended_path(&candidate).exists() {
+        return candidate;
+    }
+    let (stem, ext) = match filename.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() => (stem, format!(".{}", ext)),
+        _ => (filename, String::new()),
+    };
+    (1u32..)
+        .map(|n| dir.join(format!("{} ({}){}", stem, n, ext)))
+        .find(|path| !to_extended_path(path).exists())
+        .unwrap_or(candidate)
+}
+
+/// `move_file` 跨卷复制时每次读写的块大小
+const MOVE_CHUNK_SIZE: usize = 4 << 20;
+
+/// `move_file` 跨卷复制时临时文件名的后缀，复制完成并校验后才改为目标文件名
+const MOVE_TEMP_SUFFIX: &str = ".moving";
+
+/// `move_file` 的选项
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MoveOptions {
+    /// 目标文件已存在时覆盖；为 false 时返回 `MoveError::DestinationExists`
+    pub overwrite: bool,
+    /// 跨卷复制后除了核对大小，再用此算法比对源文件和目标文件的哈希值
+    pub verify: Option<crate::utils::hash::HashType>,
+}
+
+/// 移动文件失败的原因（读写错误以普通的 anyhow 错误返回）
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum MoveError {
+    #[error("目标文件已存在: {path}")]
+    DestinationExists { path: String },
+
+    #[error("移动文件已取消")]
+    Cancelled,
+
+    #[error("复制到 {path} 后校验失败：{reason}")]
+    VerifyFailed { path: String, reason: String },
+}
+
+/// 移动文件，源和目标不在同一卷时改为分块复制再删除源文件
+///
+/// 同一卷内直接改名。跨卷时先复制到目标目录下的 `<文件名>.moving`，每复制一块回调一次
+/// `progress_cb(已复制, 总字节)`，回调返回 false 即取消；复制完成后写回磁盘、核对大小（和哈希值），
+/// 再改为目标文件名并删除源文件。失败或取消时删除复制了一半的临时文件，源文件和已有的目标文件保持不变。
+/// 不覆盖时改名本身也不替换目标，检查之后才出现的同名文件同样返回 `MoveError::DestinationExists`。
+pub fn move_file(
+    src: &Path,
+    dst: &Path,
+    options: &MoveOptions,
+    mut progress_cb: impl FnMut(u64, u64) -> bool,
+) -> anyhow::Result<()> {
+    let (src_ext, dst_ext) = (to_extended_path(src), to_extended_path(dst));
+    if !options.overwrite && dst_ext.exists() {
+        return Err(MoveError::DestinationExists {
+            path: strip_extended_prefix(dst).display().to_string(),
+        }
+        .into());
+    }
+    let total = std::fs::metadata(&src_ext)
+        .map_err(|e| anyhow::anyhow!("读取 {} 失败: {}", strip_extended_prefix(src).display(), e))?
+        .len();
+    match rename_file(&src_ext, &dst_ext, options.overwrite) {
+        Ok(()) => {
+            progress_cb(total, total);
+            return Ok(());
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::CrossesDevices => {}
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+            return Err(MoveError::DestinationExists {
+                path: strip_extended_prefix(dst).display().to_string(),
+            }
+            .into());
+        }
+        Err(e) => anyhow::bail!(
+            "移动 {} 到 {} 失败: {}",
+            strip_extended_prefix(src).display(),
+            strip_extended_prefix(dst).display(),
+            e
+        ),
+    }
+
+    log::info!(
+        "[移动文件] {} 与 {} 不在同一卷，复制后删除源文件（{} MB）",
+        strip_extended_prefix(src).display(),
+        strip_extended_prefix(dst).display(),
+        total / (1024 * 1024)
+    );
+    copy_then_remove(&src_ext, &dst_ext, total, options.overwrite, options.verify, &mut progress_cb)
+}
+
+/// 改名；`replace` 为 false 时目标已存在即失败（`ErrorKind::AlreadyExists`），不像 `std::fs::rename` 那样替换目标
+#[cfg(windows)]
+fn rename_file(src: &Path, dst: &Path, replace: bool) -> std::io::Result<()> {
+    use std::os::windows::ffi::OsStrExt;
+    use windows::core::PCWSTR;
+    use windows::Win32::Storage::FileSystem::{MoveFileExW, MOVE_FILE_FLAGS};
+
+    if replace {
+        return std::fs::rename(src, dst);
+    }
+    let wide = |path: &Path| -> Vec<u16> { path.as_os_str().encode_wide().chain(std::iter::once(0)).collect() };
+    let (src, dst) = (wide(src), wide(dst));
+    // 不带 MOVEFILE_COPY_ALLOWED，跨卷时返回 ERROR_NOT_SAME_DEVICE（ErrorKind::CrossesDevices）
+    unsafe { MoveFileExW(PCWSTR(src.as_ptr()), PCWSTR(dst.as_ptr()), MOVE_FILE_FLAGS(0)) }
+        // 错误来自 GetLastError，HRESULT 的低 16 位即 Win32 错误码（ERROR_ALREADY_EXISTS、ERROR_FILE_EXISTS 等）
+        .map_err(|e| std::io::Error::from_raw_os_error(e.code().0 & 0xFFFF))
+}
+
+#[cfg(not(windows))]
+fn rename_file(src: &Path, dst: &Path, replace: bool) -> std::io::Result<()> {
+    if replace {
+        return std::fs::rename(src, dst);
+    }
+    // 目标已存在时创建硬链接失败，不会替换
+    std::fs::hard_link(src, dst)?;
+    std::fs::remove_file(src)
+}
+
+fn copy_then_remove(
+    src: &Path,
+    dst: &Path,
+    total: u64,
+    overwrite: bool,
+    verify: Option<crate::utils::hash::HashType>,
+    progress_cb: &mut impl FnMut(u64, u64) -> bool,
+) -> anyhow::Result<()> {
+    use std::io::{Read, Write};
+
+    let mut temp = dst.as_os_str().to_owned();
+    temp.push(MOVE_TEMP_SUFFIX);
+    let temp = PathBuf::from(temp);
+    let display = strip_extended_prefix(dst).display().to_string();
+
+    let mut copy = || -> anyhow::Result<()> {
+        let mut reader = std::fs::File::open(src)?;
+        let mut writer = std::fs::File::create(&temp)?;
+        let mut buffer = vec![0u8; MOVE_CHUNK_SIZE];
+        let mut copied = 0u64;
+        loop {
+            let n = reader.read(&mut buffer)?;
+            if n == 0 {
+                break;
+            }
+            writer.write_all(&buffer[..n])?;
+            copied += n as u64;
+            if !progress_cb(copied, total) {
+                return Err(MoveError::Cancelled.into());
+            }
+        }
+        writer.sync_all()?;
+
+        let written = writer.metadata()?.len();
+        if written != total {
+            return Err(MoveError::VerifyFailed {
+                path: display.clone(),
+                reason: format!("大小不符，源文件 {} 字节，复制后 {} 字节", total, written),
+            }
+            .into());
+        }
+        if let Some(algo) = verify {
+            let expected = crate::utils::hash::hash_file_blocking(src, algo, |_, _| true)?;
+            let actual = crate::utils::hash::hash_file_blocking(&temp, algo, |_, _| true)?;
+            if expected != actual {
+                return Err(MoveError::VerifyFailed {
+                    path: display.clone(),
+                    reason: "哈希值与源文件不一致".to_string(),
+                }
+                .into());
+            }
+        }
+        rename_file(&temp, dst, overwrite).map_err(|e| -> anyhow::Error {
+            if e.kind() == std::io::ErrorKind::AlreadyExists {
+                MoveError::DestinationExists { path: display.clone() }.into()
+            } else {
+                e.into()
+            }
+        })
+    };
+    if let Err(e) = copy() {
+        let _ = std::fs::remove_file(&temp);
+        return Err(e);
+    }
+
+    // 目标文件已经完整，删除源文件失败时保留两份并报告
+    std::fs::remove_file(src).map_err(|e| {
+        anyhow::anyhow!("已复制到 {}，但删除源文件 {} 失败: {}", display, strip_extended_prefix(src).display(), e)
+    })
+}
+
+/// `dir_size` 每统计多少个文件回调一次进度
+const DIR_SIZE_PROGRESS_EVERY: u64 = 1000;
+
+/// 重解析点（符号链接、目录联接、挂载点）的文件属性
+const FILE_ATTRIBUTE_REPARSE_POINT: u32 = 0x400;
+
+/// 目录大小的统计结果
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DirStats {
+    /// 文件总大小（字节）
+    pub total_bytes: u64,
+    pub file_count: u64,
+    pub dir_count: u64,
+    /// 最大的文件（最多为调用时指定的 `largest` 个），从大到小
+    pub largest: Vec<(PathBuf, u64)>,
+    /// 无法读取（通常是拒绝访问）而跳过的目录
+    pub inaccessible: Vec<PathBuf>,
+    /// 跳过的符号链接和目录联接数，它们指向的内容在别处已经统计或不属于该目录
+    pub skipped_links: u64,
+}
+
+/// 目录大小统计被取消
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[error("目录大小统计已取消")]
+pub struct DirSizeCancelled;
+
+/// 在阻塞线程池中统计目录大小，不卡住界面
+///
+/// `cancel` 置为 true 后尽快返回 `DirSizeCancelled`；`progress_cb(已统计文件数, 已统计字节)` 每统计一批文件回调一次。
+/// 详见 `dir_size_blocking`。
+pub async fn dir_size<F>(
+    path: impl Into<PathBuf>,
+    largest: usize,
+    cancel: Arc<AtomicBool>,
+    progress_cb: F,
+) -> anyhow::Result<DirStats>
+where
+    F: FnMut(u64, u64) + Send + 'static,
+{
+    let path = path.into();
+    tokio::task::spawn_blocking(move || dir_size_blocking(&path, largest, &cancel, progress_cb)).await?
+}
+
+/// 统计目录中所有文件的总大小、数量和最大的文件（会阻塞当前线程）
+///
+/// 不进入符号链接和目录联接（如用户目录下的「Application Data」），避免重复统计或循环；
+/// 无法读取的子目录记录到 `inaccessible` 后跳过，不会让整个统计失败。超过 MAX_PATH 的深层目录也能读取。
+/// `largest` 为 `DirStats::largest` 中保留的最大文件数，只要总大小时传 0。
+pub fn dir_size_blocking(
+    path: &Path,
+    largest: usize,
+    cancel: &AtomicBool,
+    mut progress_cb: impl FnMut(u64, u64),
+) -> anyhow::Result<DirStats> {
+    let root = std::fs::symlink_metadata(to_extended_path(path))
+        .map_err(|e| anyhow::anyhow!("无法读取 {}: {}", strip_extended_prefix(path).display(), e))?;
+    let mut stats = DirStats::default();
+    let mut heap = std::collections::BinaryHeap::new();
+    let mut count_file = |stats: &mut DirStats, path: PathBuf, size: u64| {
+        stats.file_count += 1;
+        stats.total_bytes += size;
+        if largest > 0 {
+            heap.push(std::cmp::Reverse((size, path)));
+            if heap.len() > largest {
+                heap.pop();
+            }
+        }
+    };
+    if !root.is_dir() {
+        count_file(&mut stats, path.to_path_buf(), root.len());
+    }
+
+    let mut pending = if root.is_dir() { vec![path.to_path_buf()] } else { Vec::new() };
+    while let Some(dir) = pending.pop() {
+        if cancel.load(Ordering::Relaxed) {
+            return Err(DirSizeCancelled.into());
+        }
+        let entries = match std::fs::read_dir(to_extended_path(&dir)) {
+            Ok(entries) => entries,
+            Err(e) => {
+                log::debug!("[目录大小] 跳过无法读取的目录 {}: {}", dir.display(), e);
+                stats.inaccessible.push(dir);
+                continue;
+            }
+        };
+        stats.dir_count += 1;
+        for entry in entries.flatten() {
+            // Windows 上 DirEntry::metadata 直接取自目录列表，不跟随链接，也不用再打开文件
+            let Ok(meta) = entry.metadata() else { continue };
+            let child = dir.join(entry.file_name());
+            if is_link(&meta) {
+                stats.skipped_links += 1;
+            } else if meta.is_dir() {
+                pending.push(child);
+            } else {
+                count_file(&mut stats, child, meta.len());
+                if stats.file_count % DIR_SIZE_PROGRESS_EVERY == 0 {
+                    progress_cb(stats.file_count, stats.total_bytes);
+                }
+            }
+        }
+    }
+    stats.dir_count = stats.dir_count.saturating_sub(1);
+    stats.largest = heap.into_sorted_vec().into_iter().map(|std::cmp::Reverse((size, path))| (path, size)).collect();
+    progress_cb(stats.file_count, stats.total_bytes);
+    Ok(stats)
+}
+
+/// 符号链接，或带重解析点属性的目录（目录联接、挂载点）
+fn is_link(meta: &std::fs::Metadata) -> bool {
+    #[cfg(windows)]
+    let reparse_dir = {
+        use std::os::windows::fs::MetadataExt;
+        meta.is_dir() && meta.file_attributes() & FILE_ATTRIBUTE_REPARSE_POINT != 0
+    };
+    #[cfg(not(windows))]
+    let reparse_dir = false;
+    meta.file_type().is_symlink() || reparse_dir
+}
+
+/// 卷的空间信息
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VolumeSpace {
+    /// 卷的根目录：`C:\`、挂载到文件夹的卷为该文件夹，网络共享为 `\\server\share`
+    pub volume_root: PathBuf,
+    /// 当前用户可用的剩余空间（字节，已扣除磁盘配额）
+    pub free: u64,
+    /// 卷的总大小（字节）
+    pub total: u64,
+}
+
+/// 本机固定磁盘上带盘符的卷
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FixedVolume {
+    /// 盘符（大写）
+    pub letter: char,
+    pub label: String,
+    pub space: VolumeSpace,
+}
+
+/// 路径所在卷的剩余空间和总大小
+///
+/// 盘符路径、任意深度的子目录和 UNC 路径都可以；路径不存在时按最近的已存在的上级目录所在的卷计算
+/// （保存目录通常还没有创建）。
+#[cfg(windows)]
+pub fn volume_space(path: &Path) -> anyhow::Result<VolumeSpace> {
+    use std::os::windows::ffi::OsStrExt;
+    use windows::core::PCWSTR;
+    use windows::Win32::Storage::FileSystem::{GetDiskFreeSpaceExW, GetVolumePathNameW};
+
+    let absolute = PathBuf::from(normalize_path(&path.to_string_lossy()));
+    let existing = nearest_existing_ancestor(&absolute)
+        .ok_or_else(|| anyhow::anyhow!("无法确定 {} 所在的卷", path.display()))?;
+    let wide: Vec<u16> = to_extended_path(existing)
+        .as_os_str()
+        .encode_wide()
+        .chain(std::iter::once(0))
+        .collect();
+
+    let mut root = vec![0u16; 32768];
+    unsafe { GetVolumePathNameW(PCWSTR(wide.as_ptr()), &mut root) }
+        .map_err(|e| anyhow::anyhow!("无法确定 {} 所在的卷: {}", path.display(), e))?;
+    let (mut free, mut total) = (0u64, 0u64);
+    unsafe { GetDiskFreeSpaceExW(PCWSTR(root.as_ptr()), Some(&mut free), Some(&mut total), None) }
+        .map_err(|e| anyhow::anyhow!("读取 {} 所在卷的剩余空间失败: {}", path.display(), e))?;
+
+    let len = root.iter().position(|&c| c == 0).unwrap_or(root.len());
+    Ok(VolumeSpace {
+        volume_root: PathBuf::from(normalize_path(&String::from_utf16_lossy(&root[..len]))),
+        free,
+        total,
+    })
+}
+
+#[cfg(not(windows))]
+pub fn volume_space(_path: &Path) -> anyhow::Result<VolumeSpace> {
+    anyhow::bail!("仅支持 Windows 平台")
+}
+
+/// 列出本机所有固定磁盘上带盘符的卷（不含可移动磁盘、网络驱动器和光驱），按盘符排序
+///
+/// 读取不到空间的卷（如未格式化、BitLocker 已锁定）跳过。
+#[cfg(windows)]
+pub fn list_fixed_volumes() -> Vec<FixedVolume> {
+    use windows::core::PCWSTR;
+    use windows::Win32::Storage::FileSystem::{GetDriveTypeW, GetVolumeInformationW};
+
+    const DRIVE_FIXED: u32 = 3;
+
+    (b'A'..=b'Z')
+        .map(char::from)
+        .filter_map(|letter| {
+            let root = format!("{}:\\", letter);
+            let wide: Vec<u16> = root.encode_utf16().chain(std::iter::once(0)).collect();
+            if unsafe { GetDriveTypeW(PCWSTR(wide.as_ptr())) } != DRIVE_FIXED {
+                return None;
+            }
+            let space = volume_space(Path::new(&root)).ok()?;
+            let mut label = [0u16; 261];
+            let _ = unsafe { GetVolumeInformationW(PCWSTR(wide.as_ptr()), Some(&mut label), None, None, None, None) };
+            Some(FixedVolume {
+                letter,
+                label: String::from_utf16_lossy(&label).trim_end_matches('\0').to_string(),
+                space,
+            })
+        })
+        .collect()
+}
+
+#[cfg(not(windows))]
+pub fn list_fixed_volumes() -> Vec<FixedVolume> {
+    Vec::new()
+}
+
+/// 路径本身或最近的已存在的上级目录
+#[cfg_attr(not(windows), allow(dead_code))]
+fn nearest_existing_ancestor(path: &Path) -> Option<&Path> {
+    path.ancestors().find(|p| !p.as_os_str().is_empty() && to_extended_path(p).exists())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_session_temp_dir() {
+        let first = temp_file("dp_script", "txt").unwrap();
+        let second = temp_file("dp_script", "txt").unwrap();
+        assert_ne!(first, second);
+        assert_eq!(first.parent(), Some(session_temp_dir().unwrap().as_path()));
+        assert!(first.file_name().unwrap().to_string_lossy().starts_with("dp_script_"));
+        cleanup_session_temp();
+    }
+
+    #[test]
+    fn test_remove_dir_best_effort() {
+        let dir = std::env::temp_dir().join(format!("lr_remove_tree_{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("a").join("b")).unwrap();
+        std::fs::write(dir.join("a").join("b").join("x.txt"), b"x").unwrap();
+        std::fs::write(dir.join("y.txt"), b"y").unwrap();
+        assert_eq!(remove_dir_best_effort(&dir), 0);
+        assert!(!dir.exists());
+    }
+
+    #[test]
+    fn test_nearest_existing_ancestor() {
+        let dir = std::env::temp_dir();
+        let missing = dir.join("lr_missing_dir").join("a").join("b");
+        assert_eq!(nearest_existing_ancestor(&missing), Some(dir.as_path()));
+        assert_eq!(nearest_existing_ancestor(&dir), Some(dir.as_path()));
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_volume_space() {
+        let dir = std::env::temp_dir();
+        let space = volume_space(&dir.join("lr_missing_dir").join("a")).unwrap();
+        assert!(space.total > 0 && space.free <= space.total);
+        assert!(dir.starts_with(&space.volume_root));
+        assert!(list_fixed_volumes().iter().any(|v| v.space.total > 0));
+    }
+
+    #[test]
+    fn test_pick_bin_dir() {
+        let existing = std::env::temp_dir().join(format!("lr_bin_dir_{}", std::process::id()));
+        std::fs::create_dir_all(&existing).unwrap();
+        let missing = existing.join("missing");
+        let default = PathBuf::from("default");
+
+        let picked = pick_bin_dir([(Some(missing.clone()), "配置文件"), (Some(existing.clone()), "环境变量")], default.clone());
+        assert_eq!(picked, (existing.clone(), "环境变量"));
+        let picked = pick_bin_dir([(Some(existing.clone()), "配置文件"), (None, "环境变量")], default.clone());
+        assert_eq!(picked, (existing.clone(), "配置文件"));
+        let picked = pick_bin_dir([(None, "配置文件"), (Some(missing), "环境变量")], default.clone());
+        assert_eq!(picked, (default, "程序所在目录"));
+        let _ = std::fs::remove_dir_all(&existing);
+    }
+
+    /// 超过 260 个字符、包含中文和 emoji 的目录
+    fn long_cjk_dir() -> String {
+        let segment = "系统恢复备份📦镜像文件夹";
+        let mut dir = r"C:\Users\张伟\桌面".to_string();
+        while dir.encode_utf16().count() < 300 {
+            dir.push('\\');
+            dir.push_str(segment);
+        }
+        dir
+    }
+
+    #[test]
+    fn test_normalize_path() {
+        assert_eq!(normalize_path(r"c:/Users/张伟/./桌面//系统恢复/"), r"C:\Users\张伟\桌面\系统恢复");
+        assert_eq!(normalize_path(r"D:\a\b\..\c"), r"D:\a\c");
+        assert_eq!(normalize_path(r"D:\.."), r"D:\");
+        assert_eq!(normalize_path(r"\\?\D:\Images\win11"), r"D:\Images\win11");
+        assert_eq!(normalize_path(r"\\?\UNC\fileserver\images\pe\"), r"\\fileserver\images\pe");
+        assert_eq!(normalize_path("//fileserver/images/"), r"\\fileserver\images");
+        assert_eq!(normalize_path(r"\\fileserver\images\\pe"), r"\\fileserver\images\pe");
+    }
+
+    #[test]
+    fn test_extended_form_of_long_cjk_path() {
+        let dir = long_cjk_dir();
+        let file = format!(r"{}\install🚀.esd", dir);
+        assert!(exceeds_max_path(Path::new(&file)));
+
+        let normalized = normalize_path(&file);
+        assert_eq!(normalized, file);
+        let extended = extended_form(&normalized);
+        assert_eq!(extended, format!(r"\\?\{}", file));
+        // 已带前缀的路径再次规范化得到相同结果
+        assert_eq!(extended_form(&normalize_path(&extended)), extended);
+
+        assert_eq!(extended_form(r"\\fileserver\images\a.wim"), r"\\?\UNC\fileserver\images\a.wim");
+        assert!(!exceeds_max_path(Path::new(r"C:\Users\张伟\桌面")));
+    }
+
+    #[test]
+    fn test_detect_path_mode() {
+        let dir = std::env::temp_dir().join(format!("lr_path_mode_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        assert_eq!(detect_path_mode(&dir, true), PathMode::Installed);
+        // 没有 %LOCALAPPDATA% 时只能放在程序所在目录
+        assert_eq!(detect_path_mode(&dir, false), PathMode::Portable);
+
+        std::fs::write(dir.join(PORTABLE_FLAG), b"").unwrap();
+        assert_eq!(detect_path_mode(&dir, true), PathMode::Portable);
+        assert!(dir.read_dir().unwrap().all(|e| !e.unwrap().file_name().to_string_lossy().starts_with(".letrecovery")));
+
+        std::fs::remove_file(dir.join(PORTABLE_FLAG)).unwrap();
+        std::fs::create_dir(dir.join("data")).unwrap();
+        assert_eq!(detect_path_mode(&dir, true), PathMode::Portable);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_ensure_writable() {
+        let root = std::env::temp_dir().join(format!("lr_writable_{}", std::process::id()));
+        let dir = root.join("镜像").join("下载");
+        ensure_writable(&dir).unwrap();
+        assert!(dir.is_dir());
+        assert_eq!(dir.read_dir().unwrap().count(), 0);
+
+        // 上级是文件，无法创建目录
+        std::fs::write(root.join("file"), b"").unwrap();
+        let error = ensure_writable(&root.join("file").join("sub")).unwrap_err();
+        assert!(matches!(error.downcast_ref::<DirNotWritable>(), Some(DirNotWritable::Other { .. })));
+        std::fs::remove_dir_all(&root).unwrap();
+
+        let denied = DirNotWritable::from_io(Path::new(r"\\?\D:\Backup"), &std::io::ErrorKind::PermissionDenied.into());
+        assert_eq!(denied, DirNotWritable::AccessDenied { path: r"D:\Backup".to_string() });
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_not_writable_windows_codes() {
+        let classify = |code| DirNotWritable::from_io(Path::new(r"E:\"), &std::io::Error::from_raw_os_error(code));
+        assert!(matches!(classify(5), DirNotWritable::AccessDenied { .. }));
+        assert!(matches!(classify(19), DirNotWritable::ReadOnlyVolume { .. }));
+        assert!(matches!(classify(206), DirNotWritable::PathTooLong { .. }));
+        assert!(matches!(classify(362), DirNotWritable::CloudUnavailable { .. }));
+    }
+
+    #[test]
+    fn test_write_private_file() {
+        let path = std::env::temp_dir().join(format!("lr_private_{}.rpc", std::process::id()));
+        write_private_file(&path, b"first").unwrap();
+        // 已存在时替换
+        write_private_file(&path, b"second").unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"second");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_unique_path() {
+        let dir = std::env::temp_dir().join(format!("lr_unique_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        assert_eq!(unique_path(&dir, "win11.iso"), dir.join("win11.iso"));
+
+        std::fs::write(dir.join("win11.iso"), b"").unwrap();
+        assert_eq!(unique_path(&dir, "win11.iso"), dir.join("win11 (1).iso"));
+        std::fs::write(dir.join("win11 (1).iso"), b"").unwrap();
+        assert_eq!(unique_path(&dir, "win11.iso"), dir.join("win11 (2).iso"));
+
+        std::fs::write(dir.join("README"), b"").unwrap();
+        assert_eq!(unique_path(&dir, "README"), dir.join("README (1)"));
+        std::fs::write(dir.join(".hidden"), b"").unwrap();
+        assert_eq!(unique_path(&dir, ".hidden"), dir.join(".hidden (1)"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_move_file() {
+        let dir = std::env::temp_dir().join(format!("lr_move_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (src, dst) = (dir.join("install.esd"), dir.join("目标.esd"));
+        std::fs::write(&src, b"image").unwrap();
+        std::fs::write(&dst, b"old").unwrap();
+
+        let error = move_file(&src, &dst, &MoveOptions::default(), |_, _| true).unwrap_err();
+        assert!(matches!(error.downcast_ref::<MoveError>(), Some(MoveError::DestinationExists { .. })));
+        // 改名本身也不替换已有的文件
+        assert_eq!(rename_file(&src, &dst, false).unwrap_err().kind(), std::io::ErrorKind::AlreadyExists);
+        assert_eq!(std::fs::read(&dst).unwrap(), b"old");
+        assert!(src.exists());
+        let overwrite = MoveOptions { overwrite: true, ..Default::default() };
+        let mut reported = None;
+        move_file(&src, &dst, &overwrite, |done, total| {
+            reported = Some((done, total));
+            true
+        })
+        .unwrap();
+        assert_eq!(reported, Some((5, 5)));
+        assert_eq!(std::fs::read(&dst).unwrap(), b"image");
+        assert!(!src.exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_copy_then_remove() {
+        let dir = std::env::temp_dir().join(format!("lr_copy_move_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (src, dst) = (dir.join("boot.wim"), dir.join("copy.wim"));
+        let data = vec![7u8; MOVE_CHUNK_SIZE + 100];
+        std::fs::write(&src, &data).unwrap();
+        let total = data.len() as u64;
+
+        // 取消时删除临时文件，源文件保留
+        let error = copy_then_remove(&src, &dst, total, false, None, &mut |done, _| done < MOVE_CHUNK_SIZE as u64).unwrap_err();
+        assert_eq!(error.downcast_ref::<MoveError>(), Some(&MoveError::Cancelled));
+        assert!(src.exists() && !dst.exists());
+        assert_eq!(dir.read_dir().unwrap().count(), 1);
+
+        // 大小与预期不符
+        let error = copy_then_remove(&src, &dst, total + 1, false, None, &mut |_, _| true).unwrap_err();
+        assert!(matches!(error.downcast_ref::<MoveError>(), Some(MoveError::VerifyFailed { .. })));
+        assert_eq!(dir.read_dir().unwrap().count(), 1);
+
+        // 复制期间出现的同名文件不会被替换
+        std::fs::write(&dst, b"new").unwrap();
+        let error = copy_then_remove(&src, &dst, total, false, None, &mut |_, _| true).unwrap_err();
+        assert!(matches!(error.downcast_ref::<MoveError>(), Some(MoveError::DestinationExists { .. })));
+        assert_eq!(std::fs::read(&dst).unwrap(), b"new");
+        assert_eq!(dir.read_dir().unwrap().count(), 2);
+        std::fs::remove_file(&dst).unwrap();
+
+        let mut calls = 0;
+        let verify = Some(crate::utils::hash::HashType::Sha256);
+        copy_then_remove(&src, &dst, total, false, verify, &mut |_, _| {
+            calls += 1;
+            true
+        })
+        .unwrap();
+        assert_eq!(calls, 2);
+        assert_eq!(std::fs::read(&dst).unwrap(), data);
+        assert!(!src.exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_sanitize_filename() {
+        assert_eq!(sanitize_filename("download?id=123"), "download_id=123");
+        assert_eq!(sanitize_filename("win11:x64*.iso"), "win11_x64_.iso");
+        assert_eq!(sanitize_filename("con.txt"), "_con.txt");
+        assert_eq!(sanitize_filename("NUL"), "_NUL");
+        assert_eq!(sanitize_filename("COM1"), "_COM1");
+        assert_eq!(sanitize_filename("COM0.iso"), "COM0.iso");
+        assert_eq!(sanitize_filename("console.log"), "console.log");
+        assert_eq!(sanitize_filename("image.iso. . "), "image.iso");
+        assert_eq!(sanitize_filename("../../evil.exe"), "evil.exe");
+        assert_eq!(sanitize_filename("驱动\u{7}包.zip"), "驱动_包.zip");
+        assert_eq!(sanitize_filename(".."), "");
+        assert_eq!(sanitize_filename("  "), "");
+
+        // 过长时截断主文件名，保留扩展名；不拆开表情符号的代理对
+        let long = format!("{}.iso", "镜像📦".repeat(100));
+        assert_eq!(sanitize_filename(&long), format!("{}.iso", "镜像📦".repeat(59)));
+        let odd = format!("{}📦.iso", "a".repeat(235));
+        assert_eq!(sanitize_filename(&odd), format!("{}.iso", "a".repeat(235)));
+        let no_ext = "a".repeat(300);
+        assert_eq!(sanitize_filename(&no_ext).len(), MAX_FILE_NAME_LEN);
+    }
+
+    #[test]
+    fn test_strip_extended_prefix() {
+        let strip = |s: &str| strip_extended_prefix(Path::new(s));
+        assert_eq!(strip(r"\\?\D:\Images\win11.iso"), PathBuf::from(r"D:\Images\win11.iso"));
+        assert_eq!(strip(r"\\?\UNC\fileserver\images\a.wim"), PathBuf::from(r"\\fileserver\images\a.wim"));
+        assert_eq!(strip(r"\\fileserver\images"), PathBuf::from(r"\\fileserver\images"));
+        assert_eq!(strip("relative/a.txt"), PathBuf::from("relative/a.txt"));
+    }
+
+    #[test]
+    fn test_dir_size() {
+        let root = std::env::temp_dir().join(format!("lr_dir_size_{}", std::process::id()));
+        let deep = (0..6).fold(root.join("用户"), |dir, i| dir.join(format!("{}{}", "文档", "d".repeat(50 + i))));
+        std::fs::create_dir_all(to_extended_path(&deep)).unwrap();
+        std::fs::write(to_extended_path(&deep.join("deep.bin")), vec![0u8; 300]).unwrap();
+        std::fs::write(root.join("a.bin"), vec![0u8; 100]).unwrap();
+        std::fs::write(root.join("用户").join("b.bin"), vec![0u8; 200]).unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(root.join("用户"), root.join("link")).unwrap();
+
+        let cancel = AtomicBool::new(false);
+        let mut last = (0, 0);
+        let stats = dir_size_blocking(&root, 20, &cancel, |files, bytes| last = (files, bytes)).unwrap();
+        assert_eq!((stats.file_count, stats.total_bytes), (3, 600));
+        assert_eq!(last, (3, 600));
+        assert_eq!(stats.dir_count, 7);
+        assert_eq!(stats.largest[0], (deep.join("deep.bin"), 300));
+        assert_eq!(stats.largest.last().unwrap().1, 100);
+        let top = dir_size_blocking(&root, 2, &cancel, |_, _| {}).unwrap();
+        assert_eq!(top.largest.iter().map(|(_, size)| *size).collect::<Vec<_>>(), [300, 200]);
+        assert_eq!(top.total_bytes, 600);
+        assert!(dir_size_blocking(&root, 0, &cancel, |_, _| {}).unwrap().largest.is_empty());
+        assert!(stats.inaccessible.is_empty());
+        #[cfg(unix)]
+        assert_eq!(stats.skipped_links, 1);
+
+        let file = dir_size_blocking(&root.join("a.bin"), 20, &cancel, |_, _| {}).unwrap();
+        assert_eq!((file.file_count, file.total_bytes), (1, 100));
+        cancel.store(true, Ordering::Relaxed);
+        let error = dir_size_blocking(&root, 20, &cancel, |_, _| {}).unwrap_err();
+        assert!(error.is::<DirSizeCancelled>());
+        assert!(dir_size_blocking(&root.join("missing"), 20, &cancel, |_, _| {}).is_err());
+        std::fs::remove_dir_all(to_extended_path(&root)).unwrap();
+    }
+
+    #[test]
+    fn test_long_path_round_trip() {
+        // 300 多个字符的多层目录，每层都在单个文件名的长度限制以内
+        let root = std::env::temp_dir().join(format!("lr_long_path_{}", std::process::id()));
+        let deep = (0..6).fold(root.clone(), |dir, i| dir.join(format!("{}{}", "深层目录", "d".repeat(50 + i))));
+        assert!(exceeds_max_path(&deep));
+        let file = deep.join("install.esd.part");
+        let renamed = deep.join("install.esd");
+
+        std::fs::create_dir_all(to_extended_path(&deep)).unwrap();
+        std::fs::write(to_extended_path(&file), b"LetRecovery").unwrap();
+        std::fs::rename(to_extended_path(&file), to_extended_path(&renamed)).unwrap();
+        assert_eq!(std::fs::metadata(to_extended_path(&renamed)).unwrap().len(), 11);
+        let md5 = crate::utils::hash::hash_file_blocking(&renamed, crate::utils::hash::HashType::Md5, |_, _| true).unwrap();
+        assert_eq!(md5.len(), 32);
+        assert_eq!(strip_extended_prefix(&to_extended_path(&renamed)), PathBuf::from(normalize_path(&renamed.to_string_lossy())));
+
+        std::fs::remove_dir_all(to_extended_path(&root)).unwrap();
+        assert!(!to_extended_path(&root).exists());
+    }
 }
@@ -0,0 +1,275 @@
+//! 外部工具退出码的解释
+//!
+//! DISM、bcdboot、diskpart、aria2c、7-Zip、wimlib 失败时只给出一个数字（DISM 常把 HRESULT 当作退出码，日志里是 -2147024891
+//! 这样的十进制负数），用户拿到也不知道该怎么办。`ToolError` 把已知的退出码换成原因说明和处理建议，
+//! 未知的退出码保留原始数值和错误输出。函数仍返回 `anyhow::Result`，需要区分的调用方用
+//! `downcast_ref::<ToolError>()` 判断。
+
+use std::fmt;
+
+use crate::utils::cmd::CmdOutput;
+
+/// 有退出码说明的外部工具
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tool {
+    Dism,
+    Bcdboot,
+    Diskpart,
+    Aria2c,
+    SevenZip,
+    Wimlib,
+}
+
+impl fmt::Display for Tool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Tool::Dism => "DISM",
+            Tool::Bcdboot => "bcdboot",
+            Tool::Diskpart => "diskpart",
+            Tool::Aria2c => "aria2c",
+            Tool::SevenZip => "7-Zip",
+            Tool::Wimlib => "wimlib-imagex",
+        })
+    }
+}
+
+/// 外部工具执行失败
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ToolError {
+    /// 已知的退出码：`explanation` 说明原因，`suggestion` 给出处理建议
+    #[error("{tool} 失败（错误码 {}）：{explanation}。{suggestion}", format_code(*code))]
+    Known {
+        tool: Tool,
+        code: i32,
+        explanation: &'static str,
+        suggestion: &'static str,
+    },
+
+    /// 没有说明的退出码（None 表示超时被结束或被信号终止），附带工具的错误输出
+    #[error("{tool} 失败（错误码 {}）: {}", code.map_or("无".to_string(), format_code), detail_or_default(stderr))]
+    Unknown { tool: Tool, code: Option<i32>, stderr: String },
+
+    /// 无法启动工具（文件缺失、被杀毒软件拦截等），`code` 为系统错误码
+    #[error("无法启动 {tool}（错误码 {code}）：{explanation}。{suggestion}")]
+    LaunchFailed {
+        tool: Tool,
+        code: i32,
+        explanation: &'static str,
+        suggestion: &'static str,
+    },
+}
+
+impl ToolError {
+    /// 由退出码和错误输出构造；`stderr` 只在退出码没有说明时使用
+    pub fn from_exit(tool: Tool, code: Option<i32>, stderr: &str) -> Self {
+        match code.and_then(|code| explain_exit(tool, code).map(|known| (code, known))) {
+            Some((code, (explanation, suggestion))) => ToolError::Known {
+                tool,
+                code,
+                explanation,
+                suggestion,
+            },
+            None => ToolError::Unknown {
+                tool,
+                code,
+                stderr: stderr.trim().to_string(),
+            },
+        }
+    }
+
+    /// 由 `run_with_output` 等的结果构造，成功（退出码 0）时为 None
+    ///
+    /// 错误输出为空时改用标准输出（bcdboot、diskpart 把错误信息写到标准输出）。
+    pub fn from_output(tool: Tool, output: &CmdOutput) -> Option<Self> {
+        if output.success() {
+            return None;
+        }
+        let detail = if output.stderr.trim().is_empty() { &output.stdout } else { &output.stderr };
+        Some(Self::from_exit(tool, output.exit_code, detail))
+    }
+
+    /// 由启动进程时的 IO 错误构造；没有系统错误码或错误码没有说明时为 `Unknown`
+    pub fn from_launch_error(tool: Tool, error: &std::io::Error) -> Self {
+        let known = error
+            .raw_os_error()
+            .and_then(|code| explain_launch(code).map(|known| (code, known)));
+        match known {
+            Some((code, (explanation, suggestion))) => ToolError::LaunchFailed {
+                tool,
+                code,
+                explanation,
+                suggestion,
+            },
+            None => ToolError::Unknown {
+                tool,
+                code: error.raw_os_error(),
+                stderr: format!("启动失败: {}", error),
+            },
+        }
+    }
+
+    pub fn tool(&self) -> Tool {
+        match self {
+            ToolError::Known { tool, .. } | ToolError::Unknown { tool, .. } | ToolError::LaunchFailed { tool, .. } => {
+                *tool
+            }
+        }
+    }
+
+    /// 原始退出码（启动失败时为系统错误码）
+    pub fn code(&self) -> Option<i32> {
+        match self {
+            ToolError::Known { code, .. } | ToolError::LaunchFailed { code, .. } => Some(*code),
+            ToolError::Unknown { code, .. } => *code,
+        }
+    }
+}
+
+/// HRESULT 按十六进制显示（与微软文档和搜索结果一致），其它按十进制
+fn format_code(code: i32) -> String {
+    if code < 0 {
+        format!("0x{:08X}", code as u32)
+    } else {
+        code.to_string()
+    }
+}
+
+fn detail_or_default(stderr: &str) -> &str {
+    if stderr.is_empty() {
+        "没有错误输出"
+    } else {
+        stderr
+    }
+}
+
+/// 已知退出码的 (原因, 建议)
+fn explain_exit(tool: Tool, code: i32) -> Option<(&'static str, &'static str)> {
+    match tool {
+        Tool::Dism => explain_dism(code as u32).or_else(|| win32_code(code).and_then(explain_win32)),
+        Tool::Bcdboot => win32_code(code).and_then(explain_win32),
+        Tool::Diskpart => explain_diskpart(code),
+        Tool::Aria2c => explain_aria2c(code),
+        Tool::SevenZip => explain_7z(code),
+        // wimlib 的退出码是内部错误枚举，错误输出已经说明原因
+        Tool::Wimlib => None,
+    }
+}
+
+/// 退出码对应的 Win32 错误码：本身就是 Win32 错误码，或 `HRESULT_FROM_WIN32` 形式（0x8007xxxx）
+fn win32_code(code: i32) -> Option<u32> {
+    let code = code as u32;
+    if code & 0xFFFF_0000 == 0x8007_0000 {
+        Some(code & 0xFFFF)
+    } else if code <= 0xFFFF {
+        Some(code)
+    } else {
+        None
+    }
+}
+
+fn explain_dism(hresult: u32) -> Option<(&'static str, &'static str)> {
+    Some(match hresult {
+        0x800F_081F => ("找不到所需的源文件", "请指定包含 sources\\sxs 的安装介质作为来源，或换用完整的系统镜像"),
+        0x800F_0906 => ("无法下载所需的源文件", "请检查网络连接，或指定本地安装介质作为来源"),
+        0x800F_0954 => ("无法从 Windows 更新获取文件（通常是组策略指向了 WSUS 服务器）", "请指定本地安装介质作为来源"),
+        0x800F_0922 => ("更新或功能处理失败", "请确认系统保留分区有足够的剩余空间并已联网，然后重试"),
+        0x800F_082F => ("系统中有挂起的操作", "请重启电脑后再试"),
+        0xC142_0117 => ("镜像未能完全卸载，挂载目录中仍有文件被占用", "请关闭打开挂载目录的程序，然后执行 dism /Cleanup-Wim"),
+        _ => return None,
+    })
+}
+
+fn explain_win32(code: u32) -> Option<(&'static str, &'static str)> {
+    Some(match code {
+        2 => ("找不到指定的文件", "请检查镜像或源路径是否正确"),
+        3 => ("找不到指定的路径", "请检查镜像或源路径是否正确，目标分区是否已分配盘符"),
+        5 => ("拒绝访问", "请以管理员身份运行，并关闭正在使用目标文件或分区的程序（包括杀毒软件）"),
+        13 => ("数据无效，镜像文件可能已损坏", "请重新下载镜像并校验"),
+        14 => ("内存不足", "请关闭其它程序后重试"),
+        32 => ("文件正被其它进程使用", "请关闭占用文件的程序（如资源管理器窗口、杀毒软件）后重试"),
+        50 => ("当前环境不支持此操作", "请在 PE 或相应版本的 Windows 中执行"),
+        87 => ("参数错误（如镜像索引不存在或路径格式不对）", "请检查所选的镜像索引和目标分区"),
+        112 => ("磁盘空间不足", "请清理目标磁盘，或换一个更大的分区"),
+        1392 => ("文件或目录已损坏且无法读取", "请对磁盘运行 chkdsk，或重新下载镜像"),
+        1393 => ("磁盘结构已损坏且无法读取", "请对磁盘运行 chkdsk /f 后重试"),
+        _ => return None,
+    })
+}
+
+/// diskpart 的退出码见微软文档「DiskPart error codes」
+fn explain_diskpart(code: i32) -> Option<(&'static str, &'static str)> {
+    Some(match code {
+        1 => ("发生严重错误", "请重启电脑后重试，仍然失败可能是磁盘硬件故障"),
+        2 => ("命令参数不正确", "请将日志反馈给开发者"),
+        3 => ("无法打开脚本文件", "请确认临时目录可以写入"),
+        4 => ("diskpart 使用的系统服务返回了错误", "请确认「虚拟磁盘」服务可以启动"),
+        5 => ("命令不适用于所选的磁盘或分区", "请确认所选的磁盘和分区仍然存在，并刷新分区列表后重试"),
+        _ => return None,
+    })
+}
+
+/// aria2c 的退出码见 aria2 文档「EXIT STATUS」一节
+fn explain_aria2c(code: i32) -> Option<(&'static str, &'static str)> {
+    Some(match code {
+        9 => ("磁盘空间不足", "请清理保存位置所在的磁盘，或换一个保存位置"),
+        16 | 18 => ("无法创建文件或目录", "请确认保存目录存在且有写入权限"),
+        28 => ("不支持的命令行选项", "请使用程序自带的 aria2c.exe"),
+        _ => return None,
+    })
+}
+
+/// 7-Zip 的退出码：1 为警告（解压已完成），不作为错误
+fn explain_7z(code: i32) -> Option<(&'static str, &'static str)> {
+    Some(match code {
+        2 => ("发生致命错误，压缩包可能已损坏或格式不受支持", "请重新下载压缩包，并确认解压目录所在磁盘有足够的剩余空间"),
+        7 => ("命令行参数错误", "请将日志反馈给开发者"),
+        8 => ("内存不足", "请关闭其它程序后重试"),
+        255 => ("解压被中止", "请重新解压"),
+        _ => return None,
+    })
+}
+
+fn explain_launch(code: i32) -> Option<(&'static str, &'static str)> {
+    Some(match code {
+        2 | 3 => ("找不到程序文件", "请重新解压或安装本程序，确认 bin 目录完整"),
+        5 => ("拒绝访问，程序可能被杀毒软件拦截", "请将本程序目录加入杀毒软件的信任列表"),
+        193 | 216 => ("程序文件已损坏或与系统不兼容", "请重新下载本程序"),
+        225 => ("程序被杀毒软件识别为病毒并阻止", "请将本程序目录加入杀毒软件的信任列表"),
+        1260 => ("程序被组策略阻止运行", "请联系管理员调整软件限制策略"),
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_known_exit_codes() {
+        // 0x80070005 以十进制负数形式出现在退出码中
+        let error = ToolError::from_exit(Tool::Dism, Some(-2147024891), "");
+        assert!(matches!(error, ToolError::Known { explanation: "拒绝访问", .. }));
+        assert!(error.to_string().starts_with("DISM 失败（错误码 0x80070005）：拒绝访问。"));
+        assert_eq!(error.code(), Some(-2147024891));
+
+        let error = ToolError::from_exit(Tool::Dism, Some(0x800F081Fu32 as i32), "");
+        assert!(matches!(error, ToolError::Known { explanation: "找不到所需的源文件", .. }));
+        // diskpart 的退出码不是 Win32 错误码
+        let error = ToolError::from_exit(Tool::Diskpart, Some(2), "");
+        assert!(matches!(error, ToolError::Known { explanation: "命令参数不正确", .. }));
+    }
+
+    #[test]
+    fn test_unknown_exit_code_keeps_output() {
+        let error = ToolError::from_exit(Tool::Bcdboot, Some(-1073741823), "  Failure when attempting to copy boot files.\r\n");
+        assert_eq!(
+            error.to_string(),
+            "bcdboot 失败（错误码 0xC0000001）: Failure when attempting to copy boot files."
+        );
+        let error = ToolError::from_exit(Tool::Aria2c, None, "");
+        assert_eq!(error.to_string(), "aria2c 失败（错误码 无）: 没有错误输出");
+
+        let error = ToolError::from_launch_error(Tool::Aria2c, &std::io::Error::from_raw_os_error(225));
+        assert!(matches!(error, ToolError::LaunchFailed { code: 225, .. }));
+    }
+}